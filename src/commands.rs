@@ -1,17 +1,55 @@
 pub mod cat_file;
+pub mod checkout;
 pub mod commit_tree;
+pub mod diff;
+pub mod diff_tree;
+pub mod fetch;
+pub mod fsck;
+pub mod gc;
 pub mod git_init;
 pub mod hash_object;
+pub mod index_pack;
+pub mod log;
 pub mod ls_tree;
+pub mod pack_objects;
+pub mod push;
+pub mod repack;
+pub mod rev_list;
+pub mod show;
+pub mod switch;
+pub mod verify_pack;
 pub mod write_tree;
 pub mod clone;
 
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, ArgMatches, Parser, Subcommand};
 use std::path::PathBuf;
 
+use regex::bytes::Regex;
+
+use crate::git::{
+    diff::{display, lines, patch, unified},
+    pathspec, repository,
+};
+
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 pub struct Args {
+    /// Never page output.
+    #[clap(short = 'P', long)]
+    pub no_pager: bool,
+
+    /// Run as if started in `<path>`, each one relative to the last.
+    #[clap(short = 'C', value_name = "path")]
+    pub directories: Vec<PathBuf>,
+
+    /// The repository metadata directory, instead of `.git`.
+    #[clap(long, value_name = "path")]
+    pub git_dir: Option<PathBuf>,
+
+    /// The root of the checked out files, instead of the current directory.
+    #[clap(long, value_name = "path")]
+    pub work_tree: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Cli,
 }
@@ -20,21 +58,119 @@ pub struct Args {
 pub enum Cli {
     Init,
     CatFile {
-        #[clap(short = 'p')]
+        #[clap(short = 'p', group = "mode")]
         pretty: bool,
-        object: String,
+
+        #[clap(short = 't', group = "mode")]
+        show_type: bool,
+
+        #[clap(short = 's', group = "mode")]
+        size: bool,
+
+        /// Exit with status 0 if the object exists, 1 otherwise.
+        #[clap(short = 'e', group = "mode")]
+        exists: bool,
+
+        /// Print the name, type, size and content of each object named on
+        /// standard input, or what `<format>` says before the content.
+        #[clap(long, group = "mode", value_name = "format", num_args = 0..=1, require_equals = true)]
+        batch: Option<Option<String>>,
+
+        /// Like --batch, without the content.
+        #[clap(long, group = "mode", value_name = "format", num_args = 0..=1, require_equals = true)]
+        batch_check: Option<Option<String>>,
+
+        /// Read `contents <object>`, `info <object>` and `flush` commands
+        /// from standard input.
+        #[clap(long, group = "mode", value_name = "format", num_args = 0..=1, require_equals = true)]
+        batch_command: Option<Option<String>>,
+
+        /// With a batch mode, show every object instead of reading standard input.
+        #[clap(long)]
+        batch_all_objects: bool,
+
+        /// Only flush batch output at the end, or on `flush` commands.
+        #[clap(long)]
+        buffer: bool,
+
+        /// Standard input is NUL-terminated.
+        #[clap(short = 'z')]
+        nul: bool,
+
+        /// The object, after its type without one of the options.
+        #[clap(required_unless_present_any = ["batch", "batch_check", "batch_command"], num_args = 1..=2)]
+        args: Vec<String>,
     },
     HashObject {
+        /// Type of the object to create.
+        #[clap(short = 't', default_value = "blob")]
+        kind: String,
+
         #[clap(short = 'w')]
         write: bool,
 
-        file: PathBuf,
+        /// Read the object from standard input.
+        #[clap(long)]
+        stdin: bool,
+
+        /// Read file names from standard input, one per line.
+        #[clap(long)]
+        stdin_paths: bool,
+
+        /// Hash any type and content, without checking them.
+        #[clap(long)]
+        literally: bool,
+
+        /// Hash the content as if it were at this path.
+        #[clap(long)]
+        path: Option<String>,
+
+        /// Hash the content as it is, without filters.
+        #[clap(long)]
+        no_filters: bool,
+
+        files: Vec<PathBuf>,
     },
     LsTree {
-        #[clap(long)]
+        /// List only file names.
+        #[clap(long, visible_alias = "name-status")]
         name_only: bool,
 
-        tree_hash: String,
+        /// List only object names.
+        #[clap(long)]
+        object_only: bool,
+
+        /// Recurse into subtrees.
+        #[clap(short = 'r')]
+        recursive: bool,
+
+        /// Show trees when recursing.
+        #[clap(short = 't')]
+        show_trees: bool,
+
+        /// Only show trees.
+        #[clap(short = 'd')]
+        trees_only: bool,
+
+        /// Include the size of blobs.
+        #[clap(short = 'l', long)]
+        long: bool,
+
+        /// Terminate entries with NUL.
+        #[clap(short = 'z')]
+        nul: bool,
+
+        /// Show paths from the top of the work tree, not the current directory.
+        #[clap(long)]
+        full_name: bool,
+
+        /// List the whole tree, paths given being from its top.
+        #[clap(long)]
+        full_tree: bool,
+
+        tree_ish: String,
+
+        paths: Vec<String>,
     },
     WriteTree {},
     CommitTree {
@@ -49,9 +185,655 @@ pub enum Cli {
     Clone {
         source: String,
         dir: Option<PathBuf>,
+
+        /// Check out this branch of the remote instead of the one its HEAD
+        /// is on, or detach HEAD at this tag.
+        #[clap(short = 'b', long)]
+        branch: Option<String>,
+
+        /// Leave the index and worktree empty.
+        #[clap(short = 'n', long)]
+        no_checkout: bool,
+    },
+    Switch {
+        branch: Option<String>,
+
+        #[clap(short = 'c', long = "create")]
+        create: Option<String>,
+
+        #[clap(short = 'C', long = "force-create")]
+        force_create: Option<String>,
+
+        #[clap(long)]
+        detach: bool,
+
+        #[clap(short = 'f', long = "discard-changes", alias = "force")]
+        force: bool,
+    },
+    Checkout {
+        #[clap(short = 'b')]
+        new_branch: Option<String>,
+
+        #[clap(short = 'B')]
+        force_new_branch: Option<String>,
+
+        #[clap(long)]
+        detach: bool,
+
+        #[clap(short = 'f', long)]
+        force: bool,
+
+        rev: Option<String>,
+
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
+    Log {
+        #[clap(long)]
+        oneline: bool,
+
+        #[clap(long, alias = "pretty")]
+        format: Option<String>,
+
+        #[clap(long)]
+        date: Option<String>,
+
+        #[clap(short = 'n', long)]
+        max_count: Option<usize>,
+
+        #[clap(long)]
+        skip: Option<usize>,
+
+        #[clap(long)]
+        first_parent: bool,
+
+        #[clap(long)]
+        reverse: bool,
+
+        #[clap(long)]
+        author: Vec<String>,
+
+        #[clap(long)]
+        grep: Vec<String>,
+
+        #[clap(short = 'i', long = "regexp-ignore-case")]
+        ignore_case: bool,
+
+        #[clap(long, alias = "after")]
+        since: Option<String>,
+
+        #[clap(long, alias = "before")]
+        until: Option<String>,
+
+        #[clap(flatten)]
+        diff: DiffArgs,
+
+        revisions: Vec<String>,
+
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
+    DiffTree {
+        #[clap(short = 'r')]
+        recursive: bool,
+
+        #[clap(short = 't')]
+        show_trees: bool,
+
+        #[clap(long)]
+        root: bool,
+
+        #[clap(long)]
+        no_commit_id: bool,
+
+        #[clap(flatten)]
+        diff: DiffArgs,
+
+        revisions: Vec<String>,
+
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
+    Diff {
+        /// Compare the index instead of the worktree.
+        #[clap(long, alias = "staged")]
+        cached: bool,
+
+        #[clap(flatten)]
+        diff: DiffArgs,
+
+        revisions: Vec<String>,
+
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
+    Show {
+        #[clap(long)]
+        oneline: bool,
+
+        #[clap(long, alias = "pretty")]
+        format: Option<String>,
+
+        #[clap(long)]
+        date: Option<String>,
+
+        /// Leave out the diff of commits.
+        #[clap(short = 's', long)]
+        no_patch: bool,
+
+        #[clap(flatten)]
+        diff: DiffArgs,
+
+        revisions: Vec<String>,
+
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
+    RevList {
+        #[clap(long)]
+        objects: bool,
+
+        /// Walk from every ref and HEAD.
+        #[clap(long, action = ArgAction::Append, num_args = 0..=1, require_equals = true, default_missing_value = "")]
+        all: Vec<String>,
+
+        /// Flip whether the revisions after it are walked or hidden.
+        #[clap(long, action = ArgAction::Append, num_args = 0..=1, require_equals = true, default_missing_value = "")]
+        not: Vec<String>,
+
+        #[clap(long)]
+        count: bool,
+
+        #[clap(short = 'n', long)]
+        max_count: Option<usize>,
+
+        #[clap(long)]
+        topo_order: bool,
+
+        #[clap(long)]
+        date_order: bool,
+
+        #[clap(long)]
+        left_right: bool,
+
+        #[clap(long)]
+        filter: Option<String>,
+
+        /// Revisions to walk, `^` or `--not` before them to hide them.
+        revisions: Vec<String>,
+    },
+    IndexPack {
+        /// Read the pack from standard input, into `<pack>` if given.
+        #[clap(long)]
+        stdin: bool,
+
+        /// Complete a thin pack with the bases it lacks from the object database.
+        #[clap(long)]
+        fix_thin: bool,
+
+        /// Write the index to this file.
+        #[clap(short = 'o')]
+        output: Option<PathBuf>,
+
+        /// Write a reverse index next to the index.
+        #[clap(long, overrides_with = "no_rev_index")]
+        rev_index: bool,
+
+        #[clap(long, overrides_with = "rev_index")]
+        no_rev_index: bool,
+
+        #[clap(required_unless_present = "stdin")]
+        pack: Option<PathBuf>,
+    },
+    PackObjects {
+        /// Write the pack to standard output rather than to files.
+        #[clap(long)]
+        stdout: bool,
+
+        /// Read revisions from standard input and pack the objects they
+        /// need, rather than objects named there.
+        #[clap(long)]
+        revs: bool,
+
+        /// Pack everything reachable from a ref, as if all refs were given.
+        #[clap(long)]
+        all: bool,
+
+        /// How many objects to try as the delta base of each.
+        #[clap(long)]
+        window: Option<usize>,
+
+        /// Most deltas an object may be rebuilt through.
+        #[clap(long)]
+        depth: Option<usize>,
+
+        /// Write `<base-name>-<checksum>.pack` and `.idx`.
+        base_name: Option<PathBuf>,
+    },
+    VerifyPack {
+        /// List each object, then how long delta chains are.
+        #[clap(short = 'v', long)]
+        verbose: bool,
+
+        /// Only show how long delta chains are.
+        #[clap(short = 's', long)]
+        stat_only: bool,
+
+        #[clap(required = true)]
+        packs: Vec<PathBuf>,
+    },
+    Repack {
+        /// Pack everything reachable into one pack, not only loose objects.
+        #[clap(short = 'a')]
+        all: bool,
+
+        /// Like `-a`, keeping the unreachable objects of removed packs loose.
+        #[clap(short = 'A')]
+        loosen_unreachable: bool,
+
+        /// Remove the packs and loose objects the new pack makes redundant.
+        #[clap(short = 'd')]
+        delete: bool,
+
+        #[clap(short = 'q', long)]
+        quiet: bool,
+    },
+    Gc {
+        /// Only run if there are enough loose objects or packs to be worth it.
+        #[clap(long)]
+        auto: bool,
+
+        /// Prune unreachable loose objects older than this date.
+        #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "now")]
+        prune: Option<String>,
+
+        #[clap(long, conflicts_with = "prune")]
+        no_prune: bool,
+
+        #[clap(short = 'q', long)]
+        quiet: bool,
+    },
+    Fsck {
+        /// Only check that objects are reachable, not their content.
+        #[clap(long)]
+        connectivity_only: bool,
+
+        /// Write dangling objects to `.git/lost-found`, ignoring reflogs.
+        #[clap(long)]
+        lost_found: bool,
+
+        /// Treat warnings about objects as errors.
+        #[clap(long)]
+        strict: bool,
+    },
+    Fetch {
+        remote: Option<String>,
+
+        refspecs: Vec<String>,
+
+        /// Delete remote-tracking refs for refs the remote no longer has.
+        #[clap(short = 'p', long)]
+        prune: bool,
+
+        /// Fetch all tags, along with whatever else is fetched.
+        #[clap(short = 't', long)]
+        tags: bool,
+
+        /// Allow updates that are not fast-forwards.
+        #[clap(short = 'f', long)]
+        force: bool,
+
+        /// Also show refs that are up to date.
+        #[clap(short = 'v', long)]
+        verbose: bool,
+    },
+    Push {
+        remote: Option<String>,
+
+        refspecs: Vec<String>,
+
+        /// Allow updates that are not fast-forwards.
+        #[clap(short = 'f', long)]
+        force: bool,
+
+        /// Allow updates that are not fast-forwards of refs that are where
+        /// they were last fetched from, or where given.
+        #[clap(long, value_name = "refname[:expect]", num_args = 0..=1, require_equals = true, default_missing_value = "")]
+        force_with_lease: Option<String>,
+
+        /// Delete the refs given from the remote.
+        #[clap(short = 'd', long)]
+        delete: bool,
+
+        /// Update either all refs on the remote or none.
+        #[clap(long)]
+        atomic: bool,
+
+        /// Push all tags, along with whatever else is pushed.
+        #[clap(long)]
+        tags: bool,
+
+        /// Also show refs that are up to date.
+        #[clap(short = 'v', long)]
+        verbose: bool,
     },
 }
 
+/// Options of the commands showing content changes.
+#[derive(Debug, clap::Args)]
+pub struct DiffArgs {
+    #[clap(long)]
+    pub name_only: bool,
+
+    #[clap(long)]
+    pub name_status: bool,
+
+    #[clap(short = 'p', short_alias = 'u', long)]
+    pub patch: bool,
+
+    /// Lines of context, implies `-p`.
+    #[clap(short = 'U', long)]
+    pub unified: Option<usize>,
+
+    #[clap(long)]
+    pub inter_hunk_context: Option<usize>,
+
+    #[clap(short = 'W', long)]
+    pub function_context: bool,
+
+    #[clap(long)]
+    pub raw: bool,
+
+    #[clap(long)]
+    pub stat: bool,
+
+    #[clap(long)]
+    pub numstat: bool,
+
+    #[clap(short = 'w', long)]
+    pub ignore_all_space: bool,
+
+    #[clap(short = 'b', long)]
+    pub ignore_space_change: bool,
+
+    #[clap(long)]
+    pub ignore_space_at_eol: bool,
+
+    #[clap(long)]
+    pub ignore_blank_lines: bool,
+
+    #[clap(long)]
+    pub diff_algorithm: Option<String>,
+
+    #[clap(long)]
+    pub minimal: bool,
+
+    #[clap(long)]
+    pub patience: bool,
+
+    #[clap(long)]
+    pub histogram: bool,
+
+    #[clap(long)]
+    pub no_indent_heuristic: bool,
+
+    #[clap(long)]
+    pub full_index: bool,
+
+    /// Detect renames, optionally with a minimum similarity (`-M50%`).
+    #[clap(short = 'M', long, num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    pub find_renames: Option<String>,
+
+    /// Detect copies as well as renames.
+    #[clap(short = 'C', long, num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    pub find_copies: Option<String>,
+
+    #[clap(long)]
+    pub find_copies_harder: bool,
+
+    #[clap(long)]
+    pub no_renames: bool,
+
+    /// `always`, `never` or `auto`, when writing to a terminal.
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "always")]
+    pub color: Option<String>,
+
+    #[clap(long)]
+    pub no_color: bool,
+
+    /// `plain`, `color`, `porcelain` or `none`.
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "plain")]
+    pub word_diff: Option<String>,
+
+    /// What a word is, implies `--word-diff`.
+    #[clap(long)]
+    pub word_diff_regex: Option<String>,
+
+    /// `--word-diff=color`, optionally with a word regex.
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    pub color_words: Option<String>,
+
+    /// `zebra` by default, see [`display::ColorMoved`].
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "zebra")]
+    pub color_moved: Option<String>,
+
+    #[clap(long)]
+    pub no_color_moved: bool,
+}
+
+impl DiffArgs {
+    /// Whether patches are asked for, explicitly or through an option that
+    /// only makes sense with them.
+    pub fn shows_patch(&self) -> bool {
+        self.patch || self.unified.is_some() || self.function_context
+    }
+
+    /// Whether any output format was picked, raw output being the default.
+    pub fn picks_format(&self) -> bool {
+        self.shows_patch() || self.raw || self.stat || self.numstat
+    }
+
+    /// Whether only the names of changed files are shown.
+    pub fn names(&self) -> bool {
+        self.name_only || self.name_status
+    }
+
+    /// How patches are shown, colored if `auto_color` says so and no
+    /// option decides otherwise.
+    pub fn display_options(&self, auto_color: bool) -> display::Options {
+        let mut color = match self.color.as_deref() {
+            _ if self.no_color => false,
+            Some("always") => true,
+            Some("never") => false,
+            Some("auto") | None => auto_color,
+            Some(_) => fatal("option `color' expects \"always\", \"auto\", or \"never\""),
+        };
+
+        let mut word_diff = match &self.word_diff {
+            Some(mode) => mode.parse().unwrap_or_else(|e| fatal(e)),
+            None => display::WordDiff::None,
+        };
+        let mut regex = self.word_diff_regex.clone();
+        if let Some(value) = &self.color_words {
+            word_diff = display::WordDiff::Color;
+            if !value.is_empty() {
+                regex = Some(value.clone());
+            }
+        }
+        if regex.is_some() && word_diff == display::WordDiff::None {
+            word_diff = display::WordDiff::Plain;
+        }
+        color |= word_diff == display::WordDiff::Color;
+
+        let color_moved = match &self.color_moved {
+            _ if self.no_color_moved => display::ColorMoved::No,
+            Some(mode) => mode.parse().unwrap_or_else(|e| fatal(e)),
+            None => display::ColorMoved::No,
+        };
+
+        display::Options {
+            color,
+            word_diff,
+            word_regex: regex.map(|x| {
+                Regex::new(&x)
+                    .unwrap_or_else(|e| fatal(format!("invalid regular expression: {x}: {e}")))
+            }),
+            color_moved,
+        }
+    }
+
+    pub fn patch_options(&self) -> patch::Options {
+        let mut algorithm = match &self.diff_algorithm {
+            Some(name) => name.parse().unwrap_or_else(|e| fatal(e)),
+            None => lines::Algorithm::Myers,
+        };
+        for (set, chosen) in [
+            (self.minimal, lines::Algorithm::Minimal),
+            (self.patience, lines::Algorithm::Patience),
+            (self.histogram, lines::Algorithm::Histogram),
+        ] {
+            if set {
+                algorithm = chosen;
+            }
+        }
+
+        patch::Options {
+            lines: lines::Options {
+                algorithm,
+                ignore_all_space: self.ignore_all_space,
+                ignore_space_change: self.ignore_space_change,
+                ignore_space_at_eol: self.ignore_space_at_eol,
+                ignore_blank_lines: self.ignore_blank_lines,
+                indent_heuristic: !self.no_indent_heuristic,
+            },
+            unified: unified::Options {
+                context: self.unified.unwrap_or(3),
+                inter_hunk_context: self.inter_hunk_context.unwrap_or(0),
+                function_context: self.function_context,
+            },
+            full_index: self.full_index,
+        }
+    }
+}
+
+/// Commands taking the similarity of `-M<n>` and `-C<n>` attached.
+const RENAME_COMMANDS: [&str; 4] = ["diff-tree", "diff", "log", "show"];
+
+/// Options before the command that take the next argument as their value.
+const GLOBAL_VALUE_OPTIONS: [&str; 3] = ["-C", "--git-dir", "--work-tree"];
+
+/// The revisions given to `command` with its `--not` and `--all` put back
+/// among them, in the order they came, as a revision walk reads them.
+pub fn revision_args(matches: &ArgMatches, command: &str) -> Vec<String> {
+    let Some(matches) = matches.subcommand_matches(command) else {
+        return Vec::new();
+    };
+
+    let mut args = Vec::new();
+    for (id, option) in [
+        ("not", Some("--not")),
+        ("all", Some("--all")),
+        ("revisions", None),
+    ] {
+        let values = matches.get_many::<String>(id).into_iter().flatten();
+        let indices = matches.indices_of(id).into_iter().flatten();
+        for (index, value) in indices.zip(values) {
+            if option.is_some() && !value.is_empty() {
+                fatal(format!("option '--{id}' takes no value"));
+            }
+            args.push((index, option.map_or_else(|| value.clone(), str::to_string)));
+        }
+    }
+    args.sort();
+    args.into_iter().map(|(_, x)| x).collect()
+}
+
+/// Rewrites shorthands clap cannot express, leaving everything after `--`
+/// untouched: the `-<n>` of history commands becomes `--max-count=<n>` and,
+/// for diff commands, `-M<n>`/`-C<n>` become `--find-renames=<n>` and
+/// `--find-copies=<n>`.
+pub fn expand_shorthands(args: impl Iterator<Item = String>) -> Vec<String> {
+    let mut paths = false;
+    let mut command: Option<String> = None;
+    let mut value = false;
+
+    args.enumerate()
+        .map(|(position, arg)| {
+            paths |= arg == "--";
+            if paths {
+                return arg;
+            }
+            if position > 0 && command.is_none() && !arg.starts_with('-') && !value {
+                command = Some(arg.clone());
+            }
+            value = command.is_none() && GLOBAL_VALUE_OPTIONS.contains(&arg.as_str());
+            let renames = command
+                .as_deref()
+                .is_some_and(|x| RENAME_COMMANDS.contains(&x));
+
+            match arg.strip_prefix('-') {
+                Some(n) if !n.is_empty() && n.bytes().all(|x| x.is_ascii_digit()) => {
+                    format!("--max-count={n}")
+                }
+                Some(value) if renames && value.len() > 1 && value.starts_with('M') => {
+                    format!("--find-renames={}", &value[1..])
+                }
+                Some(value) if renames && value.len() > 1 && value.starts_with('C') => {
+                    format!("--find-copies={}", &value[1..])
+                }
+                _ => arg,
+            }
+        })
+        .collect()
+}
+
+/// Paths given on the command line, relative to the current directory, as
+/// paths from the top of the work tree.
+pub fn pathspecs(paths: &[String]) -> Vec<String> {
+    pathspecs_in(&repository::prefix(), paths)
+}
+
+/// Paths given on the command line, relative to the directory `prefix`
+/// of the work tree, as paths from its top.
+pub fn pathspecs_in(prefix: &str, paths: &[String]) -> Vec<String> {
+    paths
+        .iter()
+        .map(|path| {
+            pathspec::join(prefix, path).unwrap_or_else(|| {
+                let root = repository::work_tree()
+                    .canonicalize()
+                    .unwrap_or_else(|e| fatal(e));
+                fatal(format!(
+                    "{path}: '{path}' is outside repository at '{}'",
+                    root.display()
+                ))
+            })
+        })
+        .collect()
+}
+
+/// The reason for an error, without the error code.
+pub fn reason(e: &std::io::Error) -> String {
+    let message = e.to_string();
+    message
+        .split(" (os error")
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Reports a failed operation like git's `error:` and exits with status 1.
+pub fn error(message: impl std::fmt::Display) -> ! {
+    eprintln!("error: {message}");
+    std::process::exit(1)
+}
+
+/// Reports an unrecoverable problem like git's `fatal:` and exits with status 128.
+pub fn fatal(message: impl std::fmt::Display) -> ! {
+    eprintln!("fatal: {message}");
+    std::process::exit(128)
+}
+
 #[macro_export]
 macro_rules! invoke {
     ($command: ident, $hash: expr, $($args: ident),*) => {
@@ -60,6 +842,12 @@ macro_rules! invoke {
             commands::$command::invoke($hash, options);
         }
     };
+    ($command: ident { $($args: ident),* }) => {
+        {
+            let options = commands::$command::Options { $( $args ),* };
+            commands::$command::invoke(options);
+        }
+    };
     ($command: ident) => {
         {
             commands::$command::invoke();
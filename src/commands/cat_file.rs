@@ -1,21 +1,341 @@
-use core::panic;
+use std::io::{BufRead, BufWriter, Read, Write};
 
-use crate::git::objects::Object;
+use crate::git::{
+    objects::{self, tree, Store, Stream},
+    packfile::ObjType,
+    revision,
+};
+
+use super::fatal;
 
 #[derive(Debug)]
 pub struct Options {
     pub pretty: bool,
+    pub show_type: bool,
+    pub size: bool,
+    pub exists: bool,
+    /// `--batch`, `--batch-check` and `--batch-command`, each with the
+    /// format given to it, if any.
+    pub batch: Option<Option<String>>,
+    pub batch_check: Option<Option<String>>,
+    pub batch_command: Option<Option<String>>,
+    pub batch_all_objects: bool,
+    pub buffer: bool,
+    /// Object names on standard input end with NUL rather than newline.
+    pub nul: bool,
+}
+
+const DEFAULT_FORMAT: &str = "%(objectname) %(objecttype) %(objectsize)";
+
+/// A piece of a `--batch` format.
+#[derive(Debug, PartialEq, Eq)]
+enum Atom {
+    Literal(String),
+    Name,
+    Type,
+    Size,
+    /// The input after the object name.
+    Rest,
+}
+
+/// Splits a format into literal text and `%(...)` elements, `%%` standing
+/// for a lone `%`.
+fn parse_format(format: &str) -> Result<Vec<Atom>, String> {
+    let mut atoms = Vec::new();
+    let mut literal = String::new();
+    let mut rest = format;
+
+    while let Some(start) = rest.find('%') {
+        literal.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        if let Some(after) = rest.strip_prefix('%') {
+            literal.push('%');
+            rest = after;
+            continue;
+        }
+        if !rest.starts_with('(') {
+            literal.push('%');
+            continue;
+        }
+
+        let Some(end) = rest.find(')') else {
+            return Err(format!("format element '{rest}' does not end in ')'"));
+        };
+        let atom = match &rest[1..end] {
+            "objectname" => Atom::Name,
+            "objecttype" => Atom::Type,
+            "objectsize" => Atom::Size,
+            "rest" => Atom::Rest,
+            x => return Err(format!("unknown format element: {x}")),
+        };
+        if !literal.is_empty() {
+            atoms.push(Atom::Literal(std::mem::take(&mut literal)));
+        }
+        atoms.push(atom);
+        rest = &rest[end + 1..];
+    }
+
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        atoms.push(Atom::Literal(literal));
+    }
+    Ok(atoms)
+}
+
+/// Objects looked up one request at a time from a single open store.
+struct Batch {
+    store: Store,
+    format: Vec<Atom>,
+    /// Whether the input is split into an object name and `%(rest)`.
+    split: bool,
+    buffer: bool,
+}
+
+impl Batch {
+    /// Writes the record of the object `input` names, followed by its
+    /// content if `contents`.
+    fn object(&mut self, out: &mut impl Write, input: &str, contents: bool) -> std::io::Result<()> {
+        let (name, rest) = match input.split_once(|x: char| x.is_ascii_whitespace()) {
+            Some((name, rest)) if self.split => (name, rest.trim_start()),
+            _ => (input, ""),
+        };
+
+        let hash = match revision::resolve(name) {
+            Ok(hash) if !name.is_empty() => hash,
+            _ => {
+                let ambiguous = name.len() >= 4
+                    && name.bytes().all(|x| x.is_ascii_hexdigit())
+                    && objects::find_abbrev(name)?.len() > 1;
+                let status = if ambiguous { "ambiguous" } else { "missing" };
+                return writeln!(out, "{name} {status}");
+            }
+        };
+        self.write(out, &hash, name, rest, contents)
+    }
+
+    /// Writes the record of the object `hash`, which the input called
+    /// `name`, or says that it is missing.
+    fn write(
+        &mut self,
+        out: &mut impl Write,
+        hash: &str,
+        name: &str,
+        rest: &str,
+        contents: bool,
+    ) -> std::io::Result<()> {
+        // the content is only opened when it is shown
+        let object = match contents {
+            true => self.store.stream(hash).map(|x| (x.kind, x.size, Some(x))),
+            false => self.store.info(hash).map(|(kind, size)| (kind, size, None)),
+        };
+        let (kind, size, content) = match object {
+            Ok(object) => object,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return writeln!(out, "{name} missing");
+            }
+            Err(e) => return Err(e),
+        };
+
+        for atom in self.format.iter() {
+            match atom {
+                Atom::Literal(text) => write!(out, "{text}")?,
+                Atom::Name => write!(out, "{hash}")?,
+                Atom::Type => write!(out, "{kind}")?,
+                Atom::Size => write!(out, "{size}")?,
+                Atom::Rest => write!(out, "{rest}")?,
+            }
+        }
+        writeln!(out)?;
+
+        if let Some(mut content) = content {
+            std::io::copy(&mut content, out)?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes after every request unless `--buffer` was given.
+    fn done(&self, out: &mut impl Write) -> std::io::Result<()> {
+        match self.buffer {
+            true => Ok(()),
+            false => out.flush(),
+        }
+    }
+
+    /// Runs a `--batch-command` line: `contents <object>`, `info <object>`
+    /// or `flush`.
+    fn command(&mut self, out: &mut impl Write, line: &str) -> std::io::Result<()> {
+        if line.is_empty() {
+            fatal("empty command in input");
+        }
+        if line.starts_with(|x: char| x.is_ascii_whitespace()) {
+            fatal(format!("whitespace before command: '{line}'"));
+        }
+
+        let (command, argument) = match line.split_once(' ') {
+            Some((command, argument)) => (command, Some(argument)),
+            None => (line, None),
+        };
+        match (command, argument) {
+            ("flush", None) if self.buffer => out.flush(),
+            ("flush", None) => fatal("flush is only for --buffer mode"),
+            ("flush", Some(_)) => fatal("flush takes no arguments"),
+            ("contents" | "info", None) => fatal(format!("{command} requires arguments")),
+            ("contents", Some(object)) => self.object(out, object, true),
+            ("info", Some(object)) => self.object(out, object, false),
+            _ => fatal(format!("unknown command: '{line}'")),
+        }
+    }
 }
 
-pub fn invoke(hash: &str, options: Options) {
-    let data = match Object::read_from_hash(hash) {
-        Ok(ok) => ok,
-        Err(e) => panic!("{}", e),
+/// Reads object names, or commands with `--batch-command`, from standard
+/// input until it ends, answering each in turn.
+fn batch(options: &Options) -> std::io::Result<()> {
+    let modes = [&options.batch, &options.batch_check, &options.batch_command];
+    let format = modes.into_iter().flatten().next().cloned().flatten();
+    let format = format.as_deref().unwrap_or(DEFAULT_FORMAT);
+    let format = parse_format(format).unwrap_or_else(|e| fatal(e));
+
+    let mut batch = Batch {
+        store: Store::open()?,
+        split: format.contains(&Atom::Rest),
+        format,
+        buffer: options.buffer,
+    };
+    let contents = options.batch.is_some();
+    let mut out = BufWriter::new(std::io::stdout().lock());
+
+    if options.batch_all_objects {
+        for hash in batch.store.names()? {
+            batch.write(&mut out, &hash, &hash, "", contents)?;
+            batch.done(&mut out)?;
+        }
+        return out.flush();
+    }
+
+    let delimiter = if options.nul { b'\0' } else { b'\n' };
+    let mut input = std::io::stdin().lock();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if input.read_until(delimiter, &mut line)? == 0 {
+            break;
+        }
+        if line.last() == Some(&delimiter) {
+            line.pop();
+            if !options.nul && line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        }
+
+        let line = String::from_utf8_lossy(&line);
+        match options.batch_command.is_some() {
+            true => batch.command(&mut out, &line)?,
+            false => batch.object(&mut out, &line, contents)?,
+        }
+        batch.done(&mut out)?;
+    }
+
+    out.flush()
+}
+
+/// Writes an object the way `-p` shows it: trees as a listing, anything
+/// else as it is stored.
+fn write_pretty(out: &mut impl Write, mut object: Stream) -> std::io::Result<()> {
+    if object.kind != ObjType::Tree {
+        return std::io::copy(&mut object, out).map(|_| ());
+    }
+
+    let mut data = Vec::new();
+    object.read_to_end(&mut data)?;
+    for item in tree::parse(&mut &data[..])? {
+        writeln!(
+            out,
+            "{:06o} {} {}\t{}",
+            item.mode,
+            item.kind(),
+            hex::encode(item.hash),
+            item.name
+        )?;
+    }
+    Ok(())
+}
+
+pub fn invoke(args: Vec<String>, options: Options) {
+    let batched =
+        options.batch.is_some() || options.batch_check.is_some() || options.batch_command.is_some();
+    let required = [
+        (options.batch_all_objects, "--batch-all-objects"),
+        (options.buffer, "--buffer"),
+        (options.nul, "-z"),
+    ];
+    if let Some((_, flag)) = required.iter().find(|(given, _)| *given && !batched) {
+        fatal(format!("'{flag}' requires a batch mode"));
+    }
+
+    if batched {
+        if !args.is_empty() {
+            fatal("batch modes take no arguments");
+        }
+        if let Err(e) = batch(&options) {
+            if e.kind() != std::io::ErrorKind::BrokenPipe {
+                fatal(e);
+            }
+        }
+        return;
+    }
+
+    let mode = options.pretty || options.show_type || options.size || options.exists;
+    let (kind, object) = match (args.as_slice(), mode) {
+        ([object], true) => (None, object),
+        ([kind, object], false) => {
+            let kind = kind
+                .parse::<ObjType>()
+                .unwrap_or_else(|_| fatal(format!("invalid object type \"{kind}\"")));
+            (Some(kind), object)
+        }
+        ([_], false) => fatal("only two arguments allowed in <type> <object> mode, not 1"),
+        _ => fatal("too many arguments"),
     };
 
-    if options.pretty == false {
-        panic!("we now only support pretty print");
+    let hash = revision::resolve(object).unwrap_or_else(|e| match object.contains(':') {
+        true => fatal(e),
+        false => fatal(format!("Not a valid object name {object}")),
+    });
+
+    // `<type>` dereferences tags and commits down to an object of that type
+    let hash = match kind {
+        Some(kind) => revision::peel(&hash, Some(kind))
+            .unwrap_or_else(|_| fatal(format!("git cat-file {object}: bad file"))),
+        None => hash,
+    };
+
+    let mut object = match objects::stream(&hash) {
+        Ok(object) => object,
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => fatal(e),
+        Err(_) if options.exists => std::process::exit(1),
+        Err(_) if options.pretty => fatal(format!("Not a valid object name {object}")),
+        Err(_) => fatal("git cat-file: could not get object info"),
+    };
+    if options.exists {
+        return;
     }
 
-    data.cat();
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    let written = if options.show_type {
+        writeln!(out, "{}", object.kind)
+    } else if options.size {
+        writeln!(out, "{}", object.size)
+    } else if options.pretty {
+        write_pretty(&mut out, object)
+    } else {
+        std::io::copy(&mut object, &mut out).map(|_| ())
+    };
+
+    if let Err(e) = written.and_then(|_| out.flush()) {
+        if e.kind() != std::io::ErrorKind::BrokenPipe {
+            fatal(e);
+        }
+    }
 }
@@ -0,0 +1,60 @@
+use crate::git::{
+    checkout,
+    index::Index,
+    objects::tree::{self, Files},
+    packfile::ObjType,
+    refs, repository, revision,
+};
+
+use super::{error, fatal, pathspecs, switch};
+
+#[derive(Debug)]
+pub struct Options {
+    pub new_branch: Option<String>,
+    pub force_new_branch: Option<String>,
+    pub detach: bool,
+    pub force: bool,
+    pub paths: Vec<String>,
+}
+
+pub fn invoke(rev: Option<String>, options: Options) {
+    if !options.paths.is_empty() {
+        checkout_paths(rev.as_deref(), &pathspecs(&options.paths));
+    } else if let Some(name) = options.new_branch {
+        switch::create_branch(&name, rev.as_deref(), false, options.force);
+    } else if let Some(name) = options.force_new_branch {
+        switch::create_branch(&name, rev.as_deref(), true, options.force);
+    } else {
+        let Some(rev) = rev else {
+            fatal("you must specify a branch, commit or paths to check out");
+        };
+
+        // like git, a name that is not a local branch detaches HEAD
+        if !options.detach && refs::exists(&format!("refs/heads/{rev}")) {
+            switch::switch_branch(&rev, options.force);
+        } else {
+            switch::detach(&rev, options.force);
+        }
+    }
+}
+
+/// `checkout [<rev>] -- <paths>` restores files from a commit, or from the
+/// index when no revision is given.
+fn checkout_paths(rev: Option<&str>, paths: &[String]) {
+    let (source, stage): (Files, bool) = match rev {
+        Some(rev) => {
+            let tree = revision::resolve(rev)
+                .and_then(|x| revision::peel(&x, Some(ObjType::Tree)))
+                .unwrap_or_else(|e| fatal(e));
+            (tree::flatten(&tree).unwrap_or_else(|e| fatal(e)), true)
+        }
+        None => {
+            let index = Index::open(&repository::index_file()).unwrap_or_else(|e| fatal(e));
+            (checkout::index_files(&index), false)
+        }
+    };
+
+    if let Err(e) = checkout::checkout_paths(&source, paths, stage) {
+        error(e);
+    }
+}
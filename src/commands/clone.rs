@@ -1,286 +1,277 @@
-use core::panic;
 use reqwest::blocking::Client;
-use sha1::{Digest, Sha1};
 use std::{
-    collections::{self, HashMap, VecDeque},
     fs,
-    io::{Read, Seek, Write},
-    os::unix::fs::PermissionsExt,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use crate::git::{
-    objects::*,
-    packfile::{headers, read_object, ObjType},
+    checkout,
+    config::{self, Config},
+    fetch_pack::{self, Advertisement},
+    objects::{self, commit},
+    packfile::ObjType,
+    refs, repository, revision,
 };
 
-use super::git_init;
+use super::{fatal, reason};
 
 #[derive(Debug)]
 pub struct Options {
     pub dir: Option<PathBuf>,
+    pub branch: Option<String>,
+    pub no_checkout: bool,
 }
 
-#[allow(unused)]
-pub fn invoke(url: &str, options: Options) {
-    let client = Client::builder().build().unwrap();
+/// Refs a clone asks for.
+const PREFIXES: [&str; 3] = ["HEAD", "refs/heads/", "refs/tags/"];
 
-    let mut resp = client
-        .get(format!("{url}/info/refs"))
-        .query(&[("service", "git-upload-pack")])
-        .send()
-        .unwrap();
+/// What clones call the repository they come from.
+const ORIGIN: &str = "origin";
 
-    let mut state = 0;
+/// What git says when a clone leaves HEAD detached.
+const DETACHED_ADVICE: &str = "\
+You are in 'detached HEAD' state. You can look around, make experimental
+changes and commit them, and you can discard any commits you make in this
+state without impacting any branches by switching back to a branch.
 
-    let mut pack_line: Vec<PktLine> = Vec::new();
+If you want to create a new branch to retain commits you create, you may
+do so (now or later) by using -c with the switch command. Example:
 
-    loop {
-        let mut length: [u8; 4] = [0; 4];
-        resp.read_exact(&mut length).unwrap();
+  git switch -c <new-branch-name>
 
-        let s = String::from_utf8(length.to_vec()).unwrap();
-        let length = usize::from_str_radix(&s, 16).unwrap();
+Or undo this operation with:
 
-        if length == 0 {
-            state += 1
-        }
+  git switch -
 
-        if length != 0 {
-            let mut buf = vec![0; length - 4];
-            resp.read_exact(&mut buf).unwrap();
-            pack_line.push(PktLine {
-                length,
-                value: buf.to_vec(),
-            });
-        }
+Turn off this advice by setting config variable advice.detachedHead to false
+";
 
-        if state == 2 {
-            break;
+/// The directory a clone of `url` goes in unless told: the last part of
+/// its path, without `.git`.
+fn guess_dir(url: &str) -> Option<&str> {
+    let path = url.split_once("://").map_or(url, |(_, x)| x);
+    // leave out who to log in as
+    let path = match (path.find('@'), path.find('/')) {
+        (Some(at), slash) if slash.is_none_or(|x| at < x) => &path[at + 1..],
+        _ => path,
+    };
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix("/.git").unwrap_or(path);
+    // a host alone is named without its port
+    let path = match path.rsplit_once(':') {
+        Some((host, port))
+            if !path.contains('/')
+                && !port.is_empty()
+                && port.bytes().all(|x| x.is_ascii_digit()) =>
+        {
+            host
         }
-    }
-
-    git_init::invoke();
-
-    let Some((first, elements)) = pack_line[1..].split_first() else {
-        panic!("invalid size for discover!");
+        _ => path,
     };
+    let name = path.rsplit(['/', ':']).next()?;
+    let name = name.strip_suffix(".git").unwrap_or(name);
+    (!name.is_empty()).then_some(name)
+}
 
-    let hash = &first.value[..40];
-    let hash = String::from_utf8(hash.to_vec()).unwrap();
-    let ref_head = hash.clone();
-
-    let body = format!("0054want {hash} multi_ack side-band-64k ofs-delta\n00000009done\n");
-    let request = Vec::from(body.clone());
-
-    let rq = client
-        .post(format!("{url}/git-upload-pack"))
-        .header("Accept", "application/x-git-upload-pack-result")
-        .header("Content-Type", "application/x-git-upload-pack-request")
-        .body(request);
-
-    let mut resp = rq.send().unwrap();
-
-    state = 1;
-
-    let mut packfile = Vec::new();
-
-    loop {
-        let mut length: [u8; 4] = [0; 4];
-        resp.read_exact(&mut length).unwrap();
-
-        let s = String::from_utf8(length.to_vec()).unwrap();
-        let length = usize::from_str_radix(&s, 16).unwrap();
-
-        if length == 0 {
-            state += 1
-        }
+/// The branch the remote's HEAD is on: the one it points at if the server
+/// says, otherwise one pointing at the same commit, the default branch
+/// name first, or that name for an empty repository. `None` for a
+/// detached HEAD.
+fn remote_head(advertisement: &Advertisement, default: &str) -> Option<String> {
+    if let Some(target) = advertisement.symrefs.get("HEAD") {
+        return Some(target.clone());
+    }
+    if advertisement.refs.is_empty() {
+        return Some(format!("refs/heads/{default}"));
+    }
+    let (_, head) = advertisement.refs.iter().find(|(x, _)| x == "HEAD")?;
+    let branches = advertisement
+        .refs
+        .iter()
+        .filter(|(name, hash)| name.starts_with("refs/heads/") && hash == head)
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    [
+        format!("refs/heads/{default}"),
+        "refs/heads/master".to_string(),
+    ]
+    .into_iter()
+    .find(|x| branches.contains(&x))
+    .or_else(|| branches.first().map(|x| x.to_string()))
+}
 
-        if length != 0 {
-            let mut buf = vec![0; length - 4];
-            resp.read_exact(&mut buf).unwrap();
-            if buf.starts_with(&[0b1]) {
-                packfile.append(&mut buf[1..].to_vec());
-            }
+pub fn invoke(source: &str, options: Options) {
+    let client = Client::new();
+    let url = source.trim_end_matches('/');
+
+    let dir = options.dir.clone().unwrap_or_else(|| {
+        PathBuf::from(guess_dir(url).unwrap_or_else(|| {
+            fatal("No directory name could be guessed.\nPlease specify a directory on the command line")
+        }))
+    });
+    if dir.symlink_metadata().is_ok() && !dir.read_dir().is_ok_and(|mut x| x.next().is_none()) {
+        fatal(format!(
+            "destination path '{}' already exists and is not an empty directory.",
+            dir.display()
+        ));
+    }
+    eprintln!("Cloning into '{}'...", dir.display());
+
+    let mut advertisement =
+        fetch_pack::advertise(&client, url, "git-upload-pack").unwrap_or_else(|e| fatal(e));
+    let prefixes = PREFIXES.map(str::to_string);
+    fetch_pack::ls_refs(&client, url, &mut advertisement, &prefixes).unwrap_or_else(|e| fatal(e));
+    // a v0 server advertises everything it has
+    advertisement
+        .refs
+        .retain(|(name, _)| PREFIXES.iter().any(|x| name.starts_with(x)));
+
+    // what to check out: a branch, or a tag to detach HEAD at
+    let find = |name: &str| advertisement.refs.iter().find(|(x, _)| x == name);
+    let checkout = options.branch.as_ref().map(|branch| {
+        find(&format!("refs/heads/{branch}"))
+            .or_else(|| find(&format!("refs/tags/{branch}")))
+            .unwrap_or_else(|| {
+                fatal(format!(
+                    "Remote branch {branch} not found in upstream {ORIGIN}"
+                ))
+            })
+            .clone()
+    });
+
+    // like git, an empty directory that is there already is used, and
+    // what a failed clone leaves in it is removed
+    let created = dir.symlink_metadata().is_err();
+    fs::create_dir_all(&dir).unwrap_or_else(|e| {
+        fatal(format!(
+            "could not create work tree dir '{}': {}",
+            dir.display(),
+            reason(&e)
+        ))
+    });
+    let cloned = fill(
+        &client,
+        source,
+        &dir,
+        &advertisement,
+        checkout,
+        options.no_checkout,
+    );
+    if let Err(e) = cloned {
+        let _ = match created {
+            true => fs::remove_dir_all(&dir),
+            false => empty(&dir),
+        };
+        fatal(e);
+    }
+}
 
-            if buf.starts_with(&[2]) {
-                std::io::copy(&mut buf[1..].to_vec().as_slice(), &mut std::io::stdout());
-            }
+/// Removes everything in `dir`.
+fn empty(dir: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        match path.symlink_metadata()?.is_dir() {
+            true => fs::remove_dir_all(path)?,
+            false => fs::remove_file(path)?,
         }
+    }
+    Ok(())
+}
 
-        if state == 2 {
-            break;
+/// Sets up the repository in `dir` as a clone of `source`, checking out
+/// `checkout` or what the remote's HEAD is on.
+fn fill(
+    client: &Client,
+    source: &str,
+    dir: &Path,
+    advertisement: &Advertisement,
+    checkout: Option<(String, String)>,
+    no_checkout: bool,
+) -> std::io::Result<()> {
+    let url = source.trim_end_matches('/');
+    repository::set(dir.join(".git"), dir.to_path_buf());
+    repository::init()?;
+    let git_config = repository::git_dir().join("config");
+    let set = |key: &str, value: &str| config::set(&git_config, key, value);
+    set(&format!("remote.{ORIGIN}.url"), source)?;
+    let refspec = format!("+refs/heads/*:refs/remotes/{ORIGIN}/*");
+    set(&format!("remote.{ORIGIN}.fetch"), &refspec)?;
+
+    let mut wants = Vec::new();
+    for (_, hash) in &advertisement.refs {
+        if !wants.contains(hash) {
+            wants.push(hash.clone());
         }
     }
-
-    for pkt in elements {
-        let value = String::from_utf8(pkt.value.to_vec()).unwrap();
-        println!("{}", value);
-        let Some((hash, path)) = value.split_once(' ') else {
-            panic!("unknow format of pkt-line");
-        };
-
-        let _ = fs::create_dir_all(".git/refs/heads");
-        let mut hash = hash.to_string();
-        hash.push('\n');
-        let _ = fs::write(format!(".git/{}", path.trim()), &hash);
+    if !wants.is_empty() {
+        fetch_pack::fetch(client, url, advertisement, &wants)?;
     }
 
-    let mut stream = std::io::Cursor::new(packfile.clone());
-    let (_, _, entries) = headers(&mut stream).unwrap();
-
-    let mut map = collections::HashMap::new();
-
-    let mut offset_cache: HashMap<usize, (Vec<u8>, ObjType)> = HashMap::new();
-
-    for i in 0..entries {
-        let offset = stream.stream_position().unwrap() as usize;
-        let (content, content_type) = read_object(&mut stream, offset, &offset_cache).unwrap();
-        offset_cache.insert(offset, (content.clone(), content_type));
-        let next_offset = stream.stream_position().unwrap() as usize;
-
-        let mut crc_content = &packfile[offset..next_offset];
-        let mut hasher = crc32fast::Hasher::new();
-        hasher.update(&crc_content);
-        let crc32 = hasher.finalize();
-
-        let mut hasher = Sha1::new();
-        let header = format!("{} {}\0", content_type, content.len());
-        hasher.update(header);
-        hasher.update(content.clone());
-        let hash = hasher.finalize();
-        let hash = hex::encode(hash);
-        map.insert(hash, (content, content_type, offset, crc32));
+    for (name, hash) in &advertisement.refs {
+        let local = match name.strip_prefix("refs/heads/") {
+            Some(branch) => format!("refs/remotes/{ORIGIN}/{branch}"),
+            None if name == "HEAD" => continue,
+            None => name.clone(),
+        };
+        refs::update(&local, hash)?;
     }
 
-    let head = map.get(&ref_head).unwrap();
-    let commit = commit::parse(&mut head.0.as_slice()).unwrap();
-
-    let mut queue: VecDeque<(String, PathBuf)> = VecDeque::new();
-    queue.push_back((commit.tree, ".".into()));
-
-    loop {
-        if queue.is_empty() {
-            break;
-        }
-        let (hash, path) = queue.pop_front().unwrap();
-        let tree = map.get(&hash).unwrap();
-        let tree = tree::parse(&mut tree.0.as_slice()).unwrap();
-        fs::create_dir_all(path.clone()).unwrap();
-
-        for item in tree {
-            let hash = hex::encode(item.hash);
-            let (content, obj_type, _, _) = map.get(&hash).unwrap();
-            let mut path = path.clone();
-            path.push(item.name);
-            match obj_type {
-                ObjType::Tree => {
-                    queue.push_back((hash, path));
-                }
-                ObjType::Blob => {
-                    let mut file = fs::File::create(path.clone()).unwrap();
-                    let mut perm = file.metadata().unwrap().permissions();
-                    perm.set_mode(item.mode);
-                    file.write(content);
-                    file.set_permissions(perm);
-                }
-                _ => unimplemented!(),
+    let find = |name: &str| advertisement.refs.iter().find(|(x, _)| x == name);
+    let default = Config::load()
+        .ok()
+        .and_then(|x| x.get("init.defaultBranch").map(str::to_string))
+        .unwrap_or_else(|| "master".to_string());
+    let head = remote_head(advertisement, &default);
+    if let Some(head) = &head {
+        if let Some(branch) = head.strip_prefix("refs/heads/") {
+            if find(head).is_some() {
+                let target = format!("refs/remotes/{ORIGIN}/{branch}");
+                refs::set_symbolic(&format!("refs/remotes/{ORIGIN}/HEAD"), &target)?;
             }
         }
     }
 
-    let mut hashes = map.keys().collect::<Vec<_>>();
-
-    hashes.sort();
-
-    let mut fanout: [u32; 1 << 8] = [0; 1 << 8];
-    let mut acc: u32 = 0;
-
-    // create fanout could turn into another function
-    for (i, fo) in fanout.iter_mut().enumerate() {
-        loop {
-            if hashes.len() == acc as usize {
-                break;
-            }
-            let v = &hashes[acc as usize][..2];
-            let v = u8::from_str_radix(v, 16).unwrap();
-            if i as u8 != v {
-                break;
+    let (name, hash) = match checkout {
+        Some((name, hash)) => (Some(name), Some(hash)),
+        None => {
+            let hash = find("HEAD").map(|(_, x)| x.clone());
+            (head, hash)
+        }
+    };
+    // a tag is checked out at its commit
+    let hash = hash
+        .map(|x| revision::peel(&x, Some(ObjType::Commit)))
+        .transpose()?;
+    match name.as_deref().and_then(|x| x.strip_prefix("refs/heads/")) {
+        Some(branch) => {
+            let local = format!("refs/heads/{branch}");
+            if let Some(hash) = &hash {
+                refs::update(&local, hash)?;
             }
-
-            acc += 1;
+            refs::set_head(&local)?;
+            set(&format!("branch.{branch}.remote"), ORIGIN)?;
+            set(&format!("branch.{branch}.merge"), &local)?;
+        }
+        None => {
+            let hash = hash.as_deref().ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "remote HEAD is unborn and detached",
+                )
+            })?;
+            refs::detach_head(hash)?;
+            eprintln!("Note: switching to '{hash}'.\n\n{DETACHED_ADVICE}");
         }
-        *fo = acc;
     }
 
-    // create crc32
-    let crc32_section = hashes
-        .iter()
-        .map(|&x| map.get(x).unwrap().3)
-        .map(|x| x.to_be_bytes().to_vec())
-        .flatten()
-        .collect::<Vec<_>>();
-
-    // create offset table
-    let offset_table = hashes
-        .iter()
-        .map(|&x| map.get(x).unwrap().2 as u32)
-        .map(|x| x.to_be_bytes().to_vec())
-        .flatten()
-        .collect::<Vec<_>>();
-
-    fs::create_dir_all(format!(".git/objects/pack"));
-    let mut idx_file = fs::File::create(".git/objects/pack/pack.idx").unwrap();
-    let mut hasher = Sha1::new();
-    idx_file.write(b"\xfftOc");
-    idx_file.write(&(2 as u32).to_be_bytes());
-    hasher.update(b"\xfftOc");
-    hasher.update(&(2 as u32).to_be_bytes());
-
-    let fanout_bytes = &fanout
-        .iter()
-        .map(|x| x.to_be_bytes().to_vec())
-        .flatten()
-        .collect::<Vec<_>>();
-
-    idx_file.write(fanout_bytes);
-    hasher.update(fanout_bytes);
-
-    for h in hashes {
-        let h = hex::decode(h).unwrap();
-        idx_file.write(&h);
-        hasher.update(&h);
+    let Some(hash) = hash else {
+        eprintln!("warning: You appear to have cloned an empty repository.");
+        return Ok(());
+    };
+    if no_checkout {
+        return Ok(());
     }
-
-    idx_file.write(&crc32_section);
-    hasher.update(crc32_section);
-
-    idx_file.write(&offset_table);
-    hasher.update(offset_table);
-
-    let mut hash = [0; 20];
-    stream.read_exact(&mut hash).unwrap();
-
-    idx_file.write(&hash);
-    hasher.update(hash);
-
-    let idx_hash = hasher.finalize();
-    idx_file.write(&idx_hash);
-
-    fs::rename(
-        ".git/objects/pack/pack.idx",
-        format!(".git/objects/pack/pack_{}.idx", hex::encode(hash)),
-    );
-
-    fs::write(
-        format!(".git/objects/pack/pack_{}.pack", hex::encode(hash)),
-        packfile,
-    )
-    .unwrap();
-}
-
-#[allow(unused)]
-struct PktLine {
-    length: usize,
-    value: Vec<u8>,
+    let (_, data) = objects::read_raw(&hash)?;
+    let commit = commit::parse(&mut data.as_slice())?;
+    checkout::checkout(None, &commit.tree, false)
 }
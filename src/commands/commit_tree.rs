@@ -1,11 +1,9 @@
 use chrono::Local;
 use std::time::{SystemTime, UNIX_EPOCH};
-use std::io::prelude::*;
-use std::fs;
 
-use sha1::{Digest, Sha1};
-use crate::objects::Kind;
-use flate2::{write::ZlibEncoder, Compression};
+use crate::git::{objects, packfile::ObjType};
+
+use super::fatal;
 
 #[derive(Debug)]
 pub struct Options {
@@ -34,27 +32,8 @@ pub fn invoke(hash: &str, options: Options) {
 
     content = format!("{content}\n{author}\n{commiiter}\n\n{}\n", options.message);
 
-    let header = format!("{} {}\0", Kind::Commit, content.len());
-
-    let mut hasher = Sha1::new();
-    hasher.update(header.clone());
-    hasher.update(content.clone());
-
-    let hash = hasher.finalize();
-    let hash = hex::encode(hash);
-
-
-    let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
-    e.write_all(header.as_bytes()).unwrap();
-    e.write_all(content.as_bytes()).unwrap();
-    let compressed = e.finish().unwrap();
-
-    let _ = fs::create_dir(format!(".git/objects/{}", &hash[..2]));
-    fs::write(
-        format!(".git/objects/{}/{}", &hash[..2], &hash[2..]),
-        compressed,
-    )
-    .unwrap();
+    let hash = objects::write(ObjType::Commit, content.as_bytes()).unwrap_or_else(|e| fatal(e));
 
-    println!("{hash}");
+    println!("{}", hex::encode(hash));
+    super::gc::auto();
 }
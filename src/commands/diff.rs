@@ -0,0 +1,326 @@
+use std::io::{BufWriter, IsTerminal, Write};
+
+use crate::{
+    git::{
+        diff::{self, display, patch, rename, stat, worktree, Change, FileSpec, Status},
+        index::Index,
+        objects::tree::{self, Files},
+        packfile::ObjType,
+        refs, repository, revision, revwalk,
+    },
+    pager,
+};
+
+use super::{fatal, pathspecs, DiffArgs};
+
+#[derive(Debug)]
+pub struct Options {
+    pub cached: bool,
+    pub diff: DiffArgs,
+    pub paths: Vec<String>,
+    /// Whether output may go through a pager.
+    pub pager: bool,
+}
+
+/// What one side of the comparison is.
+enum Side {
+    /// A tree, `None` for the empty tree of an unborn branch.
+    Tree(Option<String>),
+    Index,
+    Worktree,
+}
+
+pub const SEPARATE_PATHS: &str =
+    "Use '--' to separate paths from revisions, like this:\n'git <command> [<revision>...] -- [<file>...]'";
+
+fn score(value: &str) -> u32 {
+    rename::parse_score(value).unwrap_or_else(|| fatal(format!("invalid similarity '{value}'")))
+}
+
+fn peel_tree(rev: &str) -> String {
+    let hash = revision::resolve(rev).unwrap_or_else(|e| fatal(e));
+    revision::peel(&hash, Some(ObjType::Tree)).unwrap_or_else(|e| fatal(e))
+}
+
+/// The revisions of `arg`: one, or both ends of `A..B` and `A...B`
+/// with the symmetric flag, a missing end standing for `HEAD`.
+fn parse_range(arg: &str) -> Option<(Vec<String>, bool)> {
+    let resolves = |x: &str| revision::resolve(x).is_ok();
+
+    for (separator, symmetric) in [("...", true), ("..", false)] {
+        if let Some((one, two)) = arg.split_once(separator) {
+            let end = |x: &str| match x {
+                "" => "HEAD".to_string(),
+                x => x.to_string(),
+            };
+            let ends = vec![end(one), end(two)];
+            return ends
+                .iter()
+                .all(|x| resolves(x))
+                .then_some((ends, symmetric));
+        }
+    }
+    resolves(arg).then(|| (vec![arg.to_string()], false))
+}
+
+/// Splits the arguments into revisions and paths. Without `--`, the first
+/// argument that is not a revision starts the paths, each of which must
+/// exist.
+fn split_args(args: Vec<String>, mut paths: Vec<String>) -> (Vec<String>, bool, Vec<String>) {
+    let separated = !paths.is_empty();
+    let mut revisions = Vec::new();
+    let mut symmetric = false;
+    let root = repository::work_tree();
+
+    for (n, arg) in args.iter().enumerate() {
+        let exists = root.join(arg).symlink_metadata().is_ok();
+        match parse_range(arg) {
+            Some(_) if !separated && exists => fatal(format!(
+                "ambiguous argument '{arg}': both revision and filename\n{SEPARATE_PATHS}"
+            )),
+            Some((ends, range)) => {
+                symmetric |= range;
+                revisions.extend(ends);
+            }
+            None if separated => fatal(format!("bad revision '{arg}'")),
+            None => {
+                if !exists {
+                    fatal(format!(
+                        "ambiguous argument '{arg}': unknown revision or path not in the working tree.\n{SEPARATE_PATHS}"
+                    ));
+                }
+                for arg in args[n + 1..].iter() {
+                    if root.join(arg).symlink_metadata().is_err() {
+                        fatal(format!(
+                            "{arg}: no such path in the working tree.\n\
+                             Use 'git <command> -- <path>...' to specify paths that do not exist locally."
+                        ));
+                    }
+                }
+                paths.splice(0..0, args[n..].iter().cloned());
+                break;
+            }
+        }
+    }
+
+    (revisions, symmetric, paths)
+}
+
+fn files(side: &Side, index: &Index, paths: &[String]) -> std::io::Result<Files> {
+    match side {
+        Side::Tree(Some(hash)) => tree::flatten_matching(hash, paths),
+        Side::Tree(None) => Ok(Files::new()),
+        Side::Index => Ok(worktree::staged(index, paths)),
+        Side::Worktree => worktree::files(index, paths),
+    }
+}
+
+/// Pairs deletions and additions up as renames, and copies if asked to,
+/// `default` telling whether renames are looked for unless disabled.
+/// `old` lists the files of the old side for `--find-copies-harder`.
+pub fn find_renames(
+    changes: Vec<Change>,
+    diff: &DiffArgs,
+    default: bool,
+    old: impl FnOnce() -> std::io::Result<Files>,
+) -> std::io::Result<Vec<Change>> {
+    let copies = diff.find_copies.is_some() || diff.find_copies_harder;
+    let threshold = diff.find_copies.as_ref().or(diff.find_renames.as_ref());
+    if diff.no_renames || !(copies || threshold.is_some() || default) {
+        return Ok(changes);
+    }
+
+    let options = rename::Options {
+        minimum_score: threshold.map_or(rename::DEFAULT_SCORE, |x| score(x)),
+        copies,
+    };
+
+    let mut unmodified = Vec::new();
+    if diff.find_copies_harder {
+        unmodified = old()?
+            .into_iter()
+            .filter(|(path, _)| {
+                !changes
+                    .iter()
+                    .any(|x| x.old.as_ref().is_some_and(|x| x.path == *path))
+            })
+            .map(|(path, (mode, hash))| FileSpec { path, mode, hash })
+            .collect();
+    }
+
+    rename::detect(changes, &options, &unmodified)
+}
+
+fn paths(change: &Change) -> String {
+    match (change.status, &change.old) {
+        (Status::Renamed | Status::Copied, Some(old)) => format!("{}\t{}", old.path, change.path()),
+        _ => change.path().to_string(),
+    }
+}
+
+fn status(change: &Change) -> String {
+    match change.status {
+        Status::Renamed | Status::Copied => {
+            format!("{}{:03}", change.status.letter(), change.similarity())
+        }
+        _ => change.status.letter().to_string(),
+    }
+}
+
+fn raw(change: &Change, abbrev: bool) -> std::io::Result<String> {
+    let side = |x: &Option<FileSpec>| {
+        let (mode, hash) = x.as_ref().map_or((0, [0; 20]), |x| (x.mode, x.hash));
+        Ok::<_, std::io::Error>((mode, patch::abbrev(&hash, !abbrev)?))
+    };
+    let (old_mode, old_hash) = side(&change.old)?;
+    let (new_mode, new_hash) = side(&change.new)?;
+
+    Ok(format!(
+        ":{old_mode:06o} {new_mode:06o} {old_hash} {new_hash} {}\t{}",
+        status(change),
+        paths(change)
+    ))
+}
+
+/// Width of `--stat` output, that of the terminal as `$COLUMNS` gives it.
+fn stat_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .filter(|x| *x > 0)
+        .unwrap_or(80)
+}
+
+/// Writes `--numstat` and `--stat` output if asked for.
+pub fn write_stat(
+    out: &mut impl Write,
+    changes: &[Change],
+    diff: &DiffArgs,
+) -> std::io::Result<()> {
+    let patch_options = diff.patch_options();
+    let files = changes
+        .iter()
+        .filter(|x| patch::has_content(x))
+        .map(|x| patch::file_stat(x, &patch_options))
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    if diff.numstat {
+        write!(out, "{}", stat::numstat(&files))?;
+    }
+    if diff.stat {
+        write!(out, "{}", stat::stat(&files, stat_width()))?;
+    }
+    Ok(())
+}
+
+/// Writes changes in the formats `diff` asks for. `porcelain` output
+/// defaults to patches rather than raw output, and abbreviates object
+/// names in the latter.
+pub fn write(
+    out: &mut impl Write,
+    changes: &[Change],
+    diff: &DiffArgs,
+    display: &display::Options,
+    porcelain: bool,
+) -> std::io::Result<()> {
+    if diff.names() {
+        for change in changes.iter() {
+            match diff.name_only {
+                true => writeln!(out, "{}", change.path())?,
+                false => writeln!(out, "{}\t{}", status(change), paths(change))?,
+            }
+        }
+        return out.flush();
+    }
+
+    let mut separate = false;
+    if diff.raw || (!porcelain && !diff.picks_format()) {
+        for change in changes.iter() {
+            writeln!(out, "{}", raw(change, porcelain)?)?;
+        }
+        separate = true;
+    }
+
+    if diff.numstat || diff.stat {
+        write_stat(out, changes, diff)?;
+        separate = true;
+    }
+
+    if diff.shows_patch() || (porcelain && !diff.picks_format()) {
+        if separate {
+            writeln!(out)?;
+        }
+        // moved lines are looked for across all files
+        let mut symbols = Vec::new();
+        for change in changes.iter() {
+            symbols.extend(patch::symbols(change, &diff.patch_options())?);
+        }
+        display::write(out, &symbols, display)?;
+    }
+
+    out.flush()
+}
+
+pub fn invoke(args: Vec<String>, options: Options) {
+    let (revisions, symmetric, paths) = split_args(args, options.paths);
+    let paths = pathspecs(&paths);
+    let head = || {
+        let commit = refs::head_commit().unwrap_or_else(|e| fatal(e));
+        commit.map(|_| peel_tree("HEAD"))
+    };
+
+    let (old, new) = match (revisions.as_slice(), options.cached) {
+        ([], false) => (Side::Index, Side::Worktree),
+        ([], true) => (Side::Tree(head()), Side::Index),
+        ([one], false) => (Side::Tree(Some(peel_tree(one))), Side::Worktree),
+        ([one], true) => (Side::Tree(Some(peel_tree(one))), Side::Index),
+        ([one, two], _) if symmetric => {
+            let resolve = |x: &str| revision::resolve(x).unwrap_or_else(|e| fatal(e));
+            let bases =
+                revwalk::merge_bases(&resolve(one), &resolve(two)).unwrap_or_else(|e| fatal(e));
+            let Some(base) = bases.first() else {
+                fatal(format!("{one}...{two}: no merge base"));
+            };
+            (
+                Side::Tree(Some(peel_tree(base))),
+                Side::Tree(Some(peel_tree(two))),
+            )
+        }
+        ([one, two], _) => (
+            Side::Tree(Some(peel_tree(one))),
+            Side::Tree(Some(peel_tree(two))),
+        ),
+        _ => fatal("usage: git diff [<options>] [<commit>] [--] [<path>...]"),
+    };
+
+    let index = Index::open(&repository::index_file()).unwrap_or_else(|e| fatal(e));
+    let changes = match (&old, &new) {
+        (Side::Tree(old), Side::Tree(new)) => {
+            let tree_options = diff::tree::Options {
+                recursive: true,
+                show_trees: false,
+                paths: paths.clone(),
+            };
+            diff::tree::diff(old.as_deref(), new.as_deref(), &tree_options)
+        }
+        _ => files(&old, &index, &paths)
+            .and_then(|one| Ok(worktree::diff(&one, &files(&new, &index, &paths)?))),
+    };
+    let changes = changes
+        .and_then(|x| find_renames(x, &options.diff, true, || files(&old, &index, &paths)))
+        .unwrap_or_else(|e| fatal(e));
+
+    if changes.is_empty() {
+        return;
+    }
+
+    let display = options
+        .diff
+        .display_options(std::io::stdout().is_terminal());
+    let mut out = BufWriter::new(pager::start(options.pager));
+    if let Err(e) = write(&mut out, &changes, &options.diff, &display, true) {
+        if e.kind() != std::io::ErrorKind::BrokenPipe {
+            fatal(e);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::io::{BufWriter, Write};
+
+use crate::git::{
+    diff::{self, Change},
+    objects::tree,
+    packfile::ObjType,
+    revision, revwalk,
+};
+
+use super::{fatal, pathspecs, DiffArgs};
+
+#[derive(Debug)]
+pub struct Options {
+    pub recursive: bool,
+    pub show_trees: bool,
+    pub root: bool,
+    pub no_commit_id: bool,
+    pub diff: DiffArgs,
+    pub paths: Vec<String>,
+}
+
+/// Object names of the trees to compare, and the commit to name in the
+/// output when a single commit is compared with its parent.
+fn trees(revisions: &[String], root: bool) -> Option<(Option<String>, String, Option<String>)> {
+    let resolve = |x: &str| revision::resolve(x).unwrap_or_else(|e| fatal(e));
+    let peel = |x: &str, kind| revision::peel(x, Some(kind)).unwrap_or_else(|e| fatal(e));
+
+    match revisions {
+        [one] => {
+            let commit = peel(&resolve(one), ObjType::Commit);
+            let parsed = revwalk::read_commit(&commit).unwrap_or_else(|e| fatal(e));
+
+            let parent = match parsed.parents.as_slice() {
+                [] if root => None,
+                [parent] => Some(peel(parent, ObjType::Tree)),
+                // root commits need --root, merges a combined diff
+                _ => return None,
+            };
+            Some((parent, parsed.tree, Some(commit)))
+        }
+        [one, two] => {
+            let old = peel(&resolve(one), ObjType::Tree);
+            let new = peel(&resolve(two), ObjType::Tree);
+            Some((Some(old), new, None))
+        }
+        _ => fatal("usage: diff-tree [<options>] <tree-ish> [<tree-ish>] [<path>...]"),
+    }
+}
+
+pub fn invoke(revisions: Vec<String>, options: Options) {
+    let Some((old, new, commit)) = trees(&revisions, options.root) else {
+        return;
+    };
+
+    // content changes are only shown for files, names replace other formats
+    let content = !options.diff.names()
+        && (options.diff.shows_patch() || options.diff.stat || options.diff.numstat);
+    let tree_options = diff::tree::Options {
+        recursive: options.recursive || options.show_trees || content,
+        show_trees: options.show_trees,
+        paths: pathspecs(&options.paths),
+    };
+    let changes =
+        diff::tree::diff(old.as_deref(), Some(&new), &tree_options).unwrap_or_else(|e| fatal(e));
+
+    let changes = super::diff::find_renames(changes, &options.diff, false, || match &old {
+        Some(old) => tree::flatten_matching(old, &tree_options.paths),
+        None => Ok(Default::default()),
+    });
+    let changes = changes.unwrap_or_else(|e| fatal(e));
+
+    if changes.is_empty() {
+        return;
+    }
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    if let Err(e) = write(&mut out, commit, &changes, &options) {
+        if e.kind() != std::io::ErrorKind::BrokenPipe {
+            fatal(e);
+        }
+    }
+}
+
+fn write(
+    out: &mut impl Write,
+    commit: Option<String>,
+    changes: &[Change],
+    options: &Options,
+) -> std::io::Result<()> {
+    if let (Some(commit), false) = (commit, options.no_commit_id) {
+        writeln!(out, "{commit}")?;
+    }
+
+    let display = options.diff.display_options(false);
+    super::diff::write(out, changes, &options.diff, &display, false)
+}
@@ -0,0 +1,408 @@
+use std::collections::HashSet;
+
+use reqwest::blocking::Client;
+
+use crate::git::{
+    config::Config,
+    fetch_pack::{self, Advertisement},
+    objects,
+    refs::{self, Head},
+    remote::{self, is_fast_forward, pretty, Refspec, Remote, SUMMARY_WIDTH},
+    repository,
+};
+
+use super::{fatal, gc};
+
+#[derive(Debug)]
+pub struct Options {
+    pub refspecs: Vec<String>,
+    pub prune: bool,
+    pub tags: bool,
+    pub force: bool,
+    pub verbose: bool,
+}
+
+/// A ref of the remote to fetch, and what to do with it.
+#[derive(Debug)]
+struct Fetched {
+    /// Full name on the remote.
+    src: String,
+    hash: String,
+    /// Local ref to point at it, if any.
+    dst: Option<String>,
+    force: bool,
+    /// Whether it goes to `FETCH_HEAD`, and if so whether to be merged.
+    fetch_head: Option<bool>,
+}
+
+/// A line of the report of what a fetch did.
+struct Line {
+    code: char,
+    summary: String,
+    remote: String,
+    local: String,
+    reason: Option<&'static str>,
+}
+
+/// The refs of the remote `refspecs` select, in the order they select
+/// them.
+fn select(
+    advertisement: &Advertisement,
+    refspecs: &[Refspec],
+    from_command_line: bool,
+    merge: &[&str],
+) -> Vec<Fetched> {
+    let mut fetched = Vec::new();
+    for spec in refspecs {
+        if spec.is_glob() {
+            for (name, hash) in &advertisement.refs {
+                if let Some(dst) = spec.map(name) {
+                    fetched.push(Fetched {
+                        src: name.clone(),
+                        hash: hash.clone(),
+                        dst: Some(dst),
+                        force: spec.force,
+                        fetch_head: Some(merge.contains(&name.as_str())),
+                    });
+                }
+            }
+            continue;
+        }
+
+        let Some((name, hash)) = remote::find(&advertisement.refs, &spec.src) else {
+            fatal(format!("couldn't find remote ref {}", spec.src));
+        };
+        let dst = spec.dst.as_ref().map(|dst| match dst.starts_with("refs/") {
+            true => dst.clone(),
+            false if name.starts_with("refs/tags/") => format!("refs/tags/{dst}"),
+            false => format!("refs/heads/{dst}"),
+        });
+        fetched.push(Fetched {
+            src: name.clone(),
+            hash: hash.clone(),
+            dst,
+            force: spec.force,
+            fetch_head: Some(from_command_line || merge.contains(&name.as_str())),
+        });
+    }
+    fetched
+}
+
+/// Tags of the remote that point at objects we have, which a fetch
+/// brings along with what it was asked for.
+fn followed_tags(advertisement: &Advertisement, fetched: &[Fetched]) -> Vec<Fetched> {
+    let known = fetched.iter().map(|x| &x.src).collect::<HashSet<_>>();
+    advertisement
+        .refs
+        .iter()
+        .filter(|(name, _)| name.starts_with("refs/tags/") && !known.contains(name))
+        .filter(|(name, _)| !refs::exists(name))
+        .filter(|(name, hash)| objects::contains(advertisement.peeled.get(name).unwrap_or(hash)))
+        .map(|(name, hash)| Fetched {
+            src: name.clone(),
+            hash: hash.clone(),
+            dst: Some(name.clone()),
+            force: false,
+            fetch_head: Some(false),
+        })
+        .collect()
+}
+
+/// Deletes the local refs `refspecs` map refs of the remote to that the
+/// remote no longer has.
+fn prune(advertisement: &Advertisement, refspecs: &[Refspec]) -> std::io::Result<Vec<Line>> {
+    let remote = advertisement
+        .refs
+        .iter()
+        .map(|(x, _)| x.as_str())
+        .collect::<HashSet<_>>();
+
+    let mut lines = Vec::new();
+    for (name, _) in refs::list("refs/")? {
+        // like origin/HEAD, they follow the refs they point at
+        if refs::is_symbolic(&name)? {
+            continue;
+        }
+        let stale = refspecs
+            .iter()
+            .filter(|x| x.is_glob())
+            .filter_map(|x| x.reverse(&name))
+            .any(|x| !remote.contains(x.as_str()));
+        if stale {
+            refs::delete(&name)?;
+            lines.push(Line {
+                code: '-',
+                summary: "[deleted]".to_string(),
+                remote: "(none)".to_string(),
+                local: pretty(&name).to_string(),
+                reason: None,
+            });
+        }
+    }
+    Ok(lines)
+}
+
+/// Points the local ref of `fetched` at what was fetched, if that is
+/// allowed, returning how it went and whether it was refused.
+fn update(fetched: &Fetched, dst: &str) -> std::io::Result<(Line, bool)> {
+    let old = refs::resolve(dst)?;
+    let new = &fetched.hash;
+    let short = |x: &str| x[..7].to_string();
+    let tag = dst.starts_with("refs/tags/");
+
+    let (code, summary, reason, write) = match old {
+        Some(old) if old == *new => ('=', "[up to date]".to_string(), None, false),
+        None => {
+            let summary = match () {
+                _ if tag => "[new tag]",
+                _ if fetched.src.starts_with("refs/heads/") => "[new branch]",
+                _ => "[new ref]",
+            };
+            ('*', summary.to_string(), None, true)
+        }
+        Some(_) if tag && !fetched.force => (
+            '!',
+            "[rejected]".to_string(),
+            Some("would clobber existing tag"),
+            false,
+        ),
+        Some(_) if tag => ('t', "[tag update]".to_string(), None, true),
+        Some(old) if is_fast_forward(&old, new) => {
+            (' ', format!("{}..{}", short(&old), short(new)), None, true)
+        }
+        Some(old) if fetched.force => (
+            '+',
+            format!("{}...{}", short(&old), short(new)),
+            Some("forced update"),
+            true,
+        ),
+        Some(_) => (
+            '!',
+            "[rejected]".to_string(),
+            Some("non-fast-forward"),
+            false,
+        ),
+    };
+
+    if write {
+        refs::update(dst, new)?;
+    }
+    let line = Line {
+        code,
+        summary,
+        remote: pretty(&fetched.src).to_string(),
+        local: pretty(dst).to_string(),
+        reason,
+    };
+    Ok((line, code == '!'))
+}
+
+/// Records what was fetched in `.git/FETCH_HEAD`, what to merge first.
+fn write_fetch_head(fetched: &[Fetched], url: &str) -> std::io::Result<()> {
+    let mut content = String::new();
+    for merge in [true, false] {
+        for entry in fetched.iter().filter(|x| x.fetch_head == Some(merge)) {
+            let name = &entry.src;
+            let description = match () {
+                _ if name == "HEAD" => url.to_string(),
+                _ if name.starts_with("refs/heads/") => {
+                    format!("branch '{}' of {url}", pretty(name))
+                }
+                _ if name.starts_with("refs/tags/") => format!("tag '{}' of {url}", pretty(name)),
+                _ if name.starts_with("refs/remotes/") => {
+                    format!("remote-tracking branch '{}' of {url}", pretty(name))
+                }
+                _ => format!("'{name}' of {url}"),
+            };
+            let marker = if merge { "" } else { "not-for-merge" };
+            content.push_str(&format!("{}\t{marker}\t{description}\n", entry.hash));
+        }
+    }
+    std::fs::write(repository::git_dir().join("FETCH_HEAD"), content)
+}
+
+/// Fetches the objects `fetched` needs that we don't have.
+fn download(
+    client: &Client,
+    url: &str,
+    advertisement: &Advertisement,
+    fetched: &[Fetched],
+) -> std::io::Result<bool> {
+    let mut wants = Vec::new();
+    for entry in fetched {
+        if !objects::contains(&entry.hash) && !wants.contains(&entry.hash) {
+            wants.push(entry.hash.clone());
+        }
+    }
+    if wants.is_empty() {
+        return Ok(false);
+    }
+    fetch_pack::fetch(client, url, advertisement, &wants)?;
+    Ok(true)
+}
+
+pub fn invoke(remote: Option<String>, options: Options) {
+    let config = Config::load().unwrap_or_else(|e| fatal(e));
+    let branch = match refs::read_head() {
+        Ok(Head::Branch(branch)) => branch.strip_prefix("refs/heads/").map(str::to_string),
+        _ => None,
+    };
+    let name = remote
+        .or_else(|| {
+            let key = format!("branch.{}.remote", branch.as_deref()?);
+            config.get(&key).map(str::to_string)
+        })
+        .unwrap_or_else(|| "origin".to_string());
+    let remote = match Remote::get(&config, &name) {
+        Ok(Some(remote)) => remote,
+        Ok(None) => fatal(format!("'{name}' does not appear to be a git repository")),
+        Err(e) => fatal(e),
+    };
+
+    // what the current branch merges, when it follows this remote
+    let mut merge = Vec::new();
+    if let Some(branch) = &branch {
+        if config.get(&format!("branch.{branch}.remote")) == Some(remote.name.as_str()) {
+            merge = config.get_all(&format!("branch.{branch}.merge"));
+        }
+    }
+
+    let from_command_line = !options.refspecs.is_empty();
+    let mut refspecs = match from_command_line {
+        true => options
+            .refspecs
+            .iter()
+            .map(|x| Refspec::parse(x).unwrap_or_else(|| fatal(format!("invalid refspec '{x}'"))))
+            .collect(),
+        false if remote.fetch.is_empty() => vec![Refspec::parse("HEAD").unwrap()],
+        false => remote.fetch.clone(),
+    };
+    // tags fetched with `--tags` are not pruned
+    let pruned = refspecs.clone();
+    if options.tags {
+        refspecs.push(Refspec::parse("refs/tags/*:refs/tags/*").unwrap());
+    }
+    if options.force {
+        refspecs.iter_mut().for_each(|x| x.force = true);
+    }
+
+    let client = Client::new();
+    let url = remote.url.trim_end_matches('/');
+    let mut advertisement =
+        fetch_pack::advertise(&client, url, "git-upload-pack").unwrap_or_else(|e| fatal(e));
+    // only the refs the refspecs may select, and tags to follow
+    let mut prefixes = Vec::new();
+    for spec in &refspecs {
+        match spec.src.split_once('*') {
+            Some((prefix, _)) => prefixes.push(prefix.to_string()),
+            None => prefixes.extend(remote::expansions(&spec.src)),
+        }
+    }
+    prefixes.push("refs/tags/".to_string());
+    fetch_pack::ls_refs(&client, url, &mut advertisement, &prefixes).unwrap_or_else(|e| fatal(e));
+    if !from_command_line && merge.is_empty() && remote.fetch.is_empty() {
+        merge.push("HEAD");
+    }
+    let mut fetched = select(&advertisement, &refspecs, from_command_line, &merge);
+
+    // refs fetched from the command line also update the remote-tracking
+    // refs the configured refspecs map them to
+    if from_command_line {
+        let mut tracking = Vec::new();
+        for entry in &fetched {
+            for spec in remote.fetch.iter().filter(|x| x.is_glob()) {
+                let Some(dst) = spec.map(&entry.src) else {
+                    continue;
+                };
+                if fetched.iter().all(|x| x.dst.as_ref() != Some(&dst)) {
+                    tracking.push(Fetched {
+                        src: entry.src.clone(),
+                        hash: entry.hash.clone(),
+                        dst: Some(dst),
+                        force: spec.force,
+                        fetch_head: None,
+                    });
+                }
+            }
+        }
+        fetched.extend(tracking);
+    }
+
+    // before anything is fetched, so that refusing changes nothing
+    let current = branch.map(|x| format!("refs/heads/{x}"));
+    for entry in &fetched {
+        let Some(dst) = entry.dst.as_ref().filter(|x| current.as_ref() == Some(x)) else {
+            continue;
+        };
+        if refs::resolve(dst).ok().flatten() != Some(entry.hash.clone()) {
+            fatal(format!(
+                "refusing to fetch into branch '{dst}' checked out at '{}'",
+                std::fs::canonicalize(repository::work_tree())
+                    .unwrap_or_else(|_| repository::work_tree())
+                    .display()
+            ));
+        }
+    }
+
+    let mut downloaded =
+        download(&client, url, &advertisement, &fetched).unwrap_or_else(|e| fatal(e));
+    if !options.tags && fetched.iter().any(|x| x.dst.is_some()) {
+        let tags = followed_tags(&advertisement, &fetched);
+        downloaded |= download(&client, url, &advertisement, &tags).unwrap_or_else(|e| fatal(e));
+        fetched.extend(tags);
+    }
+
+    let mut lines = Vec::new();
+    if options.prune {
+        lines = prune(&advertisement, &pruned).unwrap_or_else(|e| fatal(e));
+    }
+
+    let mut rejected = false;
+    for entry in &fetched {
+        let Some(dst) = &entry.dst else {
+            let kind = match () {
+                _ if entry.src.starts_with("refs/tags/") => "tag",
+                _ if entry.src.starts_with("refs/remotes/") => "remote-tracking branch",
+                _ => "branch",
+            };
+            lines.push(Line {
+                code: '*',
+                summary: kind.to_string(),
+                remote: pretty(&entry.src).to_string(),
+                local: "FETCH_HEAD".to_string(),
+                reason: None,
+            });
+            continue;
+        };
+        let (line, refused) = update(entry, dst).unwrap_or_else(|e| fatal(e));
+        rejected |= refused;
+        lines.push(line);
+    }
+
+    write_fetch_head(&fetched, remote.display_url()).unwrap_or_else(|e| fatal(e));
+
+    lines.retain(|x| x.code != '=' || options.verbose);
+    if !lines.is_empty() {
+        let width = lines
+            .iter()
+            .map(|x| x.remote.len())
+            .max()
+            .unwrap_or(0)
+            .max(10);
+        eprintln!("From {}", remote.display_url());
+        for line in lines {
+            let reason = line.reason.map(|x| format!("  ({x})")).unwrap_or_default();
+            eprintln!(
+                " {} {:<SUMMARY_WIDTH$} {:<width$} -> {}{reason}",
+                line.code, line.summary, line.remote, line.local
+            );
+        }
+    }
+
+    if downloaded {
+        gc::auto();
+    }
+    if rejected {
+        std::process::exit(1);
+    }
+}
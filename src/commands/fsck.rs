@@ -0,0 +1,341 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::Read,
+};
+
+use crate::git::{
+    fsck,
+    index::{Index, MODE_GITLINK},
+    objects::{self, Object, Store},
+    pack_index::{self, PackIndex},
+    packfile::ObjType,
+    reflog,
+    refs::{self, Head},
+    repository,
+};
+
+use super::{fatal, verify_pack};
+
+#[derive(Debug)]
+pub struct Options {
+    pub connectivity_only: bool,
+    pub lost_found: bool,
+    pub strict: bool,
+}
+
+/// An object name with the type the object has or should have.
+type Named = (String, ObjType);
+
+/// Bits of the exit status, one for each kind of problem found.
+const ERROR_OBJECT: i32 = 1;
+const ERROR_REACHABLE: i32 = 2;
+const ERROR_PACK: i32 = 4;
+const ERROR_REFS: i32 = 8;
+
+struct Fsck {
+    store: Store,
+    options: Options,
+    /// The type of every object that can be read and has the right name.
+    objects: HashMap<String, ObjType>,
+    errors: i32,
+}
+
+/// Objects `data` leads to, with the type they should have. Objects too
+/// broken to parse lead nowhere.
+fn links(kind: ObjType, data: &[u8]) -> Vec<Named> {
+    match Object::parse(kind, &mut &data[..]) {
+        Ok(Object::Tree(items)) => items
+            .into_iter()
+            .filter(|x| x.mode != MODE_GITLINK)
+            .map(|x| {
+                let kind = match x.is_tree() {
+                    true => ObjType::Tree,
+                    false => ObjType::Blob,
+                };
+                (hex::encode(x.hash), kind)
+            })
+            .collect(),
+        Ok(Object::Commit(commit)) => std::iter::once((commit.tree, ObjType::Tree))
+            .chain(commit.parents.into_iter().map(|x| (x, ObjType::Commit)))
+            .collect(),
+        Ok(Object::Tag(tag)) => match tag.kind.parse() {
+            Ok(kind) => vec![(tag.object, kind)],
+            Err(_) => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+impl Fsck {
+    /// Re-hashes an object read from `stream` and checks its syntax,
+    /// recording it if it has the name it is stored under. Returns the
+    /// name it should have if it has another.
+    fn check(
+        &mut self,
+        hash: &str,
+        mut stream: objects::Stream,
+    ) -> std::io::Result<Option<String>> {
+        let kind = stream.kind;
+        if self.options.connectivity_only {
+            self.objects.insert(hash.to_string(), kind);
+            return Ok(None);
+        }
+
+        let (size, mut data) = (stream.size, Vec::new());
+        let name = hex::encode(match kind {
+            // blobs need no syntax check, so they need not be held whole
+            ObjType::Blob => objects::hash_stream(kind, &mut stream, size)?,
+            _ => {
+                stream.read_to_end(&mut data)?;
+                objects::hash(kind, &data)
+            }
+        });
+        if name != hash {
+            self.errors |= ERROR_OBJECT;
+            return Ok(Some(name));
+        }
+
+        for problem in fsck::check(kind, &data, self.options.strict) {
+            match problem.is_error(self.options.strict) {
+                true => {
+                    eprintln!("error in {kind} {hash}: {problem}");
+                    self.errors |= ERROR_OBJECT;
+                }
+                false => eprintln!("warning in {kind} {hash}: {problem}"),
+            }
+        }
+        self.objects.insert(hash.to_string(), kind);
+        Ok(None)
+    }
+
+    fn check_loose(&mut self) -> std::io::Result<()> {
+        let mut names = objects::loose_names()?;
+        names.sort();
+        for hash in names {
+            let path = objects::loose_path(&hash);
+            let checked = objects::stream_loose_object(&hash).and_then(|x| self.check(&hash, x));
+            match checked {
+                Ok(None) => {}
+                Ok(Some(name)) => eprintln!(
+                    "error: {name}: hash-path mismatch, found at: {}",
+                    path.display()
+                ),
+                Err(e) => {
+                    if e.kind() == std::io::ErrorKind::InvalidData {
+                        eprintln!("error: {e}");
+                    }
+                    eprintln!(
+                        "error: {hash}: object corrupt or missing: {}",
+                        path.display()
+                    );
+                    self.errors |= ERROR_OBJECT;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_packs(&mut self) -> std::io::Result<()> {
+        for path in pack_index::list(&repository::objects_dir())? {
+            let mut index = PackIndex::open(&path)?;
+            let pack = index.pack.clone();
+            if !self.options.connectivity_only {
+                if let Err(e) = verify_pack::verify(&pack, &path) {
+                    eprintln!("error: {e}");
+                    self.errors |= ERROR_PACK;
+                }
+            }
+
+            for position in 0..index.len() {
+                let hash = hex::encode(index.hash_at(position)?);
+                match self.store.stream(&hash).and_then(|x| self.check(&hash, x)) {
+                    Ok(None) => {}
+                    Ok(Some(_)) => {
+                        eprintln!("error: packed {hash} from {} is corrupt", pack.display())
+                    }
+                    Err(_) => {
+                        let offset = index.offset_at(position)?;
+                        eprintln!(
+                            "error: cannot unpack {hash} from {} at offset {offset}",
+                            pack.display()
+                        );
+                        self.errors |= ERROR_PACK;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that refs, HEAD and unless looking for lost objects reflog
+    /// entries name objects that exist, returning those they name along
+    /// with the blobs of the index.
+    fn roots(&mut self) -> std::io::Result<Vec<Named>> {
+        let mut roots = Vec::new();
+        let mut named = |errors: &mut i32, name: &str, hash: String, what: &str| match self
+            .objects
+            .get(&hash)
+        {
+            Some(kind) => roots.push((hash, *kind)),
+            None => {
+                eprintln!("error: {name}: {what} {hash}");
+                *errors |= ERROR_REACHABLE;
+            }
+        };
+
+        let mut errors = self.errors;
+        let refs = refs::list("refs/")?;
+        for (name, hash) in &refs {
+            let kind = self.objects.get(hash).copied();
+            if name.starts_with("refs/heads/") && kind.is_some_and(|x| x != ObjType::Commit) {
+                eprintln!("error: {name}: not a commit");
+                errors |= ERROR_REFS;
+            }
+            named(&mut errors, name, hash.clone(), "invalid sha1 pointer");
+        }
+
+        let head = refs::read_head()?;
+        match refs::head_commit()? {
+            Some(hash) => named(&mut errors, "HEAD", hash, "invalid sha1 pointer"),
+            None => {
+                if let Head::Branch(branch) = &head {
+                    let short = branch.strip_prefix("refs/heads/").unwrap_or(branch);
+                    eprintln!("notice: HEAD points to an unborn branch ({short})");
+                }
+                if refs.is_empty() {
+                    eprintln!("notice: No default references");
+                }
+            }
+        }
+
+        if !self.options.lost_found {
+            // git goes through HEAD's reflog after those of refs
+            let mut names = reflog::names()?;
+            names.sort_by_key(|x| x == "HEAD");
+            for name in names {
+                for entry in reflog::read(&name)? {
+                    for hash in entry.objects() {
+                        named(&mut errors, &name, hash.to_string(), "invalid reflog entry");
+                    }
+                }
+            }
+        }
+        self.errors = errors;
+
+        let index = Index::open(&repository::index_file())?;
+        roots.extend(
+            index
+                .entries
+                .iter()
+                .filter(|x| x.mode != MODE_GITLINK)
+                .map(|x| (hex::encode(x.hash), ObjType::Blob)),
+        );
+        Ok(roots)
+    }
+
+    /// Objects reachable from `roots`, and those that should be but are
+    /// missing, with the type they should have.
+    fn reach(&mut self, roots: Vec<Named>) -> std::io::Result<(HashSet<String>, Vec<Named>)> {
+        let mut reachable = HashSet::new();
+        let mut missing = Vec::new();
+        let mut stack = roots;
+
+        while let Some((hash, expected)) = stack.pop() {
+            if !reachable.insert(hash.clone()) {
+                continue;
+            }
+            match self.objects.get(&hash) {
+                None => missing.push((hash, expected)),
+                Some(&kind) if kind != expected => {
+                    eprintln!("error: object {hash} is a {kind}, not a {expected}");
+                    self.errors |= ERROR_REACHABLE;
+                }
+                Some(ObjType::Blob) => {}
+                Some(&kind) => {
+                    let (_, data) = self.store.read(&hash)?;
+                    stack.extend(links(kind, &data));
+                }
+            }
+        }
+        Ok((reachable, missing))
+    }
+
+    /// Writes a dangling object to `lost-found`, commits as their name and
+    /// other objects as their content if blobs and otherwise their name.
+    fn lose(&mut self, hash: &str, kind: ObjType) -> std::io::Result<()> {
+        let dir = repository::git_dir().join("lost-found").join(match kind {
+            ObjType::Commit => "commit",
+            _ => "other",
+        });
+        fs::create_dir_all(&dir)?;
+        let content = match kind {
+            ObjType::Blob => self.store.read(hash)?.1,
+            _ => format!("{hash}\n").into_bytes(),
+        };
+        fs::write(dir.join(hash), content)
+    }
+
+    fn run(&mut self) -> std::io::Result<()> {
+        self.check_loose()?;
+        self.check_packs()?;
+
+        let roots = self.roots()?;
+        let (reachable, missing) = self.reach(roots)?;
+
+        // unreachable objects other unreachable objects lead to are not
+        // dangling, only the tips of what is lost
+        let mut unreachable = self
+            .objects
+            .iter()
+            .filter(|(hash, _)| !reachable.contains(*hash))
+            .map(|(hash, kind)| (hash.clone(), *kind))
+            .collect::<Vec<_>>();
+        let mut used = HashSet::new();
+        for (hash, kind) in &unreachable {
+            if *kind != ObjType::Blob {
+                let (_, data) = self.store.read(hash)?;
+                used.extend(links(*kind, &data).into_iter().map(|(x, _)| x));
+            }
+        }
+        unreachable.retain(|(hash, _)| !used.contains(hash));
+
+        let mut found = missing
+            .into_iter()
+            .map(|(hash, kind)| (hash, kind, true))
+            .chain(
+                unreachable
+                    .into_iter()
+                    .map(|(hash, kind)| (hash, kind, false)),
+            )
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        for (hash, kind, missing) in found {
+            if missing {
+                println!("missing {kind} {hash}");
+                self.errors |= ERROR_REACHABLE;
+                continue;
+            }
+            println!("dangling {kind} {hash}");
+            if self.options.lost_found {
+                self.lose(&hash, kind)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn invoke(options: Options) {
+    let store = Store::open().unwrap_or_else(|e| fatal(e));
+    let mut fsck = Fsck {
+        store,
+        options,
+        objects: HashMap::new(),
+        errors: 0,
+    };
+    fsck.run().unwrap_or_else(|e| fatal(e));
+
+    if fsck.errors != 0 {
+        std::process::exit(fsck.errors);
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::git::{
+    config::{self, Config},
+    objects::{self, Store},
+    pack_index, reachable, reflog, refs, repository,
+};
+
+use super::{fatal, repack};
+
+#[derive(Debug)]
+pub struct Options {
+    pub auto: bool,
+    pub prune: Option<String>,
+    pub no_prune: bool,
+    pub quiet: bool,
+}
+
+/// How much to do, from the settings of the configuration.
+struct Settings {
+    /// Loose objects above which `--auto` packs them, `gc.auto`.
+    auto: i64,
+    /// Packs above which `--auto` packs everything into one,
+    /// `gc.autoPackLimit`.
+    auto_pack_limit: i64,
+    prune_expire: u64,
+    reflog_expire: u64,
+    reflog_expire_unreachable: u64,
+}
+
+impl Settings {
+    fn load(now: u64) -> std::io::Result<Settings> {
+        let config = Config::load()?;
+        Ok(Settings {
+            auto: config.get_int("gc.auto")?.unwrap_or(6700),
+            auto_pack_limit: config.get_int("gc.autoPackLimit")?.unwrap_or(50),
+            prune_expire: config.expiry("gc.pruneExpire", "2.weeks.ago", now)?,
+            reflog_expire: config.expiry("gc.reflogExpire", "90.days.ago", now)?,
+            reflog_expire_unreachable: config.expiry(
+                "gc.reflogExpireUnreachable",
+                "30.days.ago",
+                now,
+            )?,
+        })
+    }
+
+    /// Whether `--auto` has anything to do, and if so whether to pack
+    /// everything rather than only loose objects.
+    fn needs_gc(&self) -> std::io::Result<Option<bool>> {
+        if self.auto <= 0 {
+            return Ok(None);
+        }
+
+        let packs = pack_index::list(&repository::objects_dir())?
+            .iter()
+            .filter(|x| !x.with_extension("keep").exists())
+            .count();
+        if self.auto_pack_limit > 0 && packs as i64 > self.auto_pack_limit {
+            return Ok(Some(true));
+        }
+
+        // objects are spread evenly, so one directory tells how many
+        let sample = match fs::read_dir(repository::objects_dir().join("17")) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .filter(|x| {
+                    let name = x.file_name();
+                    let name = name.to_string_lossy();
+                    name.len() == 38 && name.bytes().all(|x| x.is_ascii_hexdigit())
+                })
+                .count(),
+            Err(_) => 0,
+        };
+        match sample as i64 > (self.auto + 255) / 256 {
+            true => Ok(Some(false)),
+            false => Ok(None),
+        }
+    }
+}
+
+/// Removes loose objects that are not `reachable` and were last written
+/// at or before `expire`, along with temporary files left over as long,
+/// as `git prune` does.
+fn prune(reachable: &HashSet<&str>, expire: u64) -> std::io::Result<()> {
+    let expired = |path: &Path| -> std::io::Result<bool> {
+        let modified = fs::symlink_metadata(path)?.modified()?;
+        let seconds = modified
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |x| x.as_secs());
+        Ok(seconds <= expire)
+    };
+
+    for hash in objects::loose_names()? {
+        if !reachable.contains(hash.as_str()) && expired(&objects::loose_path(&hash))? {
+            repack::remove_loose(&hash)?;
+        }
+    }
+
+    let objects_dir = repository::objects_dir();
+    for dir in [objects_dir.clone(), objects_dir.join("pack")] {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let temporary = path
+                .file_name()
+                .is_some_and(|x| x.to_string_lossy().starts_with("tmp_"));
+            if temporary && path.is_file() && expired(&path)? {
+                fs::remove_file(path)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Packs refs, expires reflog entries, repacks, everything if `all` is
+/// given and otherwise only loose objects, and prunes what is
+/// unreachable and older than `prune_expire`.
+fn run(settings: &Settings, all: bool, prune_expire: u64) -> std::io::Result<()> {
+    refs::pack_refs()?;
+
+    let mut store = Store::open()?;
+    for name in reflog::names()? {
+        reflog::expire(
+            &name,
+            &mut store,
+            settings.reflog_expire,
+            settings.reflog_expire_unreachable,
+        )?;
+    }
+
+    let found = reachable::objects(&reachable::roots()?)?;
+    // unreachable objects leave packs as loose ones, to be pruned by age
+    repack::repack(&found, all, true, Some(prune_expire))?;
+
+    let names = found.iter().map(|(x, _)| x.as_str()).collect();
+    prune(&names, prune_expire)
+}
+
+/// Runs `gc --auto` after a command that wrote objects, reporting rather
+/// than failing on errors, as they don't undo what the command did.
+pub fn auto() {
+    if let Err(e) = run_auto(false) {
+        eprintln!("error: {e}");
+    }
+}
+
+/// Runs a `gc` if there are enough loose objects or packs to make it
+/// worth it.
+fn run_auto(quiet: bool) -> std::io::Result<()> {
+    let settings = Settings::load(now())?;
+    let Some(all) = settings.needs_gc()? else {
+        return Ok(());
+    };
+
+    if !quiet {
+        eprintln!("Auto packing the repository for optimum performance.");
+        eprintln!("See \"git help gc\" for manual housekeeping.");
+    }
+    run(&settings, all, settings.prune_expire)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
+}
+
+pub fn invoke(options: Options) {
+    if options.auto {
+        return run_auto(options.quiet).unwrap_or_else(|e| fatal(e));
+    }
+
+    let now = now();
+    let settings = Settings::load(now).unwrap_or_else(|e| fatal(e));
+    let prune_expire = match &options.prune {
+        _ if options.no_prune => 0,
+        Some(date) => config::parse_expiry(date, now)
+            .unwrap_or_else(|| fatal(format!("malformed expiration date '{date}'"))),
+        None => settings.prune_expire,
+    };
+
+    run(&settings, true, prune_expire).unwrap_or_else(|e| fatal(e));
+}
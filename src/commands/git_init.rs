@@ -1,9 +1,7 @@
-use std::fs;
+use crate::git::repository;
 
 pub fn invoke() {
-    fs::create_dir_all(".git/objects").unwrap();
-    fs::create_dir_all(".git/refs").unwrap();
-    fs::write(".git/HEAD", "ref: refs/heads/main\n").unwrap();
+    repository::init().unwrap();
 
     println!("Initialized git directory")
 }
@@ -1,40 +1,144 @@
-use std::fs;
-use std::io::prelude::*;
-use std::path::PathBuf;
+use std::{
+    fs,
+    io::{BufRead, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
 
-use sha1::Digest;
-use sha1::Sha1;
+use crate::git::{
+    config::Config,
+    objects::{self, Writer},
+    packfile::ObjType,
+};
 
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
+use super::{fatal, reason};
 
 #[derive(Debug)]
 pub struct Options {
+    pub kind: String,
     pub write: bool,
+    pub stdin: bool,
+    pub stdin_paths: bool,
+    pub literally: bool,
+    /// Path whose attributes would decide the filters to apply, of which
+    /// there are none yet.
+    pub path: Option<String>,
+    pub no_filters: bool,
 }
 
-pub fn invoke(path: PathBuf, options: Options) -> String {
-    let content = fs::read_to_string(path).unwrap();
-    let data = format!("blob {}\0{}", content.len(), content);
+/// Hashes objects, and with `-w` stores them.
+struct Hasher<'a> {
+    options: &'a Options,
+    writer: Option<Writer>,
+    /// Size above which files are streamed rather than read whole.
+    big_file_threshold: u64,
+}
+
+impl Hasher<'_> {
+    /// Hashes `data` as an object of the type asked for.
+    fn hash(&mut self, data: &[u8]) -> std::io::Result<String> {
+        let options = self.options;
+        if !options.literally {
+            let kind = options
+                .kind
+                .parse::<ObjType>()
+                .unwrap_or_else(|_| fatal(format!("invalid object type \"{}\"", options.kind)));
+            objects::check(kind, data)?;
+        }
+
+        let name = match &mut self.writer {
+            Some(writer) => writer.write(&options.kind, data)?,
+            None => objects::hash(&options.kind, data),
+        };
+        Ok(hex::encode(name))
+    }
+
+    /// Hashes the content of the file at `path`, streaming it when it is
+    /// too big to read whole and needs no checking.
+    fn hash_file(&mut self, path: &Path) -> std::io::Result<String> {
+        let mut file = fs::File::open(path).unwrap_or_else(|e| {
+            fatal(format!(
+                "could not open '{}' for reading: {}",
+                path.display(),
+                reason(&e)
+            ))
+        });
 
-    let mut hasher = Sha1::new();
-    hasher.update(data.as_bytes());
-    let hash = hasher.finalize();
-    let hash = hex::encode(hash);
- 
-    if options.write == true {
-        let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
-        e.write_all(data.as_bytes()).unwrap();
-        let compressed = e.finish().unwrap();
+        let size = file.metadata()?.len();
+        let unchecked = self.options.literally || self.options.kind == "blob";
+        if size <= self.big_file_threshold || !unchecked {
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            return self.hash(&data);
+        }
 
-        let _ = fs::create_dir(format!(".git/objects/{}", &hash[..2]));
-        let _ = fs::write(
-            format!(".git/objects/{}/{}", &hash[..2], &hash[2..]),
-            compressed,
-        );
+        let kind = &self.options.kind;
+        let name = match &mut self.writer {
+            Some(writer) => writer.write_stream(kind, file, size)?,
+            None => objects::hash_stream(kind, file, size)?,
+        };
+        Ok(hex::encode(name))
+    }
+}
+
+pub fn invoke(files: Vec<PathBuf>, options: Options) {
+    if options.stdin_paths && options.stdin {
+        fatal("Can't use --stdin-paths with --stdin");
+    }
+    if options.stdin_paths && !files.is_empty() {
+        fatal("Can't specify files with --stdin-paths");
+    }
+    if options.path.is_some() && options.no_filters {
+        fatal("Can't use --path with --no-filters");
     }
 
-    println!("{}", hash);
+    let writer = match options.write {
+        true => Some(Writer::open().unwrap_or_else(|e| fatal(e))),
+        false => None,
+    };
+    let big_file_threshold = match &writer {
+        Some(writer) => writer.big_file_threshold(),
+        None => Config::load()
+            .and_then(|x| x.big_file_threshold())
+            .unwrap_or_else(|e| fatal(e)),
+    };
+    let mut hasher = Hasher {
+        options: &options,
+        writer,
+        big_file_threshold,
+    };
 
-    hash
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    let mut print = |name: std::io::Result<String>| {
+        let name = name.unwrap_or_else(|e| fatal(e));
+        writeln!(out, "{name}").and_then(|_| out.flush())
+    };
+
+    let mut written = Ok(());
+    if options.stdin {
+        let mut data = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut data)
+            .unwrap_or_else(|e| fatal(e));
+        written = written.and_then(|_| print(hasher.hash(&data)));
+    }
+
+    if options.stdin_paths {
+        for line in std::io::stdin().lock().lines() {
+            let path = PathBuf::from(line.unwrap_or_else(|e| fatal(e)));
+            written = written.and_then(|_| print(hasher.hash_file(&path)));
+        }
+    }
+
+    for path in files.iter() {
+        written = written.and_then(|_| print(hasher.hash_file(path)));
+    }
+
+    if let Err(e) = written {
+        if e.kind() != std::io::ErrorKind::BrokenPipe {
+            fatal(e);
+        }
+    }
+    if options.write {
+        super::gc::auto();
+    }
 }
@@ -0,0 +1,115 @@
+use std::{
+    fs,
+    io::{BufReader, BufWriter},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+use crate::git::index_pack::{self, Indexer};
+
+use super::{fatal, reason};
+
+#[derive(Debug)]
+pub struct Options {
+    pub stdin: bool,
+    pub fix_thin: bool,
+    pub output: Option<PathBuf>,
+    pub rev_index: bool,
+    pub no_rev_index: bool,
+}
+
+pub fn invoke(pack: Option<PathBuf>, options: Options) {
+    if options.fix_thin && !options.stdin {
+        fatal("the option '--fix-thin' requires '--stdin'");
+    }
+    let index = match (&options.output, &pack) {
+        (Some(index), _) => Some(index.clone()),
+        (None, Some(pack)) if pack.extension().is_some_and(|x| x == "pack") => {
+            Some(pack.with_extension("idx"))
+        }
+        (None, Some(pack)) => fatal(format!(
+            "packfile name '{}' does not end with '.pack'",
+            pack.display()
+        )),
+        (None, None) => None,
+    };
+
+    let mut indexer = Indexer::open().unwrap_or_else(|e| fatal(e));
+    indexer.fix_thin = options.fix_thin;
+    if options.rev_index || options.no_rev_index {
+        indexer.rev_index = options.rev_index;
+    }
+
+    let checksum = match index {
+        Some(index) => index_file(&indexer, pack.as_deref(), options.stdin, &index),
+        None => indexer
+            .receive(std::io::stdin().lock())
+            .unwrap_or_else(|e| fatal(e)),
+    };
+
+    match options.stdin {
+        true => println!("pack\t{}", hex::encode(checksum)),
+        false => println!("{}", hex::encode(checksum)),
+    }
+}
+
+/// Indexes the pack at `pack`, first reading it from standard input if
+/// `stdin` is given, or into the object database if there is no `pack`,
+/// writing its index to `index`.
+fn index_file(indexer: &Indexer, pack: Option<&Path>, stdin: bool, index: &Path) -> [u8; 20] {
+    let (path, mut file) = match pack {
+        Some(pack) if stdin => {
+            let file = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .mode(0o444)
+                .open(pack)
+                .unwrap_or_else(|e| {
+                    fatal(format!(
+                        "unable to create '{}': {}",
+                        pack.display(),
+                        reason(&e)
+                    ))
+                });
+            (pack.to_path_buf(), file)
+        }
+        Some(pack) => {
+            let file = fs::File::open(pack).unwrap_or_else(|e| {
+                fatal(format!(
+                    "could not open '{}' for reading: {}",
+                    pack.display(),
+                    reason(&e)
+                ))
+            });
+            (pack.to_path_buf(), file)
+        }
+        None => {
+            let dir = crate::git::repository::objects_dir().join("pack");
+            fs::create_dir_all(&dir)
+                .and_then(|_| crate::git::objects::temporary_file(&dir, "tmp_pack"))
+                .unwrap_or_else(|e| fatal(e))
+        }
+    };
+
+    let scanned = match stdin {
+        true => index_pack::scan(std::io::stdin().lock(), BufWriter::new(&file)),
+        false => index_pack::scan(BufReader::new(&file), std::io::sink()),
+    };
+    let indexed = scanned.and_then(|(mut objects, checksum)| {
+        let checksum = indexer.index(&mut file, &mut objects, checksum)?;
+        if pack.is_none() {
+            let name = path.with_file_name(format!("pack-{}.pack", hex::encode(checksum)));
+            fs::rename(&path, name)?;
+        }
+        indexer.write_index(index_pack::entries(&objects), &checksum, index)?;
+        Ok(checksum)
+    });
+
+    indexed.unwrap_or_else(|e| {
+        if stdin {
+            let _ = fs::remove_file(&path);
+        }
+        fatal(e)
+    })
+}
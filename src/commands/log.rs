@@ -0,0 +1,149 @@
+use std::{
+    io::{BufWriter, IsTerminal, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::git::{
+    pretty::{self, DateFormat, Format},
+    refs,
+    revwalk::RevWalk,
+};
+
+use super::{fatal, pathspecs, DiffArgs};
+
+#[derive(Debug)]
+pub struct Options {
+    pub oneline: bool,
+    pub format: Option<String>,
+    pub date: Option<String>,
+    pub max_count: Option<usize>,
+    pub skip: Option<usize>,
+    pub first_parent: bool,
+    pub reverse: bool,
+    pub author: Vec<String>,
+    pub grep: Vec<String>,
+    pub ignore_case: bool,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub diff: DiffArgs,
+    pub paths: Vec<String>,
+}
+
+fn patterns(values: &[String], ignore_case: bool) -> Vec<Regex> {
+    values
+        .iter()
+        .map(|x| {
+            RegexBuilder::new(x)
+                .case_insensitive(ignore_case)
+                .multi_line(true)
+                .build()
+                .unwrap_or_else(|e| fatal(format!("invalid regex '{x}': {e}")))
+        })
+        .collect()
+}
+
+fn date(value: &Option<String>) -> Option<u64> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default();
+
+    value
+        .as_ref()
+        .map(|x| pretty::parse_date(x, now).unwrap_or_else(|| fatal(format!("invalid date '{x}'"))))
+}
+
+pub fn invoke(revisions: Vec<String>, mut options: Options) {
+    options.paths = pathspecs(&options.paths);
+    let format = match (&options.format, options.oneline) {
+        (Some(format), _) => format.parse().unwrap_or_else(|e| fatal(e)),
+        (None, true) => Format::Oneline,
+        (None, false) => Format::Medium,
+    };
+    let pretty = pretty::Options {
+        date: match &options.date {
+            Some(date) => date.parse().unwrap_or_else(|e| fatal(e)),
+            None => DateFormat::Default,
+        },
+        color: std::io::stdout().is_terminal(),
+        decorations: match format.decorates() {
+            true => pretty::decorations().unwrap_or_else(|e| fatal(e)),
+            false => Default::default(),
+        },
+    };
+
+    let display = options
+        .diff
+        .display_options(std::io::stdout().is_terminal());
+    let shows_changes = options.diff.picks_format() || options.diff.names();
+
+    let mut walk = RevWalk::new();
+    walk.first_parent = options.first_parent;
+    walk.paths = options.paths.clone();
+    walk.filter.max_count = options.max_count;
+    walk.filter.skip = options.skip.unwrap_or_default();
+    walk.filter.authors = patterns(&options.author, options.ignore_case);
+    walk.filter.grep = patterns(&options.grep, options.ignore_case);
+    walk.filter.since = date(&options.since);
+    walk.filter.until = date(&options.until);
+
+    if revisions.is_empty() {
+        match refs::head_commit() {
+            Ok(Some(head)) => walk.push(&head).map(|_| ()),
+            Ok(None) => fatal("your current branch does not have any commits yet"),
+            Err(e) => Err(e),
+        }
+        .unwrap_or_else(|e| fatal(e));
+    }
+
+    walk.add_revisions(&revisions).unwrap_or_else(|e| fatal(e));
+
+    let commits: Box<dyn Iterator<Item = _>> = match options.reverse {
+        true => {
+            let mut all = walk
+                .collect::<std::io::Result<Vec<_>>>()
+                .unwrap_or_else(|e| fatal(e));
+            all.reverse();
+            Box::new(all.into_iter().map(Ok))
+        }
+        false => Box::new(walk),
+    };
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    for (position, entry) in commits.enumerate() {
+        let (hash, commit) = entry.unwrap_or_else(|e| fatal(e));
+
+        let separator = if position == 0 {
+            ""
+        } else {
+            format.separator()
+        };
+        let text = pretty::render(&hash, &commit, &format, &pretty);
+
+        // a closed pipe (log | head) simply ends the output
+        if write!(out, "{separator}{text}{}", format.terminator()).is_err() {
+            return;
+        }
+
+        // merges get no diff without -m, which isn't supported
+        if shows_changes && commit.parents.len() < 2 {
+            let changes = super::show::changes(&commit, &options.diff, &options.paths)
+                .unwrap_or_else(|e| fatal(e));
+            let written = super::show::write_changes(
+                &mut out,
+                &format,
+                &commit,
+                &changes,
+                &options.diff,
+                &display,
+            );
+            if written.is_err() {
+                return;
+            }
+        }
+    }
+
+    let _ = out.flush();
+}
@@ -1,61 +1,146 @@
-use core::panic;
-use std::{
-    ffi::CStr,
-    io::{BufRead, Read},
+use std::io::{BufWriter, Write};
+
+use crate::git::{
+    objects::{tree, Store},
+    packfile::ObjType,
+    pathspec, repository, revision,
 };
 
-use crate::objects::{Kind, Object};
+use super::{fatal, pathspecs_in};
 
 #[derive(Debug)]
 pub struct Options {
     pub name_only: bool,
+    pub object_only: bool,
+    pub recursive: bool,
+    pub show_trees: bool,
+    pub trees_only: bool,
+    pub long: bool,
+    pub nul: bool,
+    pub full_name: bool,
+    pub full_tree: bool,
+    pub paths: Vec<String>,
 }
 
-pub fn invoke(tree_hash: &str, options: Options) {
-    let data = match Object::read(tree_hash) {
-        Ok(ok) => ok,
-        Err(e) => panic!("{}", e),
-    };
+/// Walks a tree, listing the entries the paths select.
+struct Listing<'a> {
+    options: &'a Options,
+    store: Store,
+    specs: Vec<String>,
+    /// The directory paths are shown relative to.
+    shown_from: String,
+    /// Trees listed along with their entries, not only in their place.
+    show_trees: bool,
+}
 
-    let mut content = if data.kind == Kind::Tree {
-        data.content
-    } else {
-        panic!("work on tree");
-    };
+impl Listing<'_> {
+    /// Whether to list the entries of the tree at `path` rather than the
+    /// tree itself: always with `-r`, otherwise when a path given is below it.
+    fn descend(&self, path: &str) -> bool {
+        self.options.recursive
+            || self
+                .specs
+                .iter()
+                .any(|spec| spec.strip_prefix(path).is_some_and(|x| x.starts_with('/')))
+    }
 
-    let mut objects: Vec<(String, String, [u8; 20])> = Vec::new();
-    let mut size = 0;
+    fn entry(
+        &mut self,
+        out: &mut impl Write,
+        item: &tree::TreeItem,
+        path: &str,
+    ) -> std::io::Result<()> {
+        let hash = hex::encode(item.hash);
+        let options = self.options;
+        let path = pathspec::relative(&self.shown_from, path);
 
-    loop {
-        let mut buf = Vec::new();
-        size += content.read_until(0, &mut buf).unwrap();
+        if options.name_only {
+            write!(out, "{path}")?;
+        } else if options.object_only {
+            write!(out, "{hash}")?;
+        } else if options.long {
+            let size = match item.kind() {
+                ObjType::Blob => self.store.stream(&hash)?.size.to_string(),
+                _ => "-".to_string(),
+            };
+            write!(
+                out,
+                "{:06o} {} {hash} {size:>7}\t{path}",
+                item.mode,
+                item.kind()
+            )?;
+        } else {
+            write!(out, "{:06o} {} {hash}\t{path}", item.mode, item.kind())?;
+        }
 
-        let mode_name = CStr::from_bytes_until_nul(&buf).unwrap();
-        let mode_name = mode_name.to_str().unwrap();
+        out.write_all(if options.nul { b"\0" } else { b"\n" })
+    }
 
-        let Some((mode, name)) = mode_name.split_once(' ') else {
-            panic!("wrong file format");
-        };
+    /// Lists the entries of the tree `hash`, whose entries are at `prefix`.
+    fn tree(&mut self, out: &mut impl Write, hash: &str, prefix: &str) -> std::io::Result<()> {
+        let (_, data) = self.store.read(hash)?;
 
-        let mut hash = [0; 20];
-        content.read_exact(&mut hash).unwrap();
-        size += 20;
-        objects.push((mode.to_string(), name.to_string(), hash));
+        for item in tree::parse(&mut &data[..])? {
+            let path = format!("{prefix}{}", item.name);
+            let selected = match item.is_tree() {
+                true => pathspec::could_contain(&self.specs, &path),
+                false => pathspec::matches(&self.specs, &path),
+            };
+            if !selected {
+                continue;
+            }
 
-        if size == data.expected_size {
-            break;
+            let descend = item.is_tree() && self.descend(&path);
+            let shown = match descend {
+                true => self.show_trees,
+                false => !self.options.trees_only || item.kind() != ObjType::Blob,
+            };
+            if shown {
+                self.entry(out, &item, &path)?;
+            }
+            if descend {
+                self.tree(out, &hex::encode(item.hash), &format!("{path}/"))?;
+            }
         }
+
+        Ok(())
     }
+}
 
-    for i in objects.iter() {
-        let out;
+pub fn invoke(tree_ish: &str, options: Options) {
+    let hash = revision::resolve(tree_ish)
+        .unwrap_or_else(|_| fatal(format!("Not a valid object name {tree_ish}")));
+    let tree =
+        revision::peel(&hash, Some(ObjType::Tree)).unwrap_or_else(|_| fatal("not a tree object"));
 
-        if options.name_only == true {
-            out = format!("{}", i.1);
-        } else {
-            out = format!("{:0>6} {} {} {}", i.0, "kind", hex::encode(i.2), i.1);
-        }
+    // like paths given, what is listed is relative to the current directory,
+    // which is listed when no path is given
+    let prefix = match options.full_tree {
+        true => String::new(),
+        false => repository::prefix(),
+    };
+    let specs = match options.paths.is_empty() && !prefix.is_empty() {
+        true => vec![prefix.clone()],
+        false => pathspecs_in(&prefix, &options.paths),
+    };
+    let shown_from = match options.full_name {
+        true => String::new(),
+        false => prefix,
+    };
+
+    let mut listing = Listing {
+        options: &options,
+        store: Store::open().unwrap_or_else(|e| fatal(e)),
+        specs,
+        shown_from,
+        // -d lists the trees -r goes through, which it would leave out
+        show_trees: options.show_trees || (options.trees_only && options.recursive),
+    };
 
-        println!("{}", out);
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    if let Err(e) = listing.tree(&mut out, &tree, "").and_then(|_| out.flush()) {
+        if e.kind() != std::io::ErrorKind::BrokenPipe {
+            fatal(e);
+        }
     }
 }
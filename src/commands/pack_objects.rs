@@ -0,0 +1,96 @@
+use std::{
+    io::{BufRead, BufWriter},
+    path::PathBuf,
+};
+
+use crate::git::{list_objects::ObjectWalk, pack_objects::PackWriter, refs, revwalk::RevWalk};
+
+use super::fatal;
+
+#[derive(Debug)]
+pub struct Options {
+    pub stdout: bool,
+    pub revs: bool,
+    pub all: bool,
+    pub window: Option<usize>,
+    pub depth: Option<usize>,
+}
+
+/// The objects to pack, named on standard input with the path they are
+/// at, or with `--revs` those the revisions on standard input need.
+fn read_objects(options: &Options) -> Vec<(String, Option<String>)> {
+    let lines = std::io::stdin()
+        .lock()
+        .lines()
+        .collect::<std::io::Result<Vec<_>>>()
+        .unwrap_or_else(|e| fatal(e));
+
+    if !options.revs && !options.all {
+        return lines
+            .iter()
+            .filter(|x| !x.is_empty())
+            .map(|line| match line.split_once(' ') {
+                Some((hash, path)) => (hash.to_string(), Some(path.to_string())),
+                None => (line.to_string(), None),
+            })
+            .map(|(hash, path)| {
+                if hash.len() != 40 || !hash.bytes().all(|x| x.is_ascii_hexdigit()) {
+                    fatal(format!("expected object ID, got garbage:\n {hash}"));
+                }
+                (hash.to_ascii_lowercase(), path)
+            })
+            .collect();
+    }
+
+    let mut walk = RevWalk::new();
+    if options.all {
+        let mut tips = refs::list("refs/").unwrap_or_else(|e| fatal(e));
+        tips.extend(
+            refs::head_commit()
+                .ok()
+                .flatten()
+                .map(|x| ("HEAD".to_string(), x)),
+        );
+        for (_, hash) in tips {
+            walk.push(&hash).unwrap_or_else(|e| fatal(e));
+        }
+    }
+    let revisions = lines
+        .into_iter()
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    walk.add_revisions(&revisions).unwrap_or_else(|e| fatal(e));
+
+    ObjectWalk::new(walk, None)
+        .unwrap_or_else(|e| fatal(e))
+        .map(|x| x.map(|x| (x.hash, x.name.filter(|x| !x.is_empty()))))
+        .collect::<std::io::Result<_>>()
+        .unwrap_or_else(|e| fatal(e))
+}
+
+pub fn invoke(base_name: Option<PathBuf>, options: Options) {
+    if options.stdout == base_name.is_some() {
+        fatal("usage: pack-objects [<options>] <base-name> | --stdout");
+    }
+
+    let mut writer = PackWriter::open().unwrap_or_else(|e| fatal(e));
+    if let Some(window) = options.window {
+        writer.window = window;
+    }
+    if let Some(depth) = options.depth {
+        writer.depth = depth.min(4095);
+    }
+
+    let objects = read_objects(&options);
+
+    let Some(base_name) = base_name else {
+        let out = BufWriter::new(std::io::stdout().lock());
+        writer.write(&objects, out).unwrap_or_else(|e| fatal(e));
+        return;
+    };
+
+    let checksum = writer
+        .write_files(&objects, &base_name)
+        .unwrap_or_else(|e| fatal(e));
+    println!("{}", hex::encode(checksum));
+}
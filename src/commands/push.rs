@@ -0,0 +1,563 @@
+use reqwest::blocking::Client;
+
+use crate::git::{
+    config::Config,
+    fetch_pack::{self, Advertisement},
+    objects,
+    packfile::ObjType,
+    refs::{self, Head},
+    remote::{self, is_fast_forward, pretty, Refspec, Remote, SUMMARY_WIDTH},
+    revision,
+    send_pack::{self, Command},
+};
+
+use super::{error, fatal};
+
+#[derive(Debug)]
+pub struct Options {
+    pub refspecs: Vec<String>,
+    pub force: bool,
+    /// `<ref>[:<expect>]`, or empty for every ref pushed.
+    pub force_with_lease: Option<String>,
+    pub delete: bool,
+    pub atomic: bool,
+    pub tags: bool,
+    pub verbose: bool,
+}
+
+/// How an update of a ref of the remote went, or will go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Status {
+    /// To be sent, and once sent done.
+    Ok,
+    UpToDate,
+    /// Refused before sending, with why.
+    Rejected(&'static str),
+    RemoteRejected(String),
+}
+
+/// A ref of the remote to update.
+#[derive(Debug)]
+struct Update {
+    /// The source as shown: a local ref without its `refs/heads/` or
+    /// `refs/tags/`, or the revision as given.
+    src: String,
+    /// The full name of the local ref the source is, if it is one.
+    local: Option<String>,
+    /// What the ref should point at, `None` to delete it.
+    new: Option<String>,
+    dst: String,
+    /// What it points at on the remote now.
+    old: Option<String>,
+    force: bool,
+    /// What the ref must point at for it to be updated, if it is leased.
+    expect: Option<Option<String>>,
+    status: Status,
+}
+
+fn is_commit(hash: &str) -> bool {
+    revision::peel(hash, Some(ObjType::Commit)).is_ok()
+}
+
+const NOT_FULL_REFNAME: &str = "\
+The destination you provided is not a full refname (i.e.,
+starting with \"refs/\"). We tried to guess what you meant by:
+
+- Looking for a ref that matches '{dst}' on the remote side.
+- Checking if the <src> being pushed ('{src}')
+  is a ref in \"refs/{heads,tags}/\". If so we add a corresponding
+  refs/{heads,tags}/ prefix on the remote side.
+
+Neither worked, so we gave up. You must fully qualify the ref.";
+
+/// The local ref or object the source of a refspec names, with the full
+/// name of the ref if it is one.
+fn resolve_src(src: &str, local: &[(String, String)]) -> Option<(Option<String>, String)> {
+    if src == "HEAD" {
+        let hash = refs::head_commit().ok()??;
+        return Some((Some("HEAD".to_string()), hash));
+    }
+    if let Some((name, hash)) = remote::find(local, src) {
+        return Some((Some(name.clone()), hash.clone()));
+    }
+    revision::resolve(src).ok().map(|x| (None, x))
+}
+
+/// The updates `refspecs` ask of the remote, in the order they ask for
+/// them, or the errors that keep any of them from being made, with the
+/// hints that may follow them.
+fn select(
+    advertisement: &Advertisement,
+    refspecs: &[Refspec],
+    options: &Options,
+) -> Result<Vec<Update>, Vec<String>> {
+    let local = refs::list("refs/").unwrap_or_else(|e| fatal(e));
+    let head = refs::read_head().unwrap_or_else(|e| fatal(e));
+    let remote_old = |dst: &str| {
+        advertisement
+            .refs
+            .iter()
+            .find(|(x, _)| x == dst)
+            .map(|(_, x)| x.clone())
+    };
+
+    let mut updates = Vec::new();
+    let mut errors = Vec::new();
+    let mut add = |src: String, local: Option<String>, new: Option<String>, dst: String, force| {
+        let old = remote_old(&dst);
+        updates.push(Update {
+            src,
+            local,
+            new,
+            old,
+            dst,
+            force: force || options.force,
+            expect: None,
+            status: Status::Ok,
+        });
+    };
+
+    for spec in refspecs {
+        if spec.is_glob() {
+            for (name, hash) in &local {
+                if let Some(dst) = spec.map(name) {
+                    let src = pretty(name).to_string();
+                    add(src, Some(name.clone()), Some(hash.clone()), dst, spec.force);
+                }
+            }
+            continue;
+        }
+
+        // `:<dst>` deletes
+        if spec.src.is_empty() {
+            let dst = spec.dst.as_deref().unwrap_or_default();
+            match remote::find(&advertisement.refs, dst) {
+                Some((name, _)) => add(String::new(), None, None, name.clone(), spec.force),
+                None => errors.push(format!(
+                    "unable to delete '{dst}': remote ref does not exist"
+                )),
+            }
+            continue;
+        }
+
+        let Some((name, hash)) = resolve_src(&spec.src, &local) else {
+            errors.push(format!("src refspec {} does not match any", spec.src));
+            continue;
+        };
+        // what HEAD stands for when it is on a branch
+        let full = match (name.as_deref(), &head) {
+            (Some("HEAD"), Head::Branch(branch)) => Some(branch.clone()),
+            (Some("HEAD"), _) => None,
+            _ => name.clone(),
+        };
+        let shown = match &name {
+            Some(name) => pretty(name).to_string(),
+            None => spec.src.clone(),
+        };
+
+        let dst = match (&spec.dst, &full) {
+            (Some(dst), _) if dst.starts_with("refs/") => Some(dst.clone()),
+            (Some(dst), _) => remote::find(&advertisement.refs, dst)
+                .map(|(x, _)| x.clone())
+                .or_else(|| {
+                    let full = full.as_deref()?;
+                    ["refs/heads/", "refs/tags/"]
+                        .into_iter()
+                        .find(|x| full.starts_with(x))
+                        .map(|x| format!("{x}{dst}"))
+                }),
+            (None, full) => full.clone(),
+        };
+        let Some(dst) = dst else {
+            let dst = spec.dst.as_deref().unwrap_or(&spec.src);
+            let mut message = NOT_FULL_REFNAME
+                .replace("{dst}", dst)
+                .replace("{src}", &spec.src);
+            let kind = objects::stream(&hash).map(|x| x.kind);
+            let (kind, what, prefix) = match kind {
+                Ok(ObjType::Commit) => ("commit", "branch", "refs/heads/"),
+                Ok(ObjType::Tag) => ("tag", "tag", "refs/tags/"),
+                _ => ("", "", ""),
+            };
+            if !kind.is_empty() {
+                message.push_str(&format!(
+                    "\nhint: The <src> part of the refspec is a {kind} object.\n\
+                     hint: Did you mean to create a new {what} by pushing to\n\
+                     hint: '{}:{prefix}{dst}'?",
+                    spec.src
+                ));
+            }
+            errors.push(message);
+            continue;
+        };
+        add(shown, full, Some(hash), dst, spec.force);
+    }
+
+    // refs the remote has go in the order it has them, before new ones
+    updates.sort_by_key(|x| {
+        advertisement
+            .refs
+            .iter()
+            .position(|(name, _)| *name == x.dst)
+            .unwrap_or(usize::MAX)
+    });
+    match errors.is_empty() {
+        true => Ok(updates),
+        false => Err(errors),
+    }
+}
+
+/// What a leased ref must point at on the remote: as given, or what its
+/// remote-tracking ref says it was last seen at.
+fn lease(remote: &Remote, lease: &str, dst: &str) -> Option<Option<String>> {
+    let (name, expect) = match lease.split_once(':') {
+        Some((name, expect)) => (name, Some(expect)),
+        None => (lease, None),
+    };
+    if !name.is_empty() && !remote::expansions(name).iter().any(|x| x == dst) {
+        return None;
+    }
+    Some(match expect {
+        Some(expect) => Some(
+            revision::resolve(expect)
+                .unwrap_or_else(|_| fatal(format!("cannot parse expected object name '{expect}'"))),
+        ),
+        None => remote
+            .fetch
+            .iter()
+            .find_map(|x| x.map(dst))
+            .and_then(|x| refs::resolve(&x).ok().flatten()),
+    })
+}
+
+/// Decides whether `update` may be sent, as git does before talking to
+/// the remote.
+fn check(update: &Update) -> Status {
+    let Some(new) = &update.new else {
+        return Status::Ok;
+    };
+    if update.old.as_ref() == Some(new) {
+        return Status::UpToDate;
+    }
+    if let Some(expect) = &update.expect {
+        return match *expect == update.old {
+            true => Status::Ok,
+            false => Status::Rejected("stale info"),
+        };
+    }
+    let Some(old) = &update.old else {
+        return Status::Ok;
+    };
+    if update.force {
+        return Status::Ok;
+    }
+
+    if update.dst.starts_with("refs/tags/") {
+        Status::Rejected("already exists")
+    } else if !objects::contains(old) {
+        Status::Rejected("fetch first")
+    } else if !is_commit(old) || !is_commit(new) {
+        Status::Rejected("needs force")
+    } else if !is_fast_forward(old, new) {
+        Status::Rejected("non-fast-forward")
+    } else {
+        Status::Ok
+    }
+}
+
+/// The line of the report of what a push did for `update`.
+fn line(update: &Update) -> String {
+    let short = |x: &str| x[..7].to_string();
+    let (code, summary, reason) = match (&update.status, &update.old, &update.new) {
+        (Status::UpToDate, _, _) => ('=', "[up to date]".to_string(), None),
+        (Status::Rejected(reason), _, _) => {
+            ('!', "[rejected]".to_string(), Some(reason.to_string()))
+        }
+        (Status::RemoteRejected(reason), _, _) => {
+            ('!', "[remote rejected]".to_string(), Some(reason.clone()))
+        }
+        (Status::Ok, _, None) => ('-', "[deleted]".to_string(), None),
+        (Status::Ok, None, Some(_)) => {
+            let summary = match () {
+                _ if update.dst.starts_with("refs/tags/") => "[new tag]",
+                _ if update.dst.starts_with("refs/heads/") => "[new branch]",
+                _ => "[new reference]",
+            };
+            ('*', summary.to_string(), None)
+        }
+        (Status::Ok, Some(old), Some(new))
+            if objects::contains(old) && is_fast_forward(old, new) =>
+        {
+            (' ', format!("{}..{}", short(old), short(new)), None)
+        }
+        (Status::Ok, Some(old), Some(new)) => (
+            '+',
+            format!("{}...{}", short(old), short(new)),
+            Some("forced update".to_string()),
+        ),
+    };
+
+    let names = match update.new {
+        Some(_) => format!("{} -> {}", update.src, pretty(&update.dst)),
+        None => pretty(&update.dst).to_string(),
+    };
+    let reason = reason.map(|x| format!(" ({x})")).unwrap_or_default();
+    format!(" {code} {summary:<SUMMARY_WIDTH$} {names}{reason}")
+}
+
+/// Advice on what to do about the updates that were rejected, for the
+/// most pressing reason.
+fn hint(updates: &[Update], current: Option<&str>) -> Option<&'static str> {
+    let rejected = |reason: &'static str, head: Option<bool>| {
+        updates.iter().any(|x| {
+            x.status == Status::Rejected(reason)
+                && head.is_none_or(|head| (x.local.as_deref() == current) == head)
+        })
+    };
+    if rejected("non-fast-forward", Some(true)) {
+        Some(
+            "Updates were rejected because the tip of your current branch is behind
+its remote counterpart. Integrate the remote changes (e.g.
+'git pull ...') before pushing again.
+See the 'Note about fast-forwards' in 'git push --help' for details.",
+        )
+    } else if rejected("non-fast-forward", Some(false)) {
+        Some(
+            "Updates were rejected because a pushed branch tip is behind its remote
+counterpart. Check out this branch and integrate the remote changes
+(e.g. 'git pull ...') before pushing again.
+See the 'Note about fast-forwards' in 'git push --help' for details.",
+        )
+    } else if rejected("already exists", None) {
+        Some("Updates were rejected because the tag already exists in the remote.")
+    } else if rejected("fetch first", None) {
+        Some(
+            "Updates were rejected because the remote contains work that you do
+not have locally. This is usually caused by another repository pushing
+to the same ref. You may want to first integrate the remote changes
+(e.g., 'git pull ...') before pushing again.
+See the 'Note about fast-forwards' in 'git push --help' for details.",
+        )
+    } else if rejected("needs force", None) {
+        Some(
+            "You cannot update a remote ref that points at a non-commit object,
+or update a remote ref to make it point at a non-commit object,
+without using the '--force' option.",
+        )
+    } else {
+        None
+    }
+}
+
+/// Points the remote-tracking refs of the updates the remote made at
+/// what it now has.
+fn update_tracking(remote: &Remote, updates: &[Update], verbose: bool) -> std::io::Result<()> {
+    for update in updates {
+        if !matches!(update.status, Status::Ok | Status::UpToDate) {
+            continue;
+        }
+        let Some(tracking) = remote.fetch.iter().find_map(|x| x.map(&update.dst)) else {
+            continue;
+        };
+        if verbose {
+            eprintln!("updating local tracking ref '{tracking}'");
+        }
+        match &update.new {
+            Some(new) => refs::update(&tracking, new)?,
+            None if refs::exists(&tracking) => refs::delete(&tracking)?,
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+/// The refspec `git push` uses without any: the current branch, to the
+/// branch it merges from when it is pushed where it is fetched from.
+fn default_refspec(config: &Config, remote: &Remote) -> Refspec {
+    let branch = match refs::read_head().unwrap_or_else(|e| fatal(e)) {
+        Head::Branch(branch) => branch,
+        _ => fatal(
+            "You are not currently on a branch.
+To push the history leading to the current (detached HEAD)
+state now, use
+
+    git push origin HEAD:<name-of-remote-branch>
+",
+        ),
+    };
+    let short = pretty(&branch);
+    let upstream = config.get(&format!("branch.{short}.merge"));
+    let tracked = config.get(&format!("branch.{short}.remote")) == Some(remote.name.as_str());
+    match upstream {
+        Some(upstream) if tracked && upstream != branch => fatal(format!(
+            "The upstream branch of your current branch does not match
+the name of your current branch.  To push to the upstream branch
+on the remote, use
+
+    git push {} HEAD:{}
+
+To push to the branch of the same name on the remote, use
+
+    git push {} HEAD
+",
+            remote.name,
+            pretty(upstream),
+            remote.name
+        )),
+        None if tracked || config.get(&format!("branch.{short}.remote")).is_none() => {
+            fatal(format!(
+                "The current branch {short} has no upstream branch.
+To push the current branch and set the remote as upstream, use
+
+    git push --set-upstream {} {short}
+
+To have this happen automatically for branches without a tracking
+upstream, see 'push.autoSetupRemote' in 'git help config'.
+",
+                remote.name
+            ))
+        }
+        _ => Refspec::parse(&format!("{branch}:{branch}")).unwrap(),
+    }
+}
+
+pub fn invoke(remote: Option<String>, options: Options) {
+    let config = Config::load().unwrap_or_else(|e| fatal(e));
+    let branch = match refs::read_head() {
+        Ok(Head::Branch(branch)) => Some(branch),
+        _ => None,
+    };
+    let name = remote
+        .or_else(|| {
+            let key = format!("branch.{}.remote", pretty(branch.as_deref()?));
+            config.get(&key).map(str::to_string)
+        })
+        .unwrap_or_else(|| "origin".to_string());
+    let remote = match Remote::get(&config, &name) {
+        Ok(Some(remote)) => remote,
+        Ok(None) => fatal(format!("'{name}' does not appear to be a git repository")),
+        Err(e) => fatal(e),
+    };
+
+    if options.delete && options.refspecs.is_empty() {
+        fatal("--delete doesn't make sense without any refs");
+    }
+    let mut refspecs = Vec::new();
+    for spec in &options.refspecs {
+        let spec = match options.delete {
+            true if spec.contains(':') => fatal("--delete only accepts plain target ref names"),
+            true => format!(":{spec}"),
+            false => spec.clone(),
+        };
+        refspecs.push(
+            Refspec::parse(&spec).unwrap_or_else(|| fatal(format!("invalid refspec '{spec}'"))),
+        );
+    }
+    if options.tags {
+        refspecs.push(Refspec::parse("refs/tags/*:refs/tags/*").unwrap());
+    }
+    if refspecs.is_empty() {
+        refspecs.push(default_refspec(&config, &remote));
+    }
+
+    if options.verbose {
+        eprintln!("Pushing to {}", remote.url);
+    }
+    let client = Client::new();
+    let url = remote.url.trim_end_matches('/');
+    let advertisement =
+        fetch_pack::advertise(&client, url, "git-receive-pack").unwrap_or_else(|e| fatal(e));
+
+    let mut updates = select(&advertisement, &refspecs, &options).unwrap_or_else(|errors| {
+        for e in errors {
+            eprintln!("error: {e}");
+        }
+        error(format!("failed to push some refs to '{}'", remote.url))
+    });
+    for update in &mut updates {
+        if let Some(lease_spec) = &options.force_with_lease {
+            update.expect = lease(&remote, lease_spec, &update.dst);
+        }
+        update.status = check(update);
+    }
+
+    let rejected = |x: &Update| matches!(x.status, Status::Rejected(_));
+    if options.atomic && updates.iter().any(rejected) {
+        for update in updates.iter_mut().filter(|x| x.status == Status::Ok) {
+            update.status = Status::Rejected("atomic push failed");
+        }
+    }
+
+    let sent = updates
+        .iter()
+        .filter(|x| x.status == Status::Ok)
+        .collect::<Vec<_>>();
+    if !sent.is_empty() {
+        let commands = sent
+            .iter()
+            .map(|x| Command {
+                name: x.dst.clone(),
+                old: x.old.clone(),
+                new: x.new.clone(),
+            })
+            .collect::<Vec<_>>();
+        let tips = sent
+            .iter()
+            .filter_map(|x| x.new.clone())
+            .collect::<Vec<_>>();
+        let have = advertisement
+            .refs
+            .iter()
+            .map(|(_, x)| x.clone())
+            .collect::<Vec<_>>();
+        let objects = send_pack::objects(&tips, &have).unwrap_or_else(|e| fatal(e));
+
+        let report = send_pack::push(
+            &client,
+            url,
+            &advertisement,
+            &commands,
+            &objects,
+            options.atomic,
+        )
+        .unwrap_or_else(|e| fatal(e));
+        if let Some(reason) = &report.unpack {
+            eprintln!("error: remote unpack failed: {reason}");
+        }
+        for update in updates.iter_mut().filter(|x| x.status == Status::Ok) {
+            if let Some(reason) = report.rejected.get(&update.dst) {
+                update.status = Status::RemoteRejected(reason.clone());
+            } else if report.unpack.is_some() {
+                update.status = Status::RemoteRejected("unpacker error".to_string());
+            }
+        }
+    }
+
+    let shown = updates
+        .iter()
+        .filter(|x| x.status != Status::UpToDate || options.verbose)
+        .collect::<Vec<_>>();
+    if !shown.is_empty() {
+        eprintln!("To {}", remote.url);
+        for update in shown {
+            eprintln!("{}", line(update));
+        }
+    }
+    update_tracking(&remote, &updates, options.verbose).unwrap_or_else(|e| fatal(e));
+
+    if updates.iter().all(|x| x.status == Status::UpToDate) {
+        eprintln!("Everything up-to-date");
+    }
+    if updates
+        .iter()
+        .any(|x| x.status != Status::Ok && x.status != Status::UpToDate)
+    {
+        eprintln!("error: failed to push some refs to '{}'", remote.url);
+        if let Some(hint) = hint(&updates, branch.as_deref()) {
+            for line in hint.lines() {
+                eprintln!("hint: {line}");
+            }
+        }
+        std::process::exit(1);
+    }
+}
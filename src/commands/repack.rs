@@ -0,0 +1,172 @@
+use std::{collections::HashSet, fs, path::Path, time::SystemTime};
+
+use crate::git::{
+    objects::{self, Store, Writer},
+    pack_index::{self, PackIndex},
+    pack_objects::PackWriter,
+    reachable, repository,
+};
+
+use super::fatal;
+
+#[derive(Debug)]
+pub struct Options {
+    pub all: bool,
+    pub loosen_unreachable: bool,
+    pub delete: bool,
+    pub quiet: bool,
+}
+
+/// Extensions of the files that make up a pack.
+const PACK_FILES: [&str; 4] = ["pack", "idx", "rev", "bitmap"];
+
+/// Packs the `reachable` objects into a new pack: all of them but those
+/// in packs with a `.keep` file if `all` is given, otherwise only loose
+/// ones. With `delete`, packs the new one makes redundant are removed and
+/// so are loose objects that are packed, the unreachable objects of a
+/// removed pack being kept loose if it is more recent than
+/// `unpack_unreachable`. Returns the checksum of the new pack, `None`
+/// when there was nothing to pack.
+pub fn repack(
+    reachable: &[(String, Option<String>)],
+    all: bool,
+    delete: bool,
+    unpack_unreachable: Option<u64>,
+) -> std::io::Result<Option<[u8; 20]>> {
+    let pack_dir = repository::objects_dir().join("pack");
+    let mut packs = Vec::new();
+    let mut kept = Vec::new();
+    for index in pack_index::list(&repository::objects_dir())? {
+        let index = PackIndex::open(&index)?;
+        match index.pack.with_extension("keep").exists() {
+            true => kept.push(index),
+            false => packs.push(index),
+        }
+    }
+
+    let mut objects = Vec::new();
+    for (hash, path) in reachable {
+        let mut name = [0; 20];
+        hex::decode_to_slice(hash, &mut name).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{hash} is not a valid object name"),
+            )
+        })?;
+        let wanted = match all {
+            true => !in_any(&mut kept, &name)?,
+            false => objects::loose_path(hash).exists(),
+        };
+        if wanted {
+            objects.push((hash.clone(), path.clone()));
+        }
+    }
+    if objects.is_empty() {
+        return Ok(None);
+    }
+
+    fs::create_dir_all(&pack_dir)?;
+    let checksum = PackWriter::open()?.write_files(&objects, &pack_dir.join("pack"))?;
+    if !delete {
+        return Ok(Some(checksum));
+    }
+
+    if all {
+        let new = pack_dir.join(format!("pack-{}.pack", hex::encode(checksum)));
+        let names = objects.iter().map(|(x, _)| x).collect::<HashSet<_>>();
+        let mut store = Store::open()?;
+        let mut writer = Writer::open()?;
+
+        for mut index in packs.into_iter().filter(|x| x.pack != new) {
+            let modified = fs::metadata(&index.pack)?.modified()?;
+            let recent = unpack_unreachable.is_some_and(|x| seconds(modified) > x);
+            for position in 0..index.len() {
+                let hash = hex::encode(index.hash_at(position)?);
+                if recent && !names.contains(&hash) {
+                    loosen(&mut store, &mut writer, &hash, modified)?;
+                }
+            }
+            for extension in PACK_FILES {
+                match fs::remove_file(index.pack.with_extension(extension)) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    prune_packed()?;
+    Ok(Some(checksum))
+}
+
+/// Whether one of `packs` holds `name`.
+fn in_any(packs: &mut [PackIndex], name: &[u8; 20]) -> std::io::Result<bool> {
+    for index in packs {
+        if index.find(name)?.is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Seconds since the epoch of `time`.
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
+}
+
+/// Stores the packed object `hash` as a loose object dated `modified`, so
+/// that pruning treats it as being as old as the pack it leaves.
+fn loosen(
+    store: &mut Store,
+    writer: &mut Writer,
+    hash: &str,
+    modified: SystemTime,
+) -> std::io::Result<()> {
+    let (kind, data) = store.read(hash)?;
+    writer.write_loose(kind, &data)?;
+    fs::File::open(objects::loose_path(hash))?.set_modified(modified)
+}
+
+/// Removes the loose objects that a pack holds, and the directories this
+/// leaves empty, as `git prune-packed` does.
+pub fn prune_packed() -> std::io::Result<()> {
+    let mut store = Store::open()?;
+    for hash in objects::loose_names()? {
+        let mut name = [0; 20];
+        hex::decode_to_slice(&hash, &mut name).unwrap();
+        if store.pack_of(&name)?.is_some() {
+            remove_loose(&hash)?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes the loose object `hash`, and its directory once empty.
+pub fn remove_loose(hash: &str) -> std::io::Result<()> {
+    let path = objects::loose_path(hash);
+    fs::remove_file(&path)?;
+    let _ = fs::remove_dir(path.parent().unwrap_or(Path::new("")));
+    Ok(())
+}
+
+pub fn invoke(options: Options) {
+    let repacked = reachable::roots()
+        .and_then(|x| reachable::objects(&x))
+        .and_then(|objects| {
+            // `-A` keeps every unreachable object, as loose ones
+            let unpack = options.loosen_unreachable.then_some(0);
+            repack(
+                &objects,
+                options.all || options.loosen_unreachable,
+                options.delete,
+                unpack,
+            )
+        });
+
+    match repacked {
+        Ok(None) if !options.quiet => println!("Nothing new to pack."),
+        Ok(_) => {}
+        Err(e) => fatal(e),
+    }
+}
@@ -0,0 +1,113 @@
+use std::io::{BufWriter, Write};
+
+use crate::git::{
+    list_objects::{ObjectFilter, ObjectWalk},
+    packfile::ObjType,
+    revwalk::{Order, RevWalk},
+};
+
+use super::fatal;
+
+#[derive(Debug)]
+pub struct Options {
+    pub objects: bool,
+    pub count: bool,
+    pub max_count: Option<usize>,
+    pub topo_order: bool,
+    pub date_order: bool,
+    pub left_right: bool,
+    pub filter: Option<String>,
+}
+
+pub fn invoke(revisions: Vec<String>, options: Options) {
+    let filter = options
+        .filter
+        .as_ref()
+        .map(|x| x.parse::<ObjectFilter>().unwrap_or_else(|e| fatal(e)));
+    if filter.is_some() && !options.objects {
+        fatal("object filtering requires --objects");
+    }
+
+    let mut walk = RevWalk::new();
+    walk.filter.max_count = options.max_count;
+    walk.order = match (options.topo_order, options.date_order) {
+        (true, _) => Order::Topo,
+        (_, true) => Order::Date,
+        _ => Order::Walk,
+    };
+
+    walk.add_revisions(&revisions).unwrap_or_else(|e| fatal(e));
+
+    if revisions.is_empty() {
+        fatal("usage: rev-list [<options>] <commit>... [--] [<path>...]");
+    }
+
+    let mut output = Output {
+        out: BufWriter::new(std::io::stdout().lock()),
+        count: options.count,
+        left_right: options.left_right,
+        left: 0,
+        right: 0,
+    };
+
+    if options.objects {
+        let mut objects = ObjectWalk::new(walk, filter).unwrap_or_else(|e| fatal(e));
+        while let Some(entry) = objects.next_object().unwrap_or_else(|e| fatal(e)) {
+            let left = entry.kind == ObjType::Commit && objects.revwalk().is_left(&entry.hash);
+            if output
+                .show(&entry.hash, entry.name.as_deref(), left)
+                .is_err()
+            {
+                return;
+            }
+        }
+    } else {
+        while let Some((hash, _)) = walk.next_commit().unwrap_or_else(|e| fatal(e)) {
+            if output.show(&hash, None, walk.is_left(&hash)).is_err() {
+                return;
+            }
+        }
+    }
+
+    let _ = output.finish();
+}
+
+struct Output<W: Write> {
+    out: W,
+    count: bool,
+    left_right: bool,
+    left: usize,
+    right: usize,
+}
+
+impl<W: Write> Output<W> {
+    fn show(&mut self, hash: &str, name: Option<&str>, left: bool) -> std::io::Result<()> {
+        match left {
+            true => self.left += 1,
+            false => self.right += 1,
+        }
+        if self.count {
+            return Ok(());
+        }
+
+        let mark = match (self.left_right, name, left) {
+            (true, None, true) => "<",
+            (true, None, false) => ">",
+            _ => "",
+        };
+        match name {
+            Some(name) => writeln!(self.out, "{hash} {name}"),
+            None => writeln!(self.out, "{mark}{hash}"),
+        }
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        if self.count {
+            match self.left_right {
+                true => writeln!(self.out, "{}\t{}", self.left, self.right)?,
+                false => writeln!(self.out, "{}", self.left + self.right)?,
+            }
+        }
+        self.out.flush()
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::VecDeque,
+    io::{BufWriter, IsTerminal, Write},
+};
+
+use crate::{
+    git::{
+        diff::{
+            self,
+            display::{self, Palette},
+            Change,
+        },
+        objects::{self, commit::Commit, tag, tree},
+        packfile::ObjType,
+        pretty::{self, DateFormat, Format},
+        revision,
+    },
+    pager,
+};
+
+use super::{fatal, pathspecs, DiffArgs};
+
+#[derive(Debug)]
+pub struct Options {
+    pub oneline: bool,
+    pub format: Option<String>,
+    pub date: Option<String>,
+    pub no_patch: bool,
+    pub diff: DiffArgs,
+    pub paths: Vec<String>,
+    /// Whether output may go through a pager.
+    pub pager: bool,
+}
+
+/// How objects are shown, and whether one was already, which puts a
+/// blank line before the next.
+struct Show<'a> {
+    options: &'a Options,
+    format: Format,
+    pretty: pretty::Options,
+    display: display::Options,
+    palette: Palette,
+    shown_one: bool,
+}
+
+impl Show<'_> {
+    /// Colors the `commit <hash>` line, or the abbreviated hash of
+    /// `--oneline`.
+    fn paint(&self, text: String) -> String {
+        let Palette { commit, reset, .. } = self.palette;
+        let split = match self.format {
+            Format::Oneline => text.find(' '),
+            Format::Custom { .. } => None,
+            _ => text.find('\n'),
+        };
+
+        match split {
+            Some(end) if !commit.is_empty() => {
+                format!("{commit}{}{reset}{}", &text[..end], &text[end..])
+            }
+            _ => text,
+        }
+    }
+
+    /// Shows the log message of a commit and its diff. With paths, commits
+    /// not touching them are left out.
+    fn commit(&mut self, out: &mut impl Write, hash: &str, commit: &Commit) -> std::io::Result<()> {
+        let merge = commit.parents.len() > 1;
+        let changes = changes(commit, &self.options.diff, &self.options.paths)?;
+        if !self.options.paths.is_empty() && changes.is_empty() && !merge {
+            return Ok(());
+        }
+
+        if self.shown_one {
+            write!(out, "{}", self.format.separator())?;
+        }
+        self.shown_one = true;
+
+        let text = pretty::render(hash, commit, &self.format, &self.pretty);
+        write!(out, "{}{}", self.paint(text), self.format.terminator())?;
+        if self.options.no_patch {
+            return Ok(());
+        }
+        write_changes(
+            out,
+            &self.format,
+            commit,
+            &changes,
+            &self.options.diff,
+            &self.display,
+        )
+    }
+
+    /// Shows the tag header, tagger and message, returning the object
+    /// tagged to show after it.
+    fn tag(&mut self, out: &mut impl Write, data: &[u8]) -> std::io::Result<String> {
+        let parsed = tag::parse(&mut &data[..])?;
+        let Palette { commit, reset, .. } = self.palette;
+
+        if self.shown_one {
+            writeln!(out)?;
+        }
+        self.shown_one = true;
+
+        writeln!(out, "{commit}tag {}{reset}", parsed.name)?;
+        if let Some(tagger) = &parsed.tagger {
+            write!(
+                out,
+                "{}",
+                pretty::tagger(tagger, &self.format, self.pretty.date)
+            )?;
+        }
+        // the message as stored, from the blank line ending the headers
+        if let Some(end) = data.windows(2).position(|x| x == b"\n\n") {
+            out.write_all(&data[end + 1..])?;
+        }
+
+        Ok(parsed.object)
+    }
+
+    fn tree(&mut self, out: &mut impl Write, name: &str, data: &[u8]) -> std::io::Result<()> {
+        let Palette { commit, reset, .. } = self.palette;
+
+        if self.shown_one {
+            writeln!(out)?;
+        }
+        self.shown_one = true;
+
+        writeln!(out, "{commit}tree {name}{reset}\n")?;
+        for item in tree::parse(&mut &data[..])? {
+            let slash = if item.is_tree() { "/" } else { "" };
+            writeln!(out, "{}{slash}", item.name)?;
+        }
+        Ok(())
+    }
+
+    /// Shows the objects named, the objects tags point at following them.
+    fn objects(
+        &mut self,
+        out: &mut impl Write,
+        mut queue: VecDeque<(String, String)>,
+    ) -> std::io::Result<()> {
+        while let Some((name, hash)) = queue.pop_front() {
+            let (kind, data) = objects::read_raw(&hash)?;
+            match kind {
+                ObjType::Blob => out.write_all(&data)?,
+                ObjType::Tree => self.tree(out, &name, &data)?,
+                ObjType::Commit => {
+                    let commit = objects::commit::parse(&mut &data[..])?;
+                    self.commit(out, &hash, &commit)?;
+                }
+                ObjType::Tag => {
+                    let target = self.tag(out, &data)?;
+                    queue.push_front((name, target));
+                }
+            }
+        }
+        out.flush()
+    }
+}
+
+/// Changes of a commit from its first parent, in `paths` if any are given.
+pub fn changes(commit: &Commit, diff: &DiffArgs, paths: &[String]) -> std::io::Result<Vec<Change>> {
+    let parent = match commit.parents.first() {
+        Some(parent) => Some(revision::peel(parent, Some(ObjType::Tree))?),
+        None => None,
+    };
+    let tree_options = diff::tree::Options {
+        recursive: true,
+        show_trees: false,
+        paths: paths.to_vec(),
+    };
+
+    let changes = diff::tree::diff(parent.as_deref(), Some(&commit.tree), &tree_options)?;
+    super::diff::find_renames(changes, diff, true, || match &parent {
+        Some(parent) => tree::flatten_matching(parent, paths),
+        None => Ok(Default::default()),
+    })
+}
+
+/// Writes the changes of a commit after its log message, as `show` and
+/// `log -p` do: merges get no combined diff, which is empty for merges
+/// without conflicts, but their stats against the first parent.
+pub fn write_changes(
+    out: &mut impl Write,
+    format: &Format,
+    commit: &Commit,
+    changes: &[Change],
+    diff: &DiffArgs,
+    display: &display::Options,
+) -> std::io::Result<()> {
+    let merge = commit.parents.len() > 1;
+    if changes.is_empty() && !merge {
+        return Ok(());
+    }
+
+    let empty = matches!(format, Format::Custom { template, .. } if template.is_empty());
+    // git separates combined diffs from the message even for --oneline
+    if (*format != Format::Oneline || merge) && !empty {
+        writeln!(out)?;
+    }
+    match merge {
+        true => super::diff::write_stat(out, changes, diff),
+        false => super::diff::write(out, changes, diff, display, true),
+    }
+}
+
+pub fn invoke(revisions: Vec<String>, mut options: Options) {
+    options.paths = pathspecs(&options.paths);
+    let format = match (&options.format, options.oneline) {
+        (Some(format), _) => format.parse().unwrap_or_else(|e| fatal(e)),
+        (None, true) => Format::Oneline,
+        (None, false) => Format::Medium,
+    };
+    let date = match &options.date {
+        Some(date) => date.parse().unwrap_or_else(|e| fatal(e)),
+        None => DateFormat::Default,
+    };
+    let decorations = match format.decorates() {
+        true => pretty::decorations().unwrap_or_else(|e| fatal(e)),
+        false => Default::default(),
+    };
+
+    let revisions = match revisions.is_empty() {
+        true => vec!["HEAD".to_string()],
+        false => revisions,
+    };
+    let queue = revisions
+        .into_iter()
+        .map(|name| match revision::resolve(&name) {
+            Ok(hash) => (name, hash),
+            Err(_) if name == "HEAD" => {
+                fatal("your current branch does not have any commits yet")
+            }
+            Err(e) if name.contains(':') => fatal(e),
+            Err(_) => fatal(format!(
+                "ambiguous argument '{name}': unknown revision or path not in the working tree.\n{}",
+                super::diff::SEPARATE_PATHS
+            )),
+        })
+        .collect();
+
+    let display = options
+        .diff
+        .display_options(std::io::stdout().is_terminal());
+    let mut show = Show {
+        options: &options,
+        format,
+        pretty: pretty::Options {
+            date,
+            color: display.color,
+            decorations,
+        },
+        palette: Palette::new(display.color),
+        display,
+        shown_one: false,
+    };
+
+    let mut out = BufWriter::new(pager::start(options.pager));
+    if let Err(e) = show.objects(&mut out, queue) {
+        if e.kind() != std::io::ErrorKind::BrokenPipe {
+            fatal(e);
+        }
+    }
+}
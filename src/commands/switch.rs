@@ -0,0 +1,133 @@
+use crate::git::{
+    checkout,
+    objects::Object,
+    packfile::ObjType,
+    refs::{self, Head},
+    revision,
+};
+
+use super::{error, fatal};
+
+#[derive(Debug)]
+pub struct Options {
+    pub create: Option<String>,
+    pub force_create: Option<String>,
+    pub detach: bool,
+    pub force: bool,
+}
+
+pub fn invoke(target: Option<String>, options: Options) {
+    let force = options.force;
+
+    if let Some(name) = options.create {
+        create_branch(&name, target.as_deref(), false, force);
+    } else if let Some(name) = options.force_create {
+        create_branch(&name, target.as_deref(), true, force);
+    } else if options.detach {
+        detach(target.as_deref().unwrap_or("HEAD"), force);
+    } else {
+        let Some(target) = target else {
+            fatal("missing branch or commit argument");
+        };
+
+        if !refs::exists(&format!("refs/heads/{target}")) {
+            match revision::resolve(&target) {
+                Ok(_) => fatal(format!(
+                    "a branch is expected, got '{target}'\nhint: use --detach to switch to a commit"
+                )),
+                Err(_) => fatal(format!("invalid reference: {target}")),
+            }
+        }
+
+        switch_branch(&target, force);
+    }
+}
+
+/// Commit a revision points at, exiting when it does not name one.
+fn resolve_commit(rev: &str) -> String {
+    revision::resolve(rev)
+        .and_then(|x| revision::peel(&x, Some(ObjType::Commit)))
+        .unwrap_or_else(|e| fatal(e))
+}
+
+/// Updates index and worktree from the current HEAD to `commit`.
+fn move_to(commit: &str, force: bool) {
+    let from = match refs::head_commit() {
+        Ok(Some(head)) => {
+            Some(revision::peel(&head, Some(ObjType::Tree)).unwrap_or_else(|e| fatal(e)))
+        }
+        Ok(None) => None,
+        Err(e) => fatal(e),
+    };
+    let to = revision::peel(commit, Some(ObjType::Tree)).unwrap_or_else(|e| fatal(e));
+
+    if let Err(e) = checkout::checkout(from.as_deref(), &to, force) {
+        error(e);
+    }
+}
+
+fn summary(commit: &str) -> String {
+    let subject = match Object::read_from_hash(commit) {
+        Ok(Object::Commit(commit)) => commit
+            .message
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        _ => String::new(),
+    };
+
+    format!("{} {subject}", &commit[..7])
+}
+
+pub fn switch_branch(name: &str, force: bool) {
+    let branch = format!("refs/heads/{name}");
+
+    if refs::read_head().ok() == Some(Head::Branch(branch.clone())) {
+        eprintln!("Already on '{name}'");
+        return;
+    }
+
+    move_to(&resolve_commit(&branch), force);
+
+    refs::set_head(&branch).unwrap_or_else(|e| fatal(e));
+    eprintln!("Switched to branch '{name}'");
+}
+
+pub fn create_branch(name: &str, start: Option<&str>, reset: bool, force: bool) {
+    if !refs::is_valid_name(name) {
+        fatal(format!("'{name}' is not a valid branch name"));
+    }
+
+    let branch = format!("refs/heads/{name}");
+    let exists = refs::exists(&branch);
+    if exists && !reset {
+        fatal(format!("a branch named '{name}' already exists"));
+    }
+
+    // on an unborn branch there is nothing to check out, only HEAD moves
+    if start.is_none() && matches!(refs::head_commit(), Ok(None)) {
+        refs::set_head(&branch).unwrap_or_else(|e| fatal(e));
+        eprintln!("Switched to a new branch '{name}'");
+        return;
+    }
+
+    let commit = resolve_commit(start.unwrap_or("HEAD"));
+    move_to(&commit, force);
+
+    refs::update(&branch, &commit).unwrap_or_else(|e| fatal(e));
+    refs::set_head(&branch).unwrap_or_else(|e| fatal(e));
+
+    match exists {
+        true => eprintln!("Reset branch '{name}'"),
+        false => eprintln!("Switched to a new branch '{name}'"),
+    }
+}
+
+pub fn detach(rev: &str, force: bool) {
+    let commit = resolve_commit(rev);
+    move_to(&commit, force);
+
+    refs::detach_head(&commit).unwrap_or_else(|e| fatal(e));
+    eprintln!("HEAD is now at {}", summary(&commit));
+}
@@ -0,0 +1,144 @@
+use std::{
+    fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use sha1::{Digest, Sha1};
+
+use crate::git::{
+    index_pack::{self, Indexer, Object},
+    pack_index,
+};
+
+use super::{error, reason};
+
+#[derive(Debug)]
+pub struct Options {
+    pub verbose: bool,
+    pub stat_only: bool,
+}
+
+/// Checks the pack at `pack` against its checksum and the index at
+/// `index`, returning its objects in pack order and where they end.
+pub fn verify(pack: &Path, index: &Path) -> std::io::Result<(Vec<Object>, u64)> {
+    let mut file = fs::File::open(pack)?;
+    let (mut objects, checksum) = index_pack::scan(BufReader::new(&file), std::io::sink())?;
+    let checksum = Indexer::open()?.index(&mut file, &mut objects, checksum)?;
+
+    let found = fs::read(index)?;
+    let (content, trailer) = found.split_at(found.len().saturating_sub(20));
+    if Sha1::digest(content)[..] != *trailer {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("packfile index for {} SHA1 mismatch", pack.display()),
+        ));
+    }
+
+    let mut entries = index_pack::entries(&objects);
+    entries.sort_by_key(|x| x.name);
+    let mut expected = Vec::new();
+    pack_index::write(&mut expected, &entries, &checksum)?;
+    if expected != found {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} does not match {}", index.display(), pack.display()),
+        ));
+    }
+
+    Ok((objects, file.metadata()?.len() - 20))
+}
+
+/// Prints each object with its size, size in the pack and offset, and
+/// for deltas the length of their chain and their base, then how many
+/// objects have chains of each length.
+fn show(objects: &[Object], end: u64, verbose: bool) {
+    let mut depths = vec![None; objects.len()];
+    for position in 0..objects.len() {
+        let mut chain = vec![position];
+        let mut depth = loop {
+            let last = *chain.last().unwrap();
+            match (depths[last], objects[last].base) {
+                (Some(depth), _) => break depth,
+                (None, None) => break 0,
+                (None, Some(base)) => chain.push(base),
+            }
+        };
+        for object in chain.into_iter().rev() {
+            if depths[object].is_none() {
+                depths[object] = Some(depth);
+            }
+            depth = depths[object].unwrap() + 1;
+        }
+    }
+    let depths = depths
+        .into_iter()
+        .map(Option::unwrap)
+        .collect::<Vec<usize>>();
+
+    let mut histogram = vec![0; depths.iter().max().map_or(0, |x| x + 1)];
+    for (position, object) in objects.iter().enumerate() {
+        let depth = depths[position];
+        histogram[depth] += 1;
+        if !verbose {
+            continue;
+        }
+
+        let next = objects.get(position + 1).map_or(end, |x| x.entry.offset);
+        let kind = object.kind.map(|x| x.to_string()).unwrap_or_default();
+        print!(
+            "{} {kind:<6} {} {} {}",
+            hex::encode(object.entry.name),
+            object.size,
+            next - object.entry.offset,
+            object.entry.offset
+        );
+        if let Some(base) = object.base {
+            print!(" {depth} {}", hex::encode(objects[base].entry.name));
+        }
+        println!();
+    }
+
+    let plural = |n: usize| if n == 1 { "object" } else { "objects" };
+    for (depth, &count) in histogram.iter().enumerate() {
+        match (depth, count) {
+            (_, 0) => {}
+            (0, n) => println!("non delta: {n} {}", plural(n)),
+            (depth, n) => println!("chain length = {depth}: {n} {}", plural(n)),
+        }
+    }
+}
+
+pub fn invoke(packs: Vec<PathBuf>, options: Options) {
+    let mut failures = Vec::new();
+
+    for path in packs {
+        let pack = path.with_extension("pack");
+        let index = path.with_extension("idx");
+
+        match verify(&pack, &index) {
+            Ok((objects, end)) => {
+                if options.verbose || options.stat_only {
+                    show(&objects, end, options.verbose && !options.stat_only);
+                }
+                if options.verbose && !options.stat_only {
+                    println!("{}: ok", pack.display());
+                }
+            }
+            Err(e) => {
+                if options.verbose {
+                    println!("{}: bad", pack.display());
+                }
+                failures.push(format!("packfile {}: {}", pack.display(), reason(&e)));
+            }
+        }
+    }
+
+    // every pack is checked before giving up
+    if let Some((last, others)) = failures.split_last() {
+        for failure in others {
+            eprintln!("error: {failure}");
+        }
+        error(last);
+    }
+}
@@ -1,54 +1,26 @@
-use core::panic;
-use std::io::prelude::*;
 use std::{fs, os::unix::fs::PermissionsExt, path::PathBuf};
 
-use flate2::{write::ZlibEncoder, Compression};
-use sha1::{Digest, Sha1};
+use crate::git::{objects::Writer, packfile::ObjType, repository};
 
-use crate::objects::Kind;
+use super::fatal;
 
 pub fn invoke() {
-    let content = create_tree(".".into());
+    let mut writer = Writer::open().unwrap_or_else(|e| fatal(e));
+    let hash = write_tree(&mut writer, repository::work_tree()).unwrap_or_else(|e| fatal(e));
 
-    let mut hasher = Sha1::new();
-    hasher.update(content.clone());
-    let hash = hasher.finalize();
-    let hash = hex::encode(hash);
-
-    let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
-    e.write_all(&content).unwrap();
-    let compressed = e.finish().unwrap();
-
-    let _ = fs::create_dir(format!(".git/objects/{}", &hash[..2]));
-    fs::write(
-        format!(".git/objects/{}/{}", &hash[..2], &hash[2..]),
-        compressed,
-    )
-    .unwrap();
-
-    println!("{}", hash);
-}
-
-#[allow(dead_code)]
-struct Tree {
-    mode: String,
-    kind: Kind,
-    name: String,
-    content: Vec<u8>,
+    println!("{}", hex::encode(hash));
+    super::gc::auto();
 }
 
-fn create_tree(path: PathBuf) -> Vec<u8> {
+/// Stores the files under `path` and the trees holding them, returning
+/// the name of the tree for `path`.
+fn write_tree(writer: &mut Writer, path: PathBuf) -> std::io::Result<[u8; 20]> {
     let ignore = vec![".git"];
 
-    let entries = match fs::read_dir(path) {
-        Ok(dir) => dir,
-        Err(err) => panic!("{}", err),
-    };
-
-    let mut data: Vec<Tree> = Vec::new();
-    let mut entries: Vec<_> = entries.map(|x| x.unwrap()).collect();
+    let mut entries = fs::read_dir(path)?.collect::<std::io::Result<Vec<_>>>()?;
     entries.sort_by_key(|x| x.path());
 
+    let mut content: Vec<u8> = Vec::new();
     for entry in entries {
         let path = entry.path();
         let name = entry.file_name().into_string().unwrap();
@@ -57,56 +29,23 @@ fn create_tree(path: PathBuf) -> Vec<u8> {
             continue;
         }
 
-        let metadata = fs::metadata(&path).unwrap();
+        let metadata = fs::metadata(&path)?;
         let mut mode = metadata.permissions().mode();
         if path.is_dir() {
             mode = mode & !0o777;
         }
 
-        let mode = format!("{:o}", mode);
-        let (content, kind) = match &path.is_dir() {
-            false => (blob(path), Kind::Blob),
-            true => (create_tree(path), Kind::Tree),
+        let hash = if path.is_dir() {
+            write_tree(writer, path)?
+        } else if metadata.len() > writer.big_file_threshold() {
+            writer.write_stream(ObjType::Blob, fs::File::open(&path)?, metadata.len())?
+        } else {
+            writer.write(ObjType::Blob, &fs::read(&path)?)?
         };
 
-        data.push(Tree {
-            mode,
-            kind,
-            name,
-            content,
-        });
-    }
-
-    let mut content: Vec<u8> = Vec::new();
-    for d in data.iter() {
-        let mut hasher = Sha1::new();
-        hasher.update(d.content.clone());
-        let hash = hasher.finalize();
-        let mut hash = Vec::from(hash.as_slice());
-
-        let formated = format!("{} {}\0", d.mode, d.name);
-        let mut bytes = Vec::from(formated);
-        content.append(&mut bytes);
-        content.append(&mut hash);
+        content.extend(format!("{mode:o} {name}\0").as_bytes());
+        content.extend(hash);
     }
 
-    let header = format!("{} {}\0", Kind::Tree, content.len());
-    let mut data = Vec::from(header);
-
-    data.append(&mut content);
-
-    data
-}
-
-fn blob(path: PathBuf) -> Vec<u8> {
-    let mut content = fs::File::open(path).unwrap();
-    let mut buf = Vec::new();
-    content.read_to_end(&mut buf).unwrap();
-
-    let header = format!("{} {}\0", Kind::Blob, buf.len());
-    let mut data = Vec::from(header);
-
-    data.append(&mut buf);
-
-    data
+    writer.write(ObjType::Tree, &content)
 }
@@ -1,3 +1,25 @@
+pub mod checkout;
+pub mod config;
+pub mod delta;
+pub mod diff;
+pub mod fetch_pack;
+pub mod fsck;
 pub mod helpers;
-pub mod packfile;
+pub mod index;
+pub mod index_pack;
+pub mod list_objects;
 pub mod objects;
+pub mod pack_index;
+pub mod pack_objects;
+pub mod packfile;
+pub mod pathspec;
+pub mod pktline;
+pub mod pretty;
+pub mod reachable;
+pub mod reflog;
+pub mod refs;
+pub mod remote;
+pub mod repository;
+pub mod revision;
+pub mod revwalk;
+pub mod send_pack;
@@ -0,0 +1,530 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::Read,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    index::{self, Entry, Index},
+    objects::{
+        self, blob,
+        tree::{self, Files, Item},
+    },
+    packfile::ObjType,
+    pathspec, repository,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+enum Update {
+    Write(String, Item),
+    Remove(String),
+    /// Worktree already holds the target content, only the index changes.
+    Stage(String, Item),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Conflict {
+    LocalChanges(String),
+    Untracked(String),
+}
+
+/// Stage 0 entries of the index in the same shape as a flattened tree.
+pub fn index_files(index: &Index) -> Files {
+    index
+        .entries
+        .iter()
+        .filter(|x| x.stage() == 0)
+        .map(|x| (x.path.clone(), (x.mode, x.hash)))
+        .collect()
+}
+
+/// Whether `e` says nothing is at a path: it is missing, or something on
+/// the way to it is not a directory.
+fn is_absent(e: &std::io::Error) -> bool {
+    e.kind() == std::io::ErrorKind::NotFound || e.raw_os_error() == Some(libc::ENOTDIR)
+}
+
+/// The first of the directories leading to `path` that is something else
+/// in the worktree under `root`.
+fn leading_file(root: &Path, path: &str) -> Option<String> {
+    path.match_indices('/')
+        .map(|(i, _)| &path[..i])
+        .map_while(|x| Some((x, fs::symlink_metadata(root.join(x)).ok()?)))
+        .find(|(_, metadata)| !metadata.is_dir())
+        .map(|(x, _)| x.to_string())
+}
+
+/// Mode and blob name of what is currently at `path` in the worktree.
+pub fn worktree_item(path: &Path) -> std::io::Result<Option<Item>> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if is_absent(&e) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let mode = index::mode_of(&metadata);
+    let hash = match mode {
+        index::MODE_SYMLINK => {
+            let target = fs::read_link(path)?;
+            blob::hash(target.as_os_str().as_encoded_bytes())
+        }
+        index::MODE_GITLINK => [0; 20],
+        _ => blob::hash(&fs::read(path)?),
+    };
+
+    Ok(Some((mode, hash)))
+}
+
+/// Checks the worktree copy of a tracked path against its index entry,
+/// using the recorded stat data to avoid hashing untouched files.
+fn is_modified(root: &Path, entry: &Entry) -> std::io::Result<bool> {
+    let path = root.join(&entry.path);
+    let metadata = match fs::symlink_metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(e) if is_absent(&e) => return Ok(true),
+        Err(e) => return Err(e),
+    };
+
+    if entry.mode == index::MODE_GITLINK {
+        return Ok(!metadata.is_dir());
+    }
+
+    if entry.is_fresh(&metadata) {
+        return Ok(false);
+    }
+
+    Ok(worktree_item(&path)? != Some((entry.mode, entry.hash)))
+}
+
+/// Whether the worktree at `path` differs from `expected` (absent when `None`).
+fn differs(
+    root: &Path,
+    path: &str,
+    expected: Option<&Item>,
+    index: &Index,
+) -> std::io::Result<bool> {
+    let full = root.join(path);
+
+    if let Some(entry) = index.find(path) {
+        if expected == Some(&(entry.mode, entry.hash)) {
+            return is_modified(root, entry);
+        }
+    }
+
+    let Some(current) = worktree_item(&full)? else {
+        // a file where a directory has to go is in the way unless tracked
+        if leading_file(root, path).is_some_and(|x| index.find(&x).is_none()) {
+            return Ok(true);
+        }
+        return Ok(expected.is_some());
+    };
+
+    // a directory only stands in the way if it holds files git does not track
+    if current.0 == index::MODE_GITLINK && expected.map(|x| x.0) != Some(index::MODE_GITLINK) {
+        return has_untracked(root, &full, index);
+    }
+
+    Ok(Some(&current) != expected)
+}
+
+fn has_untracked(root: &Path, dir: &Path, index: &Index) -> std::io::Result<bool> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_string_lossy();
+        if relative == ".git" {
+            return Ok(true);
+        }
+
+        if path.symlink_metadata()?.is_dir() {
+            if has_untracked(root, &path, index)? {
+                return Ok(true);
+            }
+        } else if index.find(&relative).is_none() {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Two-way merge of the index and worktree from `head` to `target`, the same
+/// rules `git read-tree -m -u` applies: paths that do not change between the
+/// trees keep their local modifications, paths that change must be clean.
+fn plan(
+    head: &Files,
+    index: &Files,
+    target: &Files,
+    force: bool,
+    mut differs: impl FnMut(&str, Option<&Item>) -> std::io::Result<bool>,
+) -> std::io::Result<Result<Vec<Update>, Vec<Conflict>>> {
+    let paths: BTreeSet<&String> = head
+        .keys()
+        .chain(index.keys())
+        .chain(target.keys())
+        .collect();
+
+    let mut updates = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for path in paths {
+        let h = head.get(path);
+        let i = index.get(path);
+        let m = target.get(path);
+
+        let update = match m {
+            Some(item) => Update::Write(path.clone(), *item),
+            None => Update::Remove(path.clone()),
+        };
+
+        if force {
+            if i != m || differs(path, m)? {
+                updates.push(update);
+            }
+            continue;
+        }
+
+        if h == m || i == m {
+            continue;
+        }
+
+        if i != h {
+            conflicts.push(Conflict::LocalChanges(path.clone()));
+            continue;
+        }
+
+        if !differs(path, i)? {
+            updates.push(update);
+        } else if !differs(path, m)? {
+            match m {
+                Some(item) => updates.push(Update::Stage(path.clone(), *item)),
+                None => updates.push(update),
+            }
+        } else if i.is_none() {
+            conflicts.push(Conflict::Untracked(path.clone()));
+        } else {
+            conflicts.push(Conflict::LocalChanges(path.clone()));
+        }
+    }
+
+    if conflicts.is_empty() {
+        Ok(Ok(updates))
+    } else {
+        Ok(Err(conflicts))
+    }
+}
+
+fn report(conflicts: Vec<Conflict>) -> std::io::Error {
+    let mut local = Vec::new();
+    let mut untracked = Vec::new();
+    for conflict in conflicts {
+        match conflict {
+            Conflict::LocalChanges(x) => local.push(x),
+            Conflict::Untracked(x) => untracked.push(x),
+        }
+    }
+
+    let mut message = String::new();
+    if !local.is_empty() {
+        message.push_str(
+            "Your local changes to the following files would be overwritten by checkout:\n",
+        );
+        for path in local {
+            message.push_str(&format!("\t{path}\n"));
+        }
+        message.push_str("Please commit your changes or stash them before you switch branches.\n");
+    }
+    if !untracked.is_empty() {
+        message.push_str(
+            "The following untracked working tree files would be overwritten by checkout:\n",
+        );
+        for path in untracked {
+            message.push_str(&format!("\t{path}\n"));
+        }
+        message.push_str("Please move or remove them before you switch branches.\n");
+    }
+    message.push_str("Aborting");
+
+    std::io::Error::new(std::io::ErrorKind::AlreadyExists, message)
+}
+
+/// Writes a blob to the worktree with the file type and permissions of `mode`.
+pub fn write_file(path: &Path, (mode, hash): &Item) -> std::io::Result<fs::Metadata> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if *mode == index::MODE_GITLINK {
+        fs::create_dir_all(path)?;
+        return fs::symlink_metadata(path);
+    }
+
+    let mut blob = objects::stream(&hex::encode(hash))?;
+    if blob.kind != ObjType::Blob {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} is a {}, expected a blob", hex::encode(hash), blob.kind),
+        ));
+    }
+
+    if *mode == index::MODE_SYMLINK {
+        let mut target = Vec::new();
+        blob.read_to_end(&mut target)?;
+        std::os::unix::fs::symlink(String::from_utf8_lossy(&target).as_ref(), path)?;
+        return fs::symlink_metadata(path);
+    }
+
+    std::io::copy(&mut blob, &mut fs::File::create(path)?)?;
+    let mut permissions = fs::metadata(path)?.permissions();
+    if *mode == index::MODE_EXECUTABLE {
+        // grant execute wherever read is granted, like git does under the umask
+        permissions.set_mode(permissions.mode() | ((permissions.mode() & 0o444) >> 2));
+    } else {
+        permissions.set_mode(permissions.mode() & !0o111);
+    }
+    fs::set_permissions(path, permissions)?;
+
+    fs::symlink_metadata(path)
+}
+
+/// Removes a file and any directories left empty above it.
+fn remove_file(root: &Path, path: &str) -> std::io::Result<()> {
+    let full = root.join(path);
+    match fs::symlink_metadata(&full) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&full)?,
+        Ok(_) => fs::remove_file(&full)?,
+        Err(e) if is_absent(&e) => {}
+        Err(e) => return Err(e),
+    }
+
+    let mut parent = full.parent();
+    while let Some(dir) = parent {
+        if dir == root || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        parent = dir.parent();
+    }
+
+    Ok(())
+}
+
+/// Updates the index and worktree from the tree `from` to the tree `to`.
+///
+/// Only files that differ between the two trees are touched. Nothing is
+/// written when a change would overwrite local modifications or untracked
+/// files, unless `force` is set. `from` is `None` for an empty worktree.
+pub fn checkout(from: Option<&str>, to: &str, force: bool) -> std::io::Result<()> {
+    let root = repository::work_tree();
+    let index_path = repository::index_file();
+    let mut index = Index::open(&index_path)?;
+
+    if !force && index.entries.iter().any(|x| x.stage() != 0) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "you need to resolve your current index first",
+        ));
+    }
+
+    let head = match from {
+        Some(from) => tree::flatten(from)?,
+        None => Files::new(),
+    };
+    let target = tree::flatten(to)?;
+    let current = index_files(&index);
+
+    let updates = plan(&head, &current, &target, force, |path, expected| {
+        differs(&root, path, expected, &index)
+    })?
+    .map_err(|conflicts| {
+        // what is in the way of a new file may be a file above it
+        let mut conflicts = conflicts
+            .into_iter()
+            .map(|x| match x {
+                Conflict::Untracked(path) => {
+                    Conflict::Untracked(leading_file(&root, &path).unwrap_or(path))
+                }
+                x => x,
+            })
+            .collect::<Vec<_>>();
+        conflicts.dedup();
+        report(conflicts)
+    })?;
+
+    // removals go first so files can replace directories and the reverse
+    for update in updates.iter() {
+        if let Update::Remove(path) = update {
+            remove_file(&root, path)?;
+            index.remove(path);
+        }
+    }
+
+    for update in updates {
+        match update {
+            Update::Write(path, item) => {
+                let metadata = write_file(&root.join(&path), &item)?;
+                index.add(Entry::new(&path, item.0, item.1, &metadata));
+            }
+            Update::Stage(path, item) => {
+                let metadata = fs::symlink_metadata(root.join(&path))?;
+                index.add(Entry::new(&path, item.0, item.1, &metadata));
+            }
+            Update::Remove(_) => {}
+        }
+    }
+
+    if force {
+        index.entries.retain(|x| x.stage() == 0);
+    }
+
+    index.write(&index_path)
+}
+
+/// Overwrites the paths matching `specs` in the worktree with their content
+/// in `source`, staging them as well when `source` is a tree.
+pub fn checkout_paths(source: &Files, specs: &[String], stage: bool) -> std::io::Result<()> {
+    let root = repository::work_tree();
+    let index_path = repository::index_file();
+    let mut index = Index::open(&index_path)?;
+
+    for spec in specs {
+        if !source
+            .keys()
+            .any(|x| pathspec::matches(std::slice::from_ref(spec), x))
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("pathspec '{spec}' did not match any file(s) known to git"),
+            ));
+        }
+    }
+
+    for (path, item) in source.iter().filter(|(x, _)| pathspec::matches(specs, x)) {
+        let full: PathBuf = root.join(path);
+        let metadata = write_file(&full, item)?;
+        if stage || index.find(path).is_some() {
+            index.add(Entry::new(path, item.0, item.1, &metadata));
+        }
+    }
+
+    index.write(&index_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{differs, leading_file, plan, remove_file, worktree_item};
+    use super::{Conflict, Files, Item, Update};
+    use crate::git::index::{Entry, Index};
+    use std::fs;
+
+    fn files(items: &[(&str, u8)]) -> Files {
+        items
+            .iter()
+            .map(|(path, id)| (path.to_string(), (0o100644, [*id; 20])))
+            .collect()
+    }
+
+    fn clean(_: &str, _: Option<&Item>) -> std::io::Result<bool> {
+        Ok(false)
+    }
+
+    #[test]
+    fn only_changed_paths_are_updated() {
+        let head = files(&[("same", 1), ("changed", 2), ("removed", 3)]);
+        let target = files(&[("same", 1), ("changed", 4), ("added", 5)]);
+
+        let updates = plan(&head, &head, &target, false, clean).unwrap().unwrap();
+
+        assert_eq!(
+            updates,
+            vec![
+                Update::Write("added".into(), (0o100644, [5; 20])),
+                Update::Write("changed".into(), (0o100644, [4; 20])),
+                Update::Remove("removed".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn local_changes_block_checkout() {
+        let head = files(&[("file", 1)]);
+        let target = files(&[("file", 2)]);
+
+        let dirty = |path: &str, expected: Option<&Item>| Ok(path == "file" && expected.is_some());
+        let result = plan(&head, &head, &target, false, dirty).unwrap();
+
+        assert_eq!(result, Err(vec![Conflict::LocalChanges("file".into())]));
+    }
+
+    #[test]
+    fn local_changes_carry_over_unchanged_paths() {
+        let head = files(&[("file", 1), ("other", 2)]);
+        let index = files(&[("file", 9), ("other", 2)]);
+        let target = files(&[("file", 1), ("other", 3)]);
+
+        let updates = plan(&head, &index, &target, false, clean).unwrap().unwrap();
+
+        assert_eq!(
+            updates,
+            vec![Update::Write("other".into(), (0o100644, [3; 20]))]
+        );
+    }
+
+    #[test]
+    fn untracked_files_are_not_clobbered() {
+        let head = files(&[]);
+        let target = files(&[("new", 1)]);
+
+        let untracked = |_: &str, _: Option<&Item>| Ok(true);
+        let result = plan(&head, &head, &target, false, untracked).unwrap();
+
+        assert_eq!(result, Err(vec![Conflict::Untracked("new".into())]));
+    }
+
+    #[test]
+    fn force_discards_local_changes() {
+        let head = files(&[("file", 1)]);
+        let index = files(&[("file", 9)]);
+        let target = files(&[("file", 2)]);
+
+        let updates = plan(&head, &index, &target, true, clean).unwrap().unwrap();
+
+        assert_eq!(
+            updates,
+            vec![Update::Write("file".into(), (0o100644, [2; 20]))]
+        );
+    }
+
+    #[test]
+    fn files_in_the_way_of_directories() {
+        let root = std::env::temp_dir().join(format!("checkout-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("p"), "a\n").unwrap();
+        let item = (0o100644, [1; 20]);
+
+        // nothing is at p/q, not an error reading it
+        assert_eq!(worktree_item(&root.join("p/q")).unwrap(), None);
+        assert_eq!(leading_file(&root, "p/q/r").as_deref(), Some("p"));
+        assert_eq!(leading_file(&root, "x/y"), None);
+
+        // an untracked p is in the way of p/q, a tracked one is replaced
+        let mut index = Index::default();
+        assert!(differs(&root, "p/q", None, &index).unwrap());
+        let metadata = fs::symlink_metadata(root.join("p")).unwrap();
+        index.add(Entry::new("p", 0o100644, [2; 20], &metadata));
+        assert!(!differs(&root, "p/q", None, &index).unwrap());
+        assert!(differs(&root, "p/q", Some(&item), &index).unwrap());
+
+        remove_file(&root, "p/q").unwrap();
+        assert!(root.join("p").is_file());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,592 @@
+//! Reading git configuration files: the system, global and repository
+//! ones, later files overriding earlier ones. Also setting keys in one of
+//! them.
+
+use flate2::Compression;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{pretty, refs, repository};
+
+fn invalid(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Kinds of files `core.fsync` can ask to be synced to disk, as bits of a
+/// set of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fsync {
+    LooseObject = 1,
+    Pack = 1 << 1,
+    PackMetadata = 1 << 2,
+    CommitGraph = 1 << 3,
+    Index = 1 << 4,
+    Reference = 1 << 5,
+}
+
+/// Components synced unless `core.fsync` says otherwise.
+const FSYNC_DEFAULT: u32 =
+    Fsync::Pack as u32 | Fsync::PackMetadata as u32 | Fsync::CommitGraph as u32;
+
+/// The components a `core.fsync` name stands for.
+fn fsync_components(name: &str) -> u32 {
+    let objects = Fsync::LooseObject as u32 | Fsync::Pack as u32;
+    let metadata = Fsync::PackMetadata as u32 | Fsync::CommitGraph as u32;
+    let committed = objects | Fsync::Reference as u32;
+    let added = committed | Fsync::Index as u32;
+
+    match name {
+        "loose-object" => Fsync::LooseObject as u32,
+        "pack" => Fsync::Pack as u32,
+        "pack-metadata" => Fsync::PackMetadata as u32,
+        "commit-graph" => Fsync::CommitGraph as u32,
+        "index" => Fsync::Index as u32,
+        "reference" => Fsync::Reference as u32,
+        "objects" => objects,
+        "derived-metadata" => metadata,
+        "committed" => committed,
+        "added" => added,
+        "all" => added | metadata,
+        // unknown components are ignored, as git does after warning
+        _ => 0,
+    }
+}
+
+/// Parses an expiry date such as `2.weeks.ago`: things at or before the
+/// time returned expire, none for `never` (0) and all for `now` or `all`.
+pub fn parse_expiry(value: &str, now: u64) -> Option<u64> {
+    match value {
+        "never" | "false" => Some(0),
+        "now" | "all" => Some(u64::MAX),
+        _ => pretty::parse_date(value, now),
+    }
+}
+
+/// Settings of all configuration files, in the order they were read.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Canonical key (`section.subsection.name`, section and name in lower
+    /// case) and value, `None` for a key without `=`.
+    entries: Vec<(String, Option<String>)>,
+}
+
+/// Files read, from the least to the most specific.
+fn files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if std::env::var_os("GIT_CONFIG_NOSYSTEM").is_none() {
+        files.push(PathBuf::from("/etc/gitconfig"));
+    }
+
+    let xdg = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|x| PathBuf::from(x).join(".config")));
+    if let Some(xdg) = xdg {
+        files.push(xdg.join("git/config"));
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        files.push(PathBuf::from(home).join(".gitconfig"));
+    }
+
+    files.push(repository::git_dir().join("config"));
+    files
+}
+
+/// Canonical form of `key`: section and name are case insensitive, the
+/// subsection between them is not.
+fn canonical(key: &str) -> String {
+    let (section, rest) = key.split_once('.').unwrap_or((key, ""));
+    match rest.rsplit_once('.') {
+        Some((subsection, name)) => format!(
+            "{}.{subsection}.{}",
+            section.to_ascii_lowercase(),
+            name.to_ascii_lowercase()
+        ),
+        None => format!(
+            "{}.{}",
+            section.to_ascii_lowercase(),
+            rest.to_ascii_lowercase()
+        ),
+    }
+}
+
+/// Parses a section header from just after its `[`, returning the
+/// section prefix of its keys and the rest of the line.
+fn header(line: &str) -> Option<(String, &str)> {
+    let end = line.find([']', ' ', '"'])?;
+    let section = line[..end].to_ascii_lowercase();
+    let rest = line[end..].trim_start();
+
+    if let Some(rest) = rest.strip_prefix(']') {
+        // the deprecated `[section.subsection]` has the whole name in lower case
+        return Some((section, rest));
+    }
+
+    let mut subsection = String::new();
+    let mut chars = rest.strip_prefix('"')?.chars();
+    loop {
+        match chars.next()? {
+            '"' => break,
+            '\\' => subsection.push(chars.next()?),
+            x => subsection.push(x),
+        }
+    }
+
+    let rest = chars.as_str().strip_prefix(']')?;
+    Some((format!("{section}.{subsection}"), rest))
+}
+
+/// Parses a value from just after its `=`, going on over the following
+/// lines while they end in a backslash.
+fn value<'a>(first: &'a str, lines: &mut impl Iterator<Item = &'a str>) -> Option<String> {
+    let mut value = String::new();
+    let mut quoted = false;
+    // length of the value without the trailing whitespace outside quotes
+    let mut kept = 0;
+    let mut chars = first.trim_start().chars();
+
+    loop {
+        let Some(c) = chars.next() else {
+            return (!quoted).then(|| value[..kept].to_string());
+        };
+
+        match c {
+            '"' => quoted = !quoted,
+            '#' | ';' if !quoted => return Some(value[..kept].to_string()),
+            '\\' => {
+                match chars.next() {
+                    None => {
+                        chars = lines.next()?.chars();
+                        continue;
+                    }
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('b') => {
+                        value.pop();
+                    }
+                    Some(x @ ('\\' | '"')) => value.push(x),
+                    Some(_) => return None,
+                }
+                kept = value.len();
+                continue;
+            }
+            x => value.push(x),
+        }
+
+        if quoted || !c.is_whitespace() {
+            kept = value.len();
+        }
+    }
+}
+
+/// Entries of a configuration file, in order.
+pub fn parse(text: &str, path: &str) -> std::io::Result<Vec<(String, Option<String>)>> {
+    let mut entries = Vec::new();
+    let mut section: Option<String> = None;
+    let mut lines = text.lines().enumerate();
+    let bad = |n: usize| invalid(format!("bad config line {} in file {path}", n + 1));
+
+    while let Some((n, line)) = lines.next() {
+        let mut line = line.trim_start();
+
+        while let Some(rest) = line.strip_prefix('[') {
+            let (name, rest) = header(rest).ok_or_else(|| bad(n))?;
+            section = Some(name);
+            line = rest.trim_start();
+        }
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+
+        let end = line
+            .find(|x: char| !x.is_ascii_alphanumeric() && x != '-')
+            .unwrap_or(line.len());
+        let (name, rest) = line.split_at(end);
+        let (Some(section), true) = (
+            &section,
+            name.starts_with(|x: char| x.is_ascii_alphabetic()),
+        ) else {
+            return Err(bad(n));
+        };
+
+        let key = format!("{section}.{}", name.to_ascii_lowercase());
+        let rest = rest.trim_start();
+        let value = match rest.strip_prefix('=') {
+            Some(rest) => {
+                let mut continued = lines.by_ref().map(|(_, x)| x);
+                Some(value(rest, &mut continued).ok_or_else(|| bad(n))?)
+            }
+            None if rest.is_empty() || rest.starts_with(['#', ';']) => None,
+            None => return Err(bad(n)),
+        };
+        entries.push((key, value));
+    }
+
+    Ok(entries)
+}
+
+/// `value` as it is written to a file: quoted if it has leading or
+/// trailing whitespace or comment characters, with special characters
+/// escaped.
+fn quote(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    let quoted = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', ';']);
+    match quoted {
+        true => format!("\"{escaped}\""),
+        false => escaped,
+    }
+}
+
+/// The text of a configuration file with `key` set to `value`: the last
+/// line setting it replaced, or a line added at the end of its section,
+/// or a new section at the end of the file.
+fn set_in(text: &str, key: &str, value: &str) -> String {
+    let canonical = canonical(key);
+    let (section, name) = canonical.rsplit_once('.').unwrap_or((&canonical, ""));
+    let written = key.rsplit_once('.').map_or(key, |(_, x)| x);
+    let entry = format!("\t{written} = {}", quote(value));
+
+    let mut lines = text.lines().map(str::to_string).collect::<Vec<_>>();
+    let mut current = None;
+    let (mut end, mut found) = (None, None);
+    for (i, line) in lines.iter().enumerate() {
+        let mut line = line.trim_start();
+        while let Some((header, rest)) = line.strip_prefix('[').and_then(header) {
+            current = Some(header);
+            line = rest.trim_start();
+        }
+        if current.as_deref() != Some(section) {
+            continue;
+        }
+        end = Some(i);
+        let length = line
+            .find(|x: char| !x.is_ascii_alphanumeric() && x != '-')
+            .unwrap_or(line.len());
+        if length > 0 && line[..length].eq_ignore_ascii_case(name) {
+            found = Some(i);
+        }
+    }
+
+    match (found, end) {
+        (Some(i), _) => lines[i] = entry,
+        (None, Some(i)) => lines.insert(i + 1, entry),
+        (None, None) => {
+            let header = match section.split_once('.') {
+                Some((section, subsection)) => {
+                    let subsection = subsection.replace('\\', "\\\\").replace('"', "\\\"");
+                    format!("[{section} \"{subsection}\"]")
+                }
+                None => format!("[{section}]"),
+            };
+            lines.push(header);
+            lines.push(entry);
+        }
+    }
+    lines.iter().map(|x| format!("{x}\n")).collect()
+}
+
+/// Sets `key` to `value` in the configuration file at `path`, creating
+/// it if needed, as `git config --file <path> <key> <value>` does.
+pub fn set(path: &Path, key: &str, value: &str) -> std::io::Result<()> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    refs::write_locked(path, &set_in(&text, key, value))
+}
+
+impl Config {
+    /// Reads every configuration file there is.
+    pub fn load() -> std::io::Result<Config> {
+        let mut entries = Vec::new();
+        for path in files() {
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            entries.extend(parse(&text, &path.display().to_string())?);
+        }
+
+        Ok(Config { entries })
+    }
+
+    /// The last value of `key`, an empty string for a key without `=`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = canonical(key);
+        self.entries
+            .iter()
+            .rev()
+            .find(|(x, _)| *x == key)
+            .map(|(_, value)| value.as_deref().unwrap_or(""))
+    }
+
+    /// Every value of `key`, in the order they were read.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        let key = canonical(key);
+        self.entries
+            .iter()
+            .filter(|(x, _)| *x == key)
+            .map(|(_, value)| value.as_deref().unwrap_or(""))
+            .collect()
+    }
+
+    /// The value of `key` as a boolean: a key without `=` and `true`,
+    /// `yes`, `on` or a non-zero number mean true.
+    pub fn get_bool(&self, key: &str) -> std::io::Result<Option<bool>> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+
+        let found = self
+            .entries
+            .iter()
+            .rev()
+            .find(|(x, _)| *x == canonical(key));
+        if found.is_some_and(|(_, value)| value.is_none()) {
+            return Ok(Some(true));
+        }
+
+        match value.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" => Ok(Some(true)),
+            "false" | "no" | "off" | "" => Ok(Some(false)),
+            _ => match parse_int(value) {
+                Some(n) => Ok(Some(n != 0)),
+                None => Err(invalid(format!(
+                    "bad boolean config value '{value}' for '{key}'"
+                ))),
+            },
+        }
+    }
+
+    /// The value of `key` as a number, which may end in `k`, `m` or `g`.
+    pub fn get_int(&self, key: &str) -> std::io::Result<Option<i64>> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+
+        match parse_int(value) {
+            Some(n) => Ok(Some(n)),
+            None => Err(invalid(format!(
+                "bad numeric config value '{value}' for '{key}': invalid unit"
+            ))),
+        }
+    }
+
+    /// Size above which files are streamed rather than read whole, and
+    /// stored without deltas: `core.bigFileThreshold`, 512 MiB by default.
+    pub fn big_file_threshold(&self) -> std::io::Result<u64> {
+        let threshold = self.get_int("core.bigFileThreshold")?;
+        Ok(threshold.map_or(512 << 20, |x| x.max(0) as u64))
+    }
+
+    /// Bytes of delta bases to keep in memory while rebuilding objects,
+    /// `core.deltaBaseCacheLimit`.
+    pub fn delta_base_cache_limit(&self) -> std::io::Result<u64> {
+        let limit = self.get_int("core.deltaBaseCacheLimit")?;
+        Ok(limit.map_or(96 << 20, |x| x.max(0) as u64))
+    }
+
+    /// The zlib compression level `key` gives, or `core.compression`
+    /// without it, -1 being zlib's default.
+    pub fn compression(&self, key: &str) -> std::io::Result<Option<Compression>> {
+        let level = match self.get_int(key)? {
+            Some(level) => Some(level),
+            None => self.get_int("core.compression")?,
+        };
+        match level {
+            None => Ok(None),
+            Some(-1) => Ok(Some(Compression::default())),
+            Some(level @ 0..=9) => Ok(Some(Compression::new(level as u32))),
+            Some(level) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("bad zlib compression level {level}"),
+            )),
+        }
+    }
+
+    /// The time at or before which things expire as `key` says, `default`
+    /// without it: see [`parse_expiry`].
+    pub fn expiry(&self, key: &str, default: &str, now: u64) -> std::io::Result<u64> {
+        let value = self.get(key).unwrap_or(default);
+        parse_expiry(value, now).ok_or_else(|| invalid(format!("Invalid {key}: '{value}'")))
+    }
+
+    /// Whether files of `component` are synced to disk before they are
+    /// used, as `core.fsync` says, or `core.fsyncObjectFiles` for loose
+    /// objects.
+    pub fn fsync(&self, component: Fsync) -> std::io::Result<bool> {
+        if component == Fsync::LooseObject && self.get_bool("core.fsyncObjectFiles")? == Some(true)
+        {
+            return Ok(true);
+        }
+
+        let Some(value) = self.get("core.fsync") else {
+            return Ok(FSYNC_DEFAULT & component as u32 != 0);
+        };
+
+        let (mut positive, mut negative) = (0, 0);
+        let mut current = FSYNC_DEFAULT;
+        for name in value.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            match name.strip_prefix('-') {
+                _ if name == "none" => (current, positive, negative) = (0, 0, 0),
+                Some(name) => negative |= fsync_components(name),
+                None => positive |= fsync_components(name),
+            }
+        }
+
+        Ok(((current & !negative) | positive) & component as u32 != 0)
+    }
+}
+
+/// A number with an optional `k`, `m` or `g` unit.
+fn parse_int(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (digits, unit) = match value.char_indices().last()? {
+        (n, x) if x.is_ascii_alphabetic() => (&value[..n], x.to_ascii_lowercase()),
+        _ => (value, ' '),
+    };
+
+    let factor = match unit {
+        ' ' => 1,
+        'k' => 1 << 10,
+        'm' => 1 << 20,
+        'g' => 1 << 30,
+        _ => return None,
+    };
+    digits.parse::<i64>().ok()?.checked_mul(factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, set_in, Config, Fsync};
+
+    fn config(text: &str) -> Config {
+        Config {
+            entries: parse(text, "config").unwrap(),
+        }
+    }
+
+    #[test]
+    fn setting() {
+        let text = set_in("", "remote.origin.url", "https://example.com/x");
+        let text = set_in(&text, "core.bare", "false");
+        let text = set_in(
+            &text,
+            "remote.origin.fetch",
+            "+refs/heads/*:refs/remotes/origin/*",
+        );
+        let text = set_in(&text, "Core.Bare", "true");
+        let text = set_in(&text, "branch.a\"b.merge", " #x");
+        assert_eq!(
+            text,
+            "[remote \"origin\"]\n\
+             \turl = https://example.com/x\n\
+             \tfetch = +refs/heads/*:refs/remotes/origin/*\n\
+             [core]\n\
+             \tBare = true\n\
+             [branch \"a\\\"b\"]\n\
+             \tmerge = \" #x\"\n"
+        );
+
+        let config = config(&text);
+        assert_eq!(config.get("core.bare"), Some("true"));
+        assert_eq!(config.get("branch.a\"b.merge"), Some(" #x"));
+    }
+
+    #[test]
+    fn sections_and_keys() {
+        let config = config(
+            "[core]\n\
+             \tbare = false\n\
+             \tCompression = 3 ; speed\n\
+             [remote \"Origin\"]\n\
+             \turl = https://example.com/repo.git\n\
+             [Branch.Main] remote = origin\n",
+        );
+
+        assert_eq!(config.get("core.bare"), Some("false"));
+        assert_eq!(config.get("CORE.compression"), Some("3"));
+        assert_eq!(
+            config.get("remote.Origin.url"),
+            Some("https://example.com/repo.git")
+        );
+        assert_eq!(config.get("remote.origin.url"), None);
+        assert_eq!(config.get("branch.main.remote"), Some("origin"));
+    }
+
+    #[test]
+    fn values() {
+        let config = config(
+            "[a]\n\
+             quoted = \" two  spaces \" # comment\n\
+             escaped = tab\\there\n\
+             continued = one \\\n two\n\
+             flag\n\
+             size = 512k\n\
+             multi = 1\n\
+             multi = 2\n",
+        );
+
+        assert_eq!(config.get("a.quoted"), Some(" two  spaces "));
+        assert_eq!(config.get("a.escaped"), Some("tab\there"));
+        assert_eq!(config.get("a.continued"), Some("one  two"));
+        assert_eq!(config.get_bool("a.flag").unwrap(), Some(true));
+        assert_eq!(config.get_int("a.size").unwrap(), Some(512 * 1024));
+        assert_eq!(config.get("a.multi"), Some("2"));
+    }
+
+    #[test]
+    fn fsync_components() {
+        let default = config("");
+        assert!(!default.fsync(Fsync::LooseObject).unwrap());
+        assert!(default.fsync(Fsync::Pack).unwrap());
+
+        let objects = config("[core]\nfsync = loose-object,-pack\n");
+        assert!(objects.fsync(Fsync::LooseObject).unwrap());
+        assert!(!objects.fsync(Fsync::Pack).unwrap());
+        assert!(objects.fsync(Fsync::CommitGraph).unwrap());
+
+        let none = config("[core]\nfsync = none,index\n");
+        assert!(!none.fsync(Fsync::Pack).unwrap());
+        assert!(none.fsync(Fsync::Index).unwrap());
+
+        let legacy = config("[core]\nfsyncObjectFiles\n");
+        assert!(legacy.fsync(Fsync::LooseObject).unwrap());
+    }
+
+    #[test]
+    fn expiry_dates() {
+        let now = 1_700_000_000;
+        let config = config("[gc]\npruneExpire = 1.day.ago\nreflogExpire = soon\n");
+        assert_eq!(
+            config.expiry("gc.pruneExpire", "never", now).unwrap(),
+            now - 86400
+        );
+        assert_eq!(config.expiry("gc.other", "never", now).unwrap(), 0);
+        assert_eq!(config.expiry("gc.other", "now", now).unwrap(), u64::MAX);
+        assert!(config.expiry("gc.reflogExpire", "never", now).is_err());
+    }
+
+    #[test]
+    fn bad_lines() {
+        assert!(parse("key = value\n", "config").is_err());
+        assert!(parse("[core\n", "config").is_err());
+        assert!(parse("[core]\nname = \"open\n", "config").is_err());
+    }
+}
@@ -0,0 +1,304 @@
+//! Deltas between objects, in the format [`super::packfile::process_delta`]
+//! applies: the sizes of the base and of the result, then instructions
+//! either copying a range of the base or inserting literal bytes.
+//!
+//! Like git's `diff-delta.c`, the base is indexed by the hash of each of
+//! its blocks, and a rolling hash over the target finds where it may
+//! repeat part of the base, each candidate being checked and extended as
+//! far as the bytes agree.
+
+use std::collections::HashMap;
+
+/// Bytes hashed together, the shortest repeat a copy is made for.
+const BLOCK: usize = 16;
+/// Largest copy one instruction expresses, a size of 0 meaning this.
+const MAX_COPY: usize = 0x10000;
+/// Largest insert, the size being the instruction itself.
+const MAX_INSERT: usize = 0x7f;
+/// Positions kept for blocks of the same hash, so that repetitive bases
+/// don't make every lookup slow.
+const MAX_CANDIDATES: usize = 64;
+/// Multiplier of the rolling hash.
+const PRIME: u32 = 0x0100_0193;
+
+/// Hash of `block`, the one [`Rolling`] keeps over a window of the same
+/// length.
+fn block_hash(block: &[u8]) -> u32 {
+    block.iter().fold(0u32, |hash, &x| {
+        hash.wrapping_mul(PRIME).wrapping_add(x as u32)
+    })
+}
+
+/// Hash of the last [`BLOCK`] bytes, updated a byte at a time.
+struct Rolling {
+    hash: u32,
+    /// `PRIME` to the power of `BLOCK`, to take out the byte leaving.
+    out: u32,
+}
+
+impl Rolling {
+    fn new(block: &[u8]) -> Rolling {
+        Rolling {
+            hash: block_hash(block),
+            out: (0..BLOCK).fold(1u32, |x, _| x.wrapping_mul(PRIME)),
+        }
+    }
+
+    fn roll(&mut self, leaving: u8, entering: u8) {
+        self.hash = self
+            .hash
+            .wrapping_mul(PRIME)
+            .wrapping_add(entering as u32)
+            .wrapping_sub((leaving as u32).wrapping_mul(self.out));
+    }
+}
+
+/// Where each block of a base is, by hash.
+struct Index<'a> {
+    base: &'a [u8],
+    blocks: HashMap<u32, Vec<usize>>,
+}
+
+impl<'a> Index<'a> {
+    fn new(base: &'a [u8]) -> Index<'a> {
+        let mut blocks: HashMap<u32, Vec<usize>> = HashMap::new();
+        // the later blocks are kept, as git does, so copies run to the end
+        let starts = (0..base.len().saturating_sub(BLOCK - 1))
+            .step_by(BLOCK)
+            .rev();
+        for start in starts.filter(|&x| x <= u32::MAX as usize) {
+            let positions = blocks
+                .entry(block_hash(&base[start..start + BLOCK]))
+                .or_default();
+            if positions.len() < MAX_CANDIDATES {
+                positions.push(start);
+            }
+        }
+        Index { base, blocks }
+    }
+
+    /// The longest part of the base equal to `target` from `at`, as its
+    /// offset and length, among the blocks with the hash given.
+    fn longest(&self, hash: u32, target: &[u8], at: usize) -> Option<(usize, usize)> {
+        let mut best: Option<(usize, usize)> = None;
+        for &start in self.blocks.get(&hash)? {
+            let length = self.base[start..]
+                .iter()
+                .zip(&target[at..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length >= BLOCK && best.is_none_or(|(_, x)| length > x) {
+                best = Some((start, length));
+            }
+        }
+        best
+    }
+}
+
+/// Instructions rebuilding `target` from `base`.
+pub fn encode(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    write_size(&mut delta, base.len());
+    write_size(&mut delta, target.len());
+
+    let index = Index::new(base);
+    let mut pending = 0;
+    let mut at = 0;
+    let mut rolling = None;
+
+    while at + BLOCK <= target.len() {
+        let hash = match &mut rolling {
+            Some(rolling) => rolling,
+            None => rolling.insert(Rolling::new(&target[at..at + BLOCK])),
+        }
+        .hash;
+
+        let Some((mut offset, mut length)) = index.longest(hash, target, at) else {
+            if at + BLOCK < target.len() {
+                if let Some(rolling) = &mut rolling {
+                    rolling.roll(target[at], target[at + BLOCK]);
+                }
+            }
+            at += 1;
+            continue;
+        };
+
+        // take back what was about to be inserted if the base has it too
+        while offset > 0 && at > pending && base[offset - 1] == target[at - 1] {
+            offset -= 1;
+            length += 1;
+            at -= 1;
+        }
+
+        insert(&mut delta, &target[pending..at]);
+        copy(&mut delta, offset, length);
+        at += length;
+        pending = at;
+        rolling = None;
+    }
+
+    insert(&mut delta, &target[pending..]);
+    delta
+}
+
+/// Appends a size, seven bits at a time from the lowest.
+fn write_size(delta: &mut Vec<u8>, mut size: usize) {
+    loop {
+        let byte = (size & 0x7f) as u8;
+        size >>= 7;
+        if size == 0 {
+            delta.push(byte);
+            return;
+        }
+        delta.push(byte | 0x80);
+    }
+}
+
+/// Appends instructions copying `size` bytes of the base from `offset`,
+/// giving only the bytes of each that aren't zero.
+fn copy(delta: &mut Vec<u8>, mut offset: usize, mut size: usize) {
+    while size > 0 {
+        let chunk = size.min(MAX_COPY);
+        let at = delta.len();
+        let mut instruction = 0x80;
+        delta.push(0);
+
+        for i in 0..4 {
+            let byte = (offset >> (i * 8)) as u8;
+            if byte != 0 {
+                instruction |= 1 << i;
+                delta.push(byte);
+            }
+        }
+        // a copy of 0x10000 has no size bytes at all
+        for i in 0..3 {
+            let byte = ((chunk % MAX_COPY) >> (i * 8)) as u8;
+            if byte != 0 {
+                instruction |= 0x10 << i;
+                delta.push(byte);
+            }
+        }
+
+        delta[at] = instruction;
+        offset += chunk;
+        size -= chunk;
+    }
+}
+
+/// Appends instructions inserting `data`.
+fn insert(delta: &mut Vec<u8>, data: &[u8]) {
+    for chunk in data.chunks(MAX_INSERT) {
+        delta.push(chunk.len() as u8);
+        delta.extend_from_slice(chunk);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, BLOCK, MAX_COPY};
+    use crate::git::packfile::process_delta;
+
+    /// Deterministic pseudo-random numbers, xorshift64.
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n.max(1) as u64) as usize
+        }
+
+        fn bytes(&mut self, n: usize, alphabet: u8) -> Vec<u8> {
+            (0..n)
+                .map(|_| b'a' + (self.next() % alphabet as u64) as u8)
+                .collect()
+        }
+    }
+
+    /// `base` with some ranges moved, dropped, repeated or replaced.
+    fn edit(random: &mut Random, base: &[u8]) -> Vec<u8> {
+        let mut target = Vec::new();
+        for _ in 0..random.below(8) + 1 {
+            match random.below(3) {
+                0 => {
+                    let (length, alphabet) = (random.below(300), 1 + random.below(26) as u8);
+                    target.extend(random.bytes(length, alphabet))
+                }
+                _ => {
+                    let start = random.below(base.len());
+                    let end = start + random.below(base.len() - start + 1);
+                    target.extend_from_slice(&base[start..end]);
+                }
+            }
+        }
+        target
+    }
+
+    #[test]
+    fn rebuilds_target() {
+        let base = b"the quick brown fox jumps over the lazy dog".repeat(3000);
+        let mut target = base.clone();
+        target.splice(70_000..70_010, b"cat".repeat(100));
+
+        let delta = encode(&base, &target);
+        assert!(delta.len() < 400);
+        assert_eq!(process_delta(&delta, &base).unwrap(), target);
+    }
+
+    #[test]
+    fn unrelated_and_empty() {
+        for (base, target) in [
+            (&b"abc"[..], &b"xyz"[..]),
+            (b"", b"new content"),
+            (b"old content", b""),
+            (b"same", b"same"),
+            (b"", b""),
+        ] {
+            let delta = encode(base, target);
+            assert_eq!(process_delta(&delta, base).unwrap(), target);
+        }
+    }
+
+    #[test]
+    fn encoded_deltas_apply() {
+        let mut random = Random(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..500 {
+            let (length, alphabet) = (random.below(5000), 1 + random.below(26) as u8);
+            let base = random.bytes(length, alphabet);
+            let target = match base.is_empty() {
+                true => random.bytes(10, alphabet),
+                false => edit(&mut random, &base),
+            };
+
+            let delta = encode(&base, &target);
+            assert_eq!(process_delta(&delta, &base).unwrap(), target);
+        }
+    }
+
+    #[test]
+    fn moved_blocks_are_copied() {
+        let mut random = Random(42);
+        let base = random.bytes(64 * 1024, 26);
+        let target = [&base[40_000..], &base[..40_000]].concat();
+
+        let delta = encode(&base, &target);
+        assert!(delta.len() < 32, "{} bytes", delta.len());
+        assert_eq!(process_delta(&delta, &base).unwrap(), target);
+    }
+
+    #[test]
+    fn copies_of_the_implicit_size() {
+        let base = Random(7).bytes(MAX_COPY * 2 + BLOCK, 26);
+        let target = base.clone();
+
+        let delta = encode(&base, &target);
+        // sizes, then copies of 0x10000 without size bytes
+        assert_eq!(delta[6], 0x80);
+        assert_eq!(process_delta(&delta, &base).unwrap(), target);
+    }
+}
@@ -0,0 +1,115 @@
+pub mod display;
+mod histogram;
+pub mod lines;
+mod moved;
+mod myers;
+pub mod patch;
+mod patience;
+pub mod rename;
+pub mod stat;
+pub mod tree;
+pub mod unified;
+mod words;
+pub mod worktree;
+
+use crate::git::objects;
+
+const TYPE_MASK: u32 = 0o170000;
+const GITLINK: u32 = 0o160000;
+
+/// Kind of change recorded for a path, shown as a letter by `--name-status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Added,
+    Deleted,
+    Modified,
+    /// The path changed between file, symlink and submodule.
+    TypeChanged,
+    Renamed,
+    Copied,
+}
+
+impl Status {
+    pub fn letter(&self) -> char {
+        match self {
+            Status::Added => 'A',
+            Status::Deleted => 'D',
+            Status::Modified => 'M',
+            Status::TypeChanged => 'T',
+            Status::Renamed => 'R',
+            Status::Copied => 'C',
+        }
+    }
+}
+
+/// One side of a change: where the content lives and what it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSpec {
+    pub path: String,
+    pub mode: u32,
+    pub hash: [u8; 20],
+}
+
+/// A difference between two trees for a single path, or for a pair of paths
+/// when the content was renamed or copied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub status: Status,
+    /// `None` when the path was added.
+    pub old: Option<FileSpec>,
+    /// `None` when the path was deleted.
+    pub new: Option<FileSpec>,
+    /// Similarity of a rename or copy, out of [`rename::MAX_SCORE`].
+    pub score: u32,
+}
+
+impl Change {
+    /// Path the change is listed under, the destination for renames.
+    pub fn path(&self) -> &str {
+        match (&self.new, &self.old) {
+            (Some(new), _) => &new.path,
+            (None, Some(old)) => &old.path,
+            (None, None) => unreachable!(),
+        }
+    }
+
+    /// Similarity in percent, as printed after `R` and `C`.
+    pub fn similarity(&self) -> u32 {
+        self.score * 100 / rename::MAX_SCORE
+    }
+}
+
+/// Content of one side of a change, a submodule showing its commit. Files
+/// compared from the worktree are not in the object database, their
+/// content is read back from the worktree.
+pub fn content(spec: &FileSpec) -> std::io::Result<Vec<u8>> {
+    if spec.mode & TYPE_MASK == GITLINK {
+        let text = format!("Subproject commit {}\n", hex::encode(spec.hash));
+        return Ok(text.into_bytes());
+    }
+
+    match objects::read_raw(&hex::encode(spec.hash)) {
+        Ok((_, data)) => Ok(data),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => worktree::content(spec)?.ok_or(e),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether content looks binary, following git: a NUL byte within the
+/// first 8000 bytes.
+pub fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(8000).any(|x| *x == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn binary_detection() {
+        assert!(!super::is_binary(b"plain text\n"));
+        assert!(super::is_binary(b"PK\x03\x04\0\0"));
+
+        let mut late = vec![b'a'; 8000];
+        late.push(0);
+        assert!(!super::is_binary(&late));
+    }
+}
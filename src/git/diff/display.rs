@@ -0,0 +1,471 @@
+//! How patches look on screen: colors, whitespace errors, word diffs and
+//! moved lines, all following git's default color scheme.
+
+use std::{io::Write, str::FromStr};
+
+use regex::bytes::Regex;
+
+use super::{
+    lines::is_space,
+    moved::{self, Mark},
+    patch::Symbol,
+    words,
+};
+
+pub const RESET: &str = "\x1b[m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+const BG_RED: &str = "\x1b[41m";
+const BOLD_MAGENTA: &str = "\x1b[1;35m";
+const BOLD_BLUE: &str = "\x1b[1;34m";
+const BOLD_CYAN: &str = "\x1b[1;36m";
+const BOLD_YELLOW: &str = "\x1b[1;33m";
+const FAINT: &str = "\x1b[2m";
+const FAINT_ITALIC: &str = "\x1b[2;3m";
+
+/// `--word-diff` modes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WordDiff {
+    /// Whole lines, the usual patch.
+    #[default]
+    None,
+    /// `[-removed-]{+added+}`.
+    Plain,
+    /// Changed words told apart by color only.
+    Color,
+    /// One word change per line for scripts, `~` standing for newlines.
+    Porcelain,
+}
+
+impl FromStr for WordDiff {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(WordDiff::None),
+            "plain" => Ok(WordDiff::Plain),
+            "color" => Ok(WordDiff::Color),
+            "porcelain" => Ok(WordDiff::Porcelain),
+            _ => Err(format!("bad --word-diff argument: {s}")),
+        }
+    }
+}
+
+/// `--color-moved` modes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMoved {
+    #[default]
+    No,
+    /// Every moved line, however short the block it is in.
+    Plain,
+    /// Blocks of at least 20 alphanumeric characters.
+    Blocks,
+    /// Blocks, adjacent ones in alternate colors.
+    Zebra,
+    /// Zebra, dimming the lines inside blocks to show their edges.
+    DimmedZebra,
+}
+
+impl FromStr for ColorMoved {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "no" => Ok(ColorMoved::No),
+            "plain" => Ok(ColorMoved::Plain),
+            "blocks" => Ok(ColorMoved::Blocks),
+            "zebra" | "default" => Ok(ColorMoved::Zebra),
+            "dimmed-zebra" | "dimmed_zebra" => Ok(ColorMoved::DimmedZebra),
+            _ => Err(
+                "color moved setting must be one of 'no', 'default', 'blocks', 'zebra', \
+                 'dimmed-zebra', 'plain'"
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+/// How [`write`] shows patches.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub color: bool,
+    pub word_diff: WordDiff,
+    /// What a word is for `--word-diff`, a run of non-whitespace by default.
+    pub word_regex: Option<Regex>,
+    /// Only with `color`, and not for word diffs.
+    pub color_moved: ColorMoved,
+}
+
+/// Escape sequences of every part of a patch, all empty without color.
+pub struct Palette {
+    pub reset: &'static str,
+    /// The `commit`, `tag` and `tree` lines of `show`.
+    pub commit: &'static str,
+    pub meta: &'static str,
+    pub frag: &'static str,
+    pub func: &'static str,
+    pub context: &'static str,
+    pub old: &'static str,
+    pub new: &'static str,
+    pub whitespace: &'static str,
+    /// Moved lines, then alternate blocks, dimmed lines and dimmed lines
+    /// of alternate blocks.
+    pub old_moved: [&'static str; 4],
+    pub new_moved: [&'static str; 4],
+}
+
+impl Palette {
+    pub fn new(color: bool) -> Palette {
+        match color {
+            true => Palette {
+                reset: RESET,
+                commit: YELLOW,
+                meta: BOLD,
+                frag: CYAN,
+                func: "",
+                context: "",
+                old: RED,
+                new: GREEN,
+                whitespace: BG_RED,
+                old_moved: [BOLD_MAGENTA, BOLD_BLUE, FAINT, FAINT_ITALIC],
+                new_moved: [BOLD_CYAN, BOLD_YELLOW, FAINT, FAINT_ITALIC],
+            },
+            false => Palette {
+                reset: "",
+                commit: "",
+                meta: "",
+                frag: "",
+                func: "",
+                context: "",
+                old: "",
+                new: "",
+                whitespace: "",
+                old_moved: [""; 4],
+                new_moved: [""; 4],
+            },
+        }
+    }
+
+    fn moved(&self, colors: &[&'static str; 4], normal: &'static str, mark: Mark) -> &'static str {
+        match (mark.moved, mark.alternate, mark.dim) {
+            (false, _, _) => normal,
+            (true, false, false) => colors[0],
+            (true, true, false) => colors[1],
+            (true, false, true) => colors[2],
+            (true, true, true) => colors[3],
+        }
+    }
+}
+
+/// Whether a line only holds whitespace.
+pub fn is_blank(line: &[u8]) -> bool {
+    line.iter().all(|x| is_space(*x))
+}
+
+/// What follows a `---`/`+++` line: a tab when the path holds a space, so
+/// that tools can tell where it ends.
+pub fn label_end(label: &str) -> &'static str {
+    match label.get(4..).is_some_and(|x| x.contains(' ')) {
+        true => "\t",
+        false => "",
+    }
+}
+
+fn count_lines(data: &[u8]) -> usize {
+    let lines = data.iter().filter(|x| **x == b'\n').count();
+    match data.last() {
+        None => 0,
+        Some(b'\n') => lines,
+        Some(_) => lines + 1,
+    }
+}
+
+/// Blank lines ending the content, the first line never counting.
+fn count_trailing_blank(data: &[u8]) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+
+    let mut end = data.len() as isize - 1;
+    if data[end as usize] == b'\n' {
+        end -= 1;
+    }
+
+    let mut count = 0;
+    while end > 0 {
+        let start = data[..=end as usize]
+            .iter()
+            .rposition(|x| *x == b'\n')
+            .map_or(0, |x| x + 1);
+        if !is_blank(&data[start..=end as usize]) {
+            break;
+        }
+        count += 1;
+        end = start as isize - 2;
+    }
+    count
+}
+
+/// Where blank lines added at the end of a file start, as line numbers of
+/// the old and new content, when the change adds some.
+pub fn blank_at_eof(old: &[u8], new: &[u8]) -> Option<(usize, usize)> {
+    let (one, two) = (count_trailing_blank(old), count_trailing_blank(new));
+    if two <= one {
+        return None;
+    }
+
+    Some((count_lines(old) - one + 1, count_lines(new) - two + 1))
+}
+
+/// Writes `line` with its newline apart from the color, like git does for
+/// a `\r` ending it too.
+fn write_line(
+    out: &mut impl Write,
+    set: &str,
+    reset: &str,
+    sign: Option<u8>,
+    line: &[u8],
+) -> std::io::Result<()> {
+    let (line, newline) = match line.strip_suffix(b"\n") {
+        Some(line) => (line, true),
+        None => (line, false),
+    };
+    let (line, cr) = match line.strip_suffix(b"\r") {
+        Some(line) => (line, true),
+        None => (line, false),
+    };
+
+    if !line.is_empty() || sign.is_some() {
+        out.write_all(set.as_bytes())?;
+        if let Some(sign) = sign {
+            out.write_all(&[sign])?;
+        }
+        out.write_all(line)?;
+        out.write_all(reset.as_bytes())?;
+    }
+    if cr {
+        out.write_all(b"\r")?;
+    }
+    if newline {
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Writes an added line after its sign, highlighting with `ws` the
+/// whitespace errors of git's default rules: spaces before a tab in the
+/// indentation and whitespace at the end of the line.
+fn write_checked(
+    out: &mut impl Write,
+    line: &[u8],
+    set: &str,
+    reset: &str,
+    ws: &str,
+) -> std::io::Result<()> {
+    let (line, newline) = match line.strip_suffix(b"\n") {
+        Some(line) => (line, true),
+        None => (line, false),
+    };
+    let trailing = line
+        .iter()
+        .rposition(|x| !is_space(*x))
+        .map_or(0, |x| x + 1);
+
+    let mut written = 0;
+    for (i, c) in line[..trailing].iter().enumerate() {
+        match c {
+            b' ' => continue,
+            b'\t' if written < i => {
+                write!(out, "{ws}")?;
+                out.write_all(&line[written..i])?;
+                write!(out, "{reset}\t")?;
+            }
+            b'\t' => out.write_all(&line[written..=i])?,
+            _ => break,
+        }
+        written = i + 1;
+    }
+
+    if trailing > written {
+        out.write_all(set.as_bytes())?;
+        out.write_all(&line[written..trailing])?;
+        out.write_all(reset.as_bytes())?;
+    }
+    if trailing < line.len() {
+        out.write_all(ws.as_bytes())?;
+        out.write_all(&line[trailing..])?;
+        out.write_all(reset.as_bytes())?;
+    }
+    if newline {
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// The `@@ -a,b +c,d @@` part of a hunk header and the function name after
+/// it in their own colors.
+fn write_hunk_header(
+    out: &mut impl Write,
+    header: &[u8],
+    palette: &Palette,
+) -> std::io::Result<()> {
+    let end = header
+        .windows(2)
+        .skip(2)
+        .position(|x| x == b"@@")
+        .map_or(header.len(), |x| x + 4);
+    let (range, rest) = header.split_at(end);
+    let function = rest
+        .iter()
+        .position(|x| !matches!(x, b' ' | b'\t'))
+        .unwrap_or(rest.len());
+
+    write!(out, "{}", palette.frag)?;
+    out.write_all(range)?;
+    write!(out, "{}", palette.reset)?;
+    if function > 0 {
+        write!(out, "{}", palette.context)?;
+        out.write_all(&rest[..function])?;
+        write!(out, "{}", palette.reset)?;
+    }
+    if function < rest.len() {
+        write!(out, "{}", palette.func)?;
+        out.write_all(&rest[function..])?;
+        write!(out, "{}", palette.reset)?;
+    }
+    out.write_all(b"\n")
+}
+
+/// Writes the symbols that are not lines of a hunk.
+fn write_other(out: &mut impl Write, symbol: &Symbol, palette: &Palette) -> std::io::Result<()> {
+    let Palette { meta, reset, .. } = palette;
+    match symbol {
+        Symbol::Meta(text) => writeln!(out, "{meta}{text}{reset}"),
+        Symbol::Label(text) => writeln!(out, "{meta}{text}{reset}{}", label_end(text)),
+        Symbol::Binary(text) => writeln!(out, "{text}"),
+        Symbol::Hunk(header) => write_hunk_header(out, header, palette),
+        Symbol::NoNewline => write_line(
+            out,
+            palette.context,
+            reset,
+            None,
+            b"\\ No newline at end of file\n",
+        ),
+        _ => unreachable!(),
+    }
+}
+
+fn write_symbol(
+    out: &mut impl Write,
+    symbol: &Symbol,
+    mark: Mark,
+    palette: &Palette,
+) -> std::io::Result<()> {
+    match symbol {
+        Symbol::Context(text) => write_line(out, palette.context, palette.reset, Some(b' '), text),
+        Symbol::Deleted(text) => {
+            let set = palette.moved(&palette.old_moved, palette.old, mark);
+            write_line(out, set, palette.reset, Some(b'-'), text)
+        }
+        Symbol::Added(text, _) if palette.whitespace.is_empty() => {
+            write_line(out, palette.new, palette.reset, Some(b'+'), text)
+        }
+        Symbol::Added(text, true) => {
+            write_line(out, palette.whitespace, palette.reset, Some(b'+'), text)
+        }
+        Symbol::Added(text, false) => {
+            let set = palette.moved(&palette.new_moved, palette.new, mark);
+            write!(out, "{set}+{}", palette.reset)?;
+            write_checked(out, text, set, palette.reset, palette.whitespace)
+        }
+        _ => write_other(out, symbol, palette),
+    }
+}
+
+/// Writes patch symbols, styled as `options` ask.
+pub fn write(out: &mut impl Write, symbols: &[Symbol], options: &Options) -> std::io::Result<()> {
+    let palette = Palette::new(options.color);
+
+    if options.word_diff != WordDiff::None {
+        let regex = options.word_regex.as_ref();
+        let mut words = words::WordDiff::new(options.word_diff, regex, &palette);
+        for symbol in symbols {
+            if !words.push(out, symbol)? {
+                write_other(out, symbol, &palette)?;
+            }
+        }
+        return words.flush(out);
+    }
+
+    let marks = match (options.color, options.color_moved) {
+        (true, mode) if mode != ColorMoved::No => moved::mark(symbols, mode),
+        _ => vec![Mark::default(); symbols.len()],
+    };
+    for (symbol, mark) in symbols.iter().zip(marks) {
+        write_symbol(out, symbol, mark, &palette)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blank_at_eof, write, Options};
+    use crate::git::diff::patch::Symbol;
+
+    fn render(symbols: &[Symbol], color: bool) -> String {
+        let mut out = Vec::new();
+        let options = Options {
+            color,
+            ..Default::default()
+        };
+        write(&mut out, symbols, &options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn blank_lines_at_end_of_file() {
+        assert_eq!(blank_at_eof(b"a\n", b"a\n\n\n"), Some((2, 2)));
+        assert_eq!(blank_at_eof(b"a\n\n", b"a\n\n"), None);
+        assert_eq!(blank_at_eof(b"a\n", b"a\nb\n"), None);
+        assert_eq!(blank_at_eof(b"", b"a\n\n\n"), Some((1, 2)));
+        // the first line is never counted
+        assert_eq!(blank_at_eof(b"", b"\n\n"), None);
+    }
+
+    #[test]
+    fn plain_without_color() {
+        let symbols = [
+            Symbol::Hunk(b"@@ -1 +1 @@ fn main()".to_vec()),
+            Symbol::Deleted(b"a \n".to_vec()),
+            Symbol::Added(b"b \n".to_vec(), false),
+            Symbol::NoNewline,
+        ];
+        assert_eq!(
+            render(&symbols, false),
+            "@@ -1 +1 @@ fn main()\n-a \n+b \n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn colors_and_whitespace_errors() {
+        let symbols = [
+            Symbol::Label("--- a/my file".to_string()),
+            Symbol::Hunk(b"@@ -1,2 +1,2 @@ fn main()".to_vec()),
+            Symbol::Context(b"\n".to_vec()),
+            Symbol::Deleted(b"a\r\n".to_vec()),
+            Symbol::Added(b"  \tb  \n".to_vec(), false),
+            Symbol::Added(b"\n".to_vec(), true),
+        ];
+        assert_eq!(
+            render(&symbols, true),
+            "\x1b[1m--- a/my file\x1b[m\t\n\
+             \x1b[36m@@ -1,2 +1,2 @@\x1b[m \x1b[mfn main()\x1b[m\n \x1b[m\n\
+             \x1b[31m-a\x1b[m\r\n\
+             \x1b[32m+\x1b[m\x1b[41m  \x1b[m\t\x1b[32mb\x1b[m\x1b[41m  \x1b[m\n\
+             \x1b[41m+\x1b[m\n"
+        );
+    }
+}
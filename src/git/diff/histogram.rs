@@ -0,0 +1,218 @@
+//! Histogram diff: the longest common run built around the line of the
+//! first range occurring least often splits the ranges, both sides of it
+//! being compared the same way. Lines occurring too often fall back to
+//! Myers.
+
+use std::collections::HashMap;
+
+use super::myers;
+
+/// Occurrences beyond which a line is not used to split the ranges.
+const MAX_CHAIN_LENGTH: usize = 64;
+
+/// Occurrences of a line in the first range.
+#[derive(Clone, Copy)]
+struct Record {
+    /// First occurrence, numbered from one.
+    ptr: usize,
+    cnt: usize,
+}
+
+/// Lines `begin1..=end1` equal to `begin2..=end2`, numbered from one.
+#[derive(Default)]
+struct Region {
+    begin1: usize,
+    end1: usize,
+    begin2: usize,
+    end2: usize,
+}
+
+struct Index {
+    records: HashMap<usize, Record>,
+    /// Next occurrence of each line of the first range, 0 for none.
+    next_ptrs: Vec<usize>,
+    ptr_shift: usize,
+    /// Occurrences of the rarest line of the best region so far.
+    cnt: usize,
+    has_common: bool,
+}
+
+struct Histogram<'a> {
+    one: &'a [usize],
+    two: &'a [usize],
+    changed1: Vec<bool>,
+    changed2: Vec<bool>,
+}
+
+impl Histogram<'_> {
+    fn same(&self, line1: usize, line2: usize) -> bool {
+        self.one[line1 - 1] == self.two[line2 - 1]
+    }
+
+    fn scan(&self, line1: usize, count1: usize) -> Index {
+        let mut index = Index {
+            records: HashMap::new(),
+            next_ptrs: vec![0; count1],
+            ptr_shift: line1,
+            cnt: MAX_CHAIN_LENGTH + 1,
+            has_common: false,
+        };
+
+        for ptr in (line1..line1 + count1).rev() {
+            match index.records.get_mut(&self.one[ptr - 1]) {
+                Some(record) => {
+                    index.next_ptrs[ptr - line1] = record.ptr;
+                    record.ptr = ptr;
+                    record.cnt += 1;
+                }
+                None => {
+                    index
+                        .records
+                        .insert(self.one[ptr - 1], Record { ptr, cnt: 1 });
+                }
+            }
+        }
+
+        index
+    }
+
+    /// Grows a region around each occurrence of line `b_ptr` of the second
+    /// range, keeping the best one in `lcs`. Returns the next line to try.
+    #[allow(clippy::too_many_arguments)]
+    fn try_lcs(
+        &self,
+        index: &mut Index,
+        lcs: &mut Region,
+        b_ptr: usize,
+        line1: usize,
+        count1: usize,
+        line2: usize,
+        count2: usize,
+    ) -> usize {
+        let mut b_next = b_ptr + 1;
+        let Some(&record) = index.records.get(&self.two[b_ptr - 1]) else {
+            return b_next;
+        };
+        index.has_common = true;
+        if record.cnt > index.cnt {
+            return b_next;
+        }
+
+        let (end1, end2) = (line1 + count1 - 1, line2 + count2 - 1);
+        let cnt = |index: &Index, line: usize| index.records[&self.one[line - 1]].cnt;
+        let next_ptr = |index: &Index, ptr: usize| index.next_ptrs[ptr - index.ptr_shift];
+
+        let mut a_ptr = record.ptr;
+        loop {
+            let mut np = next_ptr(index, a_ptr);
+            let (mut as_, mut bs) = (a_ptr, b_ptr);
+            let (mut ae, mut be) = (as_, bs);
+            let mut rc = record.cnt;
+
+            while line1 < as_ && line2 < bs && self.same(as_ - 1, bs - 1) {
+                as_ -= 1;
+                bs -= 1;
+                if rc > 1 {
+                    rc = rc.min(cnt(index, as_));
+                }
+            }
+            while ae < end1 && be < end2 && self.same(ae + 1, be + 1) {
+                ae += 1;
+                be += 1;
+                if rc > 1 {
+                    rc = rc.min(cnt(index, ae));
+                }
+            }
+
+            if b_next <= be {
+                b_next = be + 1;
+            }
+            if lcs.end1 - lcs.begin1 < ae - as_ || rc < index.cnt {
+                *lcs = Region {
+                    begin1: as_,
+                    end1: ae,
+                    begin2: bs,
+                    end2: be,
+                };
+                index.cnt = rc;
+            }
+
+            // next occurrence past the region just found
+            while np != 0 && np <= ae {
+                np = next_ptr(index, np);
+            }
+            if np == 0 {
+                return b_next;
+            }
+            a_ptr = np;
+        }
+    }
+
+    fn mark(&mut self, line1: usize, count1: usize, line2: usize, count2: usize) {
+        self.changed1[line1 - 1..line1 - 1 + count1].fill(true);
+        self.changed2[line2 - 1..line2 - 1 + count2].fill(true);
+    }
+
+    fn diff(&mut self, mut line1: usize, mut count1: usize, mut line2: usize, mut count2: usize) {
+        loop {
+            if count1 == 0 || count2 == 0 {
+                self.mark(line1, count1, line2, count2);
+                return;
+            }
+
+            let mut index = self.scan(line1, count1);
+            let mut lcs = Region::default();
+            let mut b_ptr = line2;
+            while b_ptr < line2 + count2 {
+                b_ptr = self.try_lcs(&mut index, &mut lcs, b_ptr, line1, count1, line2, count2);
+            }
+
+            if index.has_common && index.cnt > MAX_CHAIN_LENGTH {
+                let (one, two) = myers::diff(
+                    &self.one[line1 - 1..line1 - 1 + count1],
+                    &self.two[line2 - 1..line2 - 1 + count2],
+                    false,
+                );
+                self.changed1[line1 - 1..line1 - 1 + count1].copy_from_slice(&one);
+                self.changed2[line2 - 1..line2 - 1 + count2].copy_from_slice(&two);
+                return;
+            }
+            if lcs.begin1 == 0 && lcs.begin2 == 0 {
+                self.mark(line1, count1, line2, count2);
+                return;
+            }
+
+            self.diff(line1, lcs.begin1 - line1, line2, lcs.begin2 - line2);
+            count1 = line1 + count1 - 1 - lcs.end1;
+            line1 = lcs.end1 + 1;
+            count2 = line2 + count2 - 1 - lcs.end2;
+            line2 = lcs.end2 + 1;
+        }
+    }
+}
+
+/// Compares two files given as line classes, returning which lines of
+/// each are changed.
+pub fn diff(one: &[usize], two: &[usize]) -> (Vec<bool>, Vec<bool>) {
+    let mut histogram = Histogram {
+        one,
+        two,
+        changed1: vec![false; one.len()],
+        changed2: vec![false; two.len()],
+    };
+    histogram.diff(1, one.len(), 1, two.len());
+
+    (histogram.changed1, histogram.changed2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff;
+
+    #[test]
+    fn splits_on_rare_lines() {
+        let (one, two) = diff(&[0, 1, 0, 2, 0], &[0, 2, 0, 1, 0]);
+        assert_eq!(one.iter().filter(|x| **x).count(), 2);
+        assert_eq!(two.iter().filter(|x| **x).count(), 2);
+    }
+}
@@ -0,0 +1,604 @@
+use std::{borrow::Cow, collections::HashMap, str::FromStr};
+
+use super::{histogram, myers, patience};
+
+/// How the longest common subsequence of two files is searched.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    #[default]
+    Myers,
+    /// Myers without the heuristics that trade size for speed.
+    Minimal,
+    /// Anchors on lines that are unique on both sides.
+    Patience,
+    /// Patience extended to lines that occur rarely.
+    Histogram,
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "myers" | "default" => Ok(Algorithm::Myers),
+            "minimal" => Ok(Algorithm::Minimal),
+            "patience" => Ok(Algorithm::Patience),
+            "histogram" => Ok(Algorithm::Histogram),
+            _ => Err(
+                "option diff-algorithm accepts \"myers\", \"minimal\", \"patience\" and \"histogram\""
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+/// What makes two lines equal, and how changes are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    pub algorithm: Algorithm,
+    /// `-w`: whitespace is ignored entirely.
+    pub ignore_all_space: bool,
+    /// `-b`: runs of whitespace compare equal, trailing whitespace is ignored.
+    pub ignore_space_change: bool,
+    /// `--ignore-space-at-eol`: trailing whitespace is ignored.
+    pub ignore_space_at_eol: bool,
+    /// `--ignore-blank-lines`: changes made only of blank lines are not shown.
+    pub ignore_blank_lines: bool,
+    /// Slide ambiguous changes to where indentation suggests a block starts.
+    pub indent_heuristic: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            algorithm: Algorithm::Myers,
+            ignore_all_space: false,
+            ignore_space_change: false,
+            ignore_space_at_eol: false,
+            ignore_blank_lines: false,
+            indent_heuristic: true,
+        }
+    }
+}
+
+impl Options {
+    fn ignores_whitespace(&self) -> bool {
+        self.ignore_all_space || self.ignore_space_change || self.ignore_space_at_eol
+    }
+
+    /// The part of a line that decides whether it equals another one.
+    fn key<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.ignore_all_space {
+            Cow::Owned(line.iter().copied().filter(|x| !is_space(*x)).collect())
+        } else if self.ignore_space_change {
+            let mut key = Vec::with_capacity(line.len());
+            for &c in line.iter() {
+                match is_space(c) {
+                    true if key.last() == Some(&b' ') => {}
+                    true => key.push(b' '),
+                    false => key.push(c),
+                }
+            }
+            if key.last() == Some(&b' ') {
+                key.pop();
+            }
+            Cow::Owned(key)
+        } else if self.ignore_space_at_eol {
+            let end = line
+                .iter()
+                .rposition(|x| !is_space(*x))
+                .map_or(0, |x| x + 1);
+            Cow::Borrowed(&line[..end])
+        } else {
+            Cow::Borrowed(line)
+        }
+    }
+
+    fn is_blank(&self, line: &[u8]) -> bool {
+        match self.ignores_whitespace() {
+            true => line.iter().all(|x| is_space(*x)),
+            false => line.len() <= 1,
+        }
+    }
+}
+
+/// A run of `old_len` lines at `old` replaced by `new_len` lines at `new`,
+/// positions counting from zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edit {
+    pub old: usize,
+    pub old_len: usize,
+    pub new: usize,
+    pub new_len: usize,
+    /// Only blank lines change, hidden with `ignore_blank_lines`.
+    pub ignore: bool,
+}
+
+/// Whitespace as git sees it: unlike C's `isspace`, vertical tabs and form
+/// feeds are not.
+pub fn is_space(c: u8) -> bool {
+    matches!(c, b' ' | b'\t' | b'\n' | b'\r')
+}
+
+/// Splits content into lines, each keeping its newline.
+pub fn split(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|x| *x == b'\n').collect()
+}
+
+/// Lines whose maximum indentation is considered, see [`indent`].
+const MAX_INDENT: i64 = 200;
+/// Blank lines counted around a split at most.
+const MAX_BLANKS: i64 = 20;
+/// Positions tried at most when sliding a change with the indent heuristic.
+const INDENT_HEURISTIC_MAX_SLIDING: i64 = 100;
+
+const START_OF_FILE_PENALTY: i64 = 1;
+const END_OF_FILE_PENALTY: i64 = 21;
+const TOTAL_BLANK_WEIGHT: i64 = -30;
+const POST_BLANK_WEIGHT: i64 = 6;
+const RELATIVE_INDENT_PENALTY: i64 = -4;
+const RELATIVE_INDENT_WITH_BLANK_PENALTY: i64 = 10;
+const RELATIVE_OUTDENT_PENALTY: i64 = 24;
+const RELATIVE_OUTDENT_WITH_BLANK_PENALTY: i64 = 17;
+const RELATIVE_DEDENT_PENALTY: i64 = 23;
+const RELATIVE_DEDENT_WITH_BLANK_PENALTY: i64 = 17;
+const INDENT_WEIGHT: i64 = 60;
+
+/// Width of the leading whitespace of a line, tabs stopping every 8
+/// columns, -1 for a blank line.
+fn indent(line: &[u8]) -> i64 {
+    let mut width = 0;
+    for &c in line.iter() {
+        if !is_space(c) {
+            return width;
+        }
+        match c {
+            b' ' => width += 1,
+            b'\t' => width += 8 - width % 8,
+            _ => {}
+        }
+        if width >= MAX_INDENT {
+            return MAX_INDENT;
+        }
+    }
+
+    -1
+}
+
+/// One file of a diff: its lines, their equivalence classes, and which of
+/// them are changed. `changed` has an unchanged sentinel before the first
+/// and after the last line.
+struct Side<'a> {
+    lines: &'a [&'a [u8]],
+    classes: Vec<usize>,
+    changed: Vec<bool>,
+}
+
+/// A run of changed lines `start..end`, empty between two unchanged lines.
+#[derive(Debug, Clone, Copy)]
+struct Group {
+    start: i64,
+    end: i64,
+}
+
+#[derive(Default)]
+struct Measure {
+    end_of_file: bool,
+    indent: i64,
+    pre_blank: i64,
+    pre_indent: i64,
+    post_blank: i64,
+    post_indent: i64,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Score {
+    effective_indent: i64,
+    penalty: i64,
+}
+
+impl Score {
+    fn add(&mut self, m: &Measure) {
+        if m.pre_indent == -1 && m.pre_blank == 0 {
+            self.penalty += START_OF_FILE_PENALTY;
+        }
+        if m.end_of_file {
+            self.penalty += END_OF_FILE_PENALTY;
+        }
+
+        let post_blank = if m.indent == -1 { 1 + m.post_blank } else { 0 };
+        let total_blank = m.pre_blank + post_blank;
+        self.penalty += TOTAL_BLANK_WEIGHT * total_blank + POST_BLANK_WEIGHT * post_blank;
+
+        let indent = if m.indent != -1 {
+            m.indent
+        } else {
+            m.post_indent
+        };
+        let any_blanks = total_blank != 0;
+        self.effective_indent += indent;
+
+        if indent == -1 || m.pre_indent == -1 || indent == m.pre_indent {
+        } else if indent > m.pre_indent {
+            self.penalty += match any_blanks {
+                true => RELATIVE_INDENT_WITH_BLANK_PENALTY,
+                false => RELATIVE_INDENT_PENALTY,
+            };
+        } else if m.post_indent != -1 && m.post_indent > indent {
+            // the next line is indented more, this probably starts a block
+            self.penalty += match any_blanks {
+                true => RELATIVE_OUTDENT_WITH_BLANK_PENALTY,
+                false => RELATIVE_OUTDENT_PENALTY,
+            };
+        } else {
+            self.penalty += match any_blanks {
+                true => RELATIVE_DEDENT_WITH_BLANK_PENALTY,
+                false => RELATIVE_DEDENT_PENALTY,
+            };
+        }
+    }
+
+    fn compare(&self, other: &Score) -> i64 {
+        let indents = (self.effective_indent > other.effective_indent) as i64
+            - (self.effective_indent < other.effective_indent) as i64;
+        INDENT_WEIGHT * indents + self.penalty - other.penalty
+    }
+}
+
+impl<'a> Side<'a> {
+    fn new(lines: &'a [&'a [u8]], classes: Vec<usize>, changed: Vec<bool>) -> Side<'a> {
+        let mut padded = Vec::with_capacity(changed.len() + 2);
+        padded.push(false);
+        padded.extend(changed);
+        padded.push(false);
+
+        Side {
+            lines,
+            classes,
+            changed: padded,
+        }
+    }
+
+    fn len(&self) -> i64 {
+        self.lines.len() as i64
+    }
+
+    fn is_changed(&self, line: i64) -> bool {
+        line >= -1 && self.changed.get((line + 1) as usize) == Some(&true)
+    }
+
+    fn set(&mut self, line: i64, changed: bool) {
+        self.changed[(line + 1) as usize] = changed;
+    }
+
+    fn same(&self, one: i64, two: i64) -> bool {
+        self.classes[one as usize] == self.classes[two as usize]
+    }
+
+    fn first_group(&self) -> Group {
+        let mut end = 0;
+        while self.is_changed(end) {
+            end += 1;
+        }
+        Group { start: 0, end }
+    }
+
+    fn next_group(&self, group: &mut Group) -> bool {
+        if group.end == self.len() {
+            return false;
+        }
+        group.start = group.end + 1;
+        group.end = group.start;
+        while self.is_changed(group.end) {
+            group.end += 1;
+        }
+        true
+    }
+
+    fn previous_group(&self, group: &mut Group) -> bool {
+        if group.start == 0 {
+            return false;
+        }
+        group.end = group.start - 1;
+        group.start = group.end;
+        while self.is_changed(group.start - 1) {
+            group.start -= 1;
+        }
+        true
+    }
+
+    fn slide_down(&mut self, group: &mut Group) -> bool {
+        if group.end >= self.len() || !self.same(group.start, group.end) {
+            return false;
+        }
+        self.set(group.start, false);
+        self.set(group.end, true);
+        group.start += 1;
+        group.end += 1;
+        while self.is_changed(group.end) {
+            group.end += 1;
+        }
+        true
+    }
+
+    fn slide_up(&mut self, group: &mut Group) -> bool {
+        if group.start <= 0 || !self.same(group.start - 1, group.end - 1) {
+            return false;
+        }
+        group.start -= 1;
+        group.end -= 1;
+        self.set(group.start, true);
+        self.set(group.end, false);
+        while self.is_changed(group.start - 1) {
+            group.start -= 1;
+        }
+        true
+    }
+
+    fn measure(&self, split: i64) -> Measure {
+        let mut m = Measure::default();
+        if split >= self.len() {
+            m.end_of_file = true;
+            m.indent = -1;
+        } else {
+            m.indent = indent(self.lines[split as usize]);
+        }
+
+        m.pre_indent = -1;
+        for line in (0..split).rev() {
+            m.pre_indent = indent(self.lines[line as usize]);
+            if m.pre_indent != -1 {
+                break;
+            }
+            m.pre_blank += 1;
+            if m.pre_blank == MAX_BLANKS {
+                m.pre_indent = 0;
+                break;
+            }
+        }
+
+        m.post_indent = -1;
+        for line in split + 1..self.len() {
+            m.post_indent = indent(self.lines[line as usize]);
+            if m.post_indent != -1 {
+                break;
+            }
+            m.post_blank += 1;
+            if m.post_blank == MAX_BLANKS {
+                m.post_indent = 0;
+                break;
+            }
+        }
+
+        m
+    }
+}
+
+/// Slides each group of changed lines of `side` as far as it goes to merge
+/// it with neighbouring groups, then settles it next to a change of `other`
+/// or, with the indent heuristic, where it reads best. Same as git's
+/// `xdl_change_compact`.
+fn compact(side: &mut Side, other: &mut Side, indent_heuristic: bool) {
+    let mut group = side.first_group();
+    let mut other_group = other.first_group();
+
+    loop {
+        if group.end != group.start {
+            let mut earliest_end;
+            let mut end_matching_other;
+
+            loop {
+                let size = group.end - group.start;
+                end_matching_other = -1;
+
+                while side.slide_up(&mut group) {
+                    other.previous_group(&mut other_group);
+                }
+                earliest_end = group.end;
+                if other_group.end > other_group.start {
+                    end_matching_other = group.end;
+                }
+
+                while side.slide_down(&mut group) {
+                    other.next_group(&mut other_group);
+                    if other_group.end > other_group.start {
+                        end_matching_other = group.end;
+                    }
+                }
+
+                if size == group.end - group.start {
+                    break;
+                }
+            }
+
+            if group.end == earliest_end {
+                // no sliding possible
+            } else if end_matching_other != -1 {
+                while other_group.end == other_group.start {
+                    side.slide_up(&mut group);
+                    other.previous_group(&mut other_group);
+                }
+            } else if indent_heuristic {
+                let size = group.end - group.start;
+                let mut shift = earliest_end
+                    .max(group.end - size - 1)
+                    .max(group.end - INDENT_HEURISTIC_MAX_SLIDING);
+                let mut best: Option<(i64, Score)> = None;
+
+                while shift <= group.end {
+                    let mut score = Score::default();
+                    score.add(&side.measure(shift));
+                    score.add(&side.measure(shift - size));
+                    if best.is_none_or(|(_, x)| score.compare(&x) <= 0) {
+                        best = Some((shift, score));
+                    }
+                    shift += 1;
+                }
+
+                let (best_shift, _) = best.unwrap();
+                while group.end > best_shift {
+                    side.slide_up(&mut group);
+                    other.previous_group(&mut other_group);
+                }
+            }
+        }
+
+        if !side.next_group(&mut group) {
+            break;
+        }
+        other.next_group(&mut other_group);
+    }
+}
+
+fn script(one: &Side, two: &Side) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let (mut i1, mut i2) = (one.len(), two.len());
+
+    while i1 >= 0 || i2 >= 0 {
+        if one.is_changed(i1 - 1) || two.is_changed(i2 - 1) {
+            let (end1, end2) = (i1, i2);
+            while one.is_changed(i1 - 1) {
+                i1 -= 1;
+            }
+            while two.is_changed(i2 - 1) {
+                i2 -= 1;
+            }
+            edits.push(Edit {
+                old: i1 as usize,
+                old_len: (end1 - i1) as usize,
+                new: i2 as usize,
+                new_len: (end2 - i2) as usize,
+                ignore: false,
+            });
+        }
+        i1 -= 1;
+        i2 -= 1;
+    }
+
+    edits.reverse();
+    edits
+}
+
+/// Compares two files split with [`split`] and returns the changed runs,
+/// laid out like git does so that hunks come out identical.
+pub fn diff(old: &[&[u8]], new: &[&[u8]], options: &Options) -> Vec<Edit> {
+    let mut classes = HashMap::new();
+    let [one, two] = [old, new].map(|lines| {
+        lines
+            .iter()
+            .map(|x| {
+                let next = classes.len();
+                *classes.entry(options.key(x)).or_insert(next)
+            })
+            .collect::<Vec<usize>>()
+    });
+
+    let (changed1, changed2) = match options.algorithm {
+        Algorithm::Myers => myers::diff(&one, &two, false),
+        Algorithm::Minimal => myers::diff(&one, &two, true),
+        Algorithm::Patience => patience::diff(&one, &two),
+        Algorithm::Histogram => histogram::diff(&one, &two),
+    };
+
+    let mut one = Side::new(old, one, changed1);
+    let mut two = Side::new(new, two, changed2);
+    compact(&mut one, &mut two, options.indent_heuristic);
+    compact(&mut two, &mut one, options.indent_heuristic);
+
+    let mut edits = script(&one, &two);
+    if options.ignore_blank_lines {
+        for edit in edits.iter_mut() {
+            edit.ignore = old[edit.old..edit.old + edit.old_len]
+                .iter()
+                .chain(new[edit.new..edit.new + edit.new_len].iter())
+                .all(|x| options.is_blank(x));
+        }
+    }
+
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, indent, split, Algorithm, Edit, Options};
+
+    fn edits(old: &str, new: &str, options: &Options) -> Vec<(usize, usize, usize, usize)> {
+        let (old, new) = (split(old.as_bytes()), split(new.as_bytes()));
+        diff(&old, &new, options)
+            .into_iter()
+            .map(
+                |Edit {
+                     old,
+                     old_len,
+                     new,
+                     new_len,
+                     ..
+                 }| (old, old_len, new, new_len),
+            )
+            .collect()
+    }
+
+    #[test]
+    fn split_keeps_newlines() {
+        assert_eq!(split(b"a\nb"), vec![&b"a\n"[..], &b"b"[..]]);
+        assert!(split(b"").is_empty());
+    }
+
+    #[test]
+    fn indentation_width() {
+        assert_eq!(indent(b"x\n"), 0);
+        assert_eq!(indent(b"  \tx\n"), 8);
+        assert_eq!(indent(b" \t \n"), -1);
+    }
+
+    #[test]
+    fn every_algorithm_finds_the_change() {
+        for algorithm in ["myers", "minimal", "patience", "histogram"] {
+            let options = Options {
+                algorithm: algorithm.parse().unwrap(),
+                ..Default::default()
+            };
+            assert_eq!(
+                edits("a\nb\nc\n", "a\nx\nc\n", &options),
+                vec![(1, 1, 1, 1)]
+            );
+            assert_eq!(edits("", "a\n", &options), vec![(0, 0, 0, 1)]);
+            assert_eq!(edits("a\n", "a\n", &options), vec![]);
+        }
+        assert!("fast".parse::<Algorithm>().is_err());
+    }
+
+    #[test]
+    fn insertion_slides_to_block_boundary() {
+        let old = "{\n  a\n}\n";
+        let new = "{\n  a\n}\n{\n  a\n}\n";
+        assert_eq!(edits(old, new, &Options::default()), vec![(3, 0, 3, 3)]);
+    }
+
+    #[test]
+    fn whitespace_options() {
+        let (old, new) = ("a b\nc\n", "a  b \nc\n");
+        assert_eq!(edits(old, new, &Options::default()), vec![(0, 1, 0, 1)]);
+
+        let change = Options {
+            ignore_space_change: true,
+            ..Default::default()
+        };
+        assert_eq!(edits(old, new, &change), vec![]);
+
+        let all = Options {
+            ignore_all_space: true,
+            ..Default::default()
+        };
+        assert_eq!(edits("ab\n", "a b\n", &all), vec![]);
+        assert_eq!(edits("ab\n", "a\x0cb\n", &all), vec![(0, 1, 0, 1)]);
+        assert_eq!(edits("ab\n", "a b\n", &change), vec![(0, 1, 0, 1)]);
+
+        let eol = Options {
+            ignore_space_at_eol: true,
+            ..Default::default()
+        };
+        assert_eq!(edits("a\n", "a \t\n", &eol), vec![]);
+        assert_eq!(edits("a b\n", "a  b\n", &eol), vec![(0, 1, 0, 1)]);
+    }
+}
@@ -0,0 +1,290 @@
+//! `--color-moved`: blocks of lines deleted in one place of a patch and
+//! added in another, found the way git does.
+
+use std::collections::HashMap;
+
+use super::{display::ColorMoved, patch::Symbol};
+
+/// Alphanumeric characters a block needs to count as moved, so that blank
+/// lines and lone braces are not.
+const MIN_ALNUM_COUNT: usize = 20;
+
+/// How a line is colored by [`mark`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Mark {
+    pub moved: bool,
+    /// The block follows another moved block directly.
+    pub alternate: bool,
+    /// Inside a block rather than at one of its edges.
+    pub dim: bool,
+}
+
+/// A deleted or added line: its text and whether it is added.
+fn line(symbol: &Symbol) -> Option<(&[u8], bool)> {
+    match symbol {
+        Symbol::Deleted(text) => Some((text, false)),
+        Symbol::Added(text, _) => Some((text, true)),
+        _ => None,
+    }
+}
+
+struct Moved<'a> {
+    symbols: &'a [Symbol],
+    mode: ColorMoved,
+    marks: Vec<Mark>,
+    /// The same number for equal lines.
+    ids: Vec<usize>,
+    /// The line after each one when it is of the same kind.
+    next_line: Vec<Option<usize>>,
+    /// Where each distinct line is deleted, and where it is added.
+    deleted: Vec<Vec<usize>>,
+    added: Vec<Vec<usize>>,
+}
+
+impl Moved<'_> {
+    fn index(&mut self) {
+        let mut ids: HashMap<&[u8], usize> = HashMap::new();
+        let mut previous: Option<(usize, bool)> = None;
+
+        for (n, symbol) in self.symbols.iter().enumerate() {
+            let Some((text, added)) = line(symbol) else {
+                previous = None;
+                continue;
+            };
+
+            let next_id = ids.len();
+            let id = *ids.entry(text).or_insert(next_id);
+            if id == self.deleted.len() {
+                self.deleted.push(Vec::new());
+                self.added.push(Vec::new());
+            }
+            self.ids[n] = id;
+
+            if let Some((previous, true)) = previous.map(|(x, kind)| (x, kind == added)) {
+                self.next_line[previous] = Some(n);
+            }
+            previous = Some((n, added));
+            match added {
+                true => self.added[id].push(n),
+                false => self.deleted[id].push(n),
+            }
+        }
+    }
+
+    /// Unmarks the block of `length` lines ending before `end` if it is too
+    /// small to be a move, returning whether it is kept.
+    fn adjust_last_block(&mut self, end: usize, length: usize) -> bool {
+        if self.mode == ColorMoved::Plain {
+            return length > 0;
+        }
+
+        let block = end - length..end;
+        let alnum: usize = self.symbols[block.clone()]
+            .iter()
+            .filter_map(line)
+            .map(|(text, _)| text.iter().filter(|x| x.is_ascii_alphanumeric()).count())
+            .sum();
+        if alnum >= MIN_ALNUM_COUNT {
+            return true;
+        }
+
+        for mark in self.marks[block].iter_mut() {
+            mark.moved = false;
+            mark.alternate = false;
+        }
+        false
+    }
+
+    /// Follows the lines that could be moved along with the current block,
+    /// starting new blocks where none goes on. A block that turns out too
+    /// small is looked at again from its second line, as a block may start
+    /// there.
+    fn mark(&mut self) {
+        // the lines of the other kind the current block may come from, and
+        // the kind of its lines
+        let mut potential: Vec<usize> = Vec::new();
+        let mut block_kind: Option<bool> = None;
+        let mut block_length = 0;
+        let mut flipped = false;
+
+        let mut n = 0;
+        while n < self.symbols.len() {
+            let kind = line(&self.symbols[n]).map(|(_, added)| added);
+            let mut matches = match kind {
+                Some(true) => Some(&self.deleted[self.ids[n]]),
+                Some(false) => Some(&self.added[self.ids[n]]),
+                None => {
+                    flipped = false;
+                    None
+                }
+            }
+            .filter(|x| !x.is_empty())
+            .cloned();
+
+            if !potential.is_empty() && (matches.is_none() || kind != block_kind) {
+                if !self.adjust_last_block(n, block_length) && block_length > 1 {
+                    matches = None;
+                    n -= block_length;
+                }
+                potential.clear();
+                block_length = 0;
+                flipped = false;
+            }
+
+            let Some(matches) = matches else {
+                block_kind = None;
+                n += 1;
+                continue;
+            };
+
+            if self.mode == ColorMoved::Plain {
+                self.marks[n].moved = true;
+                n += 1;
+                continue;
+            }
+
+            potential = potential
+                .iter()
+                .filter_map(|x| self.next_line[*x])
+                .filter(|x| self.ids[*x] == self.ids[n])
+                .collect();
+
+            if potential.is_empty() {
+                let contiguous = self.adjust_last_block(n, block_length);
+                if !contiguous && block_length > 1 {
+                    n -= block_length;
+                } else {
+                    potential = matches;
+                }
+
+                flipped = contiguous && !potential.is_empty() && block_kind == kind && !flipped;
+                block_kind = match potential.is_empty() {
+                    true => None,
+                    false => kind,
+                };
+                block_length = 0;
+            }
+
+            if !potential.is_empty() {
+                block_length += 1;
+                self.marks[n].moved = true;
+                self.marks[n].alternate = flipped && self.mode != ColorMoved::Blocks;
+            }
+            n += 1;
+        }
+
+        self.adjust_last_block(self.symbols.len(), block_length);
+    }
+
+    /// Dims the moved lines that are not at the edge of a block.
+    fn dim(&mut self) {
+        let is_line = |n: usize| line(&self.symbols[n]).is_some();
+
+        for n in 0..self.symbols.len() {
+            let mark = self.marks[n];
+            if !is_line(n) || !mark.moved {
+                continue;
+            }
+
+            let previous = n
+                .checked_sub(1)
+                .filter(|x| is_line(*x))
+                .map(|x| self.marks[x]);
+            let next = Some(n + 1)
+                .filter(|x| *x < self.symbols.len() && is_line(*x))
+                .map(|x| self.marks[x]);
+            let zebra = |x: Mark| (x.moved, x.alternate);
+
+            let inside = previous.is_some_and(|x| zebra(x) == zebra(mark))
+                && next.is_some_and(|x| zebra(x) == zebra(mark));
+            let edge =
+                |x: Option<Mark>| x.is_some_and(|x| x.moved && x.alternate != mark.alternate);
+            if inside || (!edge(previous) && !edge(next)) {
+                self.marks[n].dim = true;
+            }
+        }
+    }
+}
+
+/// Marks the deleted and added lines of `symbols` that were moved.
+pub fn mark(symbols: &[Symbol], mode: ColorMoved) -> Vec<Mark> {
+    let mut moved = Moved {
+        symbols,
+        mode,
+        marks: vec![Mark::default(); symbols.len()],
+        ids: vec![0; symbols.len()],
+        next_line: vec![None; symbols.len()],
+        deleted: Vec::new(),
+        added: Vec::new(),
+    };
+
+    moved.index();
+    moved.mark();
+    if mode == ColorMoved::DimmedZebra {
+        moved.dim();
+    }
+
+    moved.marks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mark, Mark};
+    use crate::git::diff::{display::ColorMoved, patch::Symbol};
+
+    fn moved(marks: &[Mark]) -> Vec<bool> {
+        marks.iter().map(|x| x.moved).collect()
+    }
+
+    #[test]
+    fn blocks_need_enough_characters() {
+        let long = b"a line long enough to count\n".to_vec();
+        let symbols = [
+            Symbol::Deleted(long.clone()),
+            Symbol::Deleted(b"}\n".to_vec()),
+            Symbol::Context(b"\n".to_vec()),
+            Symbol::Added(long, false),
+            Symbol::Added(b"}\n".to_vec(), false),
+        ];
+        assert_eq!(
+            moved(&mark(&symbols, ColorMoved::Zebra)),
+            vec![true, true, false, true, true]
+        );
+
+        let short = [
+            Symbol::Deleted(b"}\n".to_vec()),
+            Symbol::Context(b"\n".to_vec()),
+            Symbol::Added(b"}\n".to_vec(), false),
+        ];
+        assert_eq!(
+            moved(&mark(&short, ColorMoved::Zebra)),
+            vec![false, false, false]
+        );
+        assert_eq!(
+            moved(&mark(&short, ColorMoved::Plain)),
+            vec![true, false, true]
+        );
+    }
+
+    #[test]
+    fn adjacent_blocks_alternate() {
+        let (one, two) = (
+            b"the first block of moved text\n".to_vec(),
+            b"the second block of moved text\n".to_vec(),
+        );
+        let symbols = [
+            Symbol::Deleted(one.clone()),
+            Symbol::Context(b"\n".to_vec()),
+            Symbol::Deleted(two.clone()),
+            Symbol::Context(b"\n".to_vec()),
+            Symbol::Added(two, false),
+            Symbol::Added(one, false),
+        ];
+        let marks = mark(&symbols, ColorMoved::Zebra);
+        assert!(marks[4].moved && !marks[4].alternate);
+        assert!(marks[5].moved && marks[5].alternate);
+
+        let marks = mark(&symbols, ColorMoved::Blocks);
+        assert!(!marks[5].alternate);
+    }
+}
@@ -0,0 +1,475 @@
+//! Myers' O(ND) difference algorithm, searching from both ends for the
+//! middle snake and splitting there, with the heuristics of git's xdiff to
+//! bound its cost on large, very different inputs.
+
+/// Cost after which the search settles for the furthest reaching path.
+const MAX_COST_MIN: i64 = 256;
+/// Cost after which a long enough snake is taken as a split point.
+const HEUR_MIN_COST: i64 = 256;
+/// Length of a snake considered long enough.
+const SNAKE_CNT: i64 = 20;
+const K_HEUR: i64 = 4;
+/// Lines occurring more than this often are not worth matching.
+const MAX_EQLIMIT: i64 = 1024;
+/// Lines scanned around a line that occurs often to decide whether to skip it.
+const SIMSCAN_WINDOW: i64 = 100;
+const KPDIS_RUN: i64 = 4;
+
+/// Integer square root rounded up to a power of two.
+fn bogosqrt(mut n: i64) -> i64 {
+    let mut i = 1;
+    while n > 0 {
+        i <<= 1;
+        n >>= 2;
+    }
+    i
+}
+
+/// Whether a line occurring often, at `i` in a run of lines unmatched or
+/// occurring often too, should be left out of the search.
+fn clean_mmatch(dis: &[u8], i: i64, mut s: i64, mut e: i64) -> bool {
+    let at = |x: i64| dis[x as usize];
+    s = s.max(i - SIMSCAN_WINDOW);
+    e = e.min(i + SIMSCAN_WINDOW);
+
+    let (mut rdis0, mut rpdis0) = (0, 1);
+    let mut r = 1;
+    while i - r >= s {
+        match at(i - r) {
+            0 => rdis0 += 1,
+            2 => rpdis0 += 1,
+            _ => break,
+        }
+        r += 1;
+    }
+    if rdis0 == 0 {
+        return false;
+    }
+
+    let (mut rdis1, mut rpdis1) = (0, 1);
+    r = 1;
+    while i + r <= e {
+        match at(i + r) {
+            0 => rdis1 += 1,
+            2 => rpdis1 += 1,
+            _ => break,
+        }
+        r += 1;
+    }
+    if rdis1 == 0 {
+        return false;
+    }
+
+    rdis1 += rdis0;
+    rpdis1 += rpdis0;
+    rpdis1 * KPDIS_RUN < rpdis1 + rdis1
+}
+
+/// The lines of one file taking part in the search.
+struct Records {
+    /// Classes of the lines searched.
+    ha: Vec<usize>,
+    /// Line number of each of them.
+    rindex: Vec<usize>,
+    changed: Vec<bool>,
+}
+
+struct Split {
+    i1: i64,
+    i2: i64,
+    min_lo: bool,
+    min_hi: bool,
+}
+
+struct Search<'a> {
+    ha1: &'a [usize],
+    ha2: &'a [usize],
+    /// Furthest reaching forward and backward paths per diagonal, offset by
+    /// `offset` so that negative diagonals fit.
+    kvdf: Vec<i64>,
+    kvdb: Vec<i64>,
+    offset: i64,
+    mxcost: i64,
+}
+
+impl Search<'_> {
+    fn same(&self, i1: i64, i2: i64) -> bool {
+        self.ha1[i1 as usize] == self.ha2[i2 as usize]
+    }
+
+    fn f(&self, d: i64) -> i64 {
+        self.kvdf[(d + self.offset) as usize]
+    }
+
+    fn set_f(&mut self, d: i64, value: i64) {
+        self.kvdf[(d + self.offset) as usize] = value;
+    }
+
+    fn b(&self, d: i64) -> i64 {
+        self.kvdb[(d + self.offset) as usize]
+    }
+
+    fn set_b(&mut self, d: i64, value: i64) {
+        self.kvdb[(d + self.offset) as usize] = value;
+    }
+
+    /// Finds where to split the box `off1..lim1` × `off2..lim2`, ideally on
+    /// the middle snake of a shortest edit script.
+    fn split(&mut self, off1: i64, lim1: i64, off2: i64, lim2: i64, need_min: bool) -> Split {
+        let (dmin, dmax) = (off1 - lim2, lim1 - off2);
+        let (fmid, bmid) = (off1 - off2, lim1 - lim2);
+        let odd = (fmid - bmid) & 1 != 0;
+        let (mut fmin, mut fmax) = (fmid, fmid);
+        let (mut bmin, mut bmax) = (bmid, bmid);
+
+        self.set_f(fmid, off1);
+        self.set_b(bmid, lim1);
+
+        let mut ec = 1;
+        loop {
+            let mut got_snake = false;
+
+            if fmin > dmin {
+                fmin -= 1;
+                self.set_f(fmin - 1, -1);
+            } else {
+                fmin += 1;
+            }
+            if fmax < dmax {
+                fmax += 1;
+                self.set_f(fmax + 1, -1);
+            } else {
+                fmax -= 1;
+            }
+
+            let mut d = fmax;
+            while d >= fmin {
+                let mut i1 = match self.f(d - 1) >= self.f(d + 1) {
+                    true => self.f(d - 1) + 1,
+                    false => self.f(d + 1),
+                };
+                let prev1 = i1;
+                let mut i2 = i1 - d;
+                while i1 < lim1 && i2 < lim2 && self.same(i1, i2) {
+                    i1 += 1;
+                    i2 += 1;
+                }
+                if i1 - prev1 > SNAKE_CNT {
+                    got_snake = true;
+                }
+                self.set_f(d, i1);
+                if odd && bmin <= d && d <= bmax && self.b(d) <= i1 {
+                    return Split {
+                        i1,
+                        i2,
+                        min_lo: true,
+                        min_hi: true,
+                    };
+                }
+                d -= 2;
+            }
+
+            if bmin > dmin {
+                bmin -= 1;
+                self.set_b(bmin - 1, i64::MAX);
+            } else {
+                bmin += 1;
+            }
+            if bmax < dmax {
+                bmax += 1;
+                self.set_b(bmax + 1, i64::MAX);
+            } else {
+                bmax -= 1;
+            }
+
+            let mut d = bmax;
+            while d >= bmin {
+                let mut i1 = match self.b(d - 1) < self.b(d + 1) {
+                    true => self.b(d - 1),
+                    false => self.b(d + 1) - 1,
+                };
+                let prev1 = i1;
+                let mut i2 = i1 - d;
+                while i1 > off1 && i2 > off2 && self.same(i1 - 1, i2 - 1) {
+                    i1 -= 1;
+                    i2 -= 1;
+                }
+                if prev1 - i1 > SNAKE_CNT {
+                    got_snake = true;
+                }
+                self.set_b(d, i1);
+                if !odd && fmin <= d && d <= fmax && i1 <= self.f(d) {
+                    return Split {
+                        i1,
+                        i2,
+                        min_lo: true,
+                        min_hi: true,
+                    };
+                }
+                d -= 2;
+            }
+
+            if need_min {
+                ec += 1;
+                continue;
+            }
+
+            // past some cost, settle for a diagonal that made good progress
+            // and ends in a long enough snake
+            if got_snake && ec > HEUR_MIN_COST {
+                let mut best = 0;
+                let mut found = None;
+                let mut d = fmax;
+                while d >= fmin {
+                    let dd = (d - fmid).abs();
+                    let i1 = self.f(d);
+                    let i2 = i1 - d;
+                    let v = (i1 - off1) + (i2 - off2) - dd;
+
+                    if v > K_HEUR * ec
+                        && v > best
+                        && off1 + SNAKE_CNT <= i1
+                        && i1 < lim1
+                        && off2 + SNAKE_CNT <= i2
+                        && i2 < lim2
+                    {
+                        let mut k = 1;
+                        while self.same(i1 - k, i2 - k) {
+                            if k == SNAKE_CNT {
+                                best = v;
+                                found = Some((i1, i2));
+                                break;
+                            }
+                            k += 1;
+                        }
+                    }
+                    d -= 2;
+                }
+                if let Some((i1, i2)) = found {
+                    return Split {
+                        i1,
+                        i2,
+                        min_lo: true,
+                        min_hi: false,
+                    };
+                }
+
+                let mut best = 0;
+                let mut d = bmax;
+                while d >= bmin {
+                    let dd = (d - bmid).abs();
+                    let i1 = self.b(d);
+                    let i2 = i1 - d;
+                    let v = (lim1 - i1) + (lim2 - i2) - dd;
+
+                    if v > K_HEUR * ec
+                        && v > best
+                        && off1 < i1
+                        && i1 <= lim1 - SNAKE_CNT
+                        && off2 < i2
+                        && i2 <= lim2 - SNAKE_CNT
+                    {
+                        let mut k = 0;
+                        while self.same(i1 + k, i2 + k) {
+                            if k == SNAKE_CNT - 1 {
+                                best = v;
+                                found = Some((i1, i2));
+                                break;
+                            }
+                            k += 1;
+                        }
+                    }
+                    d -= 2;
+                }
+                if let Some((i1, i2)) = found {
+                    return Split {
+                        i1,
+                        i2,
+                        min_lo: false,
+                        min_hi: true,
+                    };
+                }
+            }
+
+            // enough is enough, take the furthest reaching path
+            if ec >= self.mxcost {
+                let (mut fbest, mut fbest1) = (-1, -1);
+                let mut d = fmax;
+                while d >= fmin {
+                    let mut i1 = self.f(d).min(lim1);
+                    let mut i2 = i1 - d;
+                    if lim2 < i2 {
+                        i1 = lim2 + d;
+                        i2 = lim2;
+                    }
+                    if fbest < i1 + i2 {
+                        fbest = i1 + i2;
+                        fbest1 = i1;
+                    }
+                    d -= 2;
+                }
+
+                let (mut bbest, mut bbest1) = (i64::MAX, i64::MAX);
+                let mut d = bmax;
+                while d >= bmin {
+                    let mut i1 = off1.max(self.b(d));
+                    let mut i2 = i1 - d;
+                    if i2 < off2 {
+                        i1 = off2 + d;
+                        i2 = off2;
+                    }
+                    if i1 + i2 < bbest {
+                        bbest = i1 + i2;
+                        bbest1 = i1;
+                    }
+                    d -= 2;
+                }
+
+                return match (lim1 + lim2) - bbest < fbest - (off1 + off2) {
+                    true => Split {
+                        i1: fbest1,
+                        i2: fbest - fbest1,
+                        min_lo: true,
+                        min_hi: false,
+                    },
+                    false => Split {
+                        i1: bbest1,
+                        i2: bbest - bbest1,
+                        min_lo: false,
+                        min_hi: true,
+                    },
+                };
+            }
+
+            ec += 1;
+        }
+    }
+}
+
+/// Leaves out of the search the lines that cannot match and the lines
+/// occurring so often that matching them would only slow it down, after
+/// skipping the common head and tail. Like git, this holds for minimal
+/// diffs too.
+fn prepare(one: &[usize], two: &[usize]) -> (Records, Records) {
+    let classes = one.iter().chain(two.iter()).max().map_or(0, |x| x + 1);
+    let (mut len1, mut len2) = (vec![0i64; classes], vec![0i64; classes]);
+    for &class in one.iter() {
+        len1[class] += 1;
+    }
+    for &class in two.iter() {
+        len2[class] += 1;
+    }
+
+    let mut start = 0;
+    while start < one.len().min(two.len()) && one[start] == two[start] {
+        start += 1;
+    }
+    let mut tail = 0;
+    while tail < one.len().min(two.len()) - start
+        && one[one.len() - 1 - tail] == two[two.len() - 1 - tail]
+    {
+        tail += 1;
+    }
+
+    let records = |lines: &[usize], counts: &[i64]| {
+        let end = lines.len() - tail;
+        let mlim = bogosqrt(lines.len() as i64).min(MAX_EQLIMIT);
+        let mut dis = vec![0u8; lines.len() + 1];
+        for i in start..end {
+            dis[i] = match counts[lines[i]] {
+                0 => 0,
+                n if n >= mlim => 2,
+                _ => 1,
+            };
+        }
+
+        let mut records = Records {
+            ha: Vec::new(),
+            rindex: Vec::new(),
+            changed: vec![false; lines.len()],
+        };
+        for i in start..end {
+            let keep = dis[i] == 1
+                || (dis[i] == 2 && !clean_mmatch(&dis, i as i64, start as i64, end as i64 - 1));
+            match keep {
+                true => {
+                    records.rindex.push(i);
+                    records.ha.push(lines[i]);
+                }
+                false => records.changed[i] = true,
+            }
+        }
+        records
+    };
+
+    (records(one, &len2), records(two, &len1))
+}
+
+/// Compares two files given as line classes, returning which lines of
+/// each are changed. `minimal` disables the cost-bounding heuristics.
+pub fn diff(one: &[usize], two: &[usize], minimal: bool) -> (Vec<bool>, Vec<bool>) {
+    let (mut rec1, mut rec2) = prepare(one, two);
+    let (n1, n2) = (rec1.ha.len() as i64, rec2.ha.len() as i64);
+    let ndiags = n1 + n2 + 3;
+
+    let mut search = Search {
+        ha1: &rec1.ha,
+        ha2: &rec2.ha,
+        kvdf: vec![0; ndiags as usize],
+        kvdb: vec![0; ndiags as usize],
+        offset: n2 + 1,
+        mxcost: bogosqrt(ndiags).max(MAX_COST_MIN),
+    };
+
+    let mut changed1 = Vec::new();
+    let mut changed2 = Vec::new();
+    let mut boxes = vec![(0, n1, 0, n2, minimal)];
+    while let Some((mut off1, mut lim1, mut off2, mut lim2, need_min)) = boxes.pop() {
+        while off1 < lim1 && off2 < lim2 && search.same(off1, off2) {
+            off1 += 1;
+            off2 += 1;
+        }
+        while off1 < lim1 && off2 < lim2 && search.same(lim1 - 1, lim2 - 1) {
+            lim1 -= 1;
+            lim2 -= 1;
+        }
+
+        if off1 == lim1 {
+            changed2.extend(off2..lim2);
+        } else if off2 == lim2 {
+            changed1.extend(off1..lim1);
+        } else {
+            let split = search.split(off1, lim1, off2, lim2, need_min);
+            boxes.push((split.i1, lim1, split.i2, lim2, split.min_hi));
+            boxes.push((off1, split.i1, off2, split.i2, split.min_lo));
+        }
+    }
+
+    for i in changed1 {
+        rec1.changed[rec1.rindex[i as usize]] = true;
+    }
+    for i in changed2 {
+        rec2.changed[rec2.rindex[i as usize]] = true;
+    }
+
+    (rec1.changed, rec2.changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bogosqrt, diff};
+
+    #[test]
+    fn square_root_approximation() {
+        assert_eq!(bogosqrt(0), 1);
+        assert_eq!(bogosqrt(16), 8);
+        assert_eq!(bogosqrt(1000), 32);
+    }
+
+    #[test]
+    fn marks_changed_lines() {
+        let (one, two) = diff(&[0, 1, 2, 3], &[0, 2, 4, 3], false);
+        assert_eq!(one, vec![false, true, false, false]);
+        assert_eq!(two, vec![false, false, true, false]);
+    }
+}
@@ -0,0 +1,260 @@
+use super::{
+    content, display, is_binary, lines,
+    stat::{self, FileStat},
+    unified, Change, FileSpec, Status,
+};
+use crate::git::objects;
+
+const TYPE_MASK: u32 = 0o170000;
+const TREE: u32 = 0o040000;
+/// Shortest object name in `index` lines.
+const DEFAULT_ABBREV: usize = 7;
+
+/// How content changes are compared and shown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    pub lines: lines::Options,
+    pub unified: unified::Options,
+    /// Full object names in `index` lines.
+    pub full_index: bool,
+}
+
+/// A line of patch output, before [`display`] decides how it looks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Symbol {
+    /// `diff --git` and the extended header lines.
+    Meta(String),
+    /// `--- a/<path>` and `+++ b/<path>`.
+    Label(String),
+    /// `Binary files ... differ`.
+    Binary(String),
+    /// The `@@ -a,b +c,d @@ function` line.
+    Hunk(Vec<u8>),
+    /// Lines of a hunk without their sign, always ending with a newline: a
+    /// missing one is added back and marked by a [`Symbol::NoNewline`].
+    Context(Vec<u8>),
+    Deleted(Vec<u8>),
+    /// An added line, and whether it is one of the blank lines added at the
+    /// end of the file.
+    Added(Vec<u8>, bool),
+    /// `\ No newline at end of file`, after the line missing it.
+    NoNewline,
+}
+
+fn read(spec: Option<&FileSpec>) -> std::io::Result<Vec<u8>> {
+    spec.map_or(Ok(Vec::new()), content)
+}
+
+/// Shortest unambiguous name of at least [`DEFAULT_ABBREV`] digits.
+pub fn abbrev(hash: &[u8; 20], full: bool) -> std::io::Result<String> {
+    let name = hex::encode(hash);
+    if full {
+        return Ok(name);
+    }
+    if *hash == [0; 20] {
+        return Ok(name[..DEFAULT_ABBREV].to_string());
+    }
+
+    let mut len = DEFAULT_ABBREV;
+    while len < name.len() && objects::find_abbrev(&name[..len])?.len() > 1 {
+        len += 1;
+    }
+    Ok(name[..len].to_string())
+}
+
+/// The `diff --git` line and the extended header lines below it, and
+/// whether they must be shown even without hunks.
+fn header(
+    change: &Change,
+    one: Option<&FileSpec>,
+    two: Option<&FileSpec>,
+    options: &Options,
+) -> std::io::Result<(String, bool)> {
+    let old_path = change.old.as_ref().map_or(change.path(), |x| &x.path);
+    let mut header = format!("diff --git a/{old_path} b/{}\n", change.path());
+    let mut must_show = true;
+
+    match (one, two) {
+        (None, Some(two)) => header += &format!("new file mode {:06o}\n", two.mode),
+        (Some(one), None) => header += &format!("deleted file mode {:06o}\n", one.mode),
+        (Some(one), Some(two)) if one.mode != two.mode => {
+            header += &format!("old mode {:06o}\nnew mode {:06o}\n", one.mode, two.mode);
+        }
+        _ => must_show = false,
+    }
+
+    let verb = match change.status {
+        Status::Renamed => Some("rename"),
+        Status::Copied => Some("copy"),
+        _ => None,
+    };
+    if let Some(verb) = verb {
+        header += &format!(
+            "similarity index {}%\n{verb} from {old_path}\n{verb} to {}\n",
+            change.similarity(),
+            change.path()
+        );
+        must_show = true;
+    }
+
+    let side = |x: Option<&FileSpec>| x.map_or(([0; 20], 0), |x| (x.hash, x.mode));
+    let ((old_hash, old_mode), (new_hash, new_mode)) = (side(one), side(two));
+    if old_hash != new_hash {
+        header += &format!(
+            "index {}..{}",
+            abbrev(&old_hash, options.full_index)?,
+            abbrev(&new_hash, options.full_index)?
+        );
+        if old_mode == new_mode {
+            header += &format!(" {old_mode:06o}");
+        }
+        header.push('\n');
+    }
+
+    Ok((header, must_show))
+}
+
+/// Symbols of the hunks, marking blank lines added at the end of the file
+/// the way git counts lines: from the start of the hunk header, each line
+/// counted before it is looked at.
+fn push_hunks(
+    symbols: &mut Vec<Symbol>,
+    hunks: &[unified::Hunk],
+    blank_at_eof: Option<(usize, usize)>,
+) {
+    for hunk in hunks {
+        symbols.push(Symbol::Hunk(hunk.header()));
+        let (mut old, mut new) = (hunk.old_start, hunk.new_start);
+
+        for line in hunk.lines.iter() {
+            let mut text = line.text.to_vec();
+            if !text.ends_with(b"\n") {
+                text.push(b'\n');
+            }
+            match line.origin {
+                b'-' => {
+                    old += 1;
+                    symbols.push(Symbol::Deleted(text));
+                }
+                b'+' => {
+                    new += 1;
+                    let at_eof = blank_at_eof.is_some_and(|(one, two)| {
+                        one <= old && two <= new && display::is_blank(&text)
+                    });
+                    symbols.push(Symbol::Added(text, at_eof));
+                }
+                _ => {
+                    old += 1;
+                    new += 1;
+                    symbols.push(Symbol::Context(text));
+                }
+            }
+            if !line.text.ends_with(b"\n") {
+                symbols.push(Symbol::NoNewline);
+            }
+        }
+    }
+}
+
+fn pair_symbols(
+    change: &Change,
+    one: Option<&FileSpec>,
+    two: Option<&FileSpec>,
+    options: &Options,
+) -> std::io::Result<Vec<Symbol>> {
+    let (header, must_show) = header(change, one, two, options)?;
+    let header = header.lines().map(|x| Symbol::Meta(x.to_string()));
+    let old_label = one.map_or("/dev/null".to_string(), |x| format!("a/{}", x.path));
+    let new_label = two.map_or("/dev/null".to_string(), |x| format!("b/{}", x.path));
+    let (old, new) = (read(one)?, read(two)?);
+
+    if is_binary(&old) || is_binary(&new) {
+        let mut symbols = Vec::new();
+        if old != new || must_show {
+            symbols.extend(header);
+        }
+        if old != new {
+            symbols.push(Symbol::Binary(format!(
+                "Binary files {old_label} and {new_label} differ"
+            )));
+        }
+        return Ok(symbols);
+    }
+
+    let blank_at_eof = display::blank_at_eof(&old, &new);
+    let (old, new) = (lines::split(&old), lines::split(&new));
+    let edits = lines::diff(&old, &new, &options.lines);
+    let hunks = unified::hunks(&old, &new, &edits, &options.unified);
+    if hunks.is_empty() {
+        return Ok(match must_show {
+            true => header.collect(),
+            false => Vec::new(),
+        });
+    }
+
+    let mut symbols: Vec<Symbol> = header.collect();
+    symbols.push(Symbol::Label(format!("--- {old_label}")));
+    symbols.push(Symbol::Label(format!("+++ {new_label}")));
+    push_hunks(&mut symbols, &hunks, blank_at_eof);
+
+    Ok(symbols)
+}
+
+/// Whether a change has content to show, trees listed by `-t` having none.
+pub fn has_content(change: &Change) -> bool {
+    let sides = [&change.old, &change.new];
+    !sides
+        .iter()
+        .any(|x| x.as_ref().is_some_and(|x| x.mode & TYPE_MASK == TREE))
+}
+
+/// The patch of a change in git's format. A path switching between file,
+/// symlink and submodule shows as a deletion and an addition.
+pub fn symbols(change: &Change, options: &Options) -> std::io::Result<Vec<Symbol>> {
+    let (one, two) = (change.old.as_ref(), change.new.as_ref());
+    match (one, two) {
+        _ if !has_content(change) => Ok(Vec::new()),
+        (Some(old), Some(new)) if (old.mode ^ new.mode) & TYPE_MASK != 0 => {
+            let mut symbols = pair_symbols(change, one, None, options)?;
+            symbols.extend(pair_symbols(change, None, two, options)?);
+            Ok(symbols)
+        }
+        _ => pair_symbols(change, one, two, options),
+    }
+}
+
+/// Lines added and deleted by a change, for `--stat` and `--numstat`.
+pub fn file_stat(change: &Change, options: &Options) -> std::io::Result<FileStat> {
+    let name = match (change.status, &change.old) {
+        (Status::Renamed | Status::Copied, Some(old)) => {
+            stat::rename_name(&old.path, change.path())
+        }
+        _ => change.path().to_string(),
+    };
+    let (old, new) = (read(change.old.as_ref())?, read(change.new.as_ref())?);
+
+    if is_binary(&old) || is_binary(&new) {
+        let changed = old != new;
+        return Ok(FileStat {
+            name,
+            added: if changed { new.len() } else { 0 },
+            deleted: if changed { old.len() } else { 0 },
+            binary: true,
+        });
+    }
+
+    let (old, new) = (lines::split(&old), lines::split(&new));
+    let edits = lines::diff(&old, &new, &options.lines);
+    let context = unified::Options {
+        function_context: false,
+        ..options.unified
+    };
+    let (added, deleted) = stat::count(&unified::hunks(&old, &new, &edits, &context));
+
+    Ok(FileStat {
+        name,
+        added,
+        deleted,
+        binary: false,
+    })
+}
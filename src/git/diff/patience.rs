@@ -0,0 +1,218 @@
+//! Patience diff: lines unique on both sides are matched along their
+//! longest common subsequence, then the gaps between them are compared
+//! recursively, falling back to Myers when no unique line is shared.
+
+use std::collections::HashMap;
+
+use super::myers;
+
+/// `line2` of a line that occurs more than once on either side.
+const NON_UNIQUE: usize = usize::MAX;
+
+/// A distinct line of the first range, numbered from one.
+struct Entry {
+    line1: usize,
+    /// Its only occurrence in the second range, 0 when there is none.
+    line2: usize,
+    next: Option<usize>,
+    previous: Option<usize>,
+}
+
+struct Patience<'a> {
+    one: &'a [usize],
+    two: &'a [usize],
+    changed1: Vec<bool>,
+    changed2: Vec<bool>,
+}
+
+impl Patience<'_> {
+    fn same(&self, line1: usize, line2: usize) -> bool {
+        self.one[line1 - 1] == self.two[line2 - 1]
+    }
+
+    /// Distinct lines of the first range in order, and whether any line is
+    /// shared at all.
+    fn entries(
+        &self,
+        line1: usize,
+        count1: usize,
+        line2: usize,
+        count2: usize,
+    ) -> (Vec<Entry>, bool) {
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut index: HashMap<usize, usize> = HashMap::new();
+        let mut has_matches = false;
+
+        for line in line1..line1 + count1 {
+            match index.get(&self.one[line - 1]) {
+                Some(&i) => entries[i].line2 = NON_UNIQUE,
+                None => {
+                    index.insert(self.one[line - 1], entries.len());
+                    entries.push(Entry {
+                        line1: line,
+                        line2: 0,
+                        next: None,
+                        previous: entries.len().checked_sub(1),
+                    });
+                }
+            }
+        }
+        for i in 1..entries.len() {
+            entries[i - 1].next = Some(i);
+        }
+
+        for line in line2..line2 + count2 {
+            if let Some(&i) = index.get(&self.two[line - 1]) {
+                has_matches = true;
+                entries[i].line2 = match entries[i].line2 {
+                    0 => line,
+                    _ => NON_UNIQUE,
+                };
+            }
+        }
+
+        (entries, has_matches)
+    }
+
+    fn mark(&mut self, line1: usize, count1: usize, line2: usize, count2: usize) {
+        self.changed1[line1 - 1..line1 - 1 + count1].fill(true);
+        self.changed2[line2 - 1..line2 - 1 + count2].fill(true);
+    }
+
+    fn diff(&mut self, line1: usize, count1: usize, line2: usize, count2: usize) {
+        if count1 == 0 || count2 == 0 {
+            self.mark(line1, count1, line2, count2);
+            return;
+        }
+
+        let (mut entries, has_matches) = self.entries(line1, count1, line2, count2);
+        if !has_matches {
+            self.mark(line1, count1, line2, count2);
+            return;
+        }
+
+        match longest_common_sequence(&mut entries) {
+            Some(first) => self.walk(&entries, first, line1, count1, line2, count2),
+            None => {
+                let (one, two) = myers::diff(
+                    &self.one[line1 - 1..line1 - 1 + count1],
+                    &self.two[line2 - 1..line2 - 1 + count2],
+                    false,
+                );
+                self.changed1[line1 - 1..line1 - 1 + count1].copy_from_slice(&one);
+                self.changed2[line2 - 1..line2 - 1 + count2].copy_from_slice(&two);
+            }
+        }
+    }
+
+    /// Compares the gaps between the unique lines of the sequence starting
+    /// at `first`, after growing each match over equal neighbours.
+    fn walk(
+        &mut self,
+        entries: &[Entry],
+        first: usize,
+        mut line1: usize,
+        count1: usize,
+        mut line2: usize,
+        count2: usize,
+    ) {
+        let (end1, end2) = (line1 + count1, line2 + count2);
+        let mut first = Some(first);
+
+        loop {
+            let (mut next1, mut next2) = match first {
+                Some(entry) => (entries[entry].line1, entries[entry].line2),
+                None => (end1, end2),
+            };
+            if first.is_some() {
+                while next1 > line1 && next2 > line2 && self.same(next1 - 1, next2 - 1) {
+                    next1 -= 1;
+                    next2 -= 1;
+                }
+            }
+            while line1 < next1 && line2 < next2 && self.same(line1, line2) {
+                line1 += 1;
+                line2 += 1;
+            }
+
+            if next1 > line1 || next2 > line2 {
+                self.diff(line1, next1 - line1, line2, next2 - line2);
+            }
+
+            let Some(mut entry) = first else {
+                return;
+            };
+            while let Some(next) = entries[entry].next {
+                if entries[next].line1 != entries[entry].line1 + 1
+                    || entries[next].line2 != entries[entry].line2 + 1
+                {
+                    break;
+                }
+                entry = next;
+            }
+
+            line1 = entries[entry].line1 + 1;
+            line2 = entries[entry].line2 + 1;
+            first = entries[entry].next;
+        }
+    }
+}
+
+/// Links the longest sequence of unique lines in increasing order on both
+/// sides through `next`, returning its first entry.
+fn longest_common_sequence(entries: &mut [Entry]) -> Option<usize> {
+    let mut sequence: Vec<usize> = Vec::new();
+    let mut entry = (!entries.is_empty()).then_some(0);
+
+    while let Some(current) = entry {
+        entry = entries[current].next;
+        let line2 = entries[current].line2;
+        if line2 == 0 || line2 == NON_UNIQUE {
+            continue;
+        }
+
+        // longest sequence ending before this line on the second side
+        let position = sequence.partition_point(|x| entries[*x].line2 <= line2);
+        entries[current].previous = position.checked_sub(1).map(|x| sequence[x]);
+        match position == sequence.len() {
+            true => sequence.push(current),
+            false => sequence[position] = current,
+        }
+    }
+
+    let mut entry = *sequence.last()?;
+    entries[entry].next = None;
+    while let Some(previous) = entries[entry].previous {
+        entries[previous].next = Some(entry);
+        entry = previous;
+    }
+
+    Some(entry)
+}
+
+/// Compares two files given as line classes, returning which lines of
+/// each are changed.
+pub fn diff(one: &[usize], two: &[usize]) -> (Vec<bool>, Vec<bool>) {
+    let mut patience = Patience {
+        one,
+        two,
+        changed1: vec![false; one.len()],
+        changed2: vec![false; two.len()],
+    };
+    patience.diff(1, one.len(), 1, two.len());
+
+    (patience.changed1, patience.changed2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff;
+
+    #[test]
+    fn anchors_on_unique_lines() {
+        // the braces repeat, the unique lines decide the alignment
+        let (one, two) = diff(&[0, 1, 2, 0, 3, 2], &[0, 3, 2, 0, 1, 2]);
+        assert_eq!(one.iter().filter(|x| **x).count(), 3);
+        assert_eq!(two.iter().filter(|x| **x).count(), 3);
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use super::{content, is_binary, Change, FileSpec, Status};
+
+/// Score of identical content, similarities are fractions of it.
+pub const MAX_SCORE: u32 = 60000;
+/// Similarity needed when `-M`/`-C` are given without a number: 50%.
+pub const DEFAULT_SCORE: u32 = MAX_SCORE / 2;
+
+/// Inexact candidates kept per destination.
+const CANDIDATES_PER_DST: usize = 4;
+const HASH_BASE: u32 = 107927;
+const MODE_FILE_TYPE: u32 = 0o100000;
+
+/// Which renames and copies [`detect`] looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    /// Minimum similarity of an inexact match, out of [`MAX_SCORE`].
+    pub minimum_score: u32,
+    /// Modified files may be the source of copies too (`-C`).
+    pub copies: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            minimum_score: DEFAULT_SCORE,
+            copies: false,
+        }
+    }
+}
+
+/// Parses the number of `-M<n>`/`-C<n>`: `50%`, or digits read as a
+/// fraction, so `5` is 50% and `05` is 5%.
+pub fn parse_score(value: &str) -> Option<u32> {
+    if value.is_empty() {
+        return Some(DEFAULT_SCORE);
+    }
+
+    let (digits, percent) = match value.strip_suffix('%') {
+        Some(digits) => (digits, true),
+        None => (value, false),
+    };
+    if digits.is_empty() || !digits.bytes().all(|x| x.is_ascii_digit()) {
+        return None;
+    }
+
+    let number: u64 = digits.parse().ok()?;
+    let scale = match percent {
+        true => 100,
+        false => 10u64.checked_pow(digits.len() as u32)?,
+    };
+
+    Some((number * MAX_SCORE as u64 / scale).min(MAX_SCORE as u64) as u32)
+}
+
+struct Source {
+    spec: FileSpec,
+    /// Destinations using it, plus one when the source itself stays.
+    used: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Candidate {
+    dst: usize,
+    src: usize,
+    score: u32,
+    same_name: bool,
+}
+
+fn is_regular(mode: u32) -> bool {
+    mode & 0o170000 == MODE_FILE_TYPE
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn same_basename(one: &FileSpec, two: &FileSpec) -> bool {
+    basename(&one.path) == basename(&two.path)
+}
+
+/// Bytes of content per chunk hash, a chunk ending at a newline or after
+/// 64 bytes. Like git, the CR of a CRLF in text is not counted.
+fn span_hashes(data: &[u8]) -> HashMap<u32, u64> {
+    let text = !is_binary(data);
+    let mut hashes = HashMap::new();
+    let (mut accum1, mut accum2, mut n) = (0u32, 0u32, 0u64);
+
+    for (i, &c) in data.iter().enumerate() {
+        if text && c == b'\r' && data.get(i + 1) == Some(&b'\n') {
+            continue;
+        }
+
+        let old = accum1;
+        accum1 = (accum1 << 7) ^ (accum2 >> 25);
+        accum2 = (accum2 << 7) ^ (old >> 25);
+        accum1 = accum1.wrapping_add(c as u32);
+        n += 1;
+
+        if n < 64 && c != b'\n' {
+            continue;
+        }
+        let hash = accum1.wrapping_add(accum2.wrapping_mul(0x61)) % HASH_BASE;
+        *hashes.entry(hash).or_default() += n;
+        (accum1, accum2, n) = (0, 0, 0);
+    }
+
+    if n > 0 {
+        let hash = accum1.wrapping_add(accum2.wrapping_mul(0x61)) % HASH_BASE;
+        *hashes.entry(hash).or_default() += n;
+    }
+
+    hashes
+}
+
+/// Bytes of `dst` that can be found in `src`.
+fn copied_bytes(src: &HashMap<u32, u64>, dst: &HashMap<u32, u64>) -> u64 {
+    src.iter()
+        .map(|(hash, count)| (*count).min(dst.get(hash).copied().unwrap_or_default()))
+        .sum()
+}
+
+struct Contents(HashMap<[u8; 20], (u64, HashMap<u32, u64>)>);
+
+impl Contents {
+    fn get(&mut self, spec: &FileSpec) -> std::io::Result<&(u64, HashMap<u32, u64>)> {
+        match self.0.entry(spec.hash) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let data = content(spec)?;
+                Ok(entry.insert((data.len() as u64, span_hashes(&data))))
+            }
+        }
+    }
+
+    fn similarity(&mut self, src: &FileSpec, dst: &FileSpec, minimum: u32) -> std::io::Result<u32> {
+        if !is_regular(src.mode) || !is_regular(dst.mode) {
+            return Ok(0);
+        }
+
+        let src_size = self.get(src)?.0;
+        let dst_size = self.get(dst)?.0;
+        let max_size = src_size.max(dst_size);
+        let delta = max_size - src_size.min(dst_size);
+
+        // the size difference alone rules out the minimum score
+        if max_size * ((MAX_SCORE - minimum) as u64) < delta * MAX_SCORE as u64 || dst_size == 0 {
+            return Ok(0);
+        }
+
+        let copied = copied_bytes(&self.get(src)?.1.clone(), &self.get(dst)?.1);
+        Ok((copied * MAX_SCORE as u64 / max_size) as u32)
+    }
+}
+
+/// Pairs added paths with deleted ones (and with modified and `unmodified`
+/// ones when looking for copies) holding identical or similar content.
+///
+/// Like git, identical content is matched first, preferring unused sources
+/// with the same file name, then the most similar pairs are taken. A
+/// deleted source used by several destinations is a rename for the last
+/// one in path order and a copy for the others.
+pub fn detect(
+    changes: Vec<Change>,
+    options: &Options,
+    unmodified: &[FileSpec],
+) -> std::io::Result<Vec<Change>> {
+    let mut sources: Vec<(Source, Option<usize>)> = Vec::new();
+    let mut dsts = Vec::new();
+
+    for (position, change) in changes.iter().enumerate() {
+        match (change.status, &change.old) {
+            (Status::Added, _) => dsts.push(position),
+            (Status::Deleted, Some(old)) => sources.push((
+                Source {
+                    spec: old.clone(),
+                    used: 0,
+                },
+                Some(position),
+            )),
+            (_, Some(old)) if options.copies => sources.push((
+                Source {
+                    spec: old.clone(),
+                    used: 1,
+                },
+                None,
+            )),
+            _ => {}
+        }
+    }
+    if options.copies {
+        sources.extend(unmodified.iter().map(|x| {
+            let source = Source {
+                spec: x.clone(),
+                used: 1,
+            };
+            (source, None)
+        }));
+    }
+    sources.sort_by(|(one, _), (two, _)| one.spec.path.cmp(&two.spec.path));
+
+    if dsts.is_empty() || sources.is_empty() {
+        return Ok(changes);
+    }
+
+    let dst_spec = |dst: usize| changes[dsts[dst]].new.as_ref().unwrap();
+    let mut renames: Vec<Option<(usize, u32)>> = vec![None; dsts.len()];
+
+    for (dst, rename) in renames.iter_mut().enumerate() {
+        let target = dst_spec(dst);
+        let mut best: Option<(usize, usize)> = None;
+
+        for (src, (source, _)) in sources.iter().enumerate() {
+            let spec = &source.spec;
+            if spec.hash != target.hash
+                || (!(is_regular(spec.mode) && is_regular(target.mode)) && spec.mode != target.mode)
+                || (source.used > 0 && !options.copies)
+            {
+                continue;
+            }
+
+            let score = (source.used == 0) as usize + same_basename(spec, target) as usize;
+            if best.is_none_or(|(_, x)| score > x) {
+                best = Some((src, score));
+                if score == 2 {
+                    break;
+                }
+            }
+        }
+
+        if let Some((src, _)) = best {
+            sources[src].0.used += 1;
+            *rename = Some((src, MAX_SCORE));
+        }
+    }
+
+    let mut contents = Contents(HashMap::new());
+    let mut candidates = Vec::new();
+    for (dst, rename) in renames.iter().enumerate() {
+        if rename.is_some() {
+            continue;
+        }
+
+        let target = dst_spec(dst);
+        let mut best = Vec::new();
+        for (src, (source, _)) in sources.iter().enumerate() {
+            let score = contents.similarity(&source.spec, target, options.minimum_score)?;
+            best.push(Candidate {
+                dst,
+                src,
+                score,
+                same_name: same_basename(&source.spec, target),
+            });
+        }
+        best.sort_by_key(|x| std::cmp::Reverse((x.score, x.same_name)));
+        best.truncate(CANDIDATES_PER_DST);
+        candidates.extend(best);
+    }
+    candidates.sort_by_key(|x| std::cmp::Reverse((x.score, x.same_name)));
+
+    // renames first, so that copies only take what renames left over
+    for copies in [false, true] {
+        if copies && !options.copies {
+            break;
+        }
+
+        for candidate in candidates.iter() {
+            if candidate.score < options.minimum_score {
+                break;
+            }
+            let source = &mut sources[candidate.src].0;
+            if renames[candidate.dst].is_some() || (!copies && source.used > 0) {
+                continue;
+            }
+            source.used += 1;
+            renames[candidate.dst] = Some((candidate.src, candidate.score));
+        }
+    }
+
+    let renamed: HashMap<usize, (usize, u32)> = renames
+        .iter()
+        .enumerate()
+        .filter_map(|(dst, x)| x.map(|x| (dsts[dst], x)))
+        .collect();
+    let consumed: Vec<usize> = sources
+        .iter()
+        .filter(|(x, _)| x.used > 0)
+        .filter_map(|(_, position)| *position)
+        .collect();
+
+    let mut result = Vec::new();
+    for (position, change) in changes.iter().enumerate() {
+        if consumed.contains(&position) {
+            continue;
+        }
+
+        let Some(&(src, score)) = renamed.get(&position) else {
+            result.push(change.clone());
+            continue;
+        };
+
+        let source = &mut sources[src].0;
+        source.used -= 1;
+        let status = match source.used {
+            0 => Status::Renamed,
+            _ => Status::Copied,
+        };
+        result.push(Change {
+            status,
+            old: Some(source.spec.clone()),
+            new: change.new.clone(),
+            score,
+        });
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{copied_bytes, parse_score, span_hashes, MAX_SCORE};
+
+    #[test]
+    fn score_notation() {
+        assert_eq!(parse_score(""), Some(MAX_SCORE / 2));
+        assert_eq!(parse_score("5"), Some(MAX_SCORE / 2));
+        assert_eq!(parse_score("05"), Some(MAX_SCORE / 20));
+        assert_eq!(parse_score("75%"), Some(MAX_SCORE * 3 / 4));
+        assert_eq!(parse_score("100%"), Some(MAX_SCORE));
+        assert_eq!(parse_score("x"), None);
+        assert_eq!(parse_score("%"), None);
+    }
+
+    #[test]
+    fn similar_content_shares_chunks() {
+        let one = span_hashes(b"a\nb\nc\nd\n");
+        let two = span_hashes(b"a\nb\nx\nd\n");
+        assert_eq!(copied_bytes(&one, &two), 6);
+        assert_eq!(copied_bytes(&one, &one), 8);
+    }
+
+    #[test]
+    fn crlf_counts_like_lf() {
+        let unix = span_hashes(b"line\nother\n");
+        let dos = span_hashes(b"line\r\nother\r\n");
+        assert_eq!(unix, dos);
+    }
+}
@@ -0,0 +1,255 @@
+use super::unified::Hunk;
+
+/// Changed lines of one file, or its sizes in bytes when it is binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStat {
+    /// Path as shown, see [`rename_name`] for renames.
+    pub name: String,
+    pub added: usize,
+    pub deleted: usize,
+    pub binary: bool,
+}
+
+/// Lines added and deleted by hunks computed without context.
+pub fn count(hunks: &[Hunk]) -> (usize, usize) {
+    let lines = hunks.iter().flat_map(|x| x.lines.iter());
+    lines.fold((0, 0), |(added, deleted), line| match line.origin {
+        b'+' => (added + 1, deleted),
+        b'-' => (added, deleted + 1),
+        _ => (added, deleted),
+    })
+}
+
+/// Name of a renamed path, the common leading and trailing directories
+/// factored out: `dir/{old => new}/file`.
+pub fn rename_name(old: &str, new: &str) -> String {
+    let (a, b) = (old.as_bytes(), new.as_bytes());
+
+    let mut prefix = 0;
+    for (i, (x, y)) in a.iter().zip(b.iter()).enumerate() {
+        if x != y {
+            break;
+        }
+        if *x == b'/' {
+            prefix = i + 1;
+        }
+    }
+
+    // walk back from the terminating NUL, up to the slash of the prefix
+    let at = |s: &[u8], i: usize| s.get(i).copied().unwrap_or(0);
+    let floor = prefix.saturating_sub(1);
+    let mut suffix = 0;
+    let (mut i, mut j) = (a.len() as i64, b.len() as i64);
+    while i >= floor as i64 && j >= floor as i64 && at(a, i as usize) == at(b, j as usize) {
+        if at(a, i as usize) == b'/' {
+            suffix = a.len() - i as usize;
+        }
+        i -= 1;
+        j -= 1;
+    }
+
+    let a_mid = (a.len() as i64 - prefix as i64 - suffix as i64).max(0) as usize;
+    let b_mid = (b.len() as i64 - prefix as i64 - suffix as i64).max(0) as usize;
+    let (a_mid, b_mid) = (&old[prefix..prefix + a_mid], &new[prefix..prefix + b_mid]);
+
+    match prefix + suffix {
+        0 => format!("{a_mid} => {b_mid}"),
+        _ => format!(
+            "{}{{{a_mid} => {b_mid}}}{}",
+            &old[..prefix],
+            &old[old.len() - suffix..]
+        ),
+    }
+}
+
+/// `--numstat` lines, `-` standing for the counts of binary files.
+pub fn numstat(files: &[FileStat]) -> String {
+    let mut out = String::new();
+    for file in files {
+        match file.binary {
+            true => out.push_str(&format!("-\t-\t{}\n", file.name)),
+            false => out.push_str(&format!(
+                "{}\t{}\t{}\n",
+                file.added, file.deleted, file.name
+            )),
+        }
+    }
+    out
+}
+
+fn decimal_width(n: usize) -> usize {
+    n.to_string().len()
+}
+
+/// Graph columns for `it` changes when `max_change` fill `width`, at least
+/// one for any change.
+fn scale_linear(it: usize, width: usize, max_change: usize) -> usize {
+    match it {
+        0 => 0,
+        _ => 1 + it * (width - 1) / max_change,
+    }
+}
+
+/// The `N files changed, N insertions(+), N deletions(-)` line.
+pub fn summary(files: &[FileStat]) -> String {
+    if files.is_empty() {
+        return " 0 files changed\n".to_string();
+    }
+
+    let text = files.iter().filter(|x| !x.binary);
+    let insertions: usize = text.clone().map(|x| x.added).sum();
+    let deletions: usize = text.map(|x| x.deleted).sum();
+    let plural = |n: usize, one: &str, many: &str| match n {
+        1 => format!("{n} {one}"),
+        _ => format!("{n} {many}"),
+    };
+
+    let mut out = format!(" {}", plural(files.len(), "file changed", "files changed"));
+    if insertions > 0 || deletions == 0 {
+        out += &format!(", {}", plural(insertions, "insertion(+)", "insertions(+)"));
+    }
+    if deletions > 0 || insertions == 0 {
+        out += &format!(", {}", plural(deletions, "deletion(-)", "deletions(-)"));
+    }
+    out.push('\n');
+    out
+}
+
+/// `--stat` output fitting `width` columns: one line per file with a graph
+/// of its changes, then the [`summary`].
+pub fn stat(files: &[FileStat], width: usize) -> String {
+    let mut max_len = 0;
+    let mut max_change = 0;
+    let mut number_width = 0;
+    let mut bin_width = 0;
+    for file in files {
+        max_len = max_len.max(file.name.chars().count());
+        if file.binary {
+            // "Bin XXX -> YYY bytes", counts aligned with "Bin"
+            bin_width = bin_width.max(14 + decimal_width(file.added) + decimal_width(file.deleted));
+            number_width = 3;
+        } else {
+            max_change = max_change.max(file.added + file.deleted);
+        }
+    }
+    number_width = number_width.max(decimal_width(max_change));
+
+    let width = width.max(16 + 6 + number_width);
+    let mut graph_width = match max_change + 4 > bin_width {
+        true => max_change,
+        false => bin_width - 4,
+    };
+    let mut name_width = max_len;
+
+    if name_width + number_width + 6 + graph_width > width {
+        let limit = (width * 3 / 8).saturating_sub(number_width + 6);
+        if graph_width > limit {
+            graph_width = limit.max(6);
+        }
+        if name_width > width - number_width - 6 - graph_width {
+            name_width = width - number_width - 6 - graph_width;
+        } else {
+            graph_width = width - number_width - 6 - name_width;
+        }
+    }
+
+    let mut out = String::new();
+    for file in files {
+        let mut prefix = "";
+        let mut name: &str = &file.name;
+        let mut len = name_width;
+        let name_len = name.chars().count();
+        if name_width < name_len {
+            prefix = "...";
+            len = len.saturating_sub(3);
+            let skip = name_len - len;
+            name = &name[name.char_indices().nth(skip).map_or(name.len(), |(i, _)| i)..];
+            if let Some(slash) = name.find('/') {
+                name = &name[slash..];
+            }
+        }
+        let padding = len.saturating_sub(name.chars().count());
+
+        if file.binary {
+            out += &format!(" {prefix}{name}{:padding$} | {:>number_width$}", "", "Bin");
+            match file.added == 0 && file.deleted == 0 {
+                true => out.push('\n'),
+                false => out += &format!(" {} -> {} bytes\n", file.deleted, file.added),
+            }
+            continue;
+        }
+
+        let (mut add, mut del) = (file.added, file.deleted);
+        if graph_width <= max_change {
+            let mut total = scale_linear(add + del, graph_width, max_change);
+            if total < 2 && add > 0 && del > 0 {
+                total = 2;
+            }
+            if add < del {
+                add = scale_linear(add, graph_width, max_change);
+                del = total - add;
+            } else {
+                del = scale_linear(del, graph_width, max_change);
+                add = total - del;
+            }
+        }
+
+        let changes = file.added + file.deleted;
+        out += &format!(
+            " {prefix}{name}{:padding$} | {changes:>number_width$}{}{}{}\n",
+            "",
+            if changes > 0 { " " } else { "" },
+            "+".repeat(add),
+            "-".repeat(del)
+        );
+    }
+
+    out + &summary(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rename_name, stat, FileStat};
+
+    fn file(name: &str, added: usize, deleted: usize, binary: bool) -> FileStat {
+        FileStat {
+            name: name.to_string(),
+            added,
+            deleted,
+            binary,
+        }
+    }
+
+    #[test]
+    fn rename_names() {
+        assert_eq!(rename_name("a.txt", "b.txt"), "a.txt => b.txt");
+        assert_eq!(rename_name("src/a.rs", "src/b.rs"), "src/{a.rs => b.rs}");
+        assert_eq!(rename_name("a/x/f", "b/x/f"), "{a => b}/x/f");
+        assert_eq!(rename_name("d/a/f", "d/b/f"), "d/{a => b}/f");
+        assert_eq!(rename_name("d/f", "d/e/f"), "d/{ => e}/f");
+    }
+
+    #[test]
+    fn graph_and_summary() {
+        let files = [file("a", 3, 1, false), file("long/name", 0, 0, false)];
+        assert_eq!(
+            stat(&files, 80),
+            " a         | 4 +++-\n long/name | 0\n 2 files changed, 3 insertions(+), 1 deletion(-)\n"
+        );
+
+        let files = [file("img.png", 20, 10, true)];
+        assert_eq!(
+            stat(&files, 80),
+            " img.png | Bin 10 -> 20 bytes\n 1 file changed, 0 insertions(+), 0 deletions(-)\n"
+        );
+    }
+
+    #[test]
+    fn graph_scales_to_width() {
+        let files = [file("f", 1000, 0, false)];
+        let out = stat(&files, 80);
+        let graph = out.lines().next().unwrap().rsplit(' ').next().unwrap();
+        // the name is short, the graph takes the rest of the line
+        assert_eq!(graph.len(), 80 - 4 - 6 - 1);
+    }
+}
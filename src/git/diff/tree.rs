@@ -0,0 +1,205 @@
+use std::cmp::Ordering;
+
+use super::{Change, FileSpec, Status};
+use crate::git::{
+    objects::{tree::TreeItem, Object},
+    pathspec,
+};
+
+/// How [`diff`] walks the trees.
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Descend into subtrees instead of reporting them as a whole.
+    pub recursive: bool,
+    /// With `recursive`, also report the subtrees that were descended into.
+    pub show_trees: bool,
+    pub paths: Vec<String>,
+}
+
+const TYPE_MASK: u32 = 0o170000;
+
+fn read_items(hash: Option<&str>) -> std::io::Result<Vec<TreeItem>> {
+    let Some(hash) = hash else {
+        return Ok(Vec::new());
+    };
+
+    match Object::read_from_hash(hash)? {
+        Object::Tree(items) => Ok(items),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{hash} is not a tree"),
+        )),
+    }
+}
+
+/// Trees sort as if their name ended with a `/`.
+fn compare(one: &TreeItem, two: &TreeItem) -> Ordering {
+    let key = |x: &TreeItem| {
+        let mut key = x.name.as_bytes().to_vec();
+        if x.is_tree() {
+            key.push(b'/');
+        }
+        key
+    };
+
+    key(one).cmp(&key(two))
+}
+
+fn spec(prefix: &str, item: &TreeItem) -> FileSpec {
+    FileSpec {
+        path: format!("{prefix}{}", item.name),
+        mode: item.mode,
+        hash: item.hash,
+    }
+}
+
+struct Walker<'a> {
+    options: &'a Options,
+    changes: Vec<Change>,
+}
+
+impl Walker<'_> {
+    fn selected(&self, item: &TreeItem, path: &str) -> bool {
+        match item.is_tree() {
+            true => pathspec::could_contain(&self.options.paths, path),
+            false => pathspec::matches(&self.options.paths, path),
+        }
+    }
+
+    fn push(&mut self, status: Status, old: Option<FileSpec>, new: Option<FileSpec>) {
+        self.changes.push(Change {
+            status,
+            old,
+            new,
+            score: 0,
+        });
+    }
+
+    /// Reports a path present on one side only, `old` telling which.
+    fn one_sided(&mut self, item: &TreeItem, prefix: &str, old: bool) -> std::io::Result<()> {
+        let side = spec(prefix, item);
+        if !self.selected(item, &side.path) {
+            return Ok(());
+        }
+
+        let descend = item.is_tree() && self.options.recursive;
+        if !descend || self.options.show_trees {
+            match old {
+                true => self.push(Status::Deleted, Some(side.clone()), None),
+                false => self.push(Status::Added, None, Some(side.clone())),
+            }
+        }
+
+        if descend {
+            let items = read_items(Some(&hex::encode(item.hash)))?;
+            let prefix = format!("{}/", side.path);
+            match old {
+                true => self.walk(items, Vec::new(), &prefix)?,
+                false => self.walk(Vec::new(), items, &prefix)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn both(&mut self, old: &TreeItem, new: &TreeItem, prefix: &str) -> std::io::Result<()> {
+        let (one, two) = (spec(prefix, old), spec(prefix, new));
+        if (old.mode == new.mode && old.hash == new.hash) || !self.selected(new, &two.path) {
+            return Ok(());
+        }
+
+        let descend = new.is_tree() && self.options.recursive;
+        if !descend || self.options.show_trees {
+            let status = match (old.mode & TYPE_MASK) == (new.mode & TYPE_MASK) {
+                true => Status::Modified,
+                false => Status::TypeChanged,
+            };
+            self.push(status, Some(one), Some(two.clone()));
+        }
+
+        if descend {
+            let items = read_items(Some(&hex::encode(old.hash)))?;
+            let other = read_items(Some(&hex::encode(new.hash)))?;
+            self.walk(items, other, &format!("{}/", two.path))?;
+        }
+
+        Ok(())
+    }
+
+    fn walk(
+        &mut self,
+        old: Vec<TreeItem>,
+        new: Vec<TreeItem>,
+        prefix: &str,
+    ) -> std::io::Result<()> {
+        let (mut old, mut new) = (old.iter().peekable(), new.iter().peekable());
+
+        loop {
+            let order = match (old.peek(), new.peek()) {
+                (None, None) => return Ok(()),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(one), Some(two)) => compare(one, two),
+            };
+
+            match order {
+                Ordering::Less => self.one_sided(old.next().unwrap(), prefix, true)?,
+                Ordering::Greater => self.one_sided(new.next().unwrap(), prefix, false)?,
+                Ordering::Equal => self.both(old.next().unwrap(), new.next().unwrap(), prefix)?,
+            }
+        }
+    }
+}
+
+/// Compares two trees, a missing one standing for the empty tree. Changes
+/// come in tree order, a path whose type switches between tree and
+/// non-tree is reported as a deletion and an addition.
+pub fn diff(
+    old: Option<&str>,
+    new: Option<&str>,
+    options: &Options,
+) -> std::io::Result<Vec<Change>> {
+    let mut walker = Walker {
+        options,
+        changes: Vec::new(),
+    };
+    walker.walk(read_items(old)?, read_items(new)?, "")?;
+
+    Ok(walker.changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::compare;
+    use crate::git::objects::tree::TreeItem;
+
+    fn item(name: &str, mode: u32) -> TreeItem {
+        TreeItem {
+            mode,
+            name: name.to_string(),
+            hash: [0; 20],
+        }
+    }
+
+    #[test]
+    fn trees_sort_with_trailing_slash() {
+        assert_eq!(
+            compare(&item("a.txt", 0o100644), &item("a", 0o40000)),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare(&item("a", 0o100644), &item("a", 0o40000)),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare(&item("a", 0o40000), &item("a0", 0o100644)),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare(&item("b", 0o100644), &item("b", 0o100755)),
+            Ordering::Equal
+        );
+    }
+}
@@ -0,0 +1,435 @@
+use super::lines::{is_space, Edit};
+
+/// Bytes of a function line kept in a hunk header.
+const FUNCTION_LINE_MAX: usize = 80;
+
+/// How changes are grouped into hunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    /// Unchanged lines shown around changes (`-U`).
+    pub context: usize,
+    /// Unchanged lines between two hunks that still merge them.
+    pub inter_hunk_context: usize,
+    /// Show the whole function around changes (`-W`).
+    pub function_context: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            context: 3,
+            inter_hunk_context: 0,
+            function_context: false,
+        }
+    }
+}
+
+/// A line of a hunk: `origin` is ` `, `-` or `+`, `text` keeps its newline
+/// when it has one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line<'a> {
+    pub origin: u8,
+    pub text: &'a [u8],
+}
+
+/// Lines around a group of nearby changes. Starts count from one, or name
+/// the line before an empty range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk<'a> {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    /// Last function line above the hunk, for the header.
+    pub function: Vec<u8>,
+    pub lines: Vec<Line<'a>>,
+}
+
+fn range(start: usize, len: usize) -> String {
+    match len {
+        1 => format!("{start}"),
+        _ => format!("{start},{len}"),
+    }
+}
+
+impl Hunk<'_> {
+    /// The `@@ -a,b +c,d @@ function` line, without its newline.
+    pub fn header(&self) -> Vec<u8> {
+        let mut header = format!(
+            "@@ -{} +{} @@",
+            range(self.old_start, self.old_len),
+            range(self.new_start, self.new_len)
+        )
+        .into_bytes();
+        if !self.function.is_empty() {
+            header.push(b' ');
+            header.extend_from_slice(&self.function);
+        }
+        header
+    }
+}
+
+/// The function name a line starts, following git's default: a line
+/// beginning with a letter, `_` or `$`, cut to 80 bytes.
+fn function_line(line: &[u8]) -> Option<&[u8]> {
+    let first = *line.first()?;
+    if !(first.is_ascii_alphabetic() || first == b'_' || first == b'$') {
+        return None;
+    }
+
+    let mut line = &line[..line.len().min(FUNCTION_LINE_MAX)];
+    while let [rest @ .., last] = line {
+        if !is_space(*last) {
+            break;
+        }
+        line = rest;
+    }
+    Some(line)
+}
+
+fn is_empty_line(line: &[u8]) -> bool {
+    line.iter().all(|x| is_space(*x))
+}
+
+struct Emitter<'a, 'b> {
+    old: &'b [&'a [u8]],
+    new: &'b [&'a [u8]],
+    edits: &'b [Edit],
+    options: &'b Options,
+}
+
+impl<'a> Emitter<'a, '_> {
+    /// Index of the last edit to put in the hunk starting at edit `*first`,
+    /// moving `*first` past blank-line edits too far from the others.
+    fn hunk_end(&self, first: &mut usize) -> Option<usize> {
+        let max_common = 2 * self.options.context + self.options.inter_hunk_context;
+        let max_ignorable = self.options.context;
+        let edits = self.edits;
+
+        let mut previous = *first;
+        while previous < edits.len() && edits[previous].ignore {
+            let next = previous + 1;
+            if next == edits.len()
+                || edits[next].old - (edits[previous].old + edits[previous].old_len)
+                    >= max_ignorable
+            {
+                *first = next;
+            }
+            previous = next;
+        }
+        if *first >= edits.len() {
+            return None;
+        }
+
+        let mut last = *first;
+        let mut ignored = 0;
+        for next in *first + 1..edits.len() {
+            let previous = &edits[next - 1];
+            let edit = &edits[next];
+            let distance = edit.old - (previous.old + previous.old_len);
+            if distance > max_common {
+                break;
+            }
+
+            if distance < max_ignorable && (!edit.ignore || last == next - 1) {
+                last = next;
+                ignored = 0;
+            } else if distance < max_ignorable && edit.ignore {
+                ignored += edit.new_len;
+            } else if last != next - 1
+                && edit.old + ignored - (edits[last].old + edits[last].old_len) > max_common
+            {
+                break;
+            } else if !edit.ignore {
+                last = next;
+                ignored = 0;
+            } else {
+                ignored += edit.new_len;
+            }
+        }
+
+        Some(last)
+    }
+
+    /// Closest function line of the old side from `start` toward `limit`,
+    /// which is excluded.
+    fn find_function(&self, start: i64, limit: i64) -> Option<usize> {
+        let step = if start > limit { -1 } else { 1 };
+        let mut line = start;
+        while line != limit && 0 <= line && line < self.old.len() as i64 {
+            if function_line(self.old[line as usize]).is_some() {
+                return Some(line as usize);
+            }
+            line += step;
+        }
+        None
+    }
+
+    fn hunks(&self) -> Vec<Hunk<'a>> {
+        let (n1, n2) = (self.old.len(), self.new.len());
+        let context = self.options.context;
+        let edits = self.edits;
+        let mut hunks = Vec::new();
+        let mut previous_function = -1;
+        // kept from the previous hunk when no function starts in between
+        let mut function = Vec::new();
+
+        let mut next = 0;
+        while next < edits.len() {
+            let mut skipped = next;
+            let mut first = next;
+            let Some(mut last) = self.hunk_end(&mut first) else {
+                break;
+            };
+
+            let (mut s1, mut s2);
+            'pre: loop {
+                s1 = edits[first].old.saturating_sub(context);
+                s2 = edits[first].new.saturating_sub(context);
+                if !self.options.function_context {
+                    break;
+                }
+
+                let mut i1 = edits[first].old;
+                if i1 >= n1 {
+                    // no extra context needed when whole functions are added
+                    if (edits[first].new..n2).any(|x| function_line(self.new[x]).is_some()) {
+                        break;
+                    }
+                    i1 = n1.saturating_sub(1);
+                }
+
+                let mut start = self.find_function(i1 as i64, -1).unwrap_or(0);
+                while start > 0
+                    && !is_empty_line(self.old[start - 1])
+                    && function_line(self.old[start - 1]).is_none()
+                {
+                    start -= 1;
+                }
+                if start < s1 {
+                    s2 = s2.saturating_sub(s1 - start);
+                    s1 = start;
+
+                    // show hidden blank-line edits the context now reaches
+                    while skipped != first
+                        && edits[skipped].old + edits[skipped].old_len <= s1
+                        && edits[skipped].new + edits[skipped].new_len <= s2
+                    {
+                        skipped += 1;
+                    }
+                    if skipped != first {
+                        first = skipped;
+                        continue 'pre;
+                    }
+                }
+                break;
+            }
+
+            let (mut e1, mut e2);
+            loop {
+                let end = &edits[last];
+                let after = context
+                    .min(n1 - (end.old + end.old_len))
+                    .min(n2 - (end.new + end.new_len));
+                e1 = end.old + end.old_len + after;
+                e2 = end.new + end.new_len + after;
+                if !self.options.function_context {
+                    break;
+                }
+
+                let function_end =
+                    match self.find_function((end.old + end.old_len) as i64, n1 as i64) {
+                        Some(mut line) => {
+                            while line > 0 && is_empty_line(self.old[line - 1]) {
+                                line -= 1;
+                            }
+                            line
+                        }
+                        None => n1,
+                    };
+                if function_end > e1 {
+                    e2 = (e2 + (function_end - e1)).min(n2);
+                    e1 = function_end;
+                }
+
+                // a following change the function overlaps joins the hunk
+                if last + 1 < edits.len() {
+                    let line = edits[last + 1].old.min(n1.saturating_sub(1));
+                    if line as i64 - context as i64 <= e1 as i64
+                        || self.find_function(line as i64, e1 as i64).is_none()
+                    {
+                        last += 1;
+                        continue;
+                    }
+                }
+                break;
+            }
+
+            if let Some(line) = self.find_function(s1 as i64 - 1, previous_function) {
+                function = function_line(self.old[line]).unwrap().to_vec();
+            }
+            previous_function = s1 as i64 - 1;
+
+            let mut lines = Vec::new();
+            let context_line = |x: usize| Line {
+                origin: b' ',
+                text: self.new[x],
+            };
+            lines.extend((s2..edits[first].new).map(context_line));
+            let (mut o, mut n) = (edits[first].old, edits[first].new);
+            for edit in &edits[first..=last] {
+                while o < edit.old && n < edit.new {
+                    lines.push(context_line(n));
+                    o += 1;
+                    n += 1;
+                }
+                lines.extend(
+                    self.old[edit.old..edit.old + edit.old_len]
+                        .iter()
+                        .map(|x| Line {
+                            origin: b'-',
+                            text: x,
+                        }),
+                );
+                lines.extend(
+                    self.new[edit.new..edit.new + edit.new_len]
+                        .iter()
+                        .map(|x| Line {
+                            origin: b'+',
+                            text: x,
+                        }),
+                );
+                o = edit.old + edit.old_len;
+                n = edit.new + edit.new_len;
+            }
+            lines.extend((n..e2).map(context_line));
+
+            let start = |s: usize, len: usize| if len == 0 { s } else { s + 1 };
+            hunks.push(Hunk {
+                old_start: start(s1, e1 - s1),
+                old_len: e1 - s1,
+                new_start: start(s2, e2 - s2),
+                new_len: e2 - s2,
+                function: function.clone(),
+                lines,
+            });
+            next = last + 1;
+        }
+
+        hunks
+    }
+}
+
+/// Groups the edits of [`super::lines::diff`] into hunks with context, the
+/// way git's unified diffs show them.
+pub fn hunks<'a>(
+    old: &[&'a [u8]],
+    new: &[&'a [u8]],
+    edits: &[Edit],
+    options: &Options,
+) -> Vec<Hunk<'a>> {
+    Emitter {
+        old,
+        new,
+        edits,
+        options,
+    }
+    .hunks()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{function_line, hunks, Hunk, Options};
+    use crate::git::diff::lines::{self, split};
+
+    /// Writes a hunk as plain text, marking a missing final newline.
+    fn write(hunk: &Hunk, out: &mut Vec<u8>) {
+        out.extend_from_slice(&hunk.header());
+        out.push(b'\n');
+        for line in hunk.lines.iter() {
+            out.push(line.origin);
+            out.extend_from_slice(line.text);
+            if !line.text.ends_with(b"\n") {
+                out.extend_from_slice(b"\n\\ No newline at end of file\n");
+            }
+        }
+    }
+
+    #[test]
+    fn function_lines() {
+        assert_eq!(function_line(b"fn main() {  \n"), Some(&b"fn main() {"[..]));
+        assert_eq!(function_line(b"$x = 1\n"), Some(&b"$x = 1"[..]));
+        assert_eq!(function_line(b"    return;\n"), None);
+        assert_eq!(function_line(b"}\n"), None);
+        assert_eq!(function_line(&[b'a'; 100]).map(|x| x.len()), Some(80));
+    }
+
+    #[test]
+    fn missing_newline_is_marked() {
+        let (old, new) = (split(b"a\nb"), split(b"a\nc\n"));
+        let edits = lines::diff(&old, &new, &Default::default());
+        let mut out = Vec::new();
+        for hunk in hunks(&old, &new, &edits, &Default::default()) {
+            write(&hunk, &mut out);
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+c\n"
+        );
+    }
+
+    /// Compares with patches git produced for the pairs of files under
+    /// `tests/fixtures/diff`. Each `<name>.<options>.diff` holds the output
+    /// of `git diff --no-index` given those options, `-` separated, for
+    /// `<name>.old` and `<name>.new`, without its file header.
+    #[test]
+    fn matches_git_on_fixtures() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/diff");
+        let mut checked = 0;
+
+        for entry in fs::read_dir(&dir).unwrap() {
+            let file_name = entry.unwrap().file_name().into_string().unwrap();
+            let Some(stem) = file_name.strip_suffix(".diff") else {
+                continue;
+            };
+            let (name, flags) = stem.split_once('.').unwrap();
+
+            let mut line_options = lines::Options::default();
+            let mut options = Options::default();
+            for flag in flags.split('-').filter(|x| !x.is_empty()) {
+                match flag {
+                    "w" => line_options.ignore_all_space = true,
+                    "b" => line_options.ignore_space_change = true,
+                    "eol" => line_options.ignore_space_at_eol = true,
+                    "blank" => line_options.ignore_blank_lines = true,
+                    "W" => options.function_context = true,
+                    "noindent" => line_options.indent_heuristic = false,
+                    x if x.starts_with('U') => options.context = x[1..].parse().unwrap(),
+                    x => line_options.algorithm = x.parse().unwrap(),
+                }
+            }
+
+            let old = fs::read(dir.join(format!("{name}.old"))).unwrap();
+            let new = fs::read(dir.join(format!("{name}.new"))).unwrap();
+            let expected = fs::read(dir.join(&file_name)).unwrap();
+
+            let (old, new) = (split(&old), split(&new));
+            let edits = lines::diff(&old, &new, &line_options);
+            let mut out = Vec::new();
+            for hunk in hunks(&old, &new, &edits, &options) {
+                write(&hunk, &mut out);
+            }
+
+            assert_eq!(
+                String::from_utf8_lossy(&out),
+                String::from_utf8_lossy(&expected),
+                "{file_name}"
+            );
+            checked += 1;
+        }
+
+        assert!(checked > 0);
+    }
+}
@@ -0,0 +1,241 @@
+//! `--word-diff`: the deleted and added lines of a hunk compared word by
+//! word, shown inline.
+
+use std::io::Write;
+
+use regex::bytes::Regex;
+
+use super::{
+    display::{self, Palette},
+    lines::{self, is_space},
+    patch::Symbol,
+};
+
+/// How one kind of text is marked: its color and what surrounds it.
+struct Style {
+    color: &'static str,
+    prefix: &'static str,
+    suffix: &'static str,
+}
+
+/// Splits `text` into words, the byte ranges of `regex` matches cut at
+/// newlines, or runs of non-whitespace.
+fn split(text: &[u8], regex: Option<&Regex>) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut begin = 0;
+
+    'words: while begin < text.len() {
+        while let Some(regex) = regex {
+            if begin >= text.len() {
+                break 'words;
+            }
+            let Some(found) = regex.find(&text[begin..]) else {
+                break 'words;
+            };
+
+            let matched = &text[begin + found.start()..begin + found.end()];
+            let end = match matched.iter().position(|x| *x == b'\n') {
+                Some(newline) => begin + found.start() + newline,
+                None => begin + found.end(),
+            };
+            begin += found.start();
+            if begin == end {
+                begin += 1;
+            } else {
+                words.push((begin, end));
+                begin = end;
+                continue 'words;
+            }
+        }
+
+        while begin < text.len() && is_space(text[begin]) {
+            begin += 1;
+        }
+        if begin >= text.len() {
+            break;
+        }
+        let end = text[begin..]
+            .iter()
+            .position(|x| is_space(*x))
+            .map_or(text.len(), |x| begin + x);
+        words.push((begin, end));
+        begin = end;
+    }
+
+    words
+}
+
+fn words<'a>(text: &'a [u8], ranges: &[(usize, usize)]) -> Vec<&'a [u8]> {
+    ranges
+        .iter()
+        .map(|(start, end)| &text[*start..*end])
+        .collect()
+}
+
+/// Collects the deleted and added lines of a hunk and shows them as word
+/// changes once a context line, a header or the end of the patch comes.
+pub struct WordDiff<'a> {
+    mode: display::WordDiff,
+    regex: Option<&'a Regex>,
+    palette: &'a Palette,
+    old: Style,
+    new: Style,
+    context: Style,
+    newline: &'static str,
+    minus: Vec<u8>,
+    plus: Vec<u8>,
+}
+
+impl<'a> WordDiff<'a> {
+    pub fn new(
+        mode: display::WordDiff,
+        regex: Option<&'a Regex>,
+        palette: &'a Palette,
+    ) -> WordDiff<'a> {
+        let style = |color, prefix, suffix| Style {
+            color,
+            prefix,
+            suffix,
+        };
+        let (old, new, context, newline) = match mode {
+            display::WordDiff::Porcelain => (("-", "\n"), ("+", "\n"), (" ", "\n"), "~\n"),
+            display::WordDiff::Plain => (("[-", "-]"), ("{+", "+}"), ("", ""), "\n"),
+            _ => (("", ""), ("", ""), ("", ""), "\n"),
+        };
+
+        WordDiff {
+            mode,
+            regex,
+            palette,
+            old: style(palette.old, old.0, old.1),
+            new: style(palette.new, new.0, new.1),
+            context: style(palette.context, context.0, context.1),
+            newline,
+            minus: Vec::new(),
+            plus: Vec::new(),
+        }
+    }
+
+    /// Writes `text` in `style`, each line of it marked on its own.
+    fn write_text(
+        &self,
+        out: &mut impl Write,
+        style: &Style,
+        mut text: &[u8],
+    ) -> std::io::Result<()> {
+        while !text.is_empty() {
+            let newline = text.iter().position(|x| *x == b'\n');
+            if newline != Some(0) {
+                let end = newline.unwrap_or(text.len());
+                write!(out, "{}{}", style.color, style.prefix)?;
+                out.write_all(&text[..end])?;
+                write!(out, "{}", style.suffix)?;
+                if !style.color.is_empty() {
+                    write!(out, "{}", display::RESET)?;
+                }
+            }
+
+            let Some(newline) = newline else {
+                break;
+            };
+            write!(out, "{}", self.newline)?;
+            text = &text[newline + 1..];
+        }
+        Ok(())
+    }
+
+    /// Compares the collected lines word by word. The unchanged text
+    /// shown around changed words is taken from the added lines.
+    pub fn flush(&mut self, out: &mut impl Write) -> std::io::Result<()> {
+        let (minus, plus) = (
+            std::mem::take(&mut self.minus),
+            std::mem::take(&mut self.plus),
+        );
+        if minus.is_empty() && plus.is_empty() {
+            return Ok(());
+        }
+        if plus.is_empty() {
+            return self.write_text(out, &self.old, &minus);
+        }
+
+        let (old, new) = (split(&minus, self.regex), split(&plus, self.regex));
+        // git compares words with plain Myers, whatever the options
+        let options = lines::Options {
+            indent_heuristic: false,
+            ..Default::default()
+        };
+        let edits = lines::diff(&words(&minus, &old), &words(&plus, &new), &options);
+
+        // the range of the words an edit covers, or the empty range after
+        // the word before it
+        let span = |ranges: &[(usize, usize)], start: usize, len: usize| match len {
+            0 => {
+                let end = start.checked_sub(1).map_or(0, |x| ranges[x].1);
+                (end, end)
+            }
+            _ => (ranges[start].0, ranges[start + len - 1].1),
+        };
+
+        let mut current = 0;
+        for edit in edits.iter() {
+            let (minus_begin, minus_end) = span(&old, edit.old, edit.old_len);
+            let (plus_begin, plus_end) = span(&new, edit.new, edit.new_len);
+
+            if current != plus_begin {
+                self.write_text(out, &self.context, &plus[current..plus_begin])?;
+            }
+            if minus_begin != minus_end {
+                self.write_text(out, &self.old, &minus[minus_begin..minus_end])?;
+            }
+            if plus_begin != plus_end {
+                self.write_text(out, &self.new, &plus[plus_begin..plus_end])?;
+            }
+            current = plus_end;
+        }
+
+        if current != plus.len() {
+            self.write_text(out, &self.context, &plus[current..])?;
+        }
+        Ok(())
+    }
+
+    /// Takes the next symbol of the patch if it is a line of a hunk,
+    /// otherwise shows what was collected so far and leaves it to the
+    /// caller.
+    pub fn push(&mut self, out: &mut impl Write, symbol: &Symbol) -> std::io::Result<bool> {
+        let Palette { context, reset, .. } = self.palette;
+
+        match symbol {
+            Symbol::Deleted(text) => self.minus.extend_from_slice(text),
+            Symbol::Added(text, _) => self.plus.extend_from_slice(text),
+            // as if the line was complete, more may follow it
+            Symbol::NoNewline => {}
+            Symbol::Context(text) => {
+                self.flush(out)?;
+                match self.mode {
+                    display::WordDiff::Porcelain => {
+                        write_context(out, context, reset, &[b" ", &text[..]].concat())?;
+                        out.write_all(b"~\n")?;
+                    }
+                    _ => write_context(out, context, reset, text)?,
+                }
+            }
+            _ => {
+                self.flush(out)?;
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+fn write_context(out: &mut impl Write, set: &str, reset: &str, line: &[u8]) -> std::io::Result<()> {
+    let text = line.strip_suffix(b"\n").unwrap_or(line);
+    let text = text.strip_suffix(b"\r").unwrap_or(text);
+    if !text.is_empty() {
+        write!(out, "{set}")?;
+        out.write_all(text)?;
+        write!(out, "{reset}")?;
+    }
+    out.write_all(&line[text.len()..])
+}
@@ -0,0 +1,164 @@
+use std::{cmp::Ordering, fs};
+
+use super::{Change, FileSpec, Status};
+use crate::git::{
+    checkout,
+    index::{self, Index},
+    objects::{
+        blob,
+        tree::{Files, Item},
+    },
+    pathspec, repository,
+};
+
+const TYPE_MASK: u32 = 0o170000;
+
+/// Files as the index has them, in the shape of a flattened tree.
+/// Unmerged paths are left out.
+pub fn staged(index: &Index, specs: &[String]) -> Files {
+    index
+        .entries
+        .iter()
+        .filter(|x| x.stage() == 0 && pathspec::matches(specs, &x.path))
+        .map(|x| (x.path.clone(), (x.mode, x.hash)))
+        .collect()
+}
+
+/// Tracked files as they are in the worktree, in the shape of a flattened
+/// tree. Files whose stat data matches their index entry are not hashed
+/// again, missing files and files replaced by a directory are left out.
+pub fn files(index: &Index, specs: &[String]) -> std::io::Result<Files> {
+    let root = repository::work_tree();
+    let mut files = Files::new();
+
+    for entry in index.entries.iter() {
+        if entry.stage() != 0 || !pathspec::matches(specs, &entry.path) {
+            continue;
+        }
+
+        let path = root.join(&entry.path);
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        let item = match (entry.mode, metadata.is_dir()) {
+            (index::MODE_GITLINK, true) => (entry.mode, entry.hash),
+            (index::MODE_GITLINK, false) | (_, true) => continue,
+            _ if entry.is_fresh(&metadata) => (entry.mode, entry.hash),
+            _ => match checkout::worktree_item(&path)? {
+                Some(item) => item,
+                None => continue,
+            },
+        };
+        files.insert(entry.path.clone(), item);
+    }
+
+    Ok(files)
+}
+
+/// Content of a worktree file whose blob is named `spec.hash`, `None` when
+/// the file is gone or changed since it was hashed.
+pub fn content(spec: &FileSpec) -> std::io::Result<Option<Vec<u8>>> {
+    let path = repository::work_tree().join(&spec.path);
+    let data = match spec.mode {
+        index::MODE_SYMLINK => {
+            fs::read_link(&path).map(|x| x.into_os_string().into_encoded_bytes())
+        }
+        _ => fs::read(&path),
+    };
+
+    match data {
+        Ok(data) if blob::hash(&data) == spec.hash => Ok(Some(data)),
+        Ok(_) => Ok(None),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn spec(path: &str, (mode, hash): &Item) -> FileSpec {
+    FileSpec {
+        path: path.to_string(),
+        mode: *mode,
+        hash: *hash,
+    }
+}
+
+/// Compares two sets of files in path order, like [`super::tree::diff`]
+/// compares trees when recursing.
+pub fn diff(old: &Files, new: &Files) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let (mut one, mut two) = (old.iter().peekable(), new.iter().peekable());
+
+    loop {
+        let order = match (one.peek(), two.peek()) {
+            (None, None) => return changes,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some((a, _)), Some((b, _))) => a.as_bytes().cmp(b.as_bytes()),
+        };
+
+        let (status, old, new) = match order {
+            Ordering::Less => {
+                let (path, item) = one.next().unwrap();
+                (Status::Deleted, Some(spec(path, item)), None)
+            }
+            Ordering::Greater => {
+                let (path, item) = two.next().unwrap();
+                (Status::Added, None, Some(spec(path, item)))
+            }
+            Ordering::Equal => {
+                let ((path, a), (_, b)) = (one.next().unwrap(), two.next().unwrap());
+                if a == b {
+                    continue;
+                }
+                let status = match (a.0 & TYPE_MASK) == (b.0 & TYPE_MASK) {
+                    true => Status::Modified,
+                    false => Status::TypeChanged,
+                };
+                (status, Some(spec(path, a)), Some(spec(path, b)))
+            }
+        };
+
+        changes.push(Change {
+            status,
+            old,
+            new,
+            score: 0,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff;
+    use crate::git::{diff::Status, objects::tree::Files};
+
+    #[test]
+    fn compares_in_path_order() {
+        let old = Files::from([
+            ("a".to_string(), (0o100644, [1; 20])),
+            ("b".to_string(), (0o100644, [2; 20])),
+            ("c".to_string(), (0o100644, [3; 20])),
+        ]);
+        let new = Files::from([
+            ("b".to_string(), (0o100755, [2; 20])),
+            ("c".to_string(), (0o120000, [4; 20])),
+            ("d".to_string(), (0o100644, [5; 20])),
+        ]);
+
+        let changes = diff(&old, &new);
+        let statuses: Vec<_> = changes.iter().map(|x| (x.status, x.path())).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (Status::Deleted, "a"),
+                (Status::Modified, "b"),
+                (Status::TypeChanged, "c"),
+                (Status::Added, "d"),
+            ]
+        );
+        assert!(diff(&old, &old).is_empty());
+    }
+}
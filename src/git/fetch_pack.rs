@@ -0,0 +1,728 @@
+//! The fetching side of the smart HTTP protocol: the refs `git-upload-pack`
+//! advertises, and the negotiation that has it send only the objects the
+//! repository lacks. Protocol v2 is asked for and used when the server
+//! offers it, v0 otherwise. The packet and request helpers are shared
+//! with [`super::send_pack`].
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    io::{IsTerminal, Read},
+};
+
+use reqwest::blocking::{Client, Response};
+
+use super::{
+    index_pack::Indexer,
+    packfile::ObjType,
+    pktline::{
+        read_line, read_packet, write_delim, write_flush, write_line, Capabilities, Packet,
+        SideBand,
+    },
+    refs, revision, revwalk,
+};
+
+/// What we tell servers that tell us what they are.
+pub const AGENT: &str = concat!("agent=git-starter-rust/", env!("CARGO_PKG_VERSION"));
+
+/// Haves sent without any of them being found common before giving up
+/// on finding more, as git does.
+const MAX_IN_VAIN: usize = 256;
+
+/// What `git-upload-pack` or `git-receive-pack` starts with: its refs and
+/// capabilities. In protocol v2 the refs are asked for separately, with
+/// [`ls_refs`].
+#[derive(Debug, Default)]
+pub struct Advertisement {
+    /// The protocol version the server speaks, 0 or 2.
+    pub version: u8,
+    /// Refs in the order they came, with the objects they point at.
+    pub refs: Vec<(String, String)>,
+    /// What the annotated tags among them peel to.
+    pub peeled: HashMap<String, String>,
+    /// What the symbolic refs among them, or unborn ones, point at.
+    pub symrefs: HashMap<String, String>,
+    pub capabilities: Capabilities,
+}
+
+fn invalid(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+pub fn http_error(e: reqwest::Error) -> std::io::Error {
+    std::io::Error::other(e)
+}
+
+/// Checks that the server answered a request for `url`.
+pub fn check_status(response: Response, url: &str) -> std::io::Result<Response> {
+    match response.status() {
+        x if x.is_success() => Ok(response),
+        x if x.as_u16() == 404 => Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("repository '{url}/' not found"),
+        )),
+        x => Err(std::io::Error::other(format!(
+            "unable to access '{url}/': The requested URL returned error: {}",
+            x.as_u16()
+        ))),
+    }
+}
+
+/// Asks `service` of the repository at `url` for the refs it has, or
+/// when it speaks protocol v2 for its capabilities only.
+/// `git-receive-pack` is always spoken to in v0.
+pub fn advertise(client: &Client, url: &str, service: &str) -> std::io::Result<Advertisement> {
+    let mut request = client
+        .get(format!("{url}/info/refs"))
+        .query(&[("service", service)]);
+    if service == "git-upload-pack" {
+        request = request.header("Git-Protocol", "version=2");
+    }
+    let mut response = check_status(request.send().map_err(http_error)?, url)?;
+    read_advertisement(&mut response, url, service)
+}
+
+/// Reads the advertisement `service` of the repository at `url` sent.
+fn read_advertisement(
+    response: &mut impl Read,
+    url: &str,
+    service: &str,
+) -> std::io::Result<Advertisement> {
+    // v0 starts by naming the service, v2 need not
+    let mut next = read_line(response)?;
+    if next.as_deref() == Some(&format!("# service={service}")) {
+        read_packet(response)?;
+        next = read_line(response)?;
+    } else if next.as_deref() != Some("version 2") {
+        return Err(invalid(format!(
+            "{url}/info/refs not valid: is this a git repository?"
+        )));
+    }
+
+    let mut advertisement = Advertisement::default();
+    if next.as_deref() == Some("version 2") {
+        advertisement.version = 2;
+        while let Some(line) = read_line(response)? {
+            advertisement.capabilities.push(&line);
+        }
+    }
+
+    while let Some(line) = next.take().filter(|_| advertisement.version == 0) {
+        next = read_line(response)?;
+        let line = match line.split_once('\0') {
+            Some((line, capabilities)) => {
+                advertisement.capabilities = Capabilities::parse(capabilities);
+                for (name, target) in advertisement.capabilities.symrefs() {
+                    advertisement
+                        .symrefs
+                        .insert(name.to_string(), target.to_string());
+                }
+                line.to_string()
+            }
+            None => line,
+        };
+
+        let (hash, name) = line
+            .split_once(' ')
+            .ok_or_else(|| invalid("bad ref line"))?;
+        // an empty repository advertises only its capabilities, and
+        // objects of alternates are no refs
+        if name == "capabilities^{}" || name == ".have" {
+            continue;
+        }
+        match name.strip_suffix("^{}") {
+            Some(tag) => {
+                advertisement
+                    .peeled
+                    .insert(tag.to_string(), hash.to_string());
+            }
+            None => advertisement
+                .refs
+                .push((name.to_string(), hash.to_string())),
+        }
+    }
+
+    match advertisement.capabilities.object_format() {
+        "sha1" => Ok(advertisement),
+        format => Err(invalid(format!(
+            "the remote uses object format '{format}', which is not supported"
+        ))),
+    }
+}
+
+/// The start of a request for `command` in protocol v2, to be followed by
+/// its arguments and a flush packet.
+fn command(advertisement: &Advertisement, command: &str) -> std::io::Result<Vec<u8>> {
+    let mut request = Vec::new();
+    write_line(&mut request, &format!("command={command}\n"))?;
+    if advertisement.capabilities.agent().is_some() {
+        write_line(&mut request, &format!("{AGENT}\n"))?;
+    }
+    if advertisement.capabilities.has("object-format") {
+        let format = advertisement.capabilities.object_format();
+        write_line(&mut request, &format!("object-format={format}\n"))?;
+    }
+    write_delim(&mut request)?;
+    Ok(request)
+}
+
+/// Asks a repository that speaks protocol v2 for its refs whose names
+/// start with one of `prefixes`, or all of them if there are none, along
+/// with what tags peel to and symbolic refs point at. In v0 the refs came
+/// with the advertisement.
+pub fn ls_refs(
+    client: &Client,
+    url: &str,
+    advertisement: &mut Advertisement,
+    prefixes: &[String],
+) -> std::io::Result<()> {
+    if advertisement.version != 2 {
+        return Ok(());
+    }
+    let mut request = command(advertisement, "ls-refs")?;
+    write_line(&mut request, "symrefs\n")?;
+    write_line(&mut request, "peel\n")?;
+    // unborn HEADs, for the branch to start a clone of an empty
+    // repository on
+    let unborn = advertisement
+        .capabilities
+        .value("ls-refs")
+        .is_some_and(|x| x.split(' ').any(|x| x == "unborn"));
+    if unborn {
+        write_line(&mut request, "unborn\n")?;
+    }
+    for prefix in prefixes {
+        write_line(&mut request, &format!("ref-prefix {prefix}\n"))?;
+    }
+    write_flush(&mut request)?;
+
+    let mut response = post(client, url, "git-upload-pack", 2, request)?;
+    read_refs(&mut response, advertisement)
+}
+
+/// Reads the refs of an `ls-refs` response into `advertisement`.
+fn read_refs(response: &mut impl Read, advertisement: &mut Advertisement) -> std::io::Result<()> {
+    while let Some(line) = read_line(response)? {
+        let mut words = line.split(' ');
+        let (Some(hash), Some(name)) = (words.next(), words.next()) else {
+            return Err(invalid("bad ref line"));
+        };
+        for attribute in words {
+            if let Some(target) = attribute.strip_prefix("symref-target:") {
+                advertisement
+                    .symrefs
+                    .insert(name.to_string(), target.to_string());
+            } else if let Some(peeled) = attribute.strip_prefix("peeled:") {
+                advertisement
+                    .peeled
+                    .insert(name.to_string(), peeled.to_string());
+            }
+        }
+        if hash != "unborn" {
+            advertisement
+                .refs
+                .push((name.to_string(), hash.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Commits to offer as haves, newest first, leaving out the history of
+/// those the server has in common with us.
+struct Negotiator {
+    queue: BinaryHeap<(u64, Reverse<usize>, String)>,
+    seen: HashSet<String>,
+    common: HashSet<String>,
+    sequence: usize,
+}
+
+impl Negotiator {
+    /// Starts from the commits of every local ref.
+    fn new() -> std::io::Result<Negotiator> {
+        let mut negotiator = Negotiator {
+            queue: BinaryHeap::new(),
+            seen: HashSet::new(),
+            common: HashSet::new(),
+            sequence: 0,
+        };
+        let tips = refs::list("refs/")?.into_iter().map(|(_, x)| x);
+        for tip in tips.chain(refs::head_commit()?) {
+            if let Ok(commit) = revision::peel(&tip, Some(ObjType::Commit)) {
+                negotiator.push(commit)?;
+            }
+        }
+        Ok(negotiator)
+    }
+
+    fn push(&mut self, hash: String) -> std::io::Result<()> {
+        if self.seen.insert(hash.clone()) {
+            let date = revwalk::read_commit(&hash)?.committer.date;
+            self.sequence += 1;
+            self.queue.push((date, Reverse(self.sequence), hash));
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> std::io::Result<Option<String>> {
+        while let Some((_, _, hash)) = self.queue.pop() {
+            if self.common.contains(&hash) {
+                continue;
+            }
+            for parent in revwalk::read_commit(&hash)?.parents {
+                self.push(parent)?;
+            }
+            return Ok(Some(hash));
+        }
+        Ok(None)
+    }
+
+    /// Records that the server has `hash`, and so all of its history,
+    /// returning whether this is news.
+    fn ack(&mut self, hash: &str) -> std::io::Result<bool> {
+        if self.common.contains(hash) {
+            return Ok(false);
+        }
+        let mut stack = vec![hash.to_string()];
+        while let Some(hash) = stack.pop() {
+            if !self.common.insert(hash.clone()) {
+                continue;
+            }
+            if let Ok(commit) = revwalk::read_commit(&hash) {
+                stack.extend(commit.parents.into_iter().filter(|x| self.seen.contains(x)));
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Sends `body` to `service` at `url`, in protocol `version`.
+pub fn post(
+    client: &Client,
+    url: &str,
+    service: &str,
+    version: u8,
+    body: Vec<u8>,
+) -> std::io::Result<Response> {
+    let mut request = client
+        .post(format!("{url}/{service}"))
+        .header("Content-Type", format!("application/x-{service}-request"))
+        .header("Accept", format!("application/x-{service}-result"));
+    if version == 2 {
+        request = request.header("Git-Protocol", "version=2");
+    }
+    let response = request.body(body).send().map_err(http_error)?;
+    check_status(response, url)
+}
+
+/// Fetches the objects `wants` lead to from the repository at `url` into
+/// the object database, offering the commits of local refs as haves in
+/// rounds until enough are found in common, as `multi_ack_detailed` or
+/// protocol v2 allow. Deltas of the pack may be against objects we
+/// already have.
+pub fn fetch(
+    client: &Client,
+    url: &str,
+    advertisement: &Advertisement,
+    wants: &[String],
+) -> std::io::Result<()> {
+    match advertisement.version {
+        2 => fetch_v2(client, url, advertisement, wants),
+        _ => fetch_v0(client, url, advertisement, wants),
+    }
+}
+
+/// Haves to send in a round of negotiation, `batch` of them at most.
+fn next_haves(negotiator: &mut Negotiator, batch: usize) -> std::io::Result<Vec<String>> {
+    let mut haves = Vec::new();
+    while haves.len() < batch {
+        match negotiator.next()? {
+            Some(hash) => haves.push(hash),
+            None => break,
+        }
+    }
+    Ok(haves)
+}
+
+/// Reads the acknowledgements of a round of negotiation, up to a `NAK` in
+/// v0 or the end of the section in v2: the haves the server has, and
+/// whether it is ready to send a pack.
+fn read_acks(response: &mut impl Read) -> std::io::Result<(Vec<String>, bool)> {
+    let (mut acked, mut ready) = (Vec::new(), false);
+    while let Some(line) = read_line(response)? {
+        let words = line.split(' ').collect::<Vec<_>>();
+        match words[..] {
+            ["NAK"] => break,
+            ["ready"] | ["ACK", _, "ready"] => ready = true,
+            ["ACK", hash] | ["ACK", hash, "common"] => acked.push(hash.to_string()),
+            _ => return Err(invalid(format!("expected ACK/NAK, got '{line}'"))),
+        }
+    }
+    Ok((acked, ready))
+}
+
+/// Records the haves the server acknowledged, adding those that are news
+/// to `common`, which starts the count of haves sent in vain over.
+fn record_acks(
+    negotiator: &mut Negotiator,
+    acked: Vec<String>,
+    common: &mut Vec<String>,
+    in_vain: &mut usize,
+) -> std::io::Result<()> {
+    for hash in acked {
+        if negotiator.ack(&hash)? {
+            common.push(hash);
+            *in_vain = 0;
+        }
+    }
+    Ok(())
+}
+
+fn fetch_v0(
+    client: &Client,
+    url: &str,
+    advertisement: &Advertisement,
+    wants: &[String],
+) -> std::io::Result<()> {
+    let capabilities = [
+        "multi_ack_detailed",
+        "side-band-64k",
+        "thin-pack",
+        "ofs-delta",
+        "include-tag",
+    ]
+    .into_iter()
+    .filter(|x| advertisement.capabilities.has(x))
+    // progress is only for people watching
+    .chain((!std::io::stderr().is_terminal()).then_some("no-progress"))
+    .chain(advertisement.capabilities.agent().map(|_| AGENT))
+    .collect::<Vec<_>>()
+    .join(" ");
+
+    let mut request = Vec::new();
+    for (i, want) in wants.iter().enumerate() {
+        match i {
+            0 => write_line(&mut request, &format!("want {want} {capabilities}\n"))?,
+            _ => write_line(&mut request, &format!("want {want}\n"))?,
+        }
+    }
+    write_flush(&mut request)?;
+
+    // requests are stateless, so each one repeats what was found common
+    let mut common = Vec::new();
+    if advertisement.capabilities.has("multi_ack_detailed") {
+        let mut negotiator = Negotiator::new()?;
+        let (mut in_vain, mut batch, mut ready) = (0, 16, false);
+
+        while !ready && (in_vain < MAX_IN_VAIN || common.is_empty()) {
+            let haves = next_haves(&mut negotiator, batch)?;
+            if haves.is_empty() {
+                break;
+            }
+
+            let mut body = request.clone();
+            for have in common.iter().chain(&haves) {
+                write_line(&mut body, &format!("have {have}\n"))?;
+            }
+            write_flush(&mut body)?;
+
+            let mut response = post(client, url, "git-upload-pack", 0, body)?;
+            in_vain += haves.len();
+            let (acked, server_ready) = read_acks(&mut response)?;
+            record_acks(&mut negotiator, acked, &mut common, &mut in_vain)?;
+            ready = server_ready;
+            batch = (batch * 2).min(1024);
+        }
+    }
+
+    for have in &common {
+        write_line(&mut request, &format!("have {have}\n"))?;
+    }
+    write_line(&mut request, "done\n")?;
+    let mut response = post(client, url, "git-upload-pack", 0, request)?;
+
+    // what is found in common ends with an ACK of the last of it, or a NAK
+    loop {
+        let line =
+            read_line(&mut response)?.ok_or_else(|| invalid("expected ACK/NAK, got flush"))?;
+        let words = line.split(' ').collect::<Vec<_>>();
+        match words[..] {
+            ["NAK"] | ["ACK", _] => break,
+            ["ACK", _, _] => continue,
+            _ => return Err(invalid(format!("expected ACK/NAK, got '{line}'"))),
+        }
+    }
+
+    match advertisement.capabilities.has("side-band-64k") {
+        true => receive(SideBand::new(response)),
+        false => receive(response),
+    }
+}
+
+/// The `fetch` command of protocol v2, whose responses come in sections:
+/// the acknowledgements of a round of negotiation, then once the server
+/// is ready or is told it is done, the pack.
+fn fetch_v2(
+    client: &Client,
+    url: &str,
+    advertisement: &Advertisement,
+    wants: &[String],
+) -> std::io::Result<()> {
+    let mut request = command(advertisement, "fetch")?;
+    for argument in ["thin-pack", "ofs-delta", "include-tag"] {
+        write_line(&mut request, &format!("{argument}\n"))?;
+    }
+    if !std::io::stderr().is_terminal() {
+        write_line(&mut request, "no-progress\n")?;
+    }
+    for want in wants {
+        write_line(&mut request, &format!("want {want}\n"))?;
+    }
+
+    let mut common = Vec::new();
+    let mut negotiator = Negotiator::new()?;
+    let (mut in_vain, mut batch) = (0, 16);
+    while in_vain < MAX_IN_VAIN || common.is_empty() {
+        let haves = next_haves(&mut negotiator, batch)?;
+        if haves.is_empty() {
+            break;
+        }
+
+        let mut body = request.clone();
+        for have in common.iter().chain(&haves) {
+            write_line(&mut body, &format!("have {have}\n"))?;
+        }
+        write_flush(&mut body)?;
+
+        let mut response = post(client, url, "git-upload-pack", 2, body)?;
+        match read_line(&mut response)?.as_deref() {
+            Some("acknowledgments") => {}
+            _ => return Err(invalid("expected 'acknowledgments'")),
+        }
+        in_vain += haves.len();
+        let (acked, ready) = read_acks(&mut response)?;
+        record_acks(&mut negotiator, acked, &mut common, &mut in_vain)?;
+        if ready {
+            return receive_v2(response);
+        }
+        batch = (batch * 2).min(1024);
+    }
+
+    for have in &common {
+        write_line(&mut request, &format!("have {have}\n"))?;
+    }
+    write_line(&mut request, "done\n")?;
+    write_flush(&mut request)?;
+    receive_v2(post(client, url, "git-upload-pack", 2, request)?)
+}
+
+/// Skips the sections of a v2 `fetch` response ahead of its pack, then
+/// receives that.
+fn receive_v2(mut response: Response) -> std::io::Result<()> {
+    skip_to_pack(&mut response)?;
+    receive(SideBand::new(response))
+}
+
+/// Reads past the sections of a v2 `fetch` response up to the header of
+/// the one holding the pack.
+fn skip_to_pack(response: &mut impl Read) -> std::io::Result<()> {
+    loop {
+        match read_line(response)? {
+            Some(section) if section == "packfile" => return Ok(()),
+            Some(_) => while let Packet::Data(_) = read_packet(response)? {},
+            None => return Err(invalid("expected 'packfile'")),
+        }
+    }
+}
+
+/// Indexes the pack `input` holds, completing it if it is thin.
+fn receive(input: impl Read) -> std::io::Result<()> {
+    let mut indexer = Indexer::open()?;
+    indexer.fix_thin = true;
+    indexer.receive(input)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_acks, read_advertisement, read_refs, skip_to_pack, Advertisement};
+    use crate::git::pktline::{read_packet, write_line, Packet};
+
+    const A: &str = "1111111111111111111111111111111111111111";
+    const B: &str = "2222222222222222222222222222222222222222";
+    const C: &str = "3333333333333333333333333333333333333333";
+
+    /// Packets holding `lines`, written as is when they are special.
+    fn packets(lines: &[&str]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for line in lines {
+            match *line {
+                "0000" | "0001" | "0002" => out.extend(line.as_bytes()),
+                _ => write_line(&mut out, &format!("{line}\n")).unwrap(),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn v0_advertisements() {
+        let input = packets(&[
+            "# service=git-upload-pack",
+            "0000",
+            &format!("{A} HEAD\0multi_ack symref=HEAD:refs/heads/main agent=git/2.39.5"),
+            &format!("{A} refs/heads/main"),
+            &format!("{B} refs/tags/v1"),
+            &format!("{C} refs/tags/v1^{{}}"),
+            &format!("{B} .have"),
+            "0000",
+        ]);
+        let advertisement = read_advertisement(&mut &input[..], "u", "git-upload-pack").unwrap();
+        assert_eq!(advertisement.version, 0);
+        assert_eq!(
+            advertisement.refs,
+            [("HEAD", A), ("refs/heads/main", A), ("refs/tags/v1", B)]
+                .map(|(x, y)| (x.to_string(), y.to_string()))
+        );
+        assert_eq!(advertisement.peeled["refs/tags/v1"], C);
+        assert_eq!(advertisement.symrefs["HEAD"], "refs/heads/main");
+        assert!(advertisement.capabilities.has("multi_ack"));
+
+        // an empty repository
+        let zero = "0".repeat(40);
+        let input = packets(&[
+            "# service=git-receive-pack",
+            "0000",
+            &format!("{zero} capabilities^{{}}\0report-status delete-refs"),
+            "0000",
+        ]);
+        let advertisement = read_advertisement(&mut &input[..], "u", "git-receive-pack").unwrap();
+        assert!(advertisement.refs.is_empty());
+        assert!(advertisement.capabilities.has("delete-refs"));
+    }
+
+    #[test]
+    fn bad_advertisements() {
+        let error = read_advertisement(&mut &packets(&["hello"])[..], "u", "git-upload-pack");
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "u/info/refs not valid: is this a git repository?"
+        );
+
+        let input = packets(&[
+            "# service=git-upload-pack",
+            "0000",
+            &format!("{A} HEAD\0object-format=sha256"),
+            "0000",
+        ]);
+        let error = read_advertisement(&mut &input[..], "u", "git-upload-pack").unwrap_err();
+        assert!(error.to_string().contains("object format 'sha256'"));
+    }
+
+    #[test]
+    fn v2_advertisements() {
+        let input = packets(&[
+            "version 2",
+            "agent=git/2.39.5",
+            "ls-refs=unborn",
+            "fetch=shallow wait-for-done",
+            "object-format=sha1",
+            "0000",
+        ]);
+        let advertisement = read_advertisement(&mut &input[..], "u", "git-upload-pack").unwrap();
+        assert_eq!(advertisement.version, 2);
+        assert!(advertisement.refs.is_empty());
+        assert_eq!(advertisement.capabilities.value("ls-refs"), Some("unborn"));
+        assert_eq!(advertisement.capabilities.agent(), Some("git/2.39.5"));
+    }
+
+    #[test]
+    fn ls_refs_responses() {
+        let input = packets(&[
+            &format!("{A} HEAD symref-target:refs/heads/main"),
+            &format!("{A} refs/heads/main"),
+            &format!("{B} refs/tags/v1 peeled:{C}"),
+            "unborn refs/remotes/origin/HEAD symref-target:refs/remotes/origin/x",
+            "0000",
+        ]);
+        let mut advertisement = Advertisement::default();
+        read_refs(&mut &input[..], &mut advertisement).unwrap();
+        assert_eq!(
+            advertisement.refs,
+            [("HEAD", A), ("refs/heads/main", A), ("refs/tags/v1", B)]
+                .map(|(x, y)| (x.to_string(), y.to_string()))
+        );
+        assert_eq!(advertisement.symrefs["HEAD"], "refs/heads/main");
+        assert_eq!(
+            advertisement.symrefs["refs/remotes/origin/HEAD"],
+            "refs/remotes/origin/x"
+        );
+        assert_eq!(advertisement.peeled["refs/tags/v1"], C);
+
+        let input = packets(&["garbage", "0000"]);
+        assert!(read_refs(&mut &input[..], &mut Advertisement::default()).is_err());
+    }
+
+    #[test]
+    fn acks() {
+        // v0 ends a round with a NAK, even when ready
+        let input = packets(&[
+            &format!("ACK {A} common"),
+            &format!("ACK {B}"),
+            &format!("ACK {C} ready"),
+            "NAK",
+            "0000",
+        ]);
+        let mut input = &input[..];
+        let (acked, ready) = read_acks(&mut input).unwrap();
+        assert_eq!(acked, [A, B]);
+        assert!(ready);
+        assert_eq!(read_packet(&mut input).unwrap(), Packet::Flush);
+
+        let input = packets(&["NAK"]);
+        assert_eq!(read_acks(&mut &input[..]).unwrap(), (vec![], false));
+
+        // v2 ends the section with a delimiter before the pack, a flush
+        // otherwise
+        let input = packets(&[&format!("ACK {A}"), "ready", "0001", "packfile"]);
+        let mut input = &input[..];
+        assert_eq!(read_acks(&mut input).unwrap(), (vec![A.to_string()], true));
+        assert_eq!(
+            read_packet(&mut input).unwrap(),
+            Packet::Data(b"packfile\n".to_vec())
+        );
+        let input = packets(&[&format!("ACK {A}"), "0000"]);
+        assert_eq!(
+            read_acks(&mut &input[..]).unwrap(),
+            (vec![A.to_string()], false)
+        );
+
+        let error = read_acks(&mut &packets(&["ERR? no"])[..]).unwrap_err();
+        assert_eq!(error.to_string(), "expected ACK/NAK, got 'ERR? no'");
+    }
+
+    #[test]
+    fn sections_before_the_pack() {
+        let mut input = packets(&[
+            "acknowledgments",
+            "NAK",
+            "0001",
+            "shallow-info",
+            &format!("shallow {A}"),
+            "0001",
+            "wanted-refs",
+            &format!("{A} refs/heads/main"),
+            "0001",
+            "packfile",
+        ]);
+        input.extend(b"0009\x01PACK0000");
+        let mut input = &input[..];
+        skip_to_pack(&mut input).unwrap();
+        assert_eq!(
+            read_packet(&mut input).unwrap(),
+            Packet::Data(b"\x01PACK".to_vec())
+        );
+
+        let input = packets(&["acknowledgments", "NAK", "0001", "0000"]);
+        let error = skip_to_pack(&mut &input[..]).unwrap_err();
+        assert_eq!(error.to_string(), "expected 'packfile'");
+    }
+}
@@ -0,0 +1,575 @@
+//! Checks that objects are well formed the way `git fsck` makes them,
+//! beyond what is needed to parse them: trees sorted and without odd
+//! names or modes, commit and tag headers in order and identities that
+//! are `Name <email> date zone`.
+
+use std::collections::HashSet;
+
+use super::{packfile::ObjType, refs};
+
+/// How bad a problem is. Warnings are errors under `--strict`, and
+/// informational problems are never more than warnings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Something wrong with an object, with git's camel-cased name for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub id: &'static str,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(id: &'static str, severity: Severity, message: impl Into<String>) -> Problem {
+        Problem {
+            id,
+            severity,
+            message: message.into(),
+        }
+    }
+
+    /// Whether the problem counts as an error, rather than a warning.
+    pub fn is_error(&self, strict: bool) -> bool {
+        match self.severity {
+            Severity::Info => false,
+            Severity::Warning => strict,
+            Severity::Error => true,
+        }
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.id, self.message)
+    }
+}
+
+/// Problems of `data` as an object of type `kind`. `strict` also rejects
+/// the group-writable file mode old versions of git wrote.
+pub fn check(kind: ObjType, data: &[u8], strict: bool) -> Vec<Problem> {
+    match kind {
+        ObjType::Blob => Vec::new(),
+        ObjType::Tree => check_tree(data, strict),
+        ObjType::Commit => check_commit(data).into_iter().collect(),
+        ObjType::Tag => check_tag(data),
+    }
+}
+
+fn error(id: &'static str, message: impl Into<String>) -> Problem {
+    Problem::new(id, Severity::Error, message)
+}
+
+/// An entry of a tree as it is stored.
+struct TreeEntry<'a> {
+    mode: &'a [u8],
+    name: &'a [u8],
+    hash: &'a [u8],
+}
+
+/// Splits a tree into its entries, `None` if it cannot be parsed.
+fn tree_entries(mut data: &[u8]) -> Option<Vec<TreeEntry<'_>>> {
+    let mut entries = Vec::new();
+    while !data.is_empty() {
+        let space = data.iter().position(|x| *x == b' ')?;
+        let nul = space + data[space..].iter().position(|x| *x == 0)?;
+        let (mode, name) = (&data[..space], &data[space + 1..nul]);
+        if mode.is_empty() || !mode.iter().all(|x| (b'0'..=b'7').contains(x)) {
+            return None;
+        }
+        let hash = data.get(nul + 1..nul + 21)?;
+        entries.push(TreeEntry { mode, name, hash });
+        data = &data[nul + 21..];
+    }
+    Some(entries)
+}
+
+/// Compares tree entry names the way trees are sorted, as if those of
+/// trees ended with a `/`.
+fn tree_order(a: &[u8], a_tree: bool, b: &[u8], b_tree: bool) -> std::cmp::Ordering {
+    let common = a.len().min(b.len());
+    a[..common].cmp(&b[..common]).then_with(|| {
+        let end = |name: &[u8], tree: bool| match name.get(common) {
+            Some(x) => Some(*x),
+            None if tree => Some(b'/'),
+            None => None,
+        };
+        end(a, a_tree).cmp(&end(b, b_tree))
+    })
+}
+
+fn check_tree(data: &[u8], strict: bool) -> Vec<Problem> {
+    let Some(entries) = tree_entries(data) else {
+        return vec![error("badTree", "cannot be parsed as a tree")];
+    };
+
+    let (mut null, mut full, mut empty, mut dot, mut dotdot, mut dotgit) =
+        (false, false, false, false, false, false);
+    let (mut zero_padded, mut bad_mode, mut duplicates, mut unsorted) =
+        (false, false, false, false);
+    let mut names = HashSet::new();
+    let mut previous: Option<(&[u8], bool)> = None;
+
+    for TreeEntry { mode, name, hash } in entries {
+        null |= hash.iter().all(|x| *x == 0);
+        full |= name.contains(&b'/');
+        empty |= name.is_empty();
+        dot |= name == b".";
+        dotdot |= name == b"..";
+        dotgit |= name.eq_ignore_ascii_case(b".git");
+        zero_padded |= mode[0] == b'0';
+
+        let mode = mode.iter().fold(0u32, |n, x| n << 3 | (x - b'0') as u32);
+        bad_mode |= match mode {
+            0o100644 | 0o100755 | 0o120000 | 0o040000 | 0o160000 => false,
+            0o100664 => strict,
+            _ => true,
+        };
+
+        // a file and a tree of the same name need not be next to each other
+        duplicates |= !names.insert(name);
+        let tree = mode == 0o040000;
+        if let Some((last, last_tree)) = previous {
+            unsorted |= tree_order(last, last_tree, name, tree).is_gt();
+        }
+        previous = Some((name, tree));
+    }
+
+    use Severity::{Error, Info, Warning};
+    [
+        (
+            null,
+            "nullSha1",
+            Warning,
+            "contains entries pointing to null sha1",
+        ),
+        (full, "fullPathname", Warning, "contains full pathnames"),
+        (empty, "emptyName", Warning, "contains empty pathname"),
+        (dot, "hasDot", Warning, "contains '.'"),
+        (dotdot, "hasDotdot", Warning, "contains '..'"),
+        (dotgit, "hasDotgit", Warning, "contains '.git'"),
+        (
+            zero_padded,
+            "zeroPaddedFilemode",
+            Warning,
+            "contains zero-padded file modes",
+        ),
+        (bad_mode, "badFilemode", Info, "contains bad file modes"),
+        (
+            duplicates,
+            "duplicateEntries",
+            Error,
+            "contains duplicate file entries",
+        ),
+        (unsorted, "treeNotSorted", Error, "not properly sorted"),
+    ]
+    .into_iter()
+    .filter(|x| x.0)
+    .map(|(_, id, severity, message)| Problem::new(id, severity, message))
+    .collect()
+}
+
+/// Checks that the header of a commit or tag ends, with an empty line or
+/// the end of the object, and holds no NUL byte.
+fn check_headers(data: &[u8]) -> Option<Problem> {
+    for (i, byte) in data.iter().enumerate() {
+        match byte {
+            0 => {
+                return Some(error(
+                    "nulInHeader",
+                    format!("unterminated header: NUL at offset {i}"),
+                ))
+            }
+            b'\n' if data.get(i + 1) == Some(&b'\n') => return None,
+            _ => {}
+        }
+    }
+    match data.last() {
+        Some(b'\n') => None,
+        _ => Some(error("unterminatedHeader", "unterminated header")),
+    }
+}
+
+/// Takes `prefix` off the start of `data`.
+fn header(data: &mut &[u8], prefix: &str) -> bool {
+    match data.strip_prefix(prefix.as_bytes()) {
+        Some(rest) => {
+            *data = rest;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Takes a line holding an object name off the start of `data`, `false`
+/// if it holds something else.
+fn object_line(data: &mut &[u8]) -> bool {
+    let valid = data.len() > 40
+        && data[..40]
+            .iter()
+            .all(|x| x.is_ascii_digit() || (b'a'..=b'f').contains(x))
+        && data[40] == b'\n';
+    let end = data
+        .iter()
+        .position(|x| *x == b'\n')
+        .map_or(data.len(), |x| x + 1);
+    *data = &data[end..];
+    valid
+}
+
+/// Checks the identity at the start of `data`, `Name <email> date zone`,
+/// and moves past its line.
+fn check_ident(data: &mut &[u8]) -> Option<Problem> {
+    let line = *data;
+    let end = line
+        .iter()
+        .position(|x| *x == b'\n')
+        .map_or(line.len(), |x| x + 1);
+    *data = &line[end..];
+
+    let invalid = |id, message: &str| {
+        Some(error(
+            id,
+            format!("invalid author/committer line - {message}"),
+        ))
+    };
+    let at = |i: usize| line.get(i).copied().unwrap_or(0);
+    let scan = |from: usize| {
+        from + line[from.min(line.len())..]
+            .iter()
+            .position(|x| b"<>\n".contains(x))
+            .unwrap_or(line.len() - from.min(line.len()))
+    };
+
+    if at(0) == b'<' {
+        return invalid("missingNameBeforeEmail", "missing space before email");
+    }
+    let mut p = scan(0);
+    match at(p) {
+        b'>' => return invalid("badName", "bad name"),
+        b'<' => {}
+        _ => return invalid("missingEmail", "missing email"),
+    }
+    if p == 0 || line[p - 1] != b' ' {
+        return invalid("missingSpaceBeforeEmail", "missing space before email");
+    }
+    p = scan(p + 1);
+    if at(p) != b'>' {
+        return invalid("badEmail", "bad email");
+    }
+    p += 1;
+    if at(p) != b' ' {
+        return invalid("missingSpaceBeforeDate", "missing space before date");
+    }
+    p += 1;
+    if at(p) == b'0' && at(p + 1) != b' ' {
+        return invalid("zeroPaddedDate", "zero-padded date");
+    }
+    let digits = line[p.min(line.len())..]
+        .iter()
+        .take_while(|x| x.is_ascii_digit())
+        .count();
+    let date = std::str::from_utf8(&line[p..p + digits]).unwrap_or_default();
+    if digits > 0 && date.parse::<i64>().is_err() {
+        return invalid("badDateOverflow", "date causes integer overflow");
+    }
+    if digits == 0 || at(p + digits) != b' ' {
+        return invalid("badDate", "bad date");
+    }
+    p += digits + 1;
+    if !matches!(at(p), b'+' | b'-')
+        || !(1..=4).all(|i| at(p + i).is_ascii_digit())
+        || at(p + 5) != b'\n'
+    {
+        return invalid("badTimezone", "bad time zone");
+    }
+    None
+}
+
+/// Checks the headers of a commit, stopping at the first problem as they
+/// are all errors.
+fn check_commit(data: &[u8]) -> Option<Problem> {
+    if let Some(problem) = check_headers(data) {
+        return Some(problem);
+    }
+    let mut rest = data;
+
+    if !header(&mut rest, "tree ") {
+        return Some(error(
+            "missingTree",
+            "invalid format - expected 'tree' line",
+        ));
+    }
+    if !object_line(&mut rest) {
+        return Some(error(
+            "badTreeSha1",
+            "invalid 'tree' line format - bad sha1",
+        ));
+    }
+    while header(&mut rest, "parent ") {
+        if !object_line(&mut rest) {
+            return Some(error(
+                "badParentSha1",
+                "invalid 'parent' line format - bad sha1",
+            ));
+        }
+    }
+
+    let mut authors = 0;
+    while header(&mut rest, "author ") {
+        authors += 1;
+        if let Some(problem) = check_ident(&mut rest) {
+            return Some(problem);
+        }
+    }
+    match authors {
+        0 => {
+            return Some(error(
+                "missingAuthor",
+                "invalid format - expected 'author' line",
+            ))
+        }
+        1 => {}
+        _ => {
+            return Some(error(
+                "multipleAuthors",
+                "invalid format - multiple 'author' lines",
+            ))
+        }
+    }
+
+    if !header(&mut rest, "committer ") {
+        return Some(error(
+            "missingCommitter",
+            "invalid format - expected 'committer' line",
+        ));
+    }
+    if let Some(problem) = check_ident(&mut rest) {
+        return Some(problem);
+    }
+
+    data.contains(&0).then(|| {
+        Problem::new(
+            "nulInCommit",
+            Severity::Warning,
+            "NUL byte in the commit object body",
+        )
+    })
+}
+
+/// Checks the headers of a tag, going on past problems that are only
+/// informational.
+fn check_tag(data: &[u8]) -> Vec<Problem> {
+    if let Some(problem) = check_headers(data) {
+        return vec![problem];
+    }
+    let mut rest = data;
+
+    if !header(&mut rest, "object ") {
+        return vec![error(
+            "missingObject",
+            "invalid format - expected 'object' line",
+        )];
+    }
+    if !object_line(&mut rest) {
+        return vec![error(
+            "badObjectSha1",
+            "invalid 'object' line format - bad sha1",
+        )];
+    }
+
+    if !header(&mut rest, "type ") {
+        return vec![error(
+            "missingTypeEntry",
+            "invalid format - expected 'type' line",
+        )];
+    }
+    let Some(end) = rest.iter().position(|x| *x == b'\n') else {
+        return vec![error(
+            "missingType",
+            "invalid format - unexpected end after 'type' line",
+        )];
+    };
+    let kind = std::str::from_utf8(&rest[..end]).unwrap_or_default();
+    if kind.parse::<ObjType>().is_err() {
+        return vec![error("badType", "invalid 'type' value")];
+    }
+    rest = &rest[end + 1..];
+
+    if !header(&mut rest, "tag ") {
+        return vec![error(
+            "missingTagEntry",
+            "invalid format - expected 'tag' line",
+        )];
+    }
+    let Some(end) = rest.iter().position(|x| *x == b'\n') else {
+        return vec![error(
+            "missingTag",
+            "invalid format - unexpected end after 'type' line",
+        )];
+    };
+    let mut problems = Vec::new();
+    let name = String::from_utf8_lossy(&rest[..end]);
+    if !refs::is_valid_name(&name) {
+        problems.push(Problem::new(
+            "badTagName",
+            Severity::Info,
+            format!("invalid 'tag' name: {name}"),
+        ));
+    }
+    rest = &rest[end + 1..];
+
+    if !header(&mut rest, "tagger ") {
+        // the earliest tags were made without one
+        problems.push(Problem::new(
+            "missingTaggerEntry",
+            Severity::Info,
+            "invalid format - expected 'tagger' line",
+        ));
+    } else {
+        problems.extend(check_ident(&mut rest));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, ObjType, Severity};
+
+    fn ids(kind: ObjType, data: &[u8], strict: bool) -> Vec<&'static str> {
+        check(kind, data, strict)
+            .into_iter()
+            .map(|x| x.id)
+            .collect()
+    }
+
+    fn tree(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (mode, name) in entries {
+            data.extend(format!("{mode} {name}\0").bytes());
+            data.extend([1; 20]);
+        }
+        data
+    }
+
+    const TREE: &str = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n";
+    const IDENT: &str = "A U Thor <author@example.com> 1700000000 +0100\n";
+
+    #[test]
+    fn well_formed_objects() {
+        let data = tree(&[("100644", "a"), ("40000", "a.b"), ("40000", "a0")]);
+        assert!(ids(ObjType::Tree, &data, true).is_empty());
+
+        let commit = format!("{TREE}author {IDENT}committer {IDENT}\nmessage\n");
+        assert!(ids(ObjType::Commit, commit.as_bytes(), true).is_empty());
+
+        let tag = format!(
+            "object 4b825dc642cb6eb9a060e54bf8d69288fbee4904\ntype tree\ntag v1\ntagger {IDENT}\nmessage\n"
+        );
+        assert!(ids(ObjType::Tag, tag.as_bytes(), true).is_empty());
+    }
+
+    #[test]
+    fn trees_sort_directories_with_a_slash() {
+        // "a.b" < "a/" for a tree named "a", but "a" < "a.b" for a file
+        let data = tree(&[("100644", "a"), ("100644", "a.b")]);
+        assert!(ids(ObjType::Tree, &data, false).is_empty());
+        let data = tree(&[("40000", "a"), ("100644", "a.b")]);
+        assert_eq!(ids(ObjType::Tree, &data, false), ["treeNotSorted"]);
+
+        let data = tree(&[("100644", "a"), ("100644", "a.b"), ("40000", "a")]);
+        assert_eq!(ids(ObjType::Tree, &data, false), ["duplicateEntries"]);
+    }
+
+    #[test]
+    fn tree_problems_come_in_order() {
+        let data = tree(&[
+            ("100664", ".git"),
+            ("0100644", "b"),
+            ("100600", "c"),
+            ("100644", ".."),
+        ]);
+        let problems = check(ObjType::Tree, &data, false);
+        let found = problems.iter().map(|x| x.id).collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                "hasDotdot",
+                "hasDotgit",
+                "zeroPaddedFilemode",
+                "badFilemode",
+                "treeNotSorted"
+            ]
+        );
+        assert_eq!(problems[3].severity, Severity::Info);
+        assert!(!problems[3].is_error(true));
+        assert!(problems[0].is_error(true) && !problems[0].is_error(false));
+
+        assert_eq!(ids(ObjType::Tree, b"100644 a\0short", false), ["badTree"]);
+    }
+
+    #[test]
+    fn commit_identities() {
+        let cases = [
+            ("<a@b> 1 +0000", "missingNameBeforeEmail"),
+            ("a> 1 +0000", "badName"),
+            ("a 1 +0000", "missingEmail"),
+            ("a<a@b> 1 +0000", "missingSpaceBeforeEmail"),
+            ("a <a@b 1 +0000", "badEmail"),
+            ("a <a@b>1 +0000", "missingSpaceBeforeDate"),
+            ("a <a@b> 01 +0000", "zeroPaddedDate"),
+            ("a <a@b> x +0000", "badDate"),
+            ("a <a@b> 1 0000", "badTimezone"),
+        ];
+        for (ident, id) in cases {
+            let commit = format!("{TREE}author {ident}\ncommitter {IDENT}\n");
+            assert_eq!(
+                ids(ObjType::Commit, commit.as_bytes(), false),
+                [id],
+                "{ident}"
+            );
+        }
+
+        let commit = format!("{TREE}author {IDENT}author {IDENT}committer {IDENT}");
+        assert_eq!(
+            ids(ObjType::Commit, commit.as_bytes(), false),
+            ["multipleAuthors"]
+        );
+        let commit = format!("{TREE}parent 123\nauthor {IDENT}committer {IDENT}");
+        assert_eq!(
+            ids(ObjType::Commit, commit.as_bytes(), false),
+            ["badParentSha1"]
+        );
+        let commit = format!("{TREE}author {IDENT}");
+        assert_eq!(
+            ids(ObjType::Commit, commit.as_bytes(), false),
+            ["missingCommitter"]
+        );
+        assert_eq!(
+            ids(ObjType::Commit, b"tree x", false),
+            ["unterminatedHeader"]
+        );
+    }
+
+    #[test]
+    fn tag_problems() {
+        let object = "object 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n";
+        let tag = format!("{object}type tree\ntag bad..name\n\nmessage\n");
+        assert_eq!(
+            ids(ObjType::Tag, tag.as_bytes(), true),
+            ["badTagName", "missingTaggerEntry"]
+        );
+
+        let tag = format!("{object}type thing\ntag v1\ntagger {IDENT}");
+        assert_eq!(ids(ObjType::Tag, tag.as_bytes(), false), ["badType"]);
+        let tag = format!("{object}tag v1\n");
+        assert_eq!(
+            ids(ObjType::Tag, tag.as_bytes(), false),
+            ["missingTypeEntry"]
+        );
+    }
+}
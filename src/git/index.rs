@@ -0,0 +1,341 @@
+use std::{
+    fs,
+    io::Write,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::Path,
+};
+
+use sha1::{Digest, Sha1};
+
+use super::helpers;
+
+const SIGNATURE: &[u8; 4] = b"DIRC";
+const NAME_MASK: u16 = 0x0fff;
+const EXTENDED: u16 = 0x4000;
+
+pub const MODE_FILE: u32 = 0o100644;
+pub const MODE_EXECUTABLE: u32 = 0o100755;
+pub const MODE_SYMLINK: u32 = 0o120000;
+pub const MODE_GITLINK: u32 = 0o160000;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub ctime: (u32, u32),
+    pub mtime: (u32, u32),
+    pub dev: u32,
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    pub hash: [u8; 20],
+    pub flags: u16,
+    pub path: String,
+}
+
+impl Entry {
+    /// Entry for `path` with the stat data of the file currently on disk.
+    pub fn new(path: &str, mode: u32, hash: [u8; 20], metadata: &fs::Metadata) -> Entry {
+        let mut entry = Entry {
+            mode,
+            hash,
+            path: path.to_string(),
+            ..Default::default()
+        };
+        entry.refresh(metadata);
+        entry
+    }
+
+    /// Stage of the entry, non zero while a merge conflict is unresolved.
+    pub fn stage(&self) -> u16 {
+        (self.flags >> 12) & 0x3
+    }
+
+    pub fn refresh(&mut self, metadata: &fs::Metadata) {
+        self.ctime = (metadata.ctime() as u32, metadata.ctime_nsec() as u32);
+        self.mtime = (metadata.mtime() as u32, metadata.mtime_nsec() as u32);
+        self.dev = metadata.dev() as u32;
+        self.ino = metadata.ino() as u32;
+        self.uid = metadata.uid();
+        self.gid = metadata.gid();
+        self.size = metadata.len() as u32;
+    }
+
+    /// Whether the stat data recorded for the entry still matches the file,
+    /// a mismatch only means the content has to be hashed again.
+    pub fn is_fresh(&self, metadata: &fs::Metadata) -> bool {
+        self.mtime == (metadata.mtime() as u32, metadata.mtime_nsec() as u32)
+            && self.ctime == (metadata.ctime() as u32, metadata.ctime_nsec() as u32)
+            && self.ino == metadata.ino() as u32
+            && self.size == metadata.len() as u32
+            && self.mode == mode_of(metadata)
+    }
+}
+
+/// Normalized git mode of a file on disk.
+pub fn mode_of(metadata: &fs::Metadata) -> u32 {
+    if metadata.file_type().is_symlink() {
+        MODE_SYMLINK
+    } else if metadata.is_dir() {
+        MODE_GITLINK
+    } else if metadata.permissions().mode() & 0o111 != 0 {
+        MODE_EXECUTABLE
+    } else {
+        MODE_FILE
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Index {
+    pub version: u32,
+    pub entries: Vec<Entry>,
+}
+
+impl Index {
+    /// Reads the index at `path`, a missing file is an empty index.
+    pub fn open(path: &Path) -> std::io::Result<Index> {
+        match fs::read(path) {
+            Ok(data) => parse(&data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Index {
+                version: 2,
+                entries: Vec::new(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the index through `index.lock` and renames it into place.
+    pub fn write(&mut self, path: &Path) -> std::io::Result<()> {
+        let lock = path.with_extension("lock");
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock)
+            .map_err(|e| {
+                std::io::Error::new(
+                    e.kind(),
+                    format!("unable to create {}: {e}", lock.display()),
+                )
+            })?;
+
+        let result = file
+            .write_all(&self.serialize())
+            .and_then(|_| file.sync_all())
+            .and_then(|_| fs::rename(&lock, path));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&lock);
+        }
+
+        result
+    }
+
+    pub fn find(&self, path: &str) -> Option<&Entry> {
+        self.position(path).ok().map(|x| &self.entries[x])
+    }
+
+    fn position(&self, path: &str) -> Result<usize, usize> {
+        self.entries.binary_search_by(|x| {
+            x.path
+                .as_bytes()
+                .cmp(path.as_bytes())
+                .then(x.stage().cmp(&0))
+        })
+    }
+
+    /// Adds or replaces the stage 0 entry for `entry.path`.
+    pub fn add(&mut self, mut entry: Entry) {
+        self.remove(&entry.path);
+        entry.flags = entry.path.len().min(NAME_MASK as usize) as u16;
+        match self.position(&entry.path) {
+            Ok(x) | Err(x) => self.entries.insert(x, entry),
+        }
+    }
+
+    /// Removes every stage of `path`.
+    pub fn remove(&mut self, path: &str) {
+        self.entries.retain(|x| x.path != path);
+    }
+
+    pub fn serialize(&mut self) -> Vec<u8> {
+        self.entries.sort_by(|a, b| {
+            a.path
+                .as_bytes()
+                .cmp(b.path.as_bytes())
+                .then(a.stage().cmp(&b.stage()))
+        });
+
+        let version: u32 = if self.entries.iter().any(|x| x.flags & EXTENDED != 0) {
+            3
+        } else {
+            2
+        };
+
+        let mut data = Vec::new();
+        data.extend_from_slice(SIGNATURE);
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+
+        for entry in self.entries.iter() {
+            let start = data.len();
+            for value in [
+                entry.ctime.0,
+                entry.ctime.1,
+                entry.mtime.0,
+                entry.mtime.1,
+                entry.dev,
+                entry.ino,
+                entry.mode,
+                entry.uid,
+                entry.gid,
+                entry.size,
+            ] {
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+            data.extend_from_slice(&entry.hash);
+
+            let length = entry.path.len().min(NAME_MASK as usize) as u16;
+            let flags = (entry.flags & !NAME_MASK) | length;
+            data.extend_from_slice(&flags.to_be_bytes());
+            if flags & EXTENDED != 0 {
+                data.extend_from_slice(&0u16.to_be_bytes());
+            }
+
+            data.extend_from_slice(entry.path.as_bytes());
+            // entries are NUL padded to a multiple of eight bytes, at least one NUL
+            let padding = 8 - (data.len() - start) % 8;
+            data.resize(data.len() + padding, 0);
+        }
+
+        let hash = Sha1::digest(&data);
+        data.extend_from_slice(&hash);
+        self.version = version;
+
+        data
+    }
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+pub fn parse(data: &[u8]) -> std::io::Result<Index> {
+    if data.len() < 32 {
+        return Err(invalid("index file is too short"));
+    }
+
+    let (content, checksum) = data.split_at(data.len() - 20);
+    if Sha1::digest(content).as_slice() != checksum {
+        return Err(invalid("index file checksum mismatch"));
+    }
+
+    let mut stream = content;
+    let signature = helpers::read_bytes::<4>(&mut stream)?;
+    let version = helpers::read_u32(&mut stream)?;
+    let count = helpers::read_u32(&mut stream)?;
+
+    if &signature != SIGNATURE {
+        return Err(invalid("index file has a bad signature"));
+    }
+    if !(2..=3).contains(&version) {
+        return Err(invalid("only index versions 2 and 3 are supported"));
+    }
+
+    let mut entries = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let start = content.len() - stream.len();
+
+        let mut values = [0; 10];
+        for value in values.iter_mut() {
+            *value = helpers::read_u32(&mut stream)?;
+        }
+        let hash = helpers::read_bytes::<20>(&mut stream)?;
+        let flags = u16::from_be_bytes(helpers::read_bytes(&mut stream)?);
+        if flags & EXTENDED != 0 {
+            let _ = helpers::read_bytes::<2>(&mut stream)?;
+        }
+
+        let Some(length) = stream.iter().position(|&x| x == 0) else {
+            return Err(invalid("index entry path is not terminated"));
+        };
+        let path = String::from_utf8_lossy(&stream[..length]).to_string();
+
+        let used = content.len() - stream.len() + length;
+        let padding = 8 - (used - start) % 8;
+        if stream.len() < length + padding {
+            return Err(invalid("index entry is truncated"));
+        }
+        stream = &stream[length + padding..];
+
+        entries.push(Entry {
+            ctime: (values[0], values[1]),
+            mtime: (values[2], values[3]),
+            dev: values[4],
+            ino: values[5],
+            mode: values[6],
+            uid: values[7],
+            gid: values[8],
+            size: values[9],
+            hash,
+            flags,
+            path,
+        });
+    }
+
+    // extensions (cached trees, resolve undo...) are dropped, they are only
+    // optimizations and git rebuilds them when missing
+    Ok(Index { version, entries })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, Index};
+
+    fn entry(path: &str) -> Entry {
+        Entry {
+            mode: super::MODE_FILE,
+            size: 12,
+            hash: [7; 20],
+            path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut index = Index::default();
+        index.add(entry("src/main.rs"));
+        index.add(entry("README.md"));
+        index.add(entry("a/very/long/path/name.txt"));
+
+        let data = index.serialize();
+        assert_eq!(&data[..4], b"DIRC");
+
+        let parsed = super::parse(&data).unwrap();
+        assert_eq!(parsed.version, 2);
+        assert_eq!(parsed.entries, index.entries);
+        assert_eq!(parsed.entries[0].path, "README.md");
+    }
+
+    #[test]
+    fn add_replaces_entry() {
+        let mut index = Index::default();
+        index.add(entry("file"));
+        let mut other = entry("file");
+        other.hash = [1; 20];
+        index.add(other);
+
+        assert_eq!(index.entries.len(), 1);
+        assert_eq!(index.find("file").unwrap().hash, [1; 20]);
+    }
+
+    #[test]
+    fn detect_corruption() {
+        let mut index = Index::default();
+        index.add(entry("file"));
+        let mut data = index.serialize();
+        data[20] ^= 0xff;
+
+        assert!(super::parse(&data).is_err());
+    }
+}
@@ -0,0 +1,664 @@
+//! Indexing packs as they are received, the way `git index-pack` does:
+//! the pack is written to disk as it is read, whole objects are hashed as
+//! they are inflated, and deltas are rebuilt afterwards from the file with
+//! only a bounded cache of their bases in memory.
+
+use flate2::{bufread::ZlibDecoder, write::ZlibEncoder, Compression};
+use sha1::{Digest, Sha1};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use super::{
+    config::{Config, Fsync},
+    helpers,
+    objects::{self, Store},
+    pack_index,
+    packfile::{self, EntryKind, ObjType},
+    repository,
+};
+
+/// An object of a pack being indexed.
+#[derive(Debug, Clone)]
+pub struct Object {
+    pub entry: pack_index::Entry,
+    pub stored: EntryKind,
+    /// Size of the data of the entry, that of the delta for deltas.
+    pub size: u64,
+    /// Type of the object, that of its base for deltas, known once it is
+    /// resolved.
+    pub kind: Option<ObjType>,
+    /// Position of the base of a delta among the objects of the pack.
+    pub base: Option<usize>,
+}
+
+/// Pack bytes as they are read from the input, written to the pack file
+/// and added to the pack checksum and the CRC of the current entry once
+/// consumed, so that inflating never takes more than the entry holds.
+struct Input<R, W> {
+    inner: R,
+    out: W,
+    buffer: Box<[u8]>,
+    start: usize,
+    end: usize,
+    /// Position in the pack of the next byte to consume.
+    offset: u64,
+    hasher: Sha1,
+    crc: crc32fast::Hasher,
+    /// Failure writing out consumed bytes, reported on the next read.
+    failed: Option<std::io::Error>,
+}
+
+impl<R: Read, W: Write> Read for Input<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read, W: Write> BufRead for Input<R, W> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if let Some(e) = self.failed.take() {
+            return Err(e);
+        }
+        if self.start == self.end {
+            self.start = 0;
+            self.end = self.inner.read(&mut self.buffer)?;
+        }
+        Ok(&self.buffer[self.start..self.end])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let consumed = &self.buffer[self.start..self.start + amt];
+        self.hasher.update(consumed);
+        self.crc.update(consumed);
+        if let Err(e) = self.out.write_all(consumed) {
+            self.failed.get_or_insert(e);
+        }
+        self.start += amt;
+        self.offset += amt as u64;
+    }
+}
+
+/// Inflates the data of an entry from `input`, passing it to `consume`
+/// and checking that there is as much of it as the entry header says.
+fn inflate(
+    input: &mut impl BufRead,
+    size: u64,
+    mut consume: impl FnMut(&[u8]),
+) -> std::io::Result<()> {
+    let mut decoder = ZlibDecoder::new(input);
+    let mut buffer = vec![0; 64 * 1024];
+    let mut total = 0;
+
+    loop {
+        let read = decoder.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        consume(&buffer[..read]);
+        total += read as u64;
+    }
+
+    if total != size {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("inflated {total} bytes of an entry of {size}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Reads a whole pack from `input`, copying it to `out`, and records
+/// where each entry is. Objects stored whole are named on the way, deltas
+/// are left for [`resolve`]. Returns them with the pack checksum.
+pub fn scan(input: impl Read, out: impl Write) -> std::io::Result<(Vec<Object>, [u8; 20])> {
+    let mut input = Input {
+        inner: input,
+        out,
+        buffer: vec![0; 64 * 1024].into_boxed_slice(),
+        start: 0,
+        end: 0,
+        offset: 0,
+        hasher: Sha1::new(),
+        crc: crc32fast::Hasher::new(),
+        failed: None,
+    };
+
+    let (_, _, count) = packfile::headers(&mut input)?;
+    let mut objects = Vec::with_capacity(count as usize);
+
+    for _ in 0..count {
+        let offset = input.offset;
+        input.crc = crc32fast::Hasher::new();
+        let (stored, size) = packfile::entry_header(&mut input, offset)?;
+
+        let mut name = [0; 20];
+        let mut hasher = Sha1::new();
+        let kind = match stored {
+            EntryKind::Base(kind) => {
+                hasher.update(format!("{kind} {size}\0"));
+                Some(kind)
+            }
+            EntryKind::Ofs(_) | EntryKind::Ref(_) => None,
+        };
+        inflate(&mut input, size, |x| {
+            if kind.is_some() {
+                hasher.update(x)
+            }
+        })
+        .map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("pack has bad object at offset {offset}: {e}"),
+            )
+        })?;
+        if kind.is_some() {
+            name = hasher.finalize().into();
+        }
+
+        let crc = std::mem::take(&mut input.crc).finalize();
+        objects.push(Object {
+            entry: pack_index::Entry { name, crc, offset },
+            stored,
+            size,
+            kind,
+            base: None,
+        });
+    }
+
+    let checksum: [u8; 20] = input.hasher.clone().finalize().into();
+    let trailer = helpers::read_bytes::<20>(&mut input)?;
+    if trailer != checksum {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "pack is corrupted (SHA1 mismatch)",
+        ));
+    }
+    if let Some(e) = input.failed.take() {
+        return Err(e);
+    }
+    input.out.flush()?;
+
+    Ok((objects, checksum))
+}
+
+/// Delta bases recently rebuilt, by pack offset, dropping the least
+/// recently used once they take more than `limit` bytes.
+struct BaseCache {
+    limit: usize,
+    used: usize,
+    tick: u64,
+    bases: HashMap<u64, (u64, ObjType, Rc<Vec<u8>>)>,
+    /// Offsets of the cached bases by the tick they were last used at.
+    order: BTreeMap<u64, u64>,
+}
+
+impl BaseCache {
+    fn new(limit: usize) -> BaseCache {
+        BaseCache {
+            limit,
+            used: 0,
+            tick: 0,
+            bases: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, offset: u64) -> Option<(ObjType, Rc<Vec<u8>>)> {
+        let (tick, kind, data) = self.bases.get_mut(&offset)?;
+        self.order.remove(tick);
+        self.tick += 1;
+        *tick = self.tick;
+        self.order.insert(self.tick, offset);
+        Some((*kind, data.clone()))
+    }
+
+    fn insert(&mut self, offset: u64, kind: ObjType, data: Rc<Vec<u8>>) {
+        if data.len() > self.limit || self.bases.contains_key(&offset) {
+            return;
+        }
+        while self.used + data.len() > self.limit {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((_, _, evicted)) = self.bases.remove(&oldest) {
+                self.used -= evicted.len();
+            }
+        }
+
+        self.tick += 1;
+        self.used += data.len();
+        self.order.insert(self.tick, offset);
+        self.bases.insert(offset, (self.tick, kind, data));
+    }
+}
+
+/// Rebuilds deltas from the pack file to name them.
+struct Resolver<'a> {
+    pack: BufReader<&'a fs::File>,
+    objects: &'a mut [Object],
+    cache: BaseCache,
+    /// Position in `objects` of every object named so far, for deltas
+    /// whose base is given by name.
+    names: HashMap<[u8; 20], usize>,
+}
+
+impl Resolver<'_> {
+    /// Inflated data of the entry at `position`.
+    fn data(&mut self, position: usize) -> std::io::Result<Vec<u8>> {
+        let offset = self.objects[position].entry.offset;
+        self.pack.seek(SeekFrom::Start(offset))?;
+        let (_, size) = packfile::entry_header(&mut self.pack, offset)?;
+
+        let mut data = Vec::with_capacity(size as usize);
+        inflate(&mut self.pack, size, |x| data.extend_from_slice(x))?;
+        Ok(data)
+    }
+
+    /// Position of the base of the delta at `position`, if it is in the
+    /// pack and named yet.
+    fn base(&self, position: usize) -> Option<usize> {
+        match self.objects[position].stored {
+            EntryKind::Base(_) => None,
+            EntryKind::Ofs(offset) => self
+                .objects
+                .binary_search_by_key(&offset, |x| x.entry.offset)
+                .ok(),
+            EntryKind::Ref(name) => self.names.get(&name).copied(),
+        }
+    }
+
+    /// Names the delta at `position`, rebuilding the chain of deltas it
+    /// ends from the nearest cached or whole object. Returns whether it
+    /// could, which it cannot while a base is missing.
+    fn object(&mut self, position: usize) -> std::io::Result<bool> {
+        let mut chain = vec![position];
+        let mut current = position;
+
+        let (kind, mut data) = loop {
+            current = match self.base(current) {
+                Some(base) if !chain.contains(&base) => base,
+                Some(_) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "delta chain refers back to itself",
+                    ))
+                }
+                None => return Ok(false),
+            };
+
+            let offset = self.objects[current].entry.offset;
+            if let Some(cached) = self.cache.get(offset) {
+                break cached;
+            }
+            if let EntryKind::Base(kind) = self.objects[current].stored {
+                let data = Rc::new(self.data(current)?);
+                self.cache.insert(offset, kind, data.clone());
+                break (kind, data);
+            }
+            chain.push(current);
+        };
+
+        for delta in chain.into_iter().rev() {
+            data = Rc::new(packfile::process_delta(&self.data(delta)?, &data)?);
+
+            let base = self.base(delta);
+            let object = &mut self.objects[delta];
+            self.cache.insert(object.entry.offset, kind, data.clone());
+            if object.kind.is_none() {
+                object.kind = Some(kind);
+                object.base = base;
+                object.entry.name = objects::hash(kind, &data);
+                self.names.insert(object.entry.name, delta);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// Names the deltas of `objects`, as read by [`scan`] from `pack`,
+/// keeping at most `cache_limit` bytes of bases in memory. Returns how
+/// many could not be resolved, their base being elsewhere.
+pub fn resolve(
+    pack: &fs::File,
+    objects: &mut [Object],
+    cache_limit: u64,
+) -> std::io::Result<usize> {
+    let names = objects
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.kind.is_some())
+        .map(|(n, x)| (x.entry.name, n))
+        .collect();
+
+    let mut resolver = Resolver {
+        pack: BufReader::new(pack),
+        objects,
+        cache: BaseCache::new(usize::try_from(cache_limit).unwrap_or(usize::MAX)),
+        names,
+    };
+
+    // deltas by name may come before their base is named, so go over
+    // them again for as long as that names more
+    loop {
+        let mut resolved = 0;
+        let mut unresolved = 0;
+        for position in 0..resolver.objects.len() {
+            if resolver.objects[position].kind.is_some() {
+                continue;
+            }
+            match resolver.object(position)? {
+                true => resolved += 1,
+                false => unresolved += 1,
+            }
+        }
+        if resolved == 0 || unresolved == 0 {
+            return Ok(unresolved);
+        }
+    }
+}
+
+/// Indexes packs, with the settings the configuration gives.
+pub struct Indexer {
+    cache_limit: u64,
+    fsync_pack: bool,
+    fsync_index: bool,
+    /// Whether to write a reverse index along with the index.
+    pub rev_index: bool,
+    /// Whether to complete thin packs with the bases of their deltas
+    /// found in the object database.
+    pub fix_thin: bool,
+}
+
+impl Indexer {
+    pub fn open() -> std::io::Result<Indexer> {
+        let config = Config::load()?;
+        Ok(Indexer {
+            cache_limit: config.delta_base_cache_limit()?,
+            fsync_pack: config.fsync(Fsync::Pack)?,
+            fsync_index: config.fsync(Fsync::PackMetadata)?,
+            rev_index: config.get_bool("pack.writeReverseIndex")?.unwrap_or(true),
+            fix_thin: false,
+        })
+    }
+
+    /// Names the deltas of the pack in `pack`, whose entries [`scan`]
+    /// found, completing it first if it is thin and that is asked for.
+    /// Returns the pack checksum, which completing changes.
+    pub fn index(
+        &self,
+        pack: &mut fs::File,
+        objects: &mut Vec<Object>,
+        mut checksum: [u8; 20],
+    ) -> std::io::Result<[u8; 20]> {
+        let mut unresolved = resolve(pack, objects, self.cache_limit)?;
+        if unresolved != 0 && self.fix_thin {
+            checksum = complete(pack, objects, self.cache_limit)?;
+            unresolved = objects.iter().filter(|x| x.kind.is_none()).count();
+        }
+
+        if unresolved != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                match unresolved {
+                    1 => "pack has 1 unresolved delta".to_string(),
+                    n => format!("pack has {n} unresolved deltas"),
+                },
+            ));
+        }
+        if self.fsync_pack {
+            pack.sync_all()?;
+        }
+        Ok(checksum)
+    }
+
+    /// Writes the index of the pack with `checksum` holding `entries` to
+    /// `index`, with its reverse index next to it if asked for, replacing
+    /// them in one go.
+    pub fn write_index(
+        &self,
+        mut entries: Vec<pack_index::Entry>,
+        checksum: &[u8; 20],
+        index: &Path,
+    ) -> std::io::Result<()> {
+        entries.sort_by_key(|x| x.name);
+
+        let dir = match index.parent() {
+            Some(dir) if dir != Path::new("") => dir,
+            _ => Path::new("."),
+        };
+        // the reverse index goes first, so it is there whenever the index is
+        let mut files = Vec::new();
+        if self.rev_index {
+            files.push(("tmp_rev", index.with_extension("rev"), true));
+        }
+        files.push(("tmp_idx", index.to_path_buf(), false));
+
+        for (prefix, path, reverse) in files {
+            let (temporary, file) = objects::temporary_file(dir, prefix)?;
+            let written = (|| {
+                let mut out = BufWriter::new(file);
+                match reverse {
+                    true => pack_index::write_reverse(&mut out, &entries, checksum)?,
+                    false => pack_index::write(&mut out, &entries, checksum)?,
+                };
+                let file = out.into_inner().map_err(|e| e.into_error())?;
+                if self.fsync_index {
+                    file.sync_all()?;
+                }
+                fs::rename(&temporary, path)
+            })();
+
+            if written.is_err() {
+                let _ = fs::remove_file(&temporary);
+            }
+            written?;
+        }
+        Ok(())
+    }
+
+    /// Stores the pack read from `input` in the object database with its
+    /// index, returning the pack checksum that names them.
+    pub fn receive(&self, input: impl Read) -> std::io::Result<[u8; 20]> {
+        let dir = repository::objects_dir().join("pack");
+        fs::create_dir_all(&dir)?;
+
+        let (temporary, file) = objects::temporary_file(&dir, "tmp_pack")?;
+        let stored = (|| {
+            let mut out = BufWriter::new(file);
+            let (mut objects, checksum) = scan(input, &mut out)?;
+            let mut pack = out.into_inner().map_err(|e| e.into_error())?;
+            let checksum = self.index(&mut pack, &mut objects, checksum)?;
+
+            // the index goes last, so the pack is there whenever it is found
+            let name = dir.join(format!("pack-{}", hex::encode(checksum)));
+            fs::rename(&temporary, name.with_extension("pack"))?;
+            self.write_index(entries(&objects), &checksum, &name.with_extension("idx"))?;
+            Ok(checksum)
+        })();
+
+        if stored.is_err() {
+            let _ = fs::remove_file(temporary);
+        }
+        stored
+    }
+}
+
+/// Index entries of `objects`.
+pub fn entries(objects: &[Object]) -> Vec<pack_index::Entry> {
+    objects.iter().map(|x| x.entry).collect()
+}
+
+/// Completes a thin pack by appending to it the bases its deltas name
+/// that are in the object database, one at a time as its deltas may
+/// rebuild others named as bases, returning its new checksum.
+fn complete(
+    pack: &mut fs::File,
+    objects: &mut Vec<Object>,
+    cache_limit: u64,
+) -> std::io::Result<[u8; 20]> {
+    let mut store = Store::open()?;
+    let mut end = pack.seek(SeekFrom::End(0))? - 20;
+    pack.set_len(end)?;
+
+    let mut tried = HashSet::new();
+    loop {
+        let present = objects
+            .iter()
+            .filter(|x| x.kind.is_some())
+            .map(|x| x.entry.name)
+            .collect::<HashSet<_>>();
+        let missing = objects
+            .iter()
+            .filter(|x| x.kind.is_none())
+            .filter_map(|x| match x.stored {
+                EntryKind::Ref(name) if !present.contains(&name) => Some(name),
+                _ => None,
+            })
+            .filter(|x| !tried.contains(x))
+            .min();
+        let Some(name) = missing else {
+            break;
+        };
+        tried.insert(name);
+
+        let mut base = match store.stream(&hex::encode(name)) {
+            Ok(base) => base,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        let header = packfile::encode_entry_header(EntryKind::Base(base.kind), base.size, end);
+        let mut encoder = ZlibEncoder::new(header.clone(), Compression::default());
+        std::io::copy(&mut base, &mut encoder)?;
+        let entry = encoder.finish()?;
+
+        pack.seek(SeekFrom::Start(end))?;
+        pack.write_all(&entry)?;
+        objects.push(Object {
+            entry: pack_index::Entry {
+                name,
+                crc: crc32fast::hash(&entry),
+                offset: end,
+            },
+            stored: EntryKind::Base(base.kind),
+            size: base.size,
+            kind: Some(base.kind),
+            base: None,
+        });
+        end += entry.len() as u64;
+
+        resolve(pack, objects, cache_limit)?;
+    }
+
+    pack.seek(SeekFrom::Start(8))?;
+    pack.write_all(&(objects.len() as u32).to_be_bytes())?;
+
+    pack.seek(SeekFrom::Start(0))?;
+    let mut hasher = Sha1::new();
+    std::io::copy(&mut BufReader::new(&mut *pack), &mut hasher)?;
+    let checksum: [u8; 20] = hasher.finalize().into();
+    pack.write_all(&checksum)?;
+    Ok(checksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::{resolve, scan, BaseCache};
+    use crate::git::{
+        objects,
+        packfile::{encode_entry_header, EntryKind::*, ObjType},
+    };
+
+    #[test]
+    fn base_cache_drops_least_recently_used() {
+        let mut cache = BaseCache::new(10);
+        cache.insert(1, ObjType::Blob, Rc::new(vec![0; 4]));
+        cache.insert(2, ObjType::Blob, Rc::new(vec![0; 4]));
+        assert!(cache.get(1).is_some());
+
+        cache.insert(3, ObjType::Blob, Rc::new(vec![0; 4]));
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(3).is_some());
+
+        cache.insert(4, ObjType::Blob, Rc::new(vec![0; 11]));
+        assert!(cache.get(4).is_none());
+    }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn scans_and_resolves_deltas() {
+        use sha1::Digest;
+
+        let base = b"hello world, hello packs\n".to_vec();
+        // copy the first 12 bytes of the base, then insert "git\n"
+        let delta = [&[25, 16, 0x90, 12, 4][..], b"git\n"].concat();
+        let target = b"hello world,git\n".to_vec();
+
+        let mut pack = [&b"PACK"[..], &2u32.to_be_bytes(), &3u32.to_be_bytes()].concat();
+        pack.extend(encode_entry_header(
+            Base(ObjType::Blob),
+            base.len() as u64,
+            12,
+        ));
+        pack.extend(deflate(&base));
+        let second = pack.len() as u64;
+        pack.extend(encode_entry_header(Ofs(12), delta.len() as u64, second));
+        pack.extend(deflate(&delta));
+        let name = objects::hash(ObjType::Blob, &base);
+        pack.extend(encode_entry_header(Ref(name), delta.len() as u64, 0));
+        pack.extend(deflate(&delta));
+        let checksum: [u8; 20] = sha1::Sha1::digest(&pack).into();
+        pack.extend(checksum);
+
+        let mut copy = Vec::new();
+        let (mut scanned, trailer) = scan(&pack[..], &mut copy).unwrap();
+        assert_eq!(copy, pack);
+        assert_eq!(trailer, checksum);
+        assert_eq!(scanned.len(), 3);
+        assert_eq!(scanned[0].entry.name, objects::hash(ObjType::Blob, &base));
+        assert_eq!(scanned[1].entry.offset, second);
+        assert!(scanned[1].kind.is_none());
+
+        let path = std::env::temp_dir().join(format!("index-pack-{}.pack", std::process::id()));
+        std::fs::write(&path, &pack).unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+        assert_eq!(resolve(&file, &mut scanned, 0).unwrap(), 0);
+        std::fs::remove_file(path).unwrap();
+
+        for object in &scanned[1..] {
+            assert_eq!(object.kind, Some(ObjType::Blob));
+            assert_eq!(object.base, Some(0));
+            assert_eq!(object.entry.name, objects::hash(ObjType::Blob, &target));
+        }
+    }
+
+    #[test]
+    fn corrupt_trailer() {
+        let mut pack = [&b"PACK"[..], &2u32.to_be_bytes(), &1u32.to_be_bytes()].concat();
+        pack.extend(encode_entry_header(Base(ObjType::Blob), 2, 12));
+        pack.extend(deflate(b"hi"));
+        pack.extend([0; 20]);
+
+        assert!(scan(&pack[..], std::io::sink()).is_err());
+    }
+}
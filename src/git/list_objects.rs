@@ -0,0 +1,202 @@
+use std::{collections::HashSet, str::FromStr};
+
+use super::{
+    index::MODE_GITLINK,
+    objects::{commit::Commit, Object},
+    packfile::ObjType,
+    revwalk::RevWalk,
+};
+
+/// Objects left out of an enumeration, as given to `--filter=<spec>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectFilter {
+    /// `blob:none`, every blob is omitted.
+    BlobNone,
+}
+
+impl FromStr for ObjectFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blob:none" => Ok(ObjectFilter::BlobNone),
+            _ => Err(format!("invalid filter-spec '{s}'")),
+        }
+    }
+}
+
+impl ObjectFilter {
+    fn accepts(&self, kind: ObjType) -> bool {
+        match self {
+            ObjectFilter::BlobNone => kind != ObjType::Blob,
+        }
+    }
+}
+
+/// An object reached by [`ObjectWalk`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub hash: String,
+    pub kind: ObjType,
+    /// Path of a tree or blob from the root tree, the tag name of a tag,
+    /// `None` for commits.
+    pub name: Option<String>,
+    /// Set for commits only.
+    pub commit: Option<Commit>,
+}
+
+/// Enumerates every object needed by the commits of a [`RevWalk`]: the
+/// commits first, then the tags naming the tips, then the trees and blobs of
+/// each commit depth first. Objects are reported once, and those reachable
+/// from the tree of an excluded tip are not reported at all.
+pub struct ObjectWalk {
+    walk: RevWalk,
+    filter: Option<ObjectFilter>,
+    seen: HashSet<String>,
+    /// Root trees of the commits output so far, in order.
+    trees: Vec<String>,
+    /// Objects still to visit, the next one last.
+    stack: Vec<(String, ObjType, String)>,
+    commits_done: bool,
+}
+
+fn read_tree(hash: &str) -> std::io::Result<Vec<(String, ObjType, String)>> {
+    let Object::Tree(items) = Object::read_from_hash(hash)? else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{hash} is not a tree"),
+        ));
+    };
+
+    Ok(items
+        .into_iter()
+        .filter(|x| x.mode != MODE_GITLINK)
+        .map(|x| {
+            let kind = match x.is_tree() {
+                true => ObjType::Tree,
+                false => ObjType::Blob,
+            };
+            (hex::encode(x.hash), kind, x.name)
+        })
+        .collect())
+}
+
+impl ObjectWalk {
+    pub fn new(walk: RevWalk, filter: Option<ObjectFilter>) -> std::io::Result<ObjectWalk> {
+        let mut seen = HashSet::new();
+
+        // everything the excluded side already has
+        let mut stack = Vec::new();
+        for hash in walk.hidden() {
+            let commit = super::revwalk::read_commit(hash)?;
+            stack.push((commit.tree, ObjType::Tree));
+        }
+        while let Some((hash, kind)) = stack.pop() {
+            if !seen.insert(hash.clone()) || kind != ObjType::Tree {
+                continue;
+            }
+            stack.extend(read_tree(&hash)?.into_iter().map(|(x, kind, _)| (x, kind)));
+        }
+
+        Ok(ObjectWalk {
+            walk,
+            filter,
+            seen,
+            trees: Vec::new(),
+            stack: Vec::new(),
+            commits_done: false,
+        })
+    }
+
+    /// The commit walk, to query flags such as [`RevWalk::is_left`].
+    pub fn revwalk(&self) -> &RevWalk {
+        &self.walk
+    }
+
+    fn next_commit(&mut self) -> std::io::Result<Option<Entry>> {
+        let Some((hash, commit)) = self.walk.next_commit()? else {
+            self.commits_done = true;
+
+            // pending objects are visited in the order they were queued
+            for tree in self.trees.drain(..).rev() {
+                self.stack.push((tree, ObjType::Tree, String::new()));
+            }
+            for (hash, name) in self.walk.tags().iter().rev() {
+                self.stack.push((hash.clone(), ObjType::Tag, name.clone()));
+            }
+            return Ok(None);
+        };
+
+        self.seen.insert(hash.clone());
+        self.trees.push(commit.tree.clone());
+
+        Ok(Some(Entry {
+            hash,
+            kind: ObjType::Commit,
+            name: None,
+            commit: Some(commit),
+        }))
+    }
+
+    pub fn next_object(&mut self) -> std::io::Result<Option<Entry>> {
+        if !self.commits_done {
+            if let Some(entry) = self.next_commit()? {
+                return Ok(Some(entry));
+            }
+        }
+
+        while let Some((hash, kind, name)) = self.stack.pop() {
+            if self.filter.is_some_and(|x| !x.accepts(kind)) || !self.seen.insert(hash.clone()) {
+                continue;
+            }
+
+            if kind == ObjType::Tree {
+                let prefix = match name.is_empty() {
+                    true => String::new(),
+                    false => format!("{name}/"),
+                };
+                for (child, kind, file) in read_tree(&hash)?.into_iter().rev() {
+                    self.stack.push((child, kind, format!("{prefix}{file}")));
+                }
+            }
+
+            return Ok(Some(Entry {
+                hash,
+                kind,
+                name: Some(name),
+                commit: None,
+            }));
+        }
+
+        Ok(None)
+    }
+}
+
+impl Iterator for ObjectWalk {
+    type Item = std::io::Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_object().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ObjectFilter;
+    use crate::git::packfile::ObjType;
+
+    #[test]
+    fn parse_filter_spec() {
+        assert_eq!("blob:none".parse(), Ok(ObjectFilter::BlobNone));
+        assert!("blob:limit=1k".parse::<ObjectFilter>().is_err());
+        assert!("tree:0".parse::<ObjectFilter>().is_err());
+    }
+
+    #[test]
+    fn blob_none_keeps_trees() {
+        let filter = ObjectFilter::BlobNone;
+        assert!(!filter.accepts(ObjType::Blob));
+        assert!(filter.accepts(ObjType::Tree));
+        assert!(filter.accepts(ObjType::Commit));
+    }
+}
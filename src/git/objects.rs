@@ -1,99 +1,746 @@
 use blob::Blob;
 use commit::Commit;
+use tag::Tag;
 use tree::TreeItem;
 
-use flate2::read::ZlibDecoder;
+use flate2::{bufread, read::ZlibDecoder, write::ZlibEncoder, Compression};
+use sha1::{Digest, Sha1};
 use std::{
     ffi::CStr,
     fs,
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    config::{Config, Fsync},
+    helpers,
+    pack_index::{self, PackIndex},
+    packfile::{self, EntryKind, ObjType},
+    repository,
 };
 
 pub mod blob;
 pub mod commit;
+pub mod tag;
 pub mod tree;
 
 #[derive(Debug, PartialEq, Eq)]
+#[allow(clippy::large_enum_variant)]
 pub enum Object {
     Blob(Blob),
     Tree(Vec<TreeItem>),
     Commit(Commit),
+    Tag(Tag),
 }
 
-impl Object {
-    pub fn read_from_hash(hash: &str) -> std::io::Result<Object> {
-        let path = format!(".git/objects/{}/{}", &hash[..2], &hash[2..]);
-        let data = fs::File::open(path)?;
+/// Path of the loose object file for `hash`.
+pub fn loose_path(hash: &str) -> PathBuf {
+    repository::objects_dir().join(&hash[..2]).join(&hash[2..])
+}
+
+/// Object name of `data` stored with the type `kind`.
+pub fn hash(kind: impl std::fmt::Display, data: &[u8]) -> [u8; 20] {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{kind} {}\0", data.len()));
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+/// Object name of `size` bytes read from `data` stored with the type
+/// `kind`, without holding them in memory.
+pub fn hash_stream(
+    kind: impl std::fmt::Display,
+    mut data: impl Read,
+    size: u64,
+) -> std::io::Result<[u8; 20]> {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{kind} {size}\0"));
+    copy(&mut data, size, |chunk| {
+        hasher.update(chunk);
+        Ok(())
+    })?;
+    Ok(hasher.finalize().into())
+}
+
+/// Passes exactly `size` bytes of `data` to `consume`, a chunk at a time.
+fn copy(
+    data: &mut impl Read,
+    size: u64,
+    mut consume: impl FnMut(&[u8]) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let mut buffer = vec![0; 64 * 1024];
+    let mut left = size;
+
+    while left > 0 {
+        let wanted = buffer
+            .len()
+            .min(usize::try_from(left).unwrap_or(usize::MAX));
+        let read = data.read(&mut buffer[..wanted])?;
+        if read == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("read {} bytes of {size}", size - left),
+            ));
+        }
+        consume(&buffer[..read])?;
+        left -= read as u64;
+    }
+
+    if data.read(&mut buffer[..1])? != 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("more than the {size} bytes expected to read"),
+        ));
+    }
+    Ok(())
+}
+
+/// Stores `data` as a loose object of type `kind`, returning its name.
+pub fn write(kind: impl std::fmt::Display, data: &[u8]) -> std::io::Result<[u8; 20]> {
+    Writer::open()?.write(kind, data)
+}
 
-        let d = ZlibDecoder::new(data);
-        let mut buff = Vec::new();
-        let mut d = BufReader::new(d);
+/// Stores loose objects the way git does: compressed into a temporary
+/// file, synced to disk if `core.fsync` asks for it, and linked into place
+/// read-only, so that readers never see a partial object.
+pub struct Writer {
+    store: Store,
+    level: Compression,
+    fsync: bool,
+    big_file_threshold: u64,
+}
+
+impl Writer {
+    pub fn open() -> std::io::Result<Writer> {
+        let config = Config::load()?;
+        // loose objects are compressed for speed unless asked otherwise
+        let level = config
+            .compression("core.looseCompression")?
+            .unwrap_or(Compression::fast());
 
-        d.read_until(0, &mut buff).unwrap();
+        Ok(Writer {
+            store: Store::open()?,
+            level,
+            fsync: config.fsync(Fsync::LooseObject)?,
+            big_file_threshold: config.big_file_threshold()?,
+        })
+    }
 
-        let header = CStr::from_bytes_until_nul(&buff).unwrap();
-        let header = header.to_str().unwrap();
+    /// Size above which files are to be stored with [`Writer::write_stream`].
+    pub fn big_file_threshold(&self) -> u64 {
+        self.big_file_threshold
+    }
 
-        let Some((kind, _)) = header.split_once(' ') else {
-            panic!("Unexpected header format!");
+    /// Stores `data` as an object of type `kind` unless it already exists,
+    /// in which case it is only marked as recently used, returning its name.
+    pub fn write(
+        &mut self,
+        kind: impl std::fmt::Display,
+        data: &[u8],
+    ) -> std::io::Result<[u8; 20]> {
+        let name = hash(&kind, data);
+        if self.freshen(&name)? {
+            return Ok(name);
+        }
+        self.write_stream(kind, data, data.len() as u64)
+    }
+
+    /// Stores `size` bytes read from `data` as an object of type `kind`,
+    /// without holding them in memory, returning its name.
+    pub fn write_stream(
+        &mut self,
+        kind: impl std::fmt::Display,
+        mut data: impl Read,
+        size: u64,
+    ) -> std::io::Result<[u8; 20]> {
+        // the name, and so the directory, is only known once all is read
+        let (temporary, file) = temporary_file(&repository::objects_dir(), "tmp_obj")?;
+        let written = self
+            .compress(file, &kind, &mut data, size)
+            .and_then(|name| self.install(&temporary, name));
+
+        let _ = fs::remove_file(&temporary);
+        written
+    }
+
+    /// Writes the compressed object to `file`, returning its name.
+    fn compress(
+        &self,
+        file: fs::File,
+        kind: &impl std::fmt::Display,
+        data: &mut impl Read,
+        size: u64,
+    ) -> std::io::Result<[u8; 20]> {
+        let header = format!("{kind} {size}\0");
+        let mut hasher = Sha1::new();
+        hasher.update(&header);
+
+        let mut encoder = ZlibEncoder::new(file, self.level);
+        encoder.write_all(header.as_bytes())?;
+        copy(data, size, |chunk| {
+            hasher.update(chunk);
+            encoder.write_all(chunk)
+        })?;
+        let file = encoder.finish()?;
+
+        if self.fsync {
+            file.sync_all()?;
+        }
+        Ok(hasher.finalize().into())
+    }
+
+    /// Stores `data` as a loose object of type `kind` even if a pack holds
+    /// it, for objects about to be dropped from their pack, returning its
+    /// name.
+    pub fn write_loose(
+        &mut self,
+        kind: impl std::fmt::Display,
+        data: &[u8],
+    ) -> std::io::Result<[u8; 20]> {
+        let name = hash(&kind, data);
+        if loose_path(&hex::encode(name)).exists() {
+            return Ok(name);
+        }
+
+        let (temporary, file) = temporary_file(&repository::objects_dir(), "tmp_obj")?;
+        let written = self
+            .compress(file, &kind, &mut &data[..], data.len() as u64)
+            .and_then(|name| link(&temporary, name));
+
+        let _ = fs::remove_file(&temporary);
+        written
+    }
+
+    /// Links the written object into place unless it is already there.
+    fn install(&mut self, temporary: &Path, name: [u8; 20]) -> std::io::Result<[u8; 20]> {
+        if self.freshen(&name)? {
+            return Ok(name);
+        }
+        link(temporary, name)
+    }
+
+    /// Updates the modification time of the loose object or pack holding
+    /// `name`, if any, so that pruning keeps it.
+    fn freshen(&mut self, name: &[u8; 20]) -> std::io::Result<bool> {
+        let loose = loose_path(&hex::encode(name));
+        let path = match fs::metadata(&loose) {
+            Ok(_) => loose,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => match self.store.pack_of(name)? {
+                Some(pack) => pack,
+                None => return Ok(false),
+            },
+            Err(e) => return Err(e),
         };
 
-        match kind {
-            "blob" => {
-                let obj = blob::parse(&mut d)?;
-                Ok(Object::Blob(obj))
+        // git carries on when it cannot, as the object is there all the same
+        if let Ok(file) = fs::File::open(path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Ok(true)
+    }
+}
+
+/// Links the object written to `temporary` into place as `name`.
+fn link(temporary: &Path, name: [u8; 20]) -> std::io::Result<[u8; 20]> {
+    let path = loose_path(&hex::encode(name));
+    fs::create_dir_all(path.parent().unwrap())?;
+    // another writer may have stored the same object meanwhile
+    match fs::hard_link(temporary, &path) {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(_) => fs::rename(temporary, &path)?,
+        Ok(()) => {}
+    }
+    Ok(name)
+}
+
+/// Creates a new read-only file in `dir`, named after `prefix`, to write
+/// an object or pack to.
+pub fn temporary_file(dir: &Path, prefix: &str) -> std::io::Result<(PathBuf, fs::File)> {
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+
+    for attempt in 0.. {
+        let name = format!("{prefix}_{:x}", seed ^ std::process::id() ^ attempt);
+        let path = dir.join(name);
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o444)
+            .open(&path);
+        match file {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+/// Checks that `data` is well formed for an object of type `kind`.
+pub fn check(kind: ObjType, data: &[u8]) -> std::io::Result<()> {
+    match kind {
+        ObjType::Blob => Ok(()),
+        ObjType::Tree => tree::check(data),
+        ObjType::Commit => commit::check(data),
+        ObjType::Tag => tag::check(data),
+    }
+}
+
+/// The store the functions below read through, with the objects directory
+/// it was opened on, so that a command reading many objects opens the pack
+/// indexes once.
+static SHARED: Mutex<Option<(PathBuf, Store)>> = Mutex::new(None);
+
+/// Runs `read` on the shared store, looking again for packs added since it
+/// was opened if the object isn't found.
+fn with_store<T>(mut read: impl FnMut(&mut Store) -> std::io::Result<T>) -> std::io::Result<T> {
+    let mut shared = SHARED.lock().unwrap();
+    let dir = repository::objects_dir();
+    let store = match &mut *shared {
+        Some((opened, store)) if *opened == dir => store,
+        _ => &mut shared.insert((dir, Store::open()?)).1,
+    };
+
+    match read(store) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && store.rescan()? => read(store),
+        result => result,
+    }
+}
+
+/// Reads the type and full content of an object, loose or packed.
+pub fn read_raw(hash: &str) -> std::io::Result<(ObjType, Vec<u8>)> {
+    with_store(|x| x.read(hash))
+}
+
+/// Opens an object for reading as it is decompressed.
+pub fn stream(hash: &str) -> std::io::Result<Stream> {
+    with_store(|x| x.stream(hash))
+}
+
+/// Whether the object `hash` is stored, loose or packed, without reading
+/// it.
+pub fn contains(hash: &str) -> bool {
+    let found = |x: &mut Store| match x.contains(hash)? {
+        true => Ok(()),
+        false => Err(std::io::ErrorKind::NotFound.into()),
+    };
+    with_store(found).is_ok()
+}
+
+/// Opens the loose object `hash` for reading, even if a pack also holds it.
+pub fn stream_loose_object(hash: &str) -> std::io::Result<Stream> {
+    stream_loose(fs::File::open(loose_path(hash))?, hash)
+}
+
+/// An object read as it is decompressed, rather than all at once.
+pub struct Stream {
+    pub kind: ObjType,
+    pub size: u64,
+    data: Box<dyn Read>,
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.data.read(buf)
+    }
+}
+
+/// The object database, keeping pack indexes and pack files open between
+/// lookups so that commands reading many objects don't reopen them each
+/// time.
+pub struct Store {
+    /// Pack indexes with their pack file, opened on the first read from it.
+    packs: Vec<(PackIndex, Option<BufReader<fs::File>>)>,
+}
+
+impl Store {
+    pub fn open() -> std::io::Result<Store> {
+        let packs = pack_index::list(&repository::objects_dir())?
+            .iter()
+            .map(|x| Ok((PackIndex::open(x)?, None)))
+            .collect::<std::io::Result<_>>()?;
+
+        Ok(Store { packs })
+    }
+
+    /// Brings the packs up to date with the pack directory, as packs may
+    /// have been added or removed since the store was opened, returning
+    /// whether any were.
+    fn rescan(&mut self) -> std::io::Result<bool> {
+        let indexes = pack_index::list(&repository::objects_dir())?;
+        let before = self.packs.len();
+        self.packs
+            .retain(|(x, _)| indexes.contains(&x.pack.with_extension("idx")));
+        let mut changed = self.packs.len() != before;
+
+        for path in indexes {
+            let pack = path.with_extension("pack");
+            if !self.packs.iter().any(|(x, _)| x.pack == pack) {
+                self.packs.push((PackIndex::open(&path)?, None));
+                changed = true;
             }
-            "tree" => {
-                let obj = tree::parse(&mut d)?;
-                Ok(Object::Tree(obj))
+        }
+        Ok(changed)
+    }
+
+    /// The loose object file for `hash`, or the pack holding it and where.
+    fn find(&mut self, hash: &str) -> std::io::Result<Location> {
+        if hash.len() != 40 || !hash.bytes().all(|x| x.is_ascii_hexdigit()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{hash} is not a valid object name"),
+            ));
+        }
+
+        match fs::File::open(loose_path(hash)) {
+            Ok(file) => return Ok(Location::Loose(file)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let mut name = [0; 20];
+        hex::decode_to_slice(hash, &mut name).unwrap();
+
+        for (n, (index, _)) in self.packs.iter_mut().enumerate() {
+            if let Some(offset) = index.find(&name)? {
+                return Ok(Location::Packed(n, offset));
             }
-            "commit" => {
-                let obj = commit::parse(&mut d)?;
-                Ok(Object::Commit(obj))
+        }
+
+        Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("object {hash} not found"),
+        ))
+    }
+
+    /// Whether the object is stored, by looking for its loose file and in
+    /// the pack indexes only.
+    pub fn contains(&mut self, hash: &str) -> std::io::Result<bool> {
+        let mut name = [0; 20];
+        if hash.len() != 40 || hex::decode_to_slice(hash, &mut name).is_err() {
+            return Ok(false);
+        }
+        if loose_path(hash).exists() {
+            return Ok(true);
+        }
+        Ok(self.pack_of(&name)?.is_some())
+    }
+
+    /// Reads the type and full content of an object, loose or packed.
+    pub fn read(&mut self, hash: &str) -> std::io::Result<(ObjType, Vec<u8>)> {
+        match self.find(hash)? {
+            Location::Loose(file) => {
+                let mut stream = stream_loose(file, hash)?;
+                let mut content = Vec::with_capacity(stream.size as usize);
+                stream.read_to_end(&mut content)?;
+                Ok((stream.kind, content))
+            }
+            Location::Packed(n, offset) => self.read_packed(n, offset),
+        }
+    }
+
+    /// The `n`th pack file, opened on the first read from it.
+    fn pack(&mut self, n: usize) -> std::io::Result<&mut BufReader<fs::File>> {
+        let (index, pack) = &mut self.packs[n];
+        Ok(match pack {
+            Some(pack) => pack,
+            None => pack.insert(BufReader::new(fs::File::open(&index.pack)?)),
+        })
+    }
+
+    /// Reads the object at `offset` in the `n`th pack, rebuilding it from
+    /// its base if it is stored as a delta.
+    fn read_packed(&mut self, n: usize, offset: u64) -> std::io::Result<(ObjType, Vec<u8>)> {
+        let pack = self.pack(n)?;
+        pack.seek(SeekFrom::Start(offset))?;
+        let (stored, size) = packfile::entry_header(pack, offset)?;
+        let mut data = Vec::with_capacity(size as usize);
+        ZlibDecoder::new(pack).read_to_end(&mut data)?;
+
+        let (kind, base) = match stored {
+            EntryKind::Base(kind) => return Ok((kind, data)),
+            EntryKind::Ofs(base) => self.read_packed(n, base)?,
+            EntryKind::Ref(name) => self.read(&hex::encode(name))?,
+        };
+        Ok((kind, packfile::process_delta(&data, &base)?))
+    }
+
+    /// The type and size of an object, without reading its content or
+    /// rebuilding it if it is a delta.
+    pub fn info(&mut self, hash: &str) -> std::io::Result<(ObjType, u64)> {
+        match self.find(hash)? {
+            Location::Loose(file) => {
+                let stream = stream_loose(file, hash)?;
+                Ok((stream.kind, stream.size))
+            }
+            Location::Packed(n, offset) => self.packed_info(n, offset),
+        }
+    }
+
+    fn packed_info(&mut self, n: usize, offset: u64) -> std::io::Result<(ObjType, u64)> {
+        let pack = self.pack(n)?;
+        pack.seek(SeekFrom::Start(offset))?;
+        let (stored, size) = packfile::entry_header(pack, offset)?;
+        if let EntryKind::Base(kind) = stored {
+            return Ok((kind, size));
+        }
+
+        // a delta starts with the size of its base, then of its result
+        let mut delta = ZlibDecoder::new(pack);
+        helpers::read_size(&mut delta)?;
+        let size = helpers::read_size(&mut delta)? as u64;
+        let kind = match stored {
+            EntryKind::Ofs(base) => self.packed_info(n, base)?.0,
+            EntryKind::Ref(name) => self.info(&hex::encode(name))?.0,
+            EntryKind::Base(kind) => kind,
+        };
+        Ok((kind, size))
+    }
+
+    /// Opens an object for reading as it is decompressed. Packed objects
+    /// stored as deltas are read whole first, as they have to be rebuilt.
+    pub fn stream(&mut self, hash: &str) -> std::io::Result<Stream> {
+        let (n, offset) = match self.find(hash)? {
+            Location::Loose(file) => return stream_loose(file, hash),
+            Location::Packed(n, offset) => (n, offset),
+        };
+
+        let pack = self.pack(n)?;
+        if let Some((kind, size)) = packfile::whole_object(pack, offset)? {
+            // read on its own, the store's reader may move on meanwhile
+            let data = PackReader {
+                file: pack.get_ref().try_clone()?,
+                position: pack.stream_position()?,
+            };
+            return Ok(Stream {
+                kind,
+                size,
+                data: Box::new(ZlibDecoder::new(BufReader::new(data)).take(size)),
+            });
+        }
+
+        let (kind, content) = self.read(hash)?;
+        Ok(Stream {
+            kind,
+            size: content.len() as u64,
+            data: Box::new(std::io::Cursor::new(content)),
+        })
+    }
+
+    /// Path of the pack holding `name`, if one does.
+    pub fn pack_of(&mut self, name: &[u8; 20]) -> std::io::Result<Option<PathBuf>> {
+        for (index, _) in self.packs.iter_mut() {
+            if index.find(name)?.is_some() {
+                return Ok(Some(index.pack.clone()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Names of every object, loose or packed, sorted.
+    pub fn names(&mut self) -> std::io::Result<Vec<String>> {
+        let mut names = loose_names()?;
+        for (index, _) in self.packs.iter_mut() {
+            for position in 0..index.len() {
+                names.push(hex::encode(index.hash_at(position)?));
+            }
+        }
+
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+}
+
+/// Reads a pack from `position` on, whatever else reads the same file.
+struct PackReader {
+    file: fs::File,
+    position: u64,
+}
+
+impl Read for PackReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.file.read_at(buf, self.position)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+/// Names of the loose objects, unsorted.
+pub fn loose_names() -> std::io::Result<Vec<String>> {
+    let mut names = Vec::new();
+
+    let objects = repository::objects_dir();
+    for entry in fs::read_dir(&objects)? {
+        let dir = entry?.file_name().to_string_lossy().to_string();
+        if dir.len() != 2 || !dir.bytes().all(|x| x.is_ascii_hexdigit()) {
+            continue;
+        }
+        for entry in fs::read_dir(objects.join(&dir))? {
+            let name = format!("{dir}{}", entry?.file_name().to_string_lossy());
+            if name.len() == 40 && name.bytes().all(|x| x.is_ascii_hexdigit()) {
+                names.push(name);
             }
-            k => panic!("Unknow object type {k}"),
         }
     }
 
-    pub fn cat(&self) {
-        match self {
-            Object::Blob(obj) => {
-                let data = &obj.0;
-                match String::from_utf8(data.to_vec()) {
-                    Ok(s) => print!("{s}"),
-                    Err(e) => print!("{e}"),
-                };
+    Ok(names)
+}
+
+/// Where an object is stored.
+enum Location {
+    Loose(fs::File),
+    /// The position of the pack in the store, and the offset in it.
+    Packed(usize, u64),
+}
+
+fn stream_loose(data: fs::File, hash: &str) -> std::io::Result<Stream> {
+    let mut d = BufReader::new(bufread::ZlibDecoder::new(BufReader::new(data)));
+    let mut buff = Vec::new();
+
+    d.read_until(0, &mut buff)?;
+
+    let header = CStr::from_bytes_until_nul(&buff)
+        .ok()
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.split_once(' '));
+
+    let parsed = header.and_then(|(kind, size)| Some((kind, size.parse::<u64>().ok()?)));
+    let Some((kind, size)) = parsed else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("unable to parse header of {}", loose_path(hash).display()),
+        ));
+    };
+
+    let kind = kind.parse::<ObjType>()?;
+
+    Ok(Stream {
+        kind,
+        size,
+        data: Box::new(LooseReader {
+            data: d,
+            left: size,
+            hash: hash.to_string(),
+        }),
+    })
+}
+
+/// The content of a loose object, checking once it is all read that the
+/// compressed stream ends there, with nothing after it in the file.
+struct LooseReader {
+    data: BufReader<bufread::ZlibDecoder<BufReader<fs::File>>>,
+    left: u64,
+    hash: String,
+}
+
+impl Read for LooseReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.left == 0 {
+            let ended = self.data.fill_buf()?.is_empty()
+                && self.data.get_mut().get_mut().fill_buf()?.is_empty();
+            if !ended {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("garbage at end of loose object '{}'", self.hash),
+                ));
             }
-            Object::Tree(obj) => {
-                for t in obj.iter() {
-                    let hash = hex::encode(t.hash);
-                    let kind = match Self::read_from_hash(&hash).unwrap() {
-                        Self::Blob(_) => "blob",
-                        Self::Tree(_) => "tree",
-                        Self::Commit(_) => "commit",
-                    };
-
-                    println!("{:06o} {kind} {} {}", t.mode, hash, t.name);
-                }
+            return Ok(0);
+        }
+
+        let wanted = buf
+            .len()
+            .min(usize::try_from(self.left).unwrap_or(usize::MAX));
+        let read = self.data.read(&mut buf[..wanted])?;
+        self.left -= read as u64;
+        Ok(read)
+    }
+}
+
+/// Full names of every object whose hex name starts with `prefix`.
+pub fn find_abbrev(prefix: &str) -> std::io::Result<Vec<String>> {
+    let prefix = prefix.to_ascii_lowercase();
+    let mut found = Vec::new();
+
+    if prefix.len() < 2 {
+        return Ok(found);
+    }
+
+    let dir = repository::objects_dir().join(&prefix[..2]);
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            let hash = format!("{}{name}", &prefix[..2]);
+            if hash.len() == 40 && hash.starts_with(&prefix) {
+                found.push(hash);
             }
-            Object::Commit(obj) => {
-                println!("tree {}", obj.tree);
-                for parent in obj.parents.iter() {
-                    println!("parent {}", parent);
-                }
-
-                let author = &obj.author;
-                println!(
-                    "author {} <{}> {} {}",
-                    author.name, author.email, author.date, author.zone
-                );
-
-                let committer = &obj.committer;
-                println!(
-                    "committer {} <{}> {} {}",
-                    committer.name, committer.email, committer.date, committer.zone
-                );
+        }
+    }
+
+    for idx in pack_index::list(&repository::objects_dir())? {
+        let mut index = PackIndex::open(&idx)?;
+        found.extend(index.find_prefix(&prefix)?.into_iter().map(hex::encode));
+    }
+
+    found.sort();
+    found.dedup();
+
+    Ok(found)
+}
+
+impl Object {
+    pub fn read_from_hash(hash: &str) -> std::io::Result<Object> {
+        let (kind, content) = read_raw(hash)?;
+        Self::parse(kind, &mut content.as_slice())
+    }
+
+    pub fn parse(kind: ObjType, d: &mut impl Read) -> std::io::Result<Object> {
+        match kind {
+            ObjType::Blob => {
+                let obj = blob::parse(d)?;
+                Ok(Object::Blob(obj))
+            }
+            ObjType::Tree => {
+                let obj = tree::parse(d)?;
+                Ok(Object::Tree(obj))
+            }
+            ObjType::Commit => {
+                let obj = commit::parse(d)?;
+                Ok(Object::Commit(obj))
+            }
+            ObjType::Tag => {
+                let obj = tag::parse(d)?;
+                Ok(Object::Tag(obj))
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{hash, hash_stream};
+    use crate::git::packfile::ObjType;
+
+    #[test]
+    fn streamed_hash_matches() {
+        let data = b"hello world\n".repeat(10_000);
+        assert_eq!(
+            hash_stream(ObjType::Blob, &data[..], data.len() as u64).unwrap(),
+            hash(ObjType::Blob, &data)
+        );
+    }
+
+    #[test]
+    fn streamed_size_must_match() {
+        let data = b"hello world\n";
+        assert!(hash_stream(ObjType::Blob, &data[..], 20).is_err());
+        assert!(hash_stream(ObjType::Blob, &data[..], 5).is_err());
+    }
+}
@@ -1,5 +1,7 @@
 use std::io::Read;
 
+use crate::git::packfile::ObjType;
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Blob(pub Vec<u8>);
 
@@ -8,3 +10,24 @@ pub fn parse(stream: &mut impl Read) -> std::io::Result<Blob> {
     stream.read_to_end(&mut data)?;
     Ok(Blob(data))
 }
+
+/// Object name `data` gets when stored as a blob.
+pub fn hash(data: &[u8]) -> [u8; 20] {
+    super::hash(ObjType::Blob, data)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn hash_matches_git() {
+        // printf 'hello world\n' | git hash-object --stdin
+        assert_eq!(
+            hex::encode(super::hash(b"hello world\n")),
+            "3b18e512dba79e4c8300dd08aeb37f8e728b8dad"
+        );
+        assert_eq!(
+            hex::encode(super::hash(b"")),
+            "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"
+        );
+    }
+}
@@ -1,6 +1,6 @@
-use std::{collections::VecDeque, io::Read};
+use std::io::Read;
 
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Author {
     pub name: String,
     pub email: String,
@@ -8,18 +8,33 @@ pub struct Author {
     pub zone: String,
 }
 
-impl From<Vec<&str>> for Author {
-    fn from(value: Vec<&str>) -> Self {
-        Self {
-            name: value[0].to_string(),
-            email: value[1].to_string(),
-            date: u64::from_str_radix(value[2], 10).unwrap(),
-            zone: value[3].to_string(),
-        }
+impl Author {
+    /// Parses an identity line body: `Name <email> timestamp zone`.
+    pub fn parse(value: &str) -> Option<Author> {
+        let (name, rest) = value.split_once('<')?;
+        let (email, rest) = rest.split_once('>')?;
+        let (date, zone) = rest.trim().split_once(' ')?;
+
+        Some(Author {
+            name: name.trim_end().to_string(),
+            email: email.to_string(),
+            date: date.parse().ok()?,
+            zone: zone.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for Author {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} <{}> {} {}",
+            self.name, self.email, self.date, self.zone
+        )
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Commit {
     pub tree: String,
     pub parents: Vec<String>,
@@ -32,51 +47,70 @@ pub fn parse(stream: &mut impl Read) -> std::io::Result<Commit> {
     let mut content = String::new();
     stream.read_to_string(&mut content)?;
 
-    let mut queue = content.lines().collect::<VecDeque<_>>();
-    let mut line = queue.pop_front().unwrap();
-    let (head, body) = line.split_once(' ').unwrap();
-    assert_eq!(head, "tree");
-    let tree = body.to_string();
-
-    let mut parent: Vec<String> = Vec::new();
-    loop {
-        line = queue.pop_front().unwrap();
-        let (head, body) = line.split_once(' ').unwrap();
-        if head != "parent" {
-            break;
+    let (headers, message) = content.split_once("\n\n").unwrap_or((&content, ""));
+
+    let mut tree = None;
+    let mut parents: Vec<String> = Vec::new();
+    let mut author = None;
+    let mut committer = None;
+
+    // continuation lines of multi-line headers (gpgsig, mergetag) start with a space
+    for line in headers.lines().filter(|x| !x.starts_with(' ')) {
+        let Some((head, body)) = line.split_once(' ') else {
+            continue;
+        };
+
+        match head {
+            "tree" => tree = Some(body.to_string()),
+            "parent" => parents.push(body.to_string()),
+            "author" => author = Author::parse(body),
+            "committer" => committer = Author::parse(body),
+            _ => {}
         }
-        parent.push(body.to_string());
     }
 
-    let (head, body) = line.split_once(' ').unwrap();
-    assert_eq!(head, "author");
-
-    let mut split = body.split(" ").collect::<Vec<_>>();
-    assert_eq!(split.len(), 4);
-    split[1] = split[1].trim_start_matches('<').trim_end_matches('>');
-    let author: Author = From::from(split.clone());
-
-    line = queue.pop_front().unwrap();
-    let (head, body) = line.split_once(' ').unwrap();
-    assert_eq!(head, "committer");
-    let mut split = body.split(" ").collect::<Vec<_>>();
-    assert_eq!(split.len(), 4);
-    split[1] = split[1].trim_start_matches('<').trim_end_matches('>');
-    let committer: Author = From::from(split.clone());
-
-    let _ = queue.pop_front();
-
-    let message = Vec::from(queue).join("");
+    let (Some(tree), Some(author), Some(committer)) = (tree, author, committer) else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "commit is missing tree, author or committer",
+        ));
+    };
 
     Ok(Commit {
         tree,
-        parents: parent,
+        parents,
         author,
         committer,
-        message,
+        message: message.lines().collect::<Vec<_>>().join("\n"),
     })
 }
 
+/// Checks that `data` starts with the tree header git requires of a
+/// commit, and that the parents following it are object names.
+pub fn check(data: &[u8]) -> std::io::Result<()> {
+    let corrupt = || std::io::Error::new(std::io::ErrorKind::InvalidData, "corrupt commit");
+    let header = |line: &[u8], name: &[u8]| {
+        line.strip_prefix(name)
+            .and_then(|x| x.strip_prefix(b" "))
+            .is_some_and(|x| x.len() == 40 && x.iter().all(u8::is_ascii_hexdigit))
+    };
+
+    let mut lines = data.split(|x| *x == b'\n');
+    let tree = lines.next().unwrap_or_default();
+    // git wants more than the tree line alone
+    if !header(tree, b"tree") || data.len() <= tree.len() + 1 {
+        return Err(corrupt());
+    }
+
+    for line in lines.take_while(|x| x.starts_with(b"parent ")) {
+        if !header(line, b"parent") {
+            return Err(corrupt());
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::BufReader;
@@ -114,4 +148,36 @@ test: Tree parsing
             }
         );
     }
+
+    #[test]
+    fn check_headers() {
+        assert!(super::check(COMMIT.as_bytes()).is_ok());
+        assert!(super::check(b"junk").is_err());
+        assert!(super::check(b"tree 11144a9d4ce9ddea810a3d8b74abbd912e5028b1\n").is_err());
+        assert!(super::check(
+            b"tree 11144a9d4ce9ddea810a3d8b74abbd912e5028b1\nparent 11144a9d4ce9ddea810a3d8b74abbd912e5028b\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn commit_parse_extra_headers() {
+        let content = "tree 11144a9d4ce9ddea810a3d8b74abbd912e5028b1
+author Some One <one@domain.com> 1717431836 +0700
+committer Some One <one@domain.com> 1717431836 +0700
+gpgsig -----BEGIN PGP SIGNATURE-----
+ 
+ iQEzBAABCAAdFiEE
+ -----END PGP SIGNATURE-----
+
+subject
+
+body line
+";
+        let commit = super::parse(&mut content.as_bytes()).unwrap();
+
+        assert!(commit.parents.is_empty());
+        assert_eq!(commit.author.name, "Some One");
+        assert_eq!(commit.message, "subject\n\nbody line");
+    }
 }
@@ -0,0 +1,116 @@
+use std::io::Read;
+
+use super::commit::Author;
+use crate::git::packfile::ObjType;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Tag {
+    pub object: String,
+    pub kind: String,
+    pub name: String,
+    pub tagger: Option<Author>,
+    pub message: String,
+}
+
+pub fn parse(stream: &mut impl Read) -> std::io::Result<Tag> {
+    let mut content = String::new();
+    stream.read_to_string(&mut content)?;
+
+    let (headers, message) = content.split_once("\n\n").unwrap_or((&content, ""));
+
+    let mut object = None;
+    let mut kind = None;
+    let mut name = None;
+    let mut tagger = None;
+
+    for line in headers.lines() {
+        let Some((head, body)) = line.split_once(' ') else {
+            continue;
+        };
+
+        match head {
+            "object" => object = Some(body.to_string()),
+            "type" => kind = Some(body.to_string()),
+            "tag" => name = Some(body.to_string()),
+            "tagger" => tagger = Author::parse(body),
+            _ => {}
+        }
+    }
+
+    let (Some(object), Some(kind)) = (object, kind) else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "tag is missing its object or type header",
+        ));
+    };
+
+    Ok(Tag {
+        object,
+        kind,
+        name: name.unwrap_or_default(),
+        tagger,
+        message: message.lines().collect::<Vec<_>>().join("\n"),
+    })
+}
+
+/// Checks that `data` has the object, type and tag headers git requires
+/// of a tag, in that order.
+pub fn check(data: &[u8]) -> std::io::Result<()> {
+    let corrupt = || std::io::Error::new(std::io::ErrorKind::InvalidData, "corrupt tag");
+    let mut lines = data.split(|x| *x == b'\n');
+
+    let object = lines.next().and_then(|x| x.strip_prefix(b"object "));
+    if !object.is_some_and(|x| x.len() == 40 && x.iter().all(u8::is_ascii_hexdigit)) {
+        return Err(corrupt());
+    }
+
+    let kind = lines.next().and_then(|x| x.strip_prefix(b"type "));
+    let known = kind
+        .and_then(|x| std::str::from_utf8(x).ok())
+        .is_some_and(|x| x.parse::<ObjType>().is_ok());
+    if !known {
+        return Err(corrupt());
+    }
+
+    // the tag line needs its newline, hence more than one part left
+    let tag = lines.next().is_some_and(|x| x.starts_with(b"tag "));
+    if !tag || lines.next().is_none() {
+        return Err(corrupt());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    const TAG: &str = "object 11144a9d4ce9ddea810a3d8b74abbd912e5028b1
+type commit
+tag v1.0
+tagger Some One <one@domain.com> 1717431836 +0700
+
+release v1.0
+";
+
+    #[test]
+    fn check_headers() {
+        assert!(super::check(TAG.as_bytes()).is_ok());
+        assert!(super::check(b"junk").is_err());
+
+        let kind = TAG.replace("type commit", "type blah");
+        assert!(super::check(kind.as_bytes()).is_err());
+
+        let name = "object 11144a9d4ce9ddea810a3d8b74abbd912e5028b1\ntype commit\n";
+        assert!(super::check(name.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn tag_parse() {
+        let tag = super::parse(&mut TAG.as_bytes()).unwrap();
+
+        assert_eq!(tag.object, "11144a9d4ce9ddea810a3d8b74abbd912e5028b1");
+        assert_eq!(tag.kind, "commit");
+        assert_eq!(tag.name, "v1.0");
+        assert_eq!(tag.tagger.unwrap().name, "Some One");
+        assert_eq!(tag.message, "release v1.0");
+    }
+}
@@ -1,8 +1,12 @@
 use std::{
+    collections::{BTreeMap, VecDeque},
     ffi::CStr,
     io::{BufRead, BufReader, Read},
 };
 
+use super::Object;
+use crate::git::{packfile::ObjType, pathspec};
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct TreeItem {
     pub mode: u32,
@@ -10,6 +14,62 @@ pub struct TreeItem {
     pub hash: [u8; 20],
 }
 
+impl TreeItem {
+    pub fn is_tree(&self) -> bool {
+        self.mode == 0o40000
+    }
+
+    /// Type of the object the entry names, as its mode tells.
+    pub fn kind(&self) -> ObjType {
+        match self.mode {
+            0o40000 => ObjType::Tree,
+            0o160000 => ObjType::Commit,
+            _ => ObjType::Blob,
+        }
+    }
+}
+
+/// Mode and object name of a tracked path.
+pub type Item = (u32, [u8; 20]);
+
+/// A tree flattened to `path -> item`, with paths relative to its root.
+pub type Files = BTreeMap<String, Item>;
+
+/// Reads a tree and all its subtrees into a flat list of files.
+pub fn flatten(tree_hash: &str) -> std::io::Result<Files> {
+    flatten_matching(tree_hash, &[])
+}
+
+/// Like [`flatten`], keeping only files selected by `specs` and skipping
+/// subtrees that cannot contain any of them.
+pub fn flatten_matching(tree_hash: &str, specs: &[String]) -> std::io::Result<Files> {
+    let mut files = Files::new();
+    let mut queue: VecDeque<(String, String)> = VecDeque::new();
+    queue.push_back((tree_hash.to_string(), String::new()));
+
+    while let Some((hash, prefix)) = queue.pop_front() {
+        let Object::Tree(items) = Object::read_from_hash(&hash)? else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{hash} is not a tree"),
+            ));
+        };
+
+        for item in items {
+            let path = format!("{prefix}{}", item.name);
+            if item.is_tree() {
+                if pathspec::could_contain(specs, &path) {
+                    queue.push_back((hex::encode(item.hash), format!("{path}/")));
+                }
+            } else if pathspec::matches(specs, &path) {
+                files.insert(path, (item.mode, item.hash));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
 pub fn parse(stream: &mut impl Read) -> std::io::Result<Vec<TreeItem>> {
     let mut reader = BufReader::new(stream);
 
@@ -25,6 +85,41 @@ pub fn parse(stream: &mut impl Read) -> std::io::Result<Vec<TreeItem>> {
     Ok(tree)
 }
 
+/// Checks that `data` is a sequence of well formed tree entries, the way
+/// git does before storing a tree it was handed.
+pub fn check(data: &[u8]) -> std::io::Result<()> {
+    let corrupt = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+    let mut rest = data;
+
+    while !rest.is_empty() {
+        // the entry has to leave room for the hash of the last one
+        if rest.len() < 23 || rest[rest.len() - 21] != 0 {
+            return Err(corrupt("too-short tree object"));
+        }
+
+        let Some(space) = rest.iter().position(|x| *x == b' ') else {
+            return Err(corrupt("malformed mode in tree entry"));
+        };
+        if !rest[..space].iter().all(|x| (b'0'..=b'7').contains(x)) {
+            return Err(corrupt("malformed mode in tree entry"));
+        }
+
+        let name = &rest[space + 1..];
+        let end = name.iter().position(|x| *x == 0).unwrap_or(name.len());
+        if end == 0 {
+            return Err(corrupt("empty filename in tree entry"));
+        }
+
+        let length = space + 1 + end + 1 + 20;
+        if rest.len() < length {
+            return Err(corrupt("too-short tree file"));
+        }
+        rest = &rest[length..];
+    }
+
+    Ok(())
+}
+
 fn take(reader: &mut impl BufRead) -> Option<TreeItem> {
     let mut data = Vec::new();
 
@@ -71,6 +166,24 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn check_entries() {
+        let mut entry = b"100644 a.txt\0".to_vec();
+        entry.extend([0xaa; 20]);
+
+        assert!(super::check(b"").is_ok());
+        assert!(super::check(&entry).is_ok());
+        assert!(super::check(&entry[..entry.len() - 1]).is_err());
+
+        let mut mode = entry.clone();
+        mode[2] = b'9';
+        assert!(super::check(&mode).is_err());
+
+        let mut empty = b"100644 \0".to_vec();
+        empty.extend([0xaa; 20]);
+        assert!(super::check(&empty).is_err());
+    }
+
     #[test]
     fn pares_correct_size() {
         let mut data = Vec::new();
@@ -0,0 +1,275 @@
+use sha1::{Digest, Sha1};
+use std::{
+    fs,
+    io::{Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use super::helpers;
+
+const MAGIC: &[u8; 4] = b"\xfftOc";
+const HEADER_SIZE: u64 = 8;
+const FANOUT_SIZE: u64 = 256 * 4;
+
+/// Lookup into a version 2 `.idx` file.
+///
+/// Only the fanout table is kept in memory, entries are binary searched
+/// directly on disk so opening an index stays cheap for large packs.
+pub struct PackIndex {
+    file: fs::File,
+    fanout: [u32; 256],
+    pub pack: PathBuf,
+}
+
+impl PackIndex {
+    pub fn open(path: &Path) -> std::io::Result<PackIndex> {
+        let mut file = fs::File::open(path)?;
+
+        let magic = helpers::read_bytes::<4>(&mut file)?;
+        let version = helpers::read_u32(&mut file)?;
+        if &magic != MAGIC || version != 2 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} is not a version 2 pack index", path.display()),
+            ));
+        }
+
+        let mut fanout = [0; 256];
+        for slot in fanout.iter_mut() {
+            *slot = helpers::read_u32(&mut file)?;
+        }
+
+        Ok(PackIndex {
+            file,
+            fanout,
+            pack: path.with_extension("pack"),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.fanout[255] as usize
+    }
+
+    pub fn hash_at(&mut self, position: usize) -> std::io::Result<[u8; 20]> {
+        self.file.seek(SeekFrom::Start(
+            HEADER_SIZE + FANOUT_SIZE + position as u64 * 20,
+        ))?;
+        helpers::read_bytes(&mut self.file)
+    }
+
+    pub fn offset_at(&mut self, position: usize) -> std::io::Result<u64> {
+        let entries = self.len() as u64;
+        let table = HEADER_SIZE + FANOUT_SIZE + entries * 24;
+        self.file
+            .seek(SeekFrom::Start(table + position as u64 * 4))?;
+        let offset = helpers::read_u32(&mut self.file)?;
+
+        if offset & 0x8000_0000 == 0 {
+            return Ok(offset as u64);
+        }
+
+        let large = table + entries * 4 + (offset & 0x7fff_ffff) as u64 * 8;
+        self.file.seek(SeekFrom::Start(large))?;
+        Ok(u64::from_be_bytes(helpers::read_bytes(&mut self.file)?))
+    }
+
+    /// Pack offset of the object, if this pack contains it.
+    pub fn find(&mut self, hash: &[u8; 20]) -> std::io::Result<Option<u64>> {
+        let first = hash[0] as usize;
+        let mut low = if first == 0 {
+            0
+        } else {
+            self.fanout[first - 1]
+        } as usize;
+        let mut high = self.fanout[first] as usize;
+
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.hash_at(mid)?.cmp(hash) {
+                std::cmp::Ordering::Equal => return self.offset_at(mid).map(Some),
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// All object names starting with the given hex prefix.
+    pub fn find_prefix(&mut self, prefix: &str) -> std::io::Result<Vec<[u8; 20]>> {
+        let Some(first) = prefix.get(..2).and_then(|x| u8::from_str_radix(x, 16).ok()) else {
+            return Ok(Vec::new());
+        };
+
+        let first = first as usize;
+        let low = if first == 0 {
+            0
+        } else {
+            self.fanout[first - 1]
+        } as usize;
+        let high = self.fanout[first] as usize;
+
+        let mut found = Vec::new();
+        for position in low..high {
+            let hash = self.hash_at(position)?;
+            if hex::encode(hash).starts_with(prefix) {
+                found.push(hash);
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+/// An object of a pack, as its index records it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub name: [u8; 20],
+    /// CRC-32 of the entry as stored in the pack.
+    pub crc: u32,
+    pub offset: u64,
+}
+
+/// Writes a version 2 index of `entries`, which must be sorted by name, for
+/// the pack whose trailing checksum is `pack`, returning the checksum of the
+/// index.
+pub fn write(
+    out: &mut impl Write,
+    entries: &[Entry],
+    pack: &[u8; 20],
+) -> std::io::Result<[u8; 20]> {
+    let mut index =
+        Vec::with_capacity(HEADER_SIZE as usize + FANOUT_SIZE as usize + entries.len() * 28);
+    index.extend(MAGIC);
+    index.extend(2u32.to_be_bytes());
+
+    let mut count = 0;
+    for first in 0..=255u8 {
+        count += entries[count..]
+            .iter()
+            .take_while(|x| x.name[0] == first)
+            .count();
+        index.extend((count as u32).to_be_bytes());
+    }
+
+    for entry in entries {
+        index.extend(entry.name);
+    }
+    for entry in entries {
+        index.extend(entry.crc.to_be_bytes());
+    }
+
+    // offsets that don't fit in 31 bits go to a table of 64 bit ones
+    let mut large = Vec::new();
+    for entry in entries {
+        let offset = match u32::try_from(entry.offset) {
+            Ok(offset) if offset & 0x8000_0000 == 0 => offset,
+            _ => {
+                large.extend(entry.offset.to_be_bytes());
+                0x8000_0000 | (large.len() / 8 - 1) as u32
+            }
+        };
+        index.extend(offset.to_be_bytes());
+    }
+    index.extend(large);
+    index.extend(pack);
+
+    out.write_all(&index)?;
+    let checksum: [u8; 20] = Sha1::digest(&index).into();
+    out.write_all(&checksum)?;
+    Ok(checksum)
+}
+
+/// Writes the reverse index of a pack whose index lists `entries`, which
+/// gives for each object, in pack order, its position in the index,
+/// returning the checksum of the reverse index.
+pub fn write_reverse(
+    out: &mut impl Write,
+    entries: &[Entry],
+    pack: &[u8; 20],
+) -> std::io::Result<[u8; 20]> {
+    let mut positions = (0..entries.len() as u32).collect::<Vec<_>>();
+    positions.sort_by_key(|&x| entries[x as usize].offset);
+
+    let mut reverse = Vec::with_capacity(12 + entries.len() * 4 + 20);
+    reverse.extend(b"RIDX");
+    reverse.extend(1u32.to_be_bytes());
+    // the hash function, sha1
+    reverse.extend(1u32.to_be_bytes());
+    for position in positions {
+        reverse.extend(position.to_be_bytes());
+    }
+    reverse.extend(pack);
+
+    out.write_all(&reverse)?;
+    let checksum: [u8; 20] = Sha1::digest(&reverse).into();
+    out.write_all(&checksum)?;
+    Ok(checksum)
+}
+
+/// Every `.idx` file in the pack directory.
+pub fn list(objects: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(objects.join("pack")) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut indexes = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|x| x == "idx") {
+            indexes.push(path);
+        }
+    }
+    indexes.sort();
+
+    Ok(indexes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write, Entry, PackIndex};
+
+    #[test]
+    fn written_index_reads_back() {
+        let mut entries = [
+            Entry {
+                name: [0x01; 20],
+                crc: 1,
+                offset: 12,
+            },
+            Entry {
+                name: [0x7f; 20],
+                crc: 2,
+                offset: 5 << 32,
+            },
+            Entry {
+                name: [0x7f; 20],
+                crc: 3,
+                offset: 0x8000_0000,
+            },
+            Entry {
+                name: [0xfe; 20],
+                crc: 4,
+                offset: 40,
+            },
+        ];
+        entries[2].name[19] = 0x80;
+
+        let dir = std::env::temp_dir().join(format!("pack-index-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pack-test.idx");
+        let mut file = std::fs::File::create(&path).unwrap();
+        write(&mut file, &entries, &[0; 20]).unwrap();
+
+        let mut index = PackIndex::open(&path).unwrap();
+        assert_eq!(index.len(), 4);
+        for entry in entries {
+            assert_eq!(index.find(&entry.name).unwrap(), Some(entry.offset));
+        }
+        assert_eq!(index.find(&[0x02; 20]).unwrap(), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,328 @@
+//! Writing packs, the way `git pack-objects` does: objects are sorted so
+//! that those likely to be alike are near each other, each is tried as a
+//! delta against the few before it, and the smallest delta that keeps its
+//! chain short enough is stored instead of the whole object.
+
+use flate2::{write::ZlibEncoder, Compression};
+use sha1::{Digest, Sha1};
+use std::{
+    collections::{HashSet, VecDeque},
+    fs,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    config::{Config, Fsync},
+    delta,
+    index_pack::Indexer,
+    objects::{self, Store},
+    pack_index,
+    packfile::{self, EntryKind, ObjType},
+};
+
+/// Hash of the path an object was found at, which sorts objects at the
+/// same path, or ending the same way, next to each other.
+pub fn name_hash(path: &str) -> u32 {
+    path.bytes()
+        .filter(|x| !x.is_ascii_whitespace())
+        .fold(0u32, |hash, x| (hash >> 2).wrapping_add((x as u32) << 24))
+}
+
+/// An object going into the pack.
+struct Packed {
+    name: [u8; 20],
+    kind: ObjType,
+    size: u64,
+    name_hash: u32,
+    /// The object it is stored as a delta against, and the delta.
+    delta: Option<(usize, Vec<u8>)>,
+    /// How many deltas rebuilding it goes through.
+    depth: usize,
+    /// Where it was written, once it is.
+    offset: Option<u64>,
+}
+
+/// Pack bytes being written, added to the pack checksum and the CRC of the
+/// current entry.
+struct Output<W> {
+    out: W,
+    offset: u64,
+    hasher: Sha1,
+    crc: crc32fast::Hasher,
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.out.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.crc.update(&buf[..written]);
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Writes packs, with the settings the configuration gives.
+pub struct PackWriter {
+    /// How many of the objects sorted before an object are tried as its
+    /// delta base.
+    pub window: usize,
+    /// Most deltas an object may be rebuilt through.
+    pub depth: usize,
+    level: Compression,
+    /// Size above which objects are stored whole.
+    big_file_threshold: u64,
+    fsync: bool,
+}
+
+impl PackWriter {
+    pub fn open() -> std::io::Result<PackWriter> {
+        let config = Config::load()?;
+        let window = config.get_int("pack.window")?.unwrap_or(10);
+        let depth = config.get_int("pack.depth")?.unwrap_or(50);
+
+        Ok(PackWriter {
+            window: window.max(0) as usize,
+            depth: depth.clamp(0, 4095) as usize,
+            level: config.compression("pack.compression")?.unwrap_or_default(),
+            big_file_threshold: config.big_file_threshold()?,
+            fsync: config.fsync(Fsync::Pack)?,
+        })
+    }
+
+    /// Writes a pack of `objects`, given by name with the path they were
+    /// found at if any, to `out`. Returns the index entries of the pack
+    /// and its checksum.
+    pub fn write(
+        &self,
+        objects: &[(String, Option<String>)],
+        out: impl Write,
+    ) -> std::io::Result<(Vec<pack_index::Entry>, [u8; 20])> {
+        let mut store = Store::open()?;
+
+        let mut seen = HashSet::new();
+        let mut packed = Vec::with_capacity(objects.len());
+        for (hash, path) in objects {
+            if !seen.insert(hash) {
+                continue;
+            }
+            let object = store.stream(hash)?;
+            let mut name = [0; 20];
+            hex::decode_to_slice(hash, &mut name).map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{hash} is not a valid object name"),
+                )
+            })?;
+            packed.push(Packed {
+                name,
+                kind: object.kind,
+                size: object.size,
+                name_hash: path.as_deref().map_or(0, name_hash),
+                delta: None,
+                depth: 0,
+                offset: None,
+            });
+        }
+
+        self.find_deltas(&mut store, &mut packed)?;
+
+        let mut output = Output {
+            out,
+            offset: 0,
+            hasher: Sha1::new(),
+            crc: crc32fast::Hasher::new(),
+        };
+        output.write_all(b"PACK")?;
+        output.write_all(&2u32.to_be_bytes())?;
+        output.write_all(&(packed.len() as u32).to_be_bytes())?;
+
+        let mut entries = Vec::with_capacity(packed.len());
+        for position in 0..packed.len() {
+            // bases go before the deltas against them, which refer back
+            let mut chain = vec![position];
+            while let Some((base, _)) = &packed[*chain.last().unwrap()].delta {
+                chain.push(*base);
+            }
+            for object in chain.into_iter().rev() {
+                if packed[object].offset.is_none() {
+                    entries.push(self.write_object(
+                        &mut store,
+                        &mut output,
+                        &mut packed,
+                        object,
+                    )?);
+                }
+            }
+        }
+
+        let checksum: [u8; 20] = output.hasher.clone().finalize().into();
+        output.out.write_all(&checksum)?;
+        output.out.flush()?;
+        Ok((entries, checksum))
+    }
+
+    /// Writes a pack of `objects` to `<base_name>-<checksum>.pack` along
+    /// with its index, returning the checksum.
+    pub fn write_files(
+        &self,
+        objects: &[(String, Option<String>)],
+        base_name: &Path,
+    ) -> std::io::Result<[u8; 20]> {
+        let dir = match base_name.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let (temporary, file) = objects::temporary_file(dir, "tmp_pack")?;
+
+        let written = self
+            .write(objects, BufWriter::new(&file))
+            .and_then(|(entries, checksum)| {
+                if self.fsync {
+                    file.sync_all()?;
+                }
+                let name = format!("{}-{}", base_name.display(), hex::encode(checksum));
+                fs::rename(&temporary, format!("{name}.pack"))?;
+                let index = PathBuf::from(format!("{name}.idx"));
+                Indexer::open()?.write_index(entries, &checksum, &index)?;
+                Ok(checksum)
+            });
+
+        if written.is_err() {
+            let _ = fs::remove_file(temporary);
+        }
+        written
+    }
+
+    /// Chooses which objects to store as deltas, and against which base.
+    fn find_deltas(&self, store: &mut Store, packed: &mut [Packed]) -> std::io::Result<()> {
+        if self.window == 0 || self.depth == 0 {
+            return Ok(());
+        }
+
+        let type_order = |kind: ObjType| match kind {
+            ObjType::Commit => 1,
+            ObjType::Tree => 2,
+            ObjType::Blob => 3,
+            ObjType::Tag => 4,
+        };
+        let mut order = (0..packed.len())
+            .filter(|&x| packed[x].size <= self.big_file_threshold)
+            .collect::<Vec<_>>();
+        // alike objects together, the larger first so that the smaller
+        // ones are made from them, which deletes rather than inserts
+        order.sort_by(|&a, &b| {
+            let (a, b) = (&packed[a], &packed[b]);
+            type_order(b.kind)
+                .cmp(&type_order(a.kind))
+                .then(b.name_hash.cmp(&a.name_hash))
+                .then(b.size.cmp(&a.size))
+        });
+
+        let mut window: VecDeque<(usize, Vec<u8>)> = VecDeque::with_capacity(self.window + 1);
+        for target in order {
+            let (_, data) = store.read(&hex::encode(packed[target].name))?;
+
+            let mut best: Option<(usize, Vec<u8>)> = None;
+            for (base, base_data) in window.iter().rev() {
+                if packed[*base].kind != packed[target].kind || packed[*base].depth >= self.depth {
+                    continue;
+                }
+
+                // a delta is only worth it well below the object's size
+                let limit = match &best {
+                    Some((_, delta)) => delta.len(),
+                    None => (data.len() / 2).saturating_sub(20),
+                };
+                if base_data.len().abs_diff(data.len()) >= limit {
+                    continue;
+                }
+
+                let delta = delta::encode(base_data, &data);
+                if delta.len() < limit {
+                    best = Some((*base, delta));
+                }
+            }
+
+            if let Some((base, delta)) = best {
+                packed[target].depth = packed[base].depth + 1;
+                packed[target].delta = Some((base, delta));
+            }
+
+            window.push_back((target, data));
+            if window.len() > self.window {
+                window.pop_front();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the object at `position`, whose delta base is written
+    /// already, returning its index entry.
+    fn write_object(
+        &self,
+        store: &mut Store,
+        output: &mut Output<impl Write>,
+        packed: &mut [Packed],
+        position: usize,
+    ) -> std::io::Result<pack_index::Entry> {
+        let offset = output.offset;
+        output.crc = crc32fast::Hasher::new();
+
+        let object = &packed[position];
+        match &object.delta {
+            Some((base, delta)) => {
+                let base = packed[*base].offset.unwrap();
+                let header =
+                    packfile::encode_entry_header(EntryKind::Ofs(base), delta.len() as u64, offset);
+                output.write_all(&header)?;
+                let mut encoder = ZlibEncoder::new(&mut *output, self.level);
+                encoder.write_all(delta)?;
+                encoder.finish()?;
+            }
+            None => {
+                let header = packfile::encode_entry_header(
+                    EntryKind::Base(object.kind),
+                    object.size,
+                    offset,
+                );
+                output.write_all(&header)?;
+                let mut data = store.stream(&hex::encode(object.name))?;
+                let mut encoder = ZlibEncoder::new(&mut *output, self.level);
+                std::io::copy(&mut data, &mut encoder)?;
+                encoder.finish()?;
+            }
+        }
+
+        let object = &mut packed[position];
+        object.offset = Some(offset);
+        // the delta is not needed anymore
+        if let Some((_, delta)) = &mut object.delta {
+            *delta = Vec::new();
+        }
+
+        Ok(pack_index::Entry {
+            name: object.name,
+            crc: std::mem::take(&mut output.crc).finalize(),
+            offset,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::name_hash;
+
+    #[test]
+    fn name_hash_sorts_by_ending() {
+        assert_eq!(name_hash(""), 0);
+        assert_eq!(name_hash("a b"), name_hash("ab"));
+        assert_eq!(name_hash("c") >> 24, b'c' as u32);
+        assert_ne!(name_hash("src/main.rs"), name_hash("src/main.rc"));
+    }
+}
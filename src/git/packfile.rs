@@ -1,8 +1,4 @@
-use flate2::read::ZlibDecoder;
-use std::{
-    collections::HashMap,
-    io::{Read, Seek, SeekFrom},
-};
+use std::io::{Read, Seek, SeekFrom};
 
 use super::helpers;
 
@@ -25,73 +21,117 @@ impl std::fmt::Display for ObjType {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-enum PackObjType {
-    Base(ObjType),
-    OFS,
-    REF,
+impl std::str::FromStr for ObjType {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blob" => Ok(ObjType::Blob),
+            "tree" => Ok(ObjType::Tree),
+            "commit" => Ok(ObjType::Commit),
+            "tag" => Ok(ObjType::Tag),
+            k => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unknown object type {k}"),
+            )),
+        }
+    }
 }
 
-pub fn read_object(
-    stream: &mut (impl Seek + Read),
-    offset: usize,
-    cache: &HashMap<usize, (Vec<u8>, ObjType)>,
-) -> std::io::Result<(Vec<u8>, ObjType)> {
-    stream.seek(SeekFrom::Start(offset as u64))?;
-
-    let size_and_type = helpers::read_size(stream)?;
-    let (size, t) = extract_size_and_type(size_and_type);
-
-    let t = match t {
-        0b001 => PackObjType::Base(ObjType::Commit),
-        0b010 => PackObjType::Base(ObjType::Tree),
-        0b011 => PackObjType::Base(ObjType::Blob),
-        0b100 => PackObjType::Base(ObjType::Tag),
-        0b110 => PackObjType::OFS,
-        0b111 => PackObjType::REF,
-        x => panic!("{x:03b} is invalid type."),
-    };
-
-    let (content, content_type) = match t {
-        PackObjType::Base(base) => {
-            let current = stream.stream_position()?;
-            let mut content = Vec::new();
-            let consume = decompress(stream, &mut content)?;
-
-            stream.seek(SeekFrom::Start(current + consume as u64))?;
+/// How an entry of a pack is stored: whole, or as a delta against an
+/// object found at an earlier offset or by name.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EntryKind {
+    Base(ObjType),
+    Ofs(u64),
+    Ref([u8; 20]),
+}
 
-            (content, base)
+/// Reads the header of the entry at `offset`, the current position of
+/// `stream`, returning how it is stored and the size of its inflated data,
+/// which follows.
+pub fn entry_header(stream: &mut impl Read, offset: u64) -> std::io::Result<(EntryKind, u64)> {
+    let (size, t) = extract_size_and_type(helpers::read_size(stream)?);
+
+    let kind = match t {
+        0b001 => EntryKind::Base(ObjType::Commit),
+        0b010 => EntryKind::Base(ObjType::Tree),
+        0b011 => EntryKind::Base(ObjType::Blob),
+        0b100 => EntryKind::Base(ObjType::Tag),
+        0b110 => {
+            let distance = helpers::read_offset(stream)? as u64;
+            match offset.checked_sub(distance) {
+                Some(base) if distance != 0 => EntryKind::Ofs(base),
+                _ => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("delta base offset is out of bound at {offset}"),
+                    ))
+                }
+            }
         }
-        PackObjType::OFS => {
-            let negativeoffset = helpers::read_offset(stream)?;
-            let current = stream.stream_position()?;
-
-            let base_offset = offset - negativeoffset;
-            let cached = cache.get(&base_offset);
+        0b111 => EntryKind::Ref(helpers::read_bytes(stream)?),
+        x => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unknown object type {x} at offset {offset}"),
+            ))
+        }
+    };
 
-            let (base, base_type) = match cached {
-                Some(c) => {
-                    let (a, t) = c;
-                    (a.clone(), t.clone())
-                }
-                None => read_object(stream, base_offset, cache)?,
-            };
+    Ok((kind, size as u64))
+}
 
-            let mut content = Vec::new();
+/// Encodes the header of an entry at `offset` stored as `kind`, with
+/// `size` bytes of inflated data.
+pub fn encode_entry_header(kind: EntryKind, size: u64, offset: u64) -> Vec<u8> {
+    let t = match kind {
+        EntryKind::Base(ObjType::Commit) => 0b001,
+        EntryKind::Base(ObjType::Tree) => 0b010,
+        EntryKind::Base(ObjType::Blob) => 0b011,
+        EntryKind::Base(ObjType::Tag) => 0b100,
+        EntryKind::Ofs(_) => 0b110,
+        EntryKind::Ref(_) => 0b111,
+    };
 
-            stream.seek(SeekFrom::Start(current))?;
-            let consume = decompress(stream, &mut content)?;
-            stream.seek(SeekFrom::Start(current + consume as u64))?;
-            let content = process_delta(&mut content, &base)?;
+    let mut header = vec![(t << 4) | (size & 0xf) as u8];
+    let mut size = size >> 4;
+    while size != 0 {
+        *header.last_mut().unwrap() |= 0x80;
+        header.push((size & 0x7f) as u8);
+        size >>= 7;
+    }
 
-            (content, base_type)
-        }
-        PackObjType::REF => {
-            unimplemented!()
+    match kind {
+        EntryKind::Base(_) => {}
+        EntryKind::Ofs(base) => {
+            // the inverse of helpers::read_offset, most significant first
+            let mut distance = offset - base;
+            let mut bytes = vec![(distance & 0x7f) as u8];
+            distance >>= 7;
+            while distance != 0 {
+                distance -= 1;
+                bytes.push(0x80 | (distance & 0x7f) as u8);
+                distance >>= 7;
+            }
+            header.extend(bytes.iter().rev());
         }
-    };
+        EntryKind::Ref(name) => header.extend(name),
+    }
+    header
+}
 
-    Ok((content, content_type))
+/// Type and size of the object at `offset` if it is stored whole rather
+/// than as a delta, leaving `stream` at the start of its compressed data.
+pub fn whole_object(
+    stream: &mut (impl Seek + Read),
+    offset: u64,
+) -> std::io::Result<Option<(ObjType, u64)>> {
+    stream.seek(SeekFrom::Start(offset))?;
+    match entry_header(stream, offset)? {
+        (EntryKind::Base(kind), size) => Ok(Some((kind, size))),
+        _ => Ok(None),
+    }
 }
 
 pub fn headers(stream: &mut impl Read) -> std::io::Result<([u8; 4], u32, u32)> {
@@ -105,32 +145,52 @@ pub fn headers(stream: &mut impl Read) -> std::io::Result<([u8; 4], u32, u32)> {
     Ok((header, version, entries))
 }
 
-fn process_delta(delta: &mut Vec<u8>, base: &Vec<u8>) -> std::io::Result<Vec<u8>> {
-    let mut reader = delta.as_slice();
-    let _ = helpers::read_size(&mut reader)?;
+/// Rebuilds an object from `base` and the instructions in `delta`.
+pub fn process_delta(delta: &[u8], base: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut reader = delta;
+    let base_size = helpers::read_size(&mut reader)?;
     let content_size = helpers::read_size(&mut reader)?;
-    let mut content: Vec<u8> = Vec::new();
+    if base_size != base.len() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "delta expects a base of {base_size} bytes, not {}",
+                base.len()
+            ),
+        ));
+    }
+    let mut content: Vec<u8> = Vec::with_capacity(content_size);
 
     loop {
-        if content.len() == content_size {
+        if content.len() >= content_size {
             break;
         }
 
         let [instruction] = helpers::read_bytes::<1>(&mut reader)?;
         if instruction & 0x80 != 0 {
             let (offset, size) = read_offset_and_size(&mut reader, instruction)?;
-            let copy = &base[offset..offset + size];
-            let mut source = Vec::from(copy);
-            content.append(&mut source);
+            let Some(copy) = base.get(offset..offset + size) else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "delta copies from outside its base",
+                ));
+            };
+            content.extend_from_slice(copy);
         } else {
             let size = instruction & 0x7f;
             let mut source = vec![0; size as usize];
-            source.reserve_exact(size as usize);
             reader.read_exact(&mut source)?;
             content.append(&mut source);
         }
     }
 
+    if content.len() != content_size || !reader.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "delta does not match the size it gives",
+        ));
+    }
+
     Ok(content)
 }
 
@@ -140,7 +200,7 @@ fn read_offset_and_size(stream: &mut impl Read, flag: u8) -> std::io::Result<(us
     for i in 0..4 {
         if offset_flag & (1 << i) != 0 {
             let [buf] = helpers::read_bytes::<1>(stream)?;
-            offset |= (buf as usize) << i * 8;
+            offset |= (buf as usize) << (i * 8);
         }
     }
 
@@ -149,9 +209,13 @@ fn read_offset_and_size(stream: &mut impl Read, flag: u8) -> std::io::Result<(us
     for i in 0..3 {
         if size_flag & (1 << i) != 0 {
             let [buf] = helpers::read_bytes::<1>(stream)?;
-            size |= (buf as usize) << i * 8;
+            size |= (buf as usize) << (i * 8);
         }
     }
+    // no size bytes, or only zero ones, stand for the largest copy
+    if size == 0 {
+        size = 0x10000;
+    }
 
     Ok((offset, size))
 }
@@ -162,18 +226,12 @@ fn extract_size_and_type(size_and_type: usize) -> (usize, u8) {
     (size, t as u8)
 }
 
-fn decompress(stream: &mut impl Read, buffer: &mut Vec<u8>) -> std::io::Result<usize> {
-    let mut z = ZlibDecoder::new(stream);
-    z.read_to_end(buffer)?;
-
-    Ok(z.total_in() as usize)
-}
-
 #[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
 mod tests {
     use std::io::BufReader;
 
-    use super::headers;
+    use super::{encode_entry_header, entry_header, headers, EntryKind, ObjType};
 
     #[test]
     fn valid_packfile_header() {
@@ -192,6 +250,26 @@ mod tests {
         let _ = headers(&mut reader);
     }
 
+    #[test]
+    fn entry_headers_round_trip() {
+        let kinds = [
+            (EntryKind::Base(ObjType::Blob), 5),
+            (EntryKind::Base(ObjType::Tree), 1 << 40),
+            (EntryKind::Ofs(1000), 300),
+            (EntryKind::Ofs(0), 16),
+            (EntryKind::Ofs(900), 0x10000),
+            (EntryKind::Ref([7; 20]), 127),
+        ];
+
+        for offset in [1001, 1128, 16512, 1 << 33] {
+            for (kind, size) in kinds {
+                let header = encode_entry_header(kind, size, offset);
+                let read = entry_header(&mut header.as_slice(), offset).unwrap();
+                assert_eq!(read, (kind, size));
+            }
+        }
+    }
+
     #[test]
     fn extract_file_type_and_size() {
         let (size, obj_type) = super::extract_size_and_type(0b110_101_0110);
@@ -208,4 +286,14 @@ mod tests {
         assert_eq!(offset, 0x800010);
         assert_eq!(size, 0xff00);
     }
+
+    #[test]
+    fn read_offset_and_implicit_size() {
+        let data = vec![0x01];
+        let (offset, size) =
+            super::read_offset_and_size(&mut data.as_slice(), 0b1_000_0001).unwrap();
+
+        assert_eq!(offset, 0x01);
+        assert_eq!(size, 0x10000);
+    }
 }
@@ -0,0 +1,138 @@
+/// `path`, given in the directory `prefix` of the work tree, as a path
+/// from its top with `.` and `..` resolved, `None` if it leads outside.
+/// Paths naming a directory the way `dir/` and `.` do end in `/`.
+pub fn join(prefix: &str, path: &str) -> Option<String> {
+    let mut parts = prefix
+        .split('/')
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+
+    let last = path.rsplit('/').next().unwrap_or_default();
+    let dir = ["", ".", ".."].contains(&last) && !parts.is_empty();
+    Some(parts.join("/") + if dir { "/" } else { "" })
+}
+
+/// `path`, from the top of the work tree, as a path from its directory
+/// `prefix`, the way git shows it: `./` for the directory itself.
+pub fn relative(prefix: &str, path: &str) -> String {
+    let mut prefix = prefix.split('/').filter(|x| !x.is_empty()).peekable();
+    let mut path = path.split('/').filter(|x| !x.is_empty()).peekable();
+    while prefix.peek().is_some() && prefix.peek() == path.peek() {
+        prefix.next();
+        path.next();
+    }
+
+    let relative = prefix
+        .map(|_| "..")
+        .chain(path)
+        .collect::<Vec<_>>()
+        .join("/");
+    match relative.is_empty() {
+        true => "./".to_string(),
+        false => relative,
+    }
+}
+
+/// Whether `path` is selected by any of `specs`.
+///
+/// A spec selects the path itself and, for directories, everything below
+/// it. An empty list or `.` selects every path.
+pub fn matches(specs: &[String], path: &str) -> bool {
+    if specs.is_empty() {
+        return true;
+    }
+
+    specs.iter().any(|spec| {
+        let spec = spec.trim_start_matches("./").trim_end_matches('/');
+        spec.is_empty()
+            || spec == "."
+            || path == spec
+            || path.strip_prefix(spec).is_some_and(|x| x.starts_with('/'))
+    })
+}
+
+/// Whether the directory `dir` may hold paths selected by `specs`.
+pub fn could_contain(specs: &[String], dir: &str) -> bool {
+    matches(specs, dir)
+        || specs.iter().any(|spec| {
+            spec.trim_start_matches("./")
+                .strip_prefix(dir)
+                .is_some_and(|x| x.starts_with('/'))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn match_files_and_directories() {
+        let specs = vec!["src".to_string(), "README.md".to_string()];
+
+        assert!(matches(&specs, "src/main.rs"));
+        assert!(matches(&specs, "src/git/index.rs"));
+        assert!(matches(&specs, "README.md"));
+        assert!(!matches(&specs, "srcs/main.rs"));
+        assert!(!matches(&specs, "Cargo.toml"));
+    }
+
+    #[test]
+    fn match_everything() {
+        assert!(matches(&[], "any/path"));
+        assert!(matches(&[".".to_string()], "any/path"));
+        assert!(matches(&["src/".to_string()], "src/main.rs"));
+    }
+
+    #[test]
+    fn directories_leading_to_specs() {
+        let specs = vec!["src/git/index.rs".to_string()];
+
+        assert!(super::could_contain(&specs, "src"));
+        assert!(super::could_contain(&specs, "src/git"));
+        assert!(!super::could_contain(&specs, "src/commands"));
+        assert!(!super::could_contain(&specs, "docs"));
+    }
+
+    #[test]
+    fn join_to_prefixes() {
+        use super::join;
+
+        assert_eq!(join("", "src/main.rs"), Some("src/main.rs".to_string()));
+        assert_eq!(join("src/", "main.rs"), Some("src/main.rs".to_string()));
+        assert_eq!(
+            join("src/git/", "./index.rs"),
+            Some("src/git/index.rs".to_string())
+        );
+        assert_eq!(
+            join("src/git/", "../main.rs"),
+            Some("src/main.rs".to_string())
+        );
+        assert_eq!(join("src/", "."), Some("src/".to_string()));
+        assert_eq!(join("src/", "git/"), Some("src/git/".to_string()));
+        assert_eq!(join("", "."), Some(String::new()));
+        assert_eq!(join("src/", ".."), Some(String::new()));
+        assert_eq!(join("src/", "../.."), None);
+        assert_eq!(join("", "../x"), None);
+    }
+
+    #[test]
+    fn relative_to_prefixes() {
+        use super::relative;
+
+        assert_eq!(relative("", "src/main.rs"), "src/main.rs");
+        assert_eq!(relative("src/", "src/git/index.rs"), "git/index.rs");
+        assert_eq!(relative("src/", "Cargo.toml"), "../Cargo.toml");
+        assert_eq!(relative("src/git/", "src/main.rs"), "../main.rs");
+        assert_eq!(relative("src/", "srcs/x"), "../srcs/x");
+        assert_eq!(relative("src/", "src"), "./");
+    }
+}
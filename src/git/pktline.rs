@@ -0,0 +1,313 @@
+//! The pkt-line framing the git protocols are spoken in: packets of data
+//! prefixed with their length in four hex digits, and the special packets
+//! ending messages (flush) and, in protocol v2, sections (delim) and
+//! responses (response-end). Also the side-band channels a pack is sent
+//! over, and the capabilities a server advertises.
+
+use std::io::{IsTerminal, Read, Write};
+
+/// The longest a packet may be, its length included.
+pub const MAX_PACKET: usize = 65520;
+
+/// Bands of a side-band response: pack data, progress, and a fatal error.
+const BAND_DATA: u8 = 1;
+const BAND_PROGRESS: u8 = 2;
+const BAND_ERROR: u8 = 3;
+
+/// What progress from the remote is shown with.
+const REMOTE_PREFIX: &str = "remote: ";
+
+/// A packet as read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet {
+    /// `0000`, the end of a message.
+    Flush,
+    /// `0001`, the end of a section of a v2 message.
+    Delim,
+    /// `0002`, the end of a v2 response.
+    ResponseEnd,
+    Data(Vec<u8>),
+}
+
+fn invalid(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+/// Reads a packet. An `ERR` packet is the error it reports.
+pub fn read_packet(input: &mut impl Read) -> std::io::Result<Packet> {
+    let mut length = [0; 4];
+    input.read_exact(&mut length)?;
+    let length = std::str::from_utf8(&length)
+        .ok()
+        .filter(|x| x.bytes().all(|x| x.is_ascii_hexdigit()))
+        .and_then(|x| usize::from_str_radix(x, 16).ok())
+        .ok_or_else(|| {
+            invalid(format!(
+                "protocol error: bad line length character: {}",
+                String::from_utf8_lossy(&length)
+            ))
+        })?;
+    match length {
+        0 => return Ok(Packet::Flush),
+        1 => return Ok(Packet::Delim),
+        2 => return Ok(Packet::ResponseEnd),
+        3 => return Err(invalid("protocol error: bad line length 3")),
+        x if x > MAX_PACKET => return Err(invalid(format!("protocol error: bad line length {x}"))),
+        _ => {}
+    }
+
+    let mut data = vec![0; length - 4];
+    input.read_exact(&mut data)?;
+    if let Some(message) = data.strip_prefix(b"ERR ") {
+        let message = String::from_utf8_lossy(message);
+        return Err(std::io::Error::other(format!(
+            "remote error: {}",
+            message.trim_end_matches('\n')
+        )));
+    }
+    Ok(Packet::Data(data))
+}
+
+/// Reads a packet holding a line, without its line feed. `None` for any
+/// of the special packets.
+pub fn read_line(input: &mut impl Read) -> std::io::Result<Option<String>> {
+    Ok(match read_packet(input)? {
+        Packet::Data(data) => {
+            let line = String::from_utf8_lossy(&data);
+            Some(line.strip_suffix('\n').unwrap_or(&line).to_string())
+        }
+        _ => None,
+    })
+}
+
+/// Writes `data` as a packet.
+pub fn write_packet(out: &mut impl Write, data: &[u8]) -> std::io::Result<()> {
+    if data.len() > MAX_PACKET - 4 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("packet of {} bytes is too long", data.len()),
+        ));
+    }
+    write!(out, "{:04x}", data.len() + 4)?;
+    out.write_all(data)
+}
+
+/// Writes `line` as a packet.
+pub fn write_line(out: &mut impl Write, line: &str) -> std::io::Result<()> {
+    write_packet(out, line.as_bytes())
+}
+
+pub fn write_flush(out: &mut impl Write) -> std::io::Result<()> {
+    out.write_all(b"0000")
+}
+
+pub fn write_delim(out: &mut impl Write) -> std::io::Result<()> {
+    out.write_all(b"0001")
+}
+
+/// The pack data sent on band 1 of a side-band response up to a flush
+/// packet, showing the progress sent on band 2 as it comes.
+pub struct SideBand<R> {
+    inner: R,
+    /// Payload of the last packet read, from `start` on not yet returned.
+    data: Vec<u8>,
+    start: usize,
+    /// Progress not yet shown, short of the end of its line.
+    progress: Vec<u8>,
+    done: bool,
+}
+
+impl<R> SideBand<R> {
+    pub fn new(inner: R) -> SideBand<R> {
+        SideBand {
+            inner,
+            data: Vec::new(),
+            start: 0,
+            progress: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Shows the lines of progress, each prefixed with `remote: ` and
+    /// suffixed so as to hide what it is written over. The rest of a line
+    /// is kept for the next packet to end, unless `all`.
+    fn show_progress(&mut self, all: bool) -> std::io::Result<()> {
+        let mut stderr = std::io::stderr().lock();
+        let suffix = match stderr.is_terminal() {
+            true => "\x1b[K",
+            false => "        ",
+        };
+
+        let mut rest = &self.progress[..];
+        loop {
+            let (line, end) = match rest.iter().position(|x| *x == b'\n' || *x == b'\r') {
+                Some(i) => (&rest[..i], rest[i]),
+                None if all && !rest.is_empty() => (rest, b'\n'),
+                None => break,
+            };
+            if !line.is_empty() {
+                stderr.write_all(REMOTE_PREFIX.as_bytes())?;
+                stderr.write_all(line)?;
+                stderr.write_all(suffix.as_bytes())?;
+            }
+            stderr.write_all(&[end])?;
+            rest = rest.get(line.len() + 1..).unwrap_or_default();
+        }
+        self.progress = rest.to_vec();
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for SideBand<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.start == self.data.len() && !self.done {
+            let data = match read_packet(&mut self.inner)? {
+                Packet::Data(data) if data.is_empty() => continue,
+                Packet::Data(data) => data,
+                _ => {
+                    self.done = true;
+                    self.show_progress(true)?;
+                    break;
+                }
+            };
+
+            self.data = data;
+            self.start = 1;
+            match self.data[0] {
+                BAND_DATA => {}
+                BAND_PROGRESS => {
+                    self.progress.extend(&self.data[1..]);
+                    self.start = self.data.len();
+                    self.show_progress(false)?;
+                }
+                BAND_ERROR => {
+                    return Err(std::io::Error::other(format!(
+                        "remote error: {}",
+                        String::from_utf8_lossy(&self.data[1..]).trim_end()
+                    )))
+                }
+                band => return Err(invalid(format!("protocol error: bad band #{band}"))),
+            }
+        }
+
+        let n = buf.len().min(self.data.len() - self.start);
+        buf[..n].copy_from_slice(&self.data[self.start..self.start + n]);
+        self.start += n;
+        Ok(n)
+    }
+}
+
+/// What a server says it can do: words, some of them `name=value`, from
+/// after the NUL of its first ref line in v0 or lines of their own in v2.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities(Vec<String>);
+
+impl Capabilities {
+    /// Parses the capabilities of a v0 advertisement.
+    pub fn parse(words: &str) -> Capabilities {
+        Capabilities(words.split_whitespace().map(str::to_string).collect())
+    }
+
+    pub fn push(&mut self, capability: &str) {
+        self.0.push(capability.to_string());
+    }
+
+    /// Whether the server offers `capability`, with any value.
+    pub fn has(&self, name: &str) -> bool {
+        self.value(name).is_some()
+    }
+
+    /// The value of `capability`, empty if it has none, the first one if
+    /// it is given more than once.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.0.iter().find_map(|x| match x.split_once('=') {
+            Some((key, value)) => (key == name).then_some(value),
+            None => (x == name).then_some(""),
+        })
+    }
+
+    /// The symbolic refs of a v0 advertisement, by name with what they
+    /// point at.
+    pub fn symrefs(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .filter_map(|x| x.strip_prefix("symref=")?.split_once(':'))
+    }
+
+    /// The name and version of the server's git.
+    pub fn agent(&self) -> Option<&str> {
+        self.value("agent")
+    }
+
+    /// The hash algorithm of the server's objects, SHA-1 unless it says.
+    pub fn object_format(&self) -> &str {
+        self.value("object-format").unwrap_or("sha1")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_line, read_packet, write_line, write_packet, Capabilities, Packet};
+    use super::{SideBand, MAX_PACKET};
+    use std::io::Read;
+
+    #[test]
+    fn packets() {
+        let mut out = Vec::new();
+        write_line(&mut out, "want x\n").unwrap();
+        super::write_delim(&mut out).unwrap();
+        super::write_flush(&mut out).unwrap();
+        out.extend(b"0002");
+        assert_eq!(&out, b"000bwant x\n000100000002");
+
+        let mut input = &out[..];
+        assert_eq!(read_line(&mut input).unwrap().as_deref(), Some("want x"));
+        assert_eq!(read_packet(&mut input).unwrap(), Packet::Delim);
+        assert_eq!(read_packet(&mut input).unwrap(), Packet::Flush);
+        assert_eq!(read_packet(&mut input).unwrap(), Packet::ResponseEnd);
+
+        assert!(write_packet(&mut Vec::new(), &[0; MAX_PACKET - 4]).is_ok());
+        assert!(write_packet(&mut Vec::new(), &[0; MAX_PACKET - 3]).is_err());
+        assert!(read_packet(&mut &b"fff1"[..]).is_err());
+        assert!(read_packet(&mut &b"0003"[..]).is_err());
+        assert!(read_packet(&mut &b"00x4"[..]).is_err());
+
+        let error = read_line(&mut &b"0016ERR access denied\n"[..]).unwrap_err();
+        assert_eq!(error.to_string(), "remote error: access denied");
+    }
+
+    #[test]
+    fn side_band() {
+        let input = b"0009\x01PACK0007\x01ab0006\x02\n0008\x01cd\n0000";
+        let mut data = Vec::new();
+        SideBand::new(&input[..]).read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"PACKabcd\n");
+
+        let input = b"0009\x03oops0000";
+        let error = SideBand::new(&input[..]).read_to_end(&mut Vec::new());
+        assert_eq!(error.unwrap_err().to_string(), "remote error: oops");
+        assert!(SideBand::new(&b"0006\x05x0000"[..])
+            .read_to_end(&mut Vec::new())
+            .is_err());
+    }
+
+    #[test]
+    fn capabilities() {
+        let capabilities = Capabilities::parse(
+            "multi_ack side-band-64k symref=HEAD:refs/heads/main agent=git/2.39.5",
+        );
+        assert!(capabilities.has("side-band-64k") && !capabilities.has("side-band"));
+        assert_eq!(
+            capabilities.symrefs().collect::<Vec<_>>(),
+            [("HEAD", "refs/heads/main")]
+        );
+        assert_eq!(capabilities.agent(), Some("git/2.39.5"));
+        assert_eq!(capabilities.object_format(), "sha1");
+
+        let mut capabilities = Capabilities::default();
+        capabilities.push("ls-refs=unborn");
+        capabilities.push("object-format=sha256");
+        assert_eq!(capabilities.value("ls-refs"), Some("unborn"));
+        assert_eq!(capabilities.object_format(), "sha256");
+    }
+}
@@ -0,0 +1,1080 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, FixedOffset};
+
+use super::{
+    objects::commit::{Author, Commit},
+    refs::{self, Head},
+    revision,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DateFormat {
+    #[default]
+    Default,
+    Iso,
+    IsoStrict,
+    Rfc,
+    Short,
+    Raw,
+    Unix,
+    Relative,
+}
+
+impl std::str::FromStr for DateFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(DateFormat::Default),
+            "iso" | "iso8601" => Ok(DateFormat::Iso),
+            "iso-strict" | "iso8601-strict" => Ok(DateFormat::IsoStrict),
+            "rfc" | "rfc2822" => Ok(DateFormat::Rfc),
+            "short" => Ok(DateFormat::Short),
+            "raw" => Ok(DateFormat::Raw),
+            "unix" => Ok(DateFormat::Unix),
+            "relative" => Ok(DateFormat::Relative),
+            x => Err(format!("unknown date format {x}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Format {
+    Oneline,
+    Short,
+    Medium,
+    Full,
+    Fuller,
+    /// `%` placeholders; `terminator` ends every entry with a newline
+    /// (`tformat:`), otherwise newlines only separate entries (`format:`).
+    Custom {
+        template: String,
+        terminator: bool,
+    },
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oneline" => Ok(Format::Oneline),
+            "short" => Ok(Format::Short),
+            "medium" => Ok(Format::Medium),
+            "full" => Ok(Format::Full),
+            "fuller" => Ok(Format::Fuller),
+            x => {
+                if let Some(template) = x.strip_prefix("format:") {
+                    Ok(Format::Custom {
+                        template: template.to_string(),
+                        terminator: false,
+                    })
+                } else if let Some(template) = x.strip_prefix("tformat:") {
+                    Ok(Format::Custom {
+                        template: template.to_string(),
+                        terminator: true,
+                    })
+                } else if x.contains('%') || x.is_empty() {
+                    Ok(Format::Custom {
+                        template: x.to_string(),
+                        terminator: true,
+                    })
+                } else {
+                    Err(format!("invalid --pretty format: {x}"))
+                }
+            }
+        }
+    }
+}
+
+impl Format {
+    /// Text printed between two consecutive entries.
+    pub fn separator(&self) -> &'static str {
+        match self {
+            Format::Oneline => "",
+            Format::Custom {
+                terminator: true, ..
+            } => "",
+            _ => "\n",
+        }
+    }
+
+    /// Text printed after every entry, none after empty ones.
+    pub fn terminator(&self) -> &'static str {
+        match self {
+            Format::Custom {
+                terminator: false, ..
+            } => "",
+            Format::Custom { template, .. } if template.is_empty() => "",
+            _ => "\n",
+        }
+    }
+
+    /// Whether entries name the refs pointing at commits (`%d`, `%D`).
+    pub fn decorates(&self) -> bool {
+        match self {
+            Format::Custom { template, .. } => template.contains("%d") || template.contains("%D"),
+            _ => false,
+        }
+    }
+}
+
+/// How [`render`] and [`expand`] show a commit.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub date: DateFormat,
+    /// Whether `%C(...)` colors are used, unless they say `always`.
+    pub color: bool,
+    /// Names `%d` and `%D` show for each commit, from [`decorations`].
+    pub decorations: HashMap<String, Vec<String>>,
+}
+
+/// The names of the refs pointing at each commit, as `git log` decorates
+/// them by default: HEAD first, then the refs in reverse order of their
+/// full names, branches and remote-tracking branches by their short name
+/// and tags as `tag: <name>` on the commit they peel to.
+pub fn decorations() -> std::io::Result<HashMap<String, Vec<String>>> {
+    let mut decorations = HashMap::<String, Vec<String>>::new();
+    let head = refs::read_head()?;
+    if let Some(hash) = refs::head_commit()? {
+        let label = match &head {
+            Head::Branch(name) => format!("HEAD -> {}", short_ref(name)),
+            Head::Detached(_) => "HEAD".to_string(),
+        };
+        decorations.entry(hash).or_default().push(label);
+    }
+
+    for (name, hash) in refs::list("refs/")?.into_iter().rev() {
+        if name.starts_with("refs/notes/") || head == Head::Branch(name.clone()) {
+            continue;
+        }
+        let label = match name.strip_prefix("refs/tags/") {
+            Some(tag) => format!("tag: {tag}"),
+            None => short_ref(&name).to_string(),
+        };
+        let hash = revision::peel(&hash, None)?;
+        decorations.entry(hash).or_default().push(label);
+    }
+
+    Ok(decorations)
+}
+
+/// Branches and remote-tracking branches without their prefix, other refs
+/// by their full name.
+fn short_ref(name: &str) -> &str {
+    name.strip_prefix("refs/heads/")
+        .or_else(|| name.strip_prefix("refs/remotes/"))
+        .unwrap_or(name)
+}
+
+fn offset(zone: &str) -> FixedOffset {
+    let sign = if zone.starts_with('-') { -1 } else { 1 };
+    let digits = zone.trim_start_matches(['+', '-']);
+    let hours = digits
+        .get(..2)
+        .and_then(|x| x.parse::<i32>().ok())
+        .unwrap_or(0);
+    let minutes = digits
+        .get(2..4)
+        .and_then(|x| x.parse::<i32>().ok())
+        .unwrap_or(0);
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+        .unwrap_or(FixedOffset::east_opt(0).unwrap())
+}
+
+fn plural(count: u64, unit: &str) -> String {
+    match count {
+        1 => format!("{count} {unit}"),
+        _ => format!("{count} {unit}s"),
+    }
+}
+
+/// Same buckets as git's `show_date_relative`.
+pub fn relative(timestamp: u64, now: u64) -> String {
+    if timestamp > now {
+        return "in the future".to_string();
+    }
+
+    let mut diff = now - timestamp;
+    if diff < 90 {
+        return format!("{} ago", plural(diff, "second"));
+    }
+    diff = (diff + 30) / 60;
+    if diff < 90 {
+        return format!("{} ago", plural(diff, "minute"));
+    }
+    diff = (diff + 30) / 60;
+    if diff < 36 {
+        return format!("{} ago", plural(diff, "hour"));
+    }
+    diff = (diff + 12) / 24;
+    if diff < 14 {
+        return format!("{} ago", plural(diff, "day"));
+    }
+    if diff < 70 {
+        return format!("{} ago", plural((diff + 3) / 7, "week"));
+    }
+    if diff < 365 {
+        return format!("{} ago", plural((diff + 15) / 30, "month"));
+    }
+    if diff < 1825 {
+        let total_months = (diff * 12 * 2 + 365) / (365 * 2);
+        let years = total_months / 12;
+        let months = total_months % 12;
+        if months > 0 {
+            return format!("{}, {} ago", plural(years, "year"), plural(months, "month"));
+        }
+        return format!("{} ago", plural(years, "year"));
+    }
+
+    format!("{} ago", plural((diff + 183) / 365, "year"))
+}
+
+pub fn format_date(who: &Author, format: DateFormat) -> String {
+    let time = DateTime::from_timestamp(who.date as i64, 0)
+        .unwrap_or_default()
+        .with_timezone(&offset(&who.zone));
+
+    match format {
+        DateFormat::Default => time.format("%a %b %-d %H:%M:%S %Y %z").to_string(),
+        DateFormat::Iso => time.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+        DateFormat::IsoStrict => time.format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+        DateFormat::Rfc => time.format("%a, %-d %b %Y %H:%M:%S %z").to_string(),
+        DateFormat::Short => time.format("%Y-%m-%d").to_string(),
+        DateFormat::Raw => format!("{} {}", who.date, who.zone),
+        DateFormat::Unix => who.date.to_string(),
+        DateFormat::Relative => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|x| x.as_secs())
+                .unwrap_or_default();
+            relative(who.date, now)
+        }
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+const WEEKDAYS: [&str; 7] = [
+    "sunday",
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+];
+
+/// Position in `names` of the one `word` abbreviates, in three letters or
+/// more.
+fn find_name(names: &[&str], word: &str) -> Option<usize> {
+    names
+        .iter()
+        .position(|x| word.len() >= 3 && x.starts_with(word))
+}
+
+/// What the words of a date say, `None` for what they leave out.
+#[derive(Debug, Default)]
+struct DateParts {
+    year: Option<i32>,
+    month: Option<u32>,
+    day: Option<u32>,
+    time: Option<(u32, u32, u32)>,
+    /// Offset from UTC in seconds, local time if not given.
+    zone: Option<i32>,
+    /// For `noon` and `midnight`, which mean the last one that has passed.
+    past: bool,
+    /// After `last`, which counts one of the unit or weekday that follows.
+    last: bool,
+    /// Numbers not yet known to count a unit, or be a day or a year.
+    numbers: Vec<u32>,
+    seconds_ago: u64,
+    months_ago: u32,
+}
+
+impl DateParts {
+    /// Takes in a word starting with a digit or a sign: a number, a date,
+    /// a time of day or a zone.
+    fn number(&mut self, word: &str) -> Option<()> {
+        let fields = |separator: char| -> Option<Vec<u32>> {
+            word.split(separator).map(|x| x.parse().ok()).collect()
+        };
+
+        if let Some(zone) = word.strip_prefix(['+', '-']) {
+            let digits = zone.replace(':', "");
+            let hours = digits.get(..2)?.parse::<i32>().ok()?;
+            let minutes = digits.get(2..)?.parse::<i32>().ok()?;
+            let sign = if word.starts_with('-') { -1 } else { 1 };
+            self.zone = Some(sign * (hours * 3600 + minutes * 60));
+        } else if word.contains(':') {
+            self.time = match fields(':')?[..] {
+                [hour, minute] => Some((hour, minute, 0)),
+                [hour, minute, second] => Some((hour, minute, second)),
+                _ => return None,
+            };
+        } else if let Some(separator) = ['-', '/', '.'].into_iter().find(|x| word.contains(*x)) {
+            let [a, b, c] = fields(separator)?[..] else {
+                return None;
+            };
+            // the year goes first, or after the month and day: day first
+            // with dots, month first otherwise
+            let (year, month, day) = match separator {
+                _ if a > 31 => (a, b, c),
+                '.' => (c, b, a),
+                _ => (c, a, b),
+            };
+            self.year = Some(year as i32);
+            self.month = Some(month);
+            self.day = Some(day);
+        } else {
+            self.numbers.push(word.parse().ok()?);
+        }
+        Some(())
+    }
+
+    /// Takes in a word starting with a letter: a unit of time, a month or
+    /// weekday name, a zone or a word like `yesterday`.
+    fn word(&mut self, word: &str, weekday: u32) -> Option<()> {
+        let word = word.to_ascii_lowercase();
+        let seconds = match word.trim_end_matches('s') {
+            "second" | "sec" => 1,
+            "minute" | "min" => 60,
+            "hour" => 3600,
+            "day" => 86400,
+            "week" => 7 * 86400,
+            // calendar months, like git
+            "month" => 0,
+            "year" => 0,
+            "ago" | "now" | "today" => return Some(()),
+            "last" => {
+                self.last = true;
+                return Some(());
+            }
+            "yesterday" => {
+                self.seconds_ago += 86400;
+                return Some(());
+            }
+            "noon" | "midnight" => {
+                let hour = if word == "noon" { 12 } else { 0 };
+                self.time = Some((hour, 0, 0));
+                self.past = true;
+                return Some(());
+            }
+            "utc" | "gmt" | "z" => {
+                self.zone = Some(0);
+                return Some(());
+            }
+            _ => {
+                if let Some(month) = find_name(&MONTHS, &word) {
+                    self.month = Some(month as u32 + 1);
+                } else if let Some(day) = find_name(&WEEKDAYS, &word) {
+                    // only `last <weekday>` goes back, a week for today's
+                    if self.last {
+                        let days = (weekday + 6 - day as u32) % 7 + 1;
+                        self.seconds_ago += u64::from(days) * 86400;
+                    }
+                } else {
+                    return None;
+                }
+                return Some(());
+            }
+        };
+
+        let count = match self.numbers.pop() {
+            Some(count) => count,
+            None if self.last => 1,
+            None => return None,
+        };
+        match word.trim_end_matches('s') {
+            "month" => self.months_ago += count,
+            "year" => self.months_ago += 12 * count,
+            _ => self.seconds_ago += u64::from(count) * seconds,
+        }
+        Some(())
+    }
+}
+
+/// The time `parts` name in `zone`, taking what they leave out from `now`.
+fn absolute<Tz: chrono::TimeZone>(zone: &Tz, parts: &DateParts, now: i64) -> Option<i64> {
+    use chrono::{Datelike, Timelike};
+
+    let now = DateTime::from_timestamp(now, 0)?.with_timezone(zone);
+    let date = chrono::NaiveDate::from_ymd_opt(
+        parts.year.unwrap_or(now.year()),
+        parts.month.unwrap_or(now.month()),
+        parts.day.unwrap_or(now.day()),
+    )?;
+    let (hour, minute, second) = parts
+        .time
+        .unwrap_or((now.hour(), now.minute(), now.second()));
+    let time = date
+        .and_hms_opt(hour, minute, second)?
+        .and_local_timezone(zone.clone())
+        .earliest()?;
+    Some(time.timestamp())
+}
+
+/// Parses the dates accepted by `--since`/`--until` much like git's
+/// approxidate: a unix timestamp, absolute dates such as `2020-01-01
+/// 12:00:00 +0000`, `2020-01-01T12:00:00Z`, RFC 2822 or `Jan 1 2020` in
+/// local time unless they give a zone, words like `yesterday`, `noon` or
+/// `last monday`, and relative ones like `2 weeks ago` (also written
+/// `2.weeks.ago`).
+pub fn parse_date(value: &str, now: u64) -> Option<u64> {
+    use chrono::{Datelike, Local, Months};
+
+    let value = value.trim();
+    if let Ok(timestamp) = value.trim_start_matches('@').parse::<u64>() {
+        return Some(timestamp);
+    }
+
+    let local = DateTime::from_timestamp(now as i64, 0)?.with_timezone(&Local);
+    let weekday = local.weekday().num_days_from_sunday();
+    let mut parts = DateParts::default();
+    for word in value.split(|x: char| x.is_whitespace() || x == ',') {
+        // `2.weeks.ago`, and the date, time and zone of ISO 8601
+        let words = match word.contains(|x: char| x.is_ascii_alphabetic()) {
+            false => vec![word],
+            true if word.contains('.') => word.split('.').collect(),
+            true => match word.split_once('T') {
+                Some((date, time)) if date.starts_with(|x: char| x.is_ascii_digit()) => {
+                    let zone = time.find(['+', '-', 'Z']).unwrap_or(time.len());
+                    vec![date, &time[..zone], &time[zone..]]
+                }
+                _ => vec![word],
+            },
+        };
+
+        for word in words.into_iter().filter(|x| !x.is_empty()) {
+            match word.starts_with(|x: char| x.is_ascii_alphabetic()) {
+                true => parts.word(word, weekday)?,
+                false => parts.number(word)?,
+            }
+        }
+    }
+
+    // numbers not counting a unit are the day of the month or the year
+    for number in std::mem::take(&mut parts.numbers) {
+        match number {
+            1..=31 if parts.day.is_none() => parts.day = Some(number),
+            1970.. if parts.year.is_none() => parts.year = Some(number as i32),
+            _ => return None,
+        }
+    }
+
+    let dated = parts.year.is_some() || parts.month.is_some() || parts.day.is_some();
+    let mut timestamp = match (dated || parts.time.is_some(), parts.zone) {
+        (false, _) => now as i64,
+        (true, Some(zone)) => absolute(&FixedOffset::east_opt(zone)?, &parts, now as i64)?,
+        (true, None) => absolute(&Local, &parts, now as i64)?,
+    };
+    if parts.past && !dated && timestamp > now as i64 {
+        timestamp -= 86400;
+    }
+
+    let time = DateTime::from_timestamp(timestamp, 0)?.with_timezone(&Local);
+    let time = time.checked_sub_months(Months::new(parts.months_ago))?;
+    u64::try_from(time.timestamp())
+        .ok()?
+        .checked_sub(parts.seconds_ago)
+}
+
+/// First paragraph of the message, joined into a single line.
+pub fn subject(message: &str) -> String {
+    message
+        .lines()
+        .take_while(|x| !x.trim().is_empty())
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Everything after the first paragraph of the message.
+pub fn body(message: &str) -> String {
+    let mut lines = message.lines().skip_while(|x| x.trim().is_empty());
+    for line in lines.by_ref() {
+        if line.trim().is_empty() {
+            break;
+        }
+    }
+
+    let rest = lines
+        .skip_while(|x| x.trim().is_empty())
+        .collect::<Vec<_>>();
+    match rest.is_empty() {
+        true => String::new(),
+        false => format!("{}\n", rest.join("\n")),
+    }
+}
+
+fn color(name: &str) -> Option<&'static str> {
+    match name {
+        "reset" => Some("\x1b[m"),
+        "normal" => Some(""),
+        "bold" => Some("\x1b[1m"),
+        "dim" => Some("\x1b[2m"),
+        "black" => Some("\x1b[30m"),
+        "red" => Some("\x1b[31m"),
+        "green" => Some("\x1b[32m"),
+        "yellow" => Some("\x1b[33m"),
+        "blue" => Some("\x1b[34m"),
+        "magenta" => Some("\x1b[35m"),
+        "cyan" => Some("\x1b[36m"),
+        "white" => Some("\x1b[37m"),
+        _ => None,
+    }
+}
+
+fn person(spec: char, who: &Author, date: DateFormat) -> Option<String> {
+    let value = match spec {
+        'n' | 'N' => who.name.clone(),
+        'e' | 'E' => who.email.clone(),
+        'l' => who.email.split('@').next().unwrap_or_default().to_string(),
+        'd' => format_date(who, date),
+        'D' => format_date(who, DateFormat::Rfc),
+        'r' => format_date(who, DateFormat::Relative),
+        't' => format_date(who, DateFormat::Unix),
+        'i' => format_date(who, DateFormat::Iso),
+        'I' => format_date(who, DateFormat::IsoStrict),
+        's' => format_date(who, DateFormat::Short),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+/// Width the placeholder after `%<(N)`, `%>(N)` or `%><(N)` is padded to,
+/// and cut to with `trunc`, `ltrunc` or `mtrunc`.
+#[derive(Debug, Clone, Copy)]
+struct Padding<'a> {
+    align: Align,
+    width: usize,
+    /// Whether `width` is the column to reach (`%<|(N)`) instead.
+    column: bool,
+    truncate: Option<&'a str>,
+}
+
+impl<'a> Padding<'a> {
+    /// Reads the padding placeholder starting `spec` (after the `%`),
+    /// along with its length.
+    fn parse(spec: &'a str) -> Option<(Self, usize)> {
+        let (align, rest) = [
+            ("><", Align::Center),
+            (">>", Align::Right),
+            ("<", Align::Left),
+            (">", Align::Right),
+        ]
+        .into_iter()
+        .find_map(|(prefix, align)| Some((align, spec.strip_prefix(prefix)?)))?;
+        let (column, rest) = match rest.strip_prefix('|') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let arguments = rest.strip_prefix('(')?;
+        let end = arguments.find(')')?;
+        let (width, truncate) = match arguments[..end].split_once(',') {
+            Some((width, truncate @ ("trunc" | "ltrunc" | "mtrunc"))) => (width, Some(truncate)),
+            Some(_) => return None,
+            None => (&arguments[..end], None),
+        };
+
+        let padding = Padding {
+            align,
+            width: width.trim().parse().ok()?,
+            column,
+            truncate,
+        };
+        Some((padding, spec.len() - arguments.len() + end + 1))
+    }
+
+    /// Pads or cuts `value`, which starts `column` characters into a line.
+    fn apply(&self, value: &str, column: usize) -> String {
+        let width = match self.column {
+            true => self.width.saturating_sub(column),
+            false => self.width,
+        };
+        let chars = value.chars().collect::<Vec<_>>();
+        if chars.len() > width {
+            let keep = width.saturating_sub(2);
+            let text = |x: &[char]| x.iter().collect::<String>();
+            return match self.truncate {
+                Some("trunc") => format!("{}..", text(&chars[..keep])),
+                Some("ltrunc") => format!("..{}", text(&chars[chars.len() - keep..])),
+                Some(_) => {
+                    let head = (width / 2).saturating_sub(1).min(keep);
+                    let tail = chars.len() - (keep - head);
+                    format!("{}..{}", text(&chars[..head]), text(&chars[tail..]))
+                }
+                None => value.to_string(),
+            };
+        }
+
+        let space = width - chars.len();
+        let left = match self.align {
+            Align::Left => 0,
+            Align::Right => space,
+            Align::Center => space / 2,
+        };
+        format!("{}{value}{}", " ".repeat(left), " ".repeat(space - left))
+    }
+}
+
+/// Expands the `git log --format` placeholders of `template` for a commit.
+/// Unknown placeholders are copied through untouched, as git does.
+pub fn expand(template: &str, hash: &str, commit: &Commit, options: &Options) -> String {
+    let date = options.date;
+    let mut out = String::new();
+    let mut rest = template;
+    let mut padding = None;
+
+    while let Some(start) = rest.find('%') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        let mut chars = rest.chars();
+        let Some(first) = chars.next() else {
+            out.push('%');
+            break;
+        };
+
+        if let Some((spec, used)) = Padding::parse(rest) {
+            padding = Some(spec);
+            rest = &rest[used..];
+            continue;
+        }
+
+        let short = |x: &str| x.get(..7).unwrap_or(x).to_string();
+        let (value, used) = match first {
+            '%' => (Some("%".to_string()), 1),
+            'n' => (Some("\n".to_string()), 1),
+            'H' => (Some(hash.to_string()), 1),
+            'h' => (Some(short(hash)), 1),
+            'T' => (Some(commit.tree.clone()), 1),
+            't' => (Some(short(&commit.tree)), 1),
+            'P' => (Some(commit.parents.join(" ")), 1),
+            'p' => (
+                Some(
+                    commit
+                        .parents
+                        .iter()
+                        .map(|x| short(x))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+                1,
+            ),
+            's' => (Some(subject(&commit.message)), 1),
+            'f' => (
+                Some(
+                    subject(&commit.message)
+                        .chars()
+                        .map(|x| if x.is_ascii_alphanumeric() { x } else { '-' })
+                        .collect::<String>()
+                        .split('-')
+                        .filter(|x| !x.is_empty())
+                        .collect::<Vec<_>>()
+                        .join("-"),
+                ),
+                1,
+            ),
+            'd' | 'D' => {
+                let names = options.decorations.get(hash).map_or(&[][..], Vec::as_slice);
+                let names = names.join(", ");
+                match (first, names.is_empty()) {
+                    ('d', false) => (Some(format!(" ({names})")), 1),
+                    _ => (Some(names), 1),
+                }
+            }
+            'b' => (Some(body(&commit.message)), 1),
+            'B' => (Some(format!("{}\n", commit.message)), 1),
+            'a' => (
+                chars.next().and_then(|x| person(x, &commit.author, date)),
+                2,
+            ),
+            'c' => (
+                chars
+                    .next()
+                    .and_then(|x| person(x, &commit.committer, date)),
+                2,
+            ),
+            'x' => (
+                rest.get(1..3)
+                    .and_then(|x| u8::from_str_radix(x, 16).ok())
+                    .map(|x| (x as char).to_string()),
+                3,
+            ),
+            'C' => {
+                if let Some(spec) = rest.strip_prefix("C(") {
+                    match spec.find(')') {
+                        Some(end) => {
+                            // colors only go to terminals unless `always`
+                            let (names, show) = match spec[..end].split_once(',') {
+                                Some(("always", names)) => (names, true),
+                                Some(("auto", names)) => (names, options.color),
+                                _ => (&spec[..end], options.color),
+                            };
+                            let value = names
+                                .split_whitespace()
+                                .map(color)
+                                .collect::<Option<String>>();
+                            (value.map(|x| if show { x } else { String::new() }), end + 3)
+                        }
+                        None => (None, 1),
+                    }
+                } else {
+                    ["reset", "red", "green", "blue"]
+                        .iter()
+                        .find(|x| rest[1..].starts_with(*x))
+                        .map(|x| match options.color {
+                            true => (color(x).map(str::to_string), x.len() + 1),
+                            false => (Some(String::new()), x.len() + 1),
+                        })
+                        .unwrap_or((None, 1))
+                }
+            }
+            _ => (None, 1),
+        };
+
+        match value {
+            Some(value) => {
+                let value = match padding.take() {
+                    Some(padding) => {
+                        let line = out.rsplit('\n').next().unwrap_or_default();
+                        padding.apply(&value, line.chars().count())
+                    }
+                    None => value,
+                };
+                out.push_str(&value);
+                rest = rest.get(used..).unwrap_or_default();
+            }
+            None => out.push('%'),
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// The lines naming the tagger of a tag for `git show`, which formats
+/// other than medium and fuller leave without a date.
+pub fn tagger(who: &Author, format: &Format, date: DateFormat) -> String {
+    match format {
+        Format::Oneline => String::new(),
+        Format::Medium => format!(
+            "Tagger: {} <{}>\nDate:   {}\n",
+            who.name,
+            who.email,
+            format_date(who, date)
+        ),
+        Format::Fuller => format!(
+            "Tagger:     {} <{}>\nTaggerDate: {}\n",
+            who.name,
+            who.email,
+            format_date(who, date)
+        ),
+        _ => format!("Tagger: {} <{}>\n", who.name, who.email),
+    }
+}
+
+fn indent(message: &str) -> String {
+    message
+        .lines()
+        .map(|x| format!("    {x}\n"))
+        .collect::<String>()
+}
+
+/// Renders one commit the way `git log --pretty=<format>` does, without the
+/// separator that goes between entries.
+pub fn render(hash: &str, commit: &Commit, format: &Format, options: &Options) -> String {
+    let date = options.date;
+    let merge = match commit.parents.len() {
+        0 | 1 => String::new(),
+        _ => format!(
+            "Merge: {}\n",
+            commit
+                .parents
+                .iter()
+                .map(|x| &x[..7])
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+    };
+    let author = &commit.author;
+    let committer = &commit.committer;
+
+    match format {
+        Format::Oneline => format!("{} {}", &hash[..7], subject(&commit.message)),
+        Format::Short => format!(
+            "commit {hash}\n{merge}Author: {} <{}>\n\n{}",
+            author.name,
+            author.email,
+            indent(&subject(&commit.message))
+        ),
+        Format::Medium => format!(
+            "commit {hash}\n{merge}Author: {} <{}>\nDate:   {}\n\n{}",
+            author.name,
+            author.email,
+            format_date(author, date),
+            indent(&commit.message)
+        ),
+        Format::Full => format!(
+            "commit {hash}\n{merge}Author: {} <{}>\nCommit: {} <{}>\n\n{}",
+            author.name,
+            author.email,
+            committer.name,
+            committer.email,
+            indent(&commit.message)
+        ),
+        Format::Fuller => format!(
+            "commit {hash}\n{merge}Author:     {} <{}>\nAuthorDate: {}\nCommit:     {} <{}>\nCommitDate: {}\n\n{}",
+            author.name,
+            author.email,
+            format_date(author, date),
+            committer.name,
+            committer.email,
+            format_date(committer, date),
+            indent(&commit.message)
+        ),
+        Format::Custom { template, .. } => return expand(template, hash, commit, options),
+    }
+    .trim_end_matches('\n')
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DateFormat, Format, Options};
+    use crate::git::objects::commit::{Author, Commit};
+
+    fn commit() -> Commit {
+        let who = Author {
+            name: "Some One".to_string(),
+            email: "one@domain.com".to_string(),
+            date: 1717431836,
+            zone: "+0700".to_string(),
+        };
+
+        Commit {
+            tree: "11144a9d4ce9ddea810a3d8b74abbd912e5028b1".to_string(),
+            parents: vec!["e1b03b60755972a80dfa8cb02326087d8b38b852".to_string()],
+            author: who.clone(),
+            committer: who,
+            message: "subject line\nwraps here\n\nbody text".to_string(),
+        }
+    }
+
+    const HASH: &str = "2e3347816d648ddef260980424acb9bec8346587";
+
+    #[test]
+    fn expand_placeholders() {
+        let out = super::expand(
+            "%h %an <%ae> %s%n%p|%b|%%|%x41",
+            HASH,
+            &commit(),
+            &Options::default(),
+        );
+        assert_eq!(
+            out,
+            "2e33478 Some One <one@domain.com> subject line wraps here\ne1b03b6|body text\n|%|A"
+        );
+    }
+
+    #[test]
+    fn expand_decorations() {
+        let mut options = Options::default();
+        assert_eq!(
+            super::expand("%h%d|%D", HASH, &commit(), &options),
+            "2e33478|"
+        );
+
+        let names = vec!["HEAD -> main".to_string(), "tag: v1".to_string()];
+        options.decorations.insert(HASH.to_string(), names);
+        assert_eq!(
+            super::expand("%h%d|%D", HASH, &commit(), &options),
+            "2e33478 (HEAD -> main, tag: v1)|HEAD -> main, tag: v1"
+        );
+    }
+
+    #[test]
+    fn expand_padding() {
+        let options = Options::default();
+        let expand = |template| super::expand(template, HASH, &commit(), &options);
+        assert_eq!(expand("%<(10)%an|"), "Some One  |");
+        assert_eq!(expand("%>(10)%an|"), "  Some One|");
+        assert_eq!(expand("%><(11)%an|"), " Some One  |");
+        assert_eq!(expand("%h %<|(20)%an|"), "2e33478 Some One    |");
+        assert_eq!(expand("%<(5)%an|"), "Some One|");
+        assert_eq!(expand("%<(6,trunc)%an|"), "Some..|");
+        assert_eq!(expand("%<(6,ltrunc)%an|"), ".. One|");
+        assert_eq!(expand("%<(6,mtrunc)%an|"), "So..ne|");
+        assert_eq!(expand("%<(4)x%an"), "xSome One");
+    }
+
+    #[test]
+    fn colors_follow_options() {
+        let mut options = Options::default();
+        let template = "%C(red)a%Creset%C(auto,blue)b%C(always,green)c";
+        assert_eq!(
+            super::expand(template, HASH, &commit(), &options),
+            "ab\x1b[32mc"
+        );
+        options.color = true;
+        assert_eq!(
+            super::expand(template, HASH, &commit(), &options),
+            "\x1b[31ma\x1b[m\x1b[34mb\x1b[32mc"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_kept() {
+        let out = super::expand("%z %a", HASH, &commit(), &Options::default());
+        assert_eq!(out, "%z %a");
+    }
+
+    #[test]
+    fn dates_keep_author_zone() {
+        let who = commit().author;
+        assert_eq!(
+            super::format_date(&who, DateFormat::Default),
+            "Mon Jun 3 23:23:56 2024 +0700"
+        );
+        assert_eq!(
+            super::format_date(&who, DateFormat::Iso),
+            "2024-06-03 23:23:56 +0700"
+        );
+        assert_eq!(super::format_date(&who, DateFormat::Short), "2024-06-03");
+    }
+
+    #[test]
+    fn taggers_follow_format() {
+        let who = commit().author;
+        assert_eq!(
+            super::tagger(&who, &Format::Medium, DateFormat::Default),
+            "Tagger: Some One <one@domain.com>\nDate:   Mon Jun 3 23:23:56 2024 +0700\n"
+        );
+        assert_eq!(
+            super::tagger(&who, &Format::Short, DateFormat::Default),
+            "Tagger: Some One <one@domain.com>\n"
+        );
+        assert_eq!(
+            super::tagger(&who, &Format::Oneline, DateFormat::Default),
+            ""
+        );
+    }
+
+    #[test]
+    fn relative_dates() {
+        assert_eq!(super::relative(100, 130), "30 seconds ago");
+        assert_eq!(super::relative(0, 3600), "60 minutes ago");
+        assert_eq!(super::relative(0, 86400 * 3), "3 days ago");
+        assert_eq!(super::relative(0, 86400 * 400), "1 year, 1 month ago");
+    }
+
+    #[test]
+    fn parse_dates() {
+        let now = 1_000_000_000;
+        assert_eq!(super::parse_date("1717431836", now), Some(1717431836));
+        assert_eq!(super::parse_date("2 days ago", now), Some(now - 2 * 86400));
+        assert_eq!(
+            super::parse_date("3.weeks.ago", now),
+            Some(now - 21 * 86400)
+        );
+        assert_eq!(super::parse_date("1 hour", now), Some(now - 3600));
+        assert_eq!(super::parse_date("yesterday", now), Some(now - 86400));
+        assert!(super::parse_date("2024-06-03", now).is_some());
+        assert!(super::parse_date("2024-06-03 10:00", now).is_some());
+        assert_eq!(super::parse_date("someday", now), None);
+    }
+
+    #[test]
+    fn parse_absolute_dates() {
+        let now = 1_000_000_000;
+        for value in [
+            "2020-01-01 12:00:00 +0000",
+            "2020-01-01T12:00:00Z",
+            "2020-01-01T13:00:00+01:00",
+            "2020-01-01 17:30:00 +05:30",
+            "Wed, 1 Jan 2020 12:00:00 +0000",
+            "Wed Jan 1 12:00:00 2020 +0000",
+            "Jan 1 2020 14:00 +0200",
+            "1 January 2020 12:00 UTC",
+            "01/01/2020 12:00:00 GMT",
+            "1.1.2020 12:00:00 Z",
+        ] {
+            assert_eq!(super::parse_date(value, now), Some(1577880000), "{value}");
+        }
+        assert_eq!(super::parse_date("Jan 40 2020", now), None);
+    }
+
+    #[test]
+    fn parse_relative_words() {
+        // Sunday 11:46:40 UTC, still Sunday from UTC-11 to UTC+12, and two
+        // years after a leap day
+        let now = 1_000_036_000;
+        assert_eq!(super::parse_date("last week", now), Some(now - 7 * 86400));
+        assert_eq!(super::parse_date("2 weeks", now), Some(now - 14 * 86400));
+        assert_eq!(super::parse_date("sunday", now), Some(now));
+        assert_eq!(super::parse_date("last sunday", now), Some(now - 7 * 86400));
+        assert_eq!(super::parse_date("last friday", now), Some(now - 2 * 86400));
+        assert_eq!(
+            super::parse_date("2 years ago", now),
+            Some(now - 731 * 86400)
+        );
+    }
+
+    #[test]
+    fn medium_format() {
+        let out = super::render(HASH, &commit(), &Format::Medium, &Options::default());
+        assert_eq!(
+            out,
+            format!(
+                "commit {HASH}\nAuthor: Some One <one@domain.com>\nDate:   Mon Jun 3 23:23:56 2024 +0700\n\n    subject line\n    wraps here\n    \n    body text"
+            )
+        );
+    }
+
+    #[test]
+    fn parse_format_names() {
+        assert_eq!("oneline".parse::<Format>(), Ok(Format::Oneline));
+        assert_eq!(
+            "%H".parse::<Format>(),
+            Ok(Format::Custom {
+                template: "%H".to_string(),
+                terminator: true
+            })
+        );
+        assert!("bogus".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn empty_format_ends_nothing() {
+        let format = "".parse::<Format>().unwrap();
+        assert_eq!(format.separator(), "");
+        assert_eq!(format.terminator(), "");
+    }
+}
@@ -0,0 +1,119 @@
+//! What a repository still uses: the objects refs, HEAD, reflogs and the
+//! index lead to, which repacking keeps and pruning never deletes.
+
+use std::collections::HashSet;
+
+use super::{
+    index::{Index, MODE_GITLINK},
+    list_objects::ObjectWalk,
+    objects::{tag, Object, Store},
+    packfile::ObjType,
+    reflog, refs, repository,
+    revwalk::RevWalk,
+};
+
+/// Objects everything else is reached from: what refs and HEAD point at,
+/// the objects reflogs name and the blobs staged in the index.
+pub fn roots() -> std::io::Result<Vec<String>> {
+    let mut roots = refs::list("refs/")?
+        .into_iter()
+        .map(|(_, hash)| hash)
+        .collect::<Vec<_>>();
+    roots.extend(refs::head_commit()?);
+
+    for name in reflog::names()? {
+        for entry in reflog::read(&name)? {
+            roots.extend(entry.objects().map(str::to_string));
+        }
+    }
+
+    let index = Index::open(&repository::index_file())?;
+    roots.extend(
+        index
+            .entries
+            .iter()
+            .filter(|x| x.mode != MODE_GITLINK)
+            .map(|x| hex::encode(x.hash)),
+    );
+
+    let mut seen = HashSet::new();
+    roots.retain(|x| seen.insert(x.clone()));
+    Ok(roots)
+}
+
+/// Every object reachable from `roots`, with the path trees and blobs were
+/// found at, commits first as history walks them and then what they need,
+/// as [`ObjectWalk`] gives them.
+pub fn objects(roots: &[String]) -> std::io::Result<Vec<(String, Option<String>)>> {
+    let mut store = Store::open()?;
+    let mut walk = RevWalk::new();
+    // objects no commit leads to, with their path
+    let mut others = Vec::new();
+
+    for root in roots {
+        let mut hash = root.clone();
+        let mut tags = Vec::new();
+        let kind = loop {
+            let kind = store.stream(&hash)?.kind;
+            if kind != ObjType::Tag {
+                break kind;
+            }
+            let (_, content) = store.read(&hash)?;
+            let tag = tag::parse(&mut content.as_slice())?;
+            tags.push((hash, ObjType::Tag, Some(tag.name)));
+            hash = tag.object;
+        };
+
+        match kind {
+            ObjType::Commit => {
+                walk.push(root)?;
+            }
+            _ => {
+                others.extend(tags);
+                others.push((hash, kind, None));
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut found = Vec::new();
+    for entry in ObjectWalk::new(walk, None)? {
+        let entry = entry?;
+        if seen.insert(entry.hash.clone()) {
+            found.push((entry.hash, entry.name.filter(|x| !x.is_empty())));
+        }
+    }
+
+    while let Some((hash, kind, path)) = others.pop() {
+        if !seen.insert(hash.clone()) {
+            continue;
+        }
+        if kind != ObjType::Tree {
+            found.push((hash, path));
+            continue;
+        }
+
+        if let Object::Tree(items) = Object::read_from_hash(&hash)? {
+            let prefix = path.as_deref().unwrap_or_default();
+            others.extend(
+                items
+                    .into_iter()
+                    .filter(|x| x.mode != MODE_GITLINK)
+                    .map(|x| {
+                        let kind = match x.is_tree() {
+                            true => ObjType::Tree,
+                            false => ObjType::Blob,
+                        };
+                        let path = match prefix.is_empty() {
+                            true => x.name,
+                            false => format!("{prefix}/{}", x.name),
+                        };
+                        (hex::encode(x.hash), kind, Some(path))
+                    }),
+            );
+        }
+        found.push((hash, path));
+    }
+
+    Ok(found)
+}
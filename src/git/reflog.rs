@@ -0,0 +1,191 @@
+//! Reflogs, `.git/logs/<ref>`: a line for each update of a ref with its
+//! old and new values, who made it and when, and why.
+
+use std::{collections::HashSet, fs, path::Path};
+
+use super::{
+    objects::{commit::Author, Store},
+    packfile::ObjType,
+    refs, repository, revision, revwalk,
+};
+
+/// Value a ref had before it existed, or after it was deleted.
+const NULL: &str = "0000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub old: String,
+    pub new: String,
+    pub who: Author,
+    pub message: String,
+}
+
+impl Entry {
+    /// Parses a line: `<old> <new> <identity>\t<message>`.
+    fn parse(line: &str) -> Option<Entry> {
+        let (header, message) = line.split_once('\t').unwrap_or((line, ""));
+        let (old, rest) = header.split_once(' ')?;
+        let (new, who) = rest.split_once(' ')?;
+        if old.len() != 40 || new.len() != 40 {
+            return None;
+        }
+
+        Some(Entry {
+            old: old.to_string(),
+            new: new.to_string(),
+            who: Author::parse(who)?,
+            message: message.to_string(),
+        })
+    }
+
+    /// The objects the entry names, leaving out the null one.
+    pub fn objects(&self) -> impl Iterator<Item = &str> {
+        [self.old.as_str(), self.new.as_str()]
+            .into_iter()
+            .filter(|x| *x != NULL)
+    }
+}
+
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.old, self.new, self.who)?;
+        if !self.message.is_empty() {
+            write!(f, "\t{}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+fn log_path(name: &str) -> std::path::PathBuf {
+    repository::git_dir().join("logs").join(name)
+}
+
+fn list(dir: &Path, name: &str, names: &mut Vec<String>) -> std::io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let full = format!("{name}/{}", entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            list(&entry.path(), &full, names)?;
+        } else if !full.ends_with(".lock") {
+            names.push(full);
+        }
+    }
+
+    Ok(())
+}
+
+/// Names of the refs that have a reflog, sorted.
+pub fn names() -> std::io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    if log_path("HEAD").is_file() {
+        names.push("HEAD".to_string());
+    }
+    list(&log_path("refs"), "refs", &mut names)?;
+    names.sort();
+    Ok(names)
+}
+
+/// Entries of the reflog of `name`, oldest first, skipping lines that
+/// cannot be parsed.
+pub fn read(name: &str) -> std::io::Result<Vec<Entry>> {
+    match fs::read_to_string(log_path(name)) {
+        Ok(content) => Ok(content.lines().filter_map(Entry::parse).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Commits reachable from `tips`, leaving out tips that are no commits.
+fn history(tips: &[String]) -> HashSet<String> {
+    let mut seen = HashSet::new();
+    let mut stack = tips
+        .iter()
+        .filter_map(|x| revision::peel(x, Some(ObjType::Commit)).ok())
+        .collect::<Vec<_>>();
+    while let Some(hash) = stack.pop() {
+        if seen.contains(&hash) {
+            continue;
+        }
+        if let Ok(commit) = revwalk::read_commit(&hash) {
+            stack.extend(commit.parents);
+        }
+        seen.insert(hash);
+    }
+    seen
+}
+
+/// Drops the entries of the reflog of `name` made at or before `expire`,
+/// those made at or before `expire_unreachable` with a commit the ref no
+/// longer reaches (that no ref reaches, for HEAD), and those naming
+/// objects that are gone, as `git reflog expire` does. Returns how many
+/// were dropped.
+pub fn expire(
+    name: &str,
+    store: &mut Store,
+    expire: u64,
+    expire_unreachable: u64,
+) -> std::io::Result<usize> {
+    let entries = read(name)?;
+    let mut reachable: Option<HashSet<String>> = None;
+
+    let mut kept = Vec::with_capacity(entries.len());
+    for entry in &entries {
+        let date = entry.who.date;
+        if date <= expire || entry.objects().any(|x| store.read(x).is_err()) {
+            continue;
+        }
+        if date <= expire_unreachable {
+            let reachable = match &mut reachable {
+                Some(reachable) => reachable,
+                None => {
+                    let tips = match name {
+                        "HEAD" => refs::list("refs/")?.into_iter().map(|(_, x)| x).collect(),
+                        _ => refs::resolve(name)?.into_iter().collect::<Vec<_>>(),
+                    };
+                    reachable.insert(history(&tips))
+                }
+            };
+            if entry.objects().any(|x| !reachable.contains(x)) {
+                continue;
+            }
+        }
+        kept.push(entry);
+    }
+
+    let dropped = entries.len() - kept.len();
+    if dropped > 0 {
+        let content = kept.iter().map(|x| format!("{x}\n")).collect::<String>();
+        refs::write_locked(&log_path(name), &content)?;
+    }
+    Ok(dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Entry;
+
+    #[test]
+    fn entries_round_trip() {
+        let line = "0000000000000000000000000000000000000000 \
+                    9b1b2b58bca2e9a4bda9cd70af0e0e2a1c1f8b49 \
+                    A U Thor <author@example.com> 1700000000 +0100\tcommit (initial): first";
+        let entry = Entry::parse(line).unwrap();
+
+        assert_eq!(entry.who.date, 1_700_000_000);
+        assert_eq!(entry.message, "commit (initial): first");
+        assert_eq!(entry.objects().count(), 1);
+        assert_eq!(entry.to_string(), line);
+
+        let bare = "9b1b2b58bca2e9a4bda9cd70af0e0e2a1c1f8b49 \
+                    9b1b2b58bca2e9a4bda9cd70af0e0e2a1c1f8b49 \
+                    A U Thor <author@example.com> 1700000000 +0100";
+        assert_eq!(Entry::parse(bare).unwrap().to_string(), bare);
+        assert!(Entry::parse("garbage").is_none());
+    }
+}
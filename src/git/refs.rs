@@ -0,0 +1,336 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use super::{repository, revision};
+
+const MAX_SYMREF_DEPTH: usize = 5;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Head {
+    /// HEAD points at a branch, holds the full ref name (`refs/heads/main`).
+    Branch(String),
+    Detached(String),
+}
+
+fn ref_path(name: &str) -> PathBuf {
+    repository::git_dir().join(name)
+}
+
+fn invalid(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+/// Raw value of a ref, either `ref: <target>` or an object name.
+fn read_raw(name: &str) -> std::io::Result<Option<String>> {
+    let path = ref_path(name);
+    if path.is_file() {
+        return Ok(Some(fs::read_to_string(path)?.trim().to_string()));
+    }
+
+    Ok(packed()?
+        .into_iter()
+        .find(|(x, _)| x == name)
+        .map(|(_, hash)| hash))
+}
+
+/// Entries of `.git/packed-refs` as `(name, hash)` pairs.
+pub fn packed() -> std::io::Result<Vec<(String, String)>> {
+    let content = match fs::read_to_string(repository::git_dir().join("packed-refs")) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    Ok(content
+        .lines()
+        .filter(|x| !x.starts_with('#') && !x.starts_with('^'))
+        .filter_map(|x| x.split_once(' '))
+        .map(|(hash, name)| (name.to_string(), hash.to_string()))
+        .collect())
+}
+
+fn list_loose(dir: &std::path::Path, name: &str, refs: &mut Vec<String>) -> std::io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let full = format!("{name}/{file_name}");
+
+        if entry.file_type()?.is_dir() {
+            list_loose(&entry.path(), &full, refs)?;
+        } else if !file_name.ends_with(".lock") {
+            refs.push(full);
+        }
+    }
+
+    Ok(())
+}
+
+/// All refs under `prefix` (e.g. `refs/heads/`) resolved to object names,
+/// loose and packed, sorted by name. Dangling symbolic refs are left out.
+pub fn list(prefix: &str) -> std::io::Result<Vec<(String, String)>> {
+    let mut names = Vec::new();
+    list_loose(&repository::git_dir().join("refs"), "refs", &mut names)?;
+    names.extend(packed()?.into_iter().map(|(name, _)| name));
+    names.retain(|x| x.starts_with(prefix));
+    names.sort();
+    names.dedup();
+
+    let mut refs = Vec::new();
+    for name in names {
+        if let Some(hash) = resolve(&name)? {
+            refs.push((name, hash));
+        }
+    }
+
+    Ok(refs)
+}
+
+/// Follows symbolic refs and returns the object name `name` points at.
+pub fn resolve(name: &str) -> std::io::Result<Option<String>> {
+    let mut name = name.to_string();
+
+    for _ in 0..MAX_SYMREF_DEPTH {
+        let Some(value) = read_raw(&name)? else {
+            return Ok(None);
+        };
+
+        match value.strip_prefix("ref: ") {
+            Some(target) => name = target.to_string(),
+            None => return Ok(Some(value)),
+        }
+    }
+
+    Err(invalid(format!("symbolic ref loop at {name}")))
+}
+
+/// Whether `name` is a symbolic ref, pointing at another ref by name.
+pub fn is_symbolic(name: &str) -> std::io::Result<bool> {
+    Ok(read_raw(name)?.is_some_and(|x| x.starts_with("ref: ")))
+}
+
+pub fn exists(name: &str) -> bool {
+    matches!(resolve(name), Ok(Some(_)))
+}
+
+pub fn read_head() -> std::io::Result<Head> {
+    let Some(value) = read_raw("HEAD")? else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "not a git repository",
+        ));
+    };
+
+    match value.strip_prefix("ref: ") {
+        Some(target) => Ok(Head::Branch(target.to_string())),
+        None => Ok(Head::Detached(value)),
+    }
+}
+
+/// Commit HEAD points at, `None` on an unborn branch.
+pub fn head_commit() -> std::io::Result<Option<String>> {
+    resolve("HEAD")
+}
+
+/// Writes a ref through a `.lock` file so readers never see a partial value.
+fn write_raw(name: &str, value: &str) -> std::io::Result<()> {
+    let path = ref_path(name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_locked(&path, &format!("{value}\n"))
+}
+
+/// Writes `content` to `path` through a `.lock` file renamed into place.
+pub fn write_locked(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut lock_name = path.as_os_str().to_owned();
+    lock_name.push(".lock");
+    let lock = PathBuf::from(lock_name);
+
+    let mut file = match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&lock)
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err(std::io::Error::new(
+                e.kind(),
+                format!("unable to lock {}: file exists", lock.display()),
+            ))
+        }
+        Err(e) => return Err(e),
+    };
+
+    let result = file
+        .write_all(content.as_bytes())
+        .and_then(|_| fs::rename(&lock, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&lock);
+    }
+
+    result
+}
+
+/// Moves every ref but symbolic ones into `.git/packed-refs`, along with
+/// what annotated tags peel to, and deletes their loose files, as
+/// `git pack-refs --all --prune` does.
+pub fn pack_refs() -> std::io::Result<()> {
+    let mut names = Vec::new();
+    list_loose(&repository::git_dir().join("refs"), "refs", &mut names)?;
+
+    let mut refs = packed()?.into_iter().collect::<BTreeMap<_, _>>();
+    let mut loose = Vec::new();
+    for name in names {
+        let value = fs::read_to_string(ref_path(&name))?.trim().to_string();
+        if !value.starts_with("ref: ") {
+            refs.insert(name.clone(), value.clone());
+            loose.push((name, value));
+        }
+    }
+
+    let mut content = String::from("# pack-refs with: peeled fully-peeled sorted \n");
+    for (name, hash) in &refs {
+        content.push_str(&format!("{hash} {name}\n"));
+        match revision::peel(hash, None) {
+            Ok(peeled) if peeled != *hash => content.push_str(&format!("^{peeled}\n")),
+            _ => {}
+        }
+    }
+    write_locked(&repository::git_dir().join("packed-refs"), &content)?;
+
+    for (name, value) in loose {
+        let path = ref_path(&name);
+        // a ref updated meanwhile stays loose, its value being the newer
+        if fs::read_to_string(&path)?.trim() != value {
+            continue;
+        }
+        fs::remove_file(&path)?;
+        // directories under `refs/heads/` and the like go once empty
+        let git_dir = repository::git_dir();
+        let depth = |x: &Path| {
+            x.strip_prefix(&git_dir)
+                .map_or(0, |x| x.components().count())
+        };
+        let mut dir = path.parent();
+        while let Some(parent) = dir.filter(|&x| depth(x) > 2) {
+            if fs::remove_dir(parent).is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+    }
+
+    Ok(())
+}
+
+/// Points `name` at `hash`, following HEAD to the branch it is attached to.
+pub fn update(name: &str, hash: &str) -> std::io::Result<()> {
+    if name == "HEAD" {
+        if let Head::Branch(branch) = read_head()? {
+            return write_raw(&branch, hash);
+        }
+    }
+
+    write_raw(name, hash)
+}
+
+/// Deletes the ref `name`, loose or packed, along with what it peels to
+/// in `.git/packed-refs`.
+pub fn delete(name: &str) -> std::io::Result<()> {
+    match fs::remove_file(ref_path(name)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+
+    let path = repository::git_dir().join("packed-refs");
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let mut kept = String::new();
+    let mut dropped = false;
+    let mut skipping = false;
+    for line in content.lines() {
+        // a `^` line belongs to the ref before it
+        if line.starts_with('^') && skipping {
+            continue;
+        }
+        skipping = line.split_once(' ').is_some_and(|(_, x)| x == name);
+        dropped |= skipping;
+        if !skipping {
+            kept.push_str(line);
+            kept.push('\n');
+        }
+    }
+    match dropped {
+        true => write_locked(&path, &kept),
+        false => Ok(()),
+    }
+}
+
+/// Attaches HEAD to the branch `target` (`refs/heads/<name>`).
+pub fn set_head(target: &str) -> std::io::Result<()> {
+    set_symbolic("HEAD", target)
+}
+
+/// Makes `name` a symbolic ref pointing at the ref `target`.
+pub fn set_symbolic(name: &str, target: &str) -> std::io::Result<()> {
+    write_raw(name, &format!("ref: {target}"))
+}
+
+/// Detaches HEAD at `hash`.
+pub fn detach_head(hash: &str) -> std::io::Result<()> {
+    write_raw("HEAD", hash)
+}
+
+/// Checks a short branch or tag name against `git check-ref-format` rules.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && !name.starts_with('/')
+        && !name.ends_with('/')
+        && !name.ends_with('.')
+        && !name.ends_with(".lock")
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name.contains("//")
+        && name != "@"
+        && !name.split('/').any(|x| x.starts_with('.'))
+        && !name
+            .chars()
+            .any(|x| x.is_ascii_control() || " ~^:?*[\\".contains(x))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn valid_ref_names() {
+        assert!(super::is_valid_name("main"));
+        assert!(super::is_valid_name("feature/login-form"));
+        assert!(super::is_valid_name("v1.0"));
+    }
+
+    #[test]
+    fn invalid_ref_names() {
+        assert!(!super::is_valid_name(""));
+        assert!(!super::is_valid_name("-b"));
+        assert!(!super::is_valid_name("a..b"));
+        assert!(!super::is_valid_name("a b"));
+        assert!(!super::is_valid_name("topic.lock"));
+        assert!(!super::is_valid_name("feature/.hidden"));
+        assert!(!super::is_valid_name("HEAD@{1}"));
+        assert!(!super::is_valid_name("trailing/"));
+    }
+}
@@ -0,0 +1,208 @@
+//! Remotes as the configuration describes them, `remote.<name>.url` and
+//! the refspecs of `remote.<name>.fetch`, which map the refs of the
+//! remote to local ones.
+
+use super::{config::Config, revwalk};
+
+/// Width the summary of an update is padded to in the reports of fetch
+/// and push, that of `abc1234...def5678`.
+pub const SUMMARY_WIDTH: usize = 17;
+
+/// A mapping from refs of one repository to refs of another:
+/// `[+]<src>[:<dst>]`, where both sides may hold one `*`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refspec {
+    /// Whether the mapping allows updates that are not fast-forwards.
+    pub force: bool,
+    pub src: String,
+    pub dst: Option<String>,
+}
+
+impl Refspec {
+    /// Parses a refspec, `None` if its sides don't both have a `*` or
+    /// neither does.
+    pub fn parse(spec: &str) -> Option<Refspec> {
+        let (force, spec) = match spec.strip_prefix('+') {
+            Some(spec) => (true, spec),
+            None => (false, spec),
+        };
+        let (src, dst) = match spec.split_once(':') {
+            Some((src, dst)) => (src, Some(dst.to_string())),
+            None => (spec, None),
+        };
+
+        let stars = |x: &str| x.matches('*').count();
+        let glob = stars(src);
+        let valid = match &dst {
+            Some(dst) if !dst.is_empty() => glob <= 1 && stars(dst) == glob,
+            _ => glob <= 1,
+        };
+        valid.then(|| Refspec {
+            force,
+            src: src.to_string(),
+            dst: dst.filter(|x| !x.is_empty()),
+        })
+    }
+
+    pub fn is_glob(&self) -> bool {
+        self.src.contains('*')
+    }
+
+    /// What `name` maps to, if it matches the source side of a glob
+    /// refspec: its destination with the `*` replaced.
+    pub fn map(&self, name: &str) -> Option<String> {
+        let matched = glob_match(&self.src, name)?;
+        Some(self.dst.as_ref()?.replacen('*', matched, 1))
+    }
+
+    /// The source `name` comes from, if it matches the destination side
+    /// of a glob refspec.
+    pub fn reverse(&self, name: &str) -> Option<String> {
+        let matched = glob_match(self.dst.as_ref()?, name)?;
+        Some(self.src.replacen('*', matched, 1))
+    }
+}
+
+impl std::fmt::Display for Refspec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.force {
+            write!(f, "+")?;
+        }
+        write!(f, "{}", self.src)?;
+        match &self.dst {
+            Some(dst) => write!(f, ":{dst}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What the `*` of `pattern` stands for in `name`, if `name` matches it.
+fn glob_match<'a>(pattern: &str, name: &'a str) -> Option<&'a str> {
+    let (prefix, suffix) = pattern.split_once('*')?;
+    let rest = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    (!rest.is_empty()).then_some(rest)
+}
+
+/// Full names a short ref name may stand for, in the order they are
+/// tried.
+pub fn expansions(name: &str) -> [String; 6] {
+    [
+        name.to_string(),
+        format!("refs/{name}"),
+        format!("refs/tags/{name}"),
+        format!("refs/heads/{name}"),
+        format!("refs/remotes/{name}"),
+        format!("refs/remotes/{name}/HEAD"),
+    ]
+}
+
+/// The ref among `refs` a possibly short `name` stands for.
+pub fn find<'a>(refs: &'a [(String, String)], name: &str) -> Option<&'a (String, String)> {
+    expansions(name)
+        .iter()
+        .find_map(|full| refs.iter().find(|(x, _)| x == full))
+}
+
+/// A ref name without the `refs/heads/`, `refs/tags/` or `refs/remotes/`
+/// it starts with, as reports show it.
+pub fn pretty(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/"]
+        .iter()
+        .find_map(|x| name.strip_prefix(x))
+        .unwrap_or(name)
+}
+
+/// Whether a ref at `old` may move to `new` without losing commits.
+pub fn is_fast_forward(old: &str, new: &str) -> bool {
+    revwalk::merge_bases(old, new).is_ok_and(|x| x.iter().any(|x| x == old))
+}
+
+/// A repository to fetch from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    pub name: String,
+    pub url: String,
+    pub fetch: Vec<Refspec>,
+}
+
+impl Remote {
+    /// The remote configured as `name`, or one without refspecs for the
+    /// repository at `name` if it is a URL or a path instead.
+    pub fn get(config: &Config, name: &str) -> std::io::Result<Option<Remote>> {
+        let url = match config.get(&format!("remote.{name}.url")) {
+            Some(url) => url.to_string(),
+            None if name.contains("://") || name.contains('/') => name.to_string(),
+            None => return Ok(None),
+        };
+
+        let mut fetch = Vec::new();
+        for spec in config.get_all(&format!("remote.{name}.fetch")) {
+            fetch.push(Refspec::parse(spec).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("invalid refspec '{spec}'"),
+                )
+            })?);
+        }
+
+        Ok(Some(Remote {
+            name: name.to_string(),
+            url,
+            fetch,
+        }))
+    }
+
+    /// The URL as it is shown, without a trailing `/` or `.git`.
+    pub fn display_url(&self) -> &str {
+        let url = self.url.trim_end_matches('/');
+        url.strip_suffix(".git").unwrap_or(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find, pretty, Refspec};
+
+    #[test]
+    fn refspecs() {
+        let spec = Refspec::parse("+refs/heads/*:refs/remotes/origin/*").unwrap();
+        assert!(spec.force && spec.is_glob());
+        assert_eq!(
+            spec.map("refs/heads/feature/x").as_deref(),
+            Some("refs/remotes/origin/feature/x")
+        );
+        assert_eq!(spec.map("refs/tags/v1"), None);
+        assert_eq!(
+            spec.reverse("refs/remotes/origin/main").as_deref(),
+            Some("refs/heads/main")
+        );
+        assert_eq!(spec.to_string(), "+refs/heads/*:refs/remotes/origin/*");
+
+        let spec = Refspec::parse("main").unwrap();
+        assert!(!spec.force && !spec.is_glob() && spec.dst.is_none());
+        assert!(Refspec::parse("refs/heads/*:refs/remotes/origin/main").is_none());
+        assert!(Refspec::parse("refs/*/*:refs/*/*").is_none());
+    }
+
+    #[test]
+    fn short_names() {
+        let refs = [
+            ("refs/heads/main".to_string(), "1".to_string()),
+            ("refs/tags/main".to_string(), "2".to_string()),
+            ("refs/heads/topic".to_string(), "3".to_string()),
+        ];
+        assert_eq!(find(&refs, "main").unwrap().1, "2");
+        assert_eq!(find(&refs, "heads/main").unwrap().1, "1");
+        assert_eq!(find(&refs, "refs/heads/topic").unwrap().1, "3");
+        assert!(find(&refs, "other").is_none());
+    }
+
+    #[test]
+    fn pretty_names() {
+        assert_eq!(pretty("refs/heads/main"), "main");
+        assert_eq!(pretty("refs/tags/v1"), "v1");
+        assert_eq!(pretty("refs/remotes/origin/main"), "origin/main");
+        assert_eq!(pretty("refs/notes/commits"), "refs/notes/commits");
+        assert_eq!(pretty("HEAD"), "HEAD");
+    }
+}
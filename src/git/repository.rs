@@ -0,0 +1,119 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+/// The metadata directory and work tree set for this process, overriding
+/// what the environment says.
+static LOCATION: RwLock<Option<(PathBuf, PathBuf)>> = RwLock::new(None);
+
+/// The current directory as a path from the top of the work tree.
+static PREFIX: RwLock<String> = RwLock::new(String::new());
+
+/// Location of the repository metadata directory: `$GIT_DIR`, otherwise
+/// `.git` in the current directory.
+pub fn git_dir() -> PathBuf {
+    if let Some((git_dir, _)) = &*LOCATION.read().unwrap() {
+        return git_dir.clone();
+    }
+    std::env::var_os("GIT_DIR").map_or_else(|| PathBuf::from(".git"), PathBuf::from)
+}
+
+/// Root of the checked out files tracked by the index: `$GIT_WORK_TREE`,
+/// otherwise the current directory.
+pub fn work_tree() -> PathBuf {
+    if let Some((_, work_tree)) = &*LOCATION.read().unwrap() {
+        return work_tree.clone();
+    }
+    std::env::var_os("GIT_WORK_TREE").map_or_else(|| PathBuf::from("."), PathBuf::from)
+}
+
+/// Makes every later access to the repository go to `git_dir`, with the
+/// files checked out in `work_tree`.
+pub fn set(git_dir: PathBuf, work_tree: PathBuf) {
+    *LOCATION.write().unwrap() = Some((git_dir, work_tree));
+}
+
+/// The current directory as a path from the top of the work tree, which
+/// paths given on the command line are relative to: empty at the top,
+/// otherwise ending in `/`.
+pub fn prefix() -> String {
+    PREFIX.read().unwrap().clone()
+}
+
+/// The closest of `dir` and its parents holding a `.git` directory.
+fn find(dir: &Path) -> Option<&Path> {
+    dir.ancestors().find(|x| x.join(".git/HEAD").is_file())
+}
+
+/// Finds the repository the current directory is in, as git does when
+/// `$GIT_DIR` doesn't say: the closest `.git` in it or its parents, with
+/// the files checked out in the directory holding it unless
+/// `$GIT_WORK_TREE` says otherwise. Records where in the work tree the
+/// current directory is, see [`prefix`].
+pub fn discover() -> std::io::Result<()> {
+    if std::env::var_os("GIT_DIR").is_some() {
+        return Ok(());
+    }
+
+    let current = std::env::current_dir()?;
+    match find(&current) {
+        // paths stay relative in the usual case
+        Some(dir) if dir == current => Ok(()),
+        Some(dir) => {
+            let work_tree = match std::env::var_os("GIT_WORK_TREE") {
+                Some(work_tree) => fs::canonicalize(work_tree)?,
+                None => dir.to_path_buf(),
+            };
+            if let Ok(path) = current.strip_prefix(&work_tree) {
+                let path = path.to_string_lossy();
+                *PREFIX.write().unwrap() = match path.is_empty() {
+                    true => String::new(),
+                    false => format!("{path}/"),
+                };
+            }
+            set(dir.join(".git"), work_tree);
+            Ok(())
+        }
+        None => Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "not a git repository (or any of the parent directories): .git",
+        )),
+    }
+}
+
+/// Creates an empty repository in [`git_dir`], its HEAD on the unborn
+/// `main`.
+pub fn init() -> std::io::Result<()> {
+    let git_dir = git_dir();
+    fs::create_dir_all(git_dir.join("objects"))?;
+    fs::create_dir_all(git_dir.join("refs"))?;
+    fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n")
+}
+
+pub fn objects_dir() -> PathBuf {
+    git_dir().join("objects")
+}
+
+pub fn index_file() -> PathBuf {
+    git_dir().join("index")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    #[test]
+    fn find_in_parents() {
+        let root = std::env::temp_dir().join(format!("repository-{}", std::process::id()));
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        assert_eq!(super::find(&root.join("a/b")), None);
+
+        fs::write(root.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        assert_eq!(super::find(&root.join("a/b")), Some(root.as_path()));
+        assert_eq!(super::find(&root), Some(root.as_path()));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
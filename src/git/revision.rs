@@ -0,0 +1,288 @@
+use super::{
+    index::Index,
+    objects::{self, Object},
+    packfile::ObjType,
+    pathspec, refs, repository,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+enum Op {
+    /// `^n`, the n-th parent (`^0` is the commit itself).
+    Parent(usize),
+    /// `~n`, n generations back following first parents.
+    Ancestor(usize),
+    /// `^{type}`, `^{}` peels tags until any non-tag object.
+    Peel(Option<ObjType>),
+}
+
+fn not_found(rev: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("ambiguous argument '{rev}': unknown revision"),
+    )
+}
+
+fn split(rev: &str) -> std::io::Result<(&str, Vec<Op>)> {
+    let start = rev.find(['^', '~']).unwrap_or(rev.len());
+    let (base, mut rest) = rev.split_at(start);
+    let mut ops = Vec::new();
+
+    while !rest.is_empty() {
+        let marker = rest.as_bytes()[0];
+        rest = &rest[1..];
+
+        if marker == b'^' && rest.starts_with('{') {
+            let Some(end) = rest.find('}') else {
+                return Err(not_found(rev));
+            };
+            let kind = match &rest[1..end] {
+                "" => None,
+                x => Some(x.parse::<ObjType>().map_err(|_| not_found(rev))?),
+            };
+            ops.push(Op::Peel(kind));
+            rest = &rest[end + 1..];
+            continue;
+        }
+
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let n = match digits {
+            0 => 1,
+            _ => rest[..digits].parse().map_err(|_| not_found(rev))?,
+        };
+        rest = &rest[digits..];
+
+        match marker {
+            b'^' => ops.push(Op::Parent(n)),
+            b'~' => ops.push(Op::Ancestor(n)),
+            _ => return Err(not_found(rev)),
+        }
+    }
+
+    Ok((base, ops))
+}
+
+/// Ref names tried for a short name, in the order `git rev-parse` uses.
+pub fn dwim_refs(name: &str) -> Vec<String> {
+    vec![
+        name.to_string(),
+        format!("refs/{name}"),
+        format!("refs/tags/{name}"),
+        format!("refs/heads/{name}"),
+        format!("refs/remotes/{name}"),
+        format!("refs/remotes/{name}/HEAD"),
+    ]
+}
+
+fn resolve_base(base: &str) -> std::io::Result<Option<String>> {
+    let base = if base == "@" || base.is_empty() {
+        "HEAD"
+    } else {
+        base
+    };
+
+    let is_hex = base.bytes().all(|x| x.is_ascii_hexdigit());
+    if is_hex && base.len() == 40 {
+        return Ok(Some(base.to_ascii_lowercase()));
+    }
+
+    // only names made of upper case letters (HEAD, FETCH_HEAD) live directly in .git
+    for name in dwim_refs(base) {
+        if !name.contains('/') && !name.bytes().all(|x| x.is_ascii_uppercase() || x == b'_') {
+            continue;
+        }
+        if let Some(hash) = refs::resolve(&name)? {
+            return Ok(Some(hash));
+        }
+    }
+
+    if is_hex && base.len() >= 4 {
+        let found = objects::find_abbrev(base)?;
+        return match found.len() {
+            0 => Ok(None),
+            1 => Ok(found.into_iter().next()),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("short object ID {base} is ambiguous"),
+            )),
+        };
+    }
+
+    Ok(None)
+}
+
+fn commit(hash: &str) -> std::io::Result<objects::commit::Commit> {
+    match Object::read_from_hash(&peel(hash, Some(ObjType::Commit))?)? {
+        Object::Commit(commit) => Ok(commit),
+        _ => unreachable!(),
+    }
+}
+
+/// Dereferences tags (and commits, for trees) until an object of `kind`.
+pub fn peel(hash: &str, kind: Option<ObjType>) -> std::io::Result<String> {
+    let mut hash = hash.to_string();
+
+    loop {
+        let (current, content) = objects::read_raw(&hash)?;
+        if Some(current) == kind || (kind.is_none() && current != ObjType::Tag) {
+            return Ok(hash);
+        }
+
+        hash = match Object::parse(current, &mut content.as_slice())? {
+            Object::Tag(tag) => tag.object,
+            Object::Commit(commit) if kind == Some(ObjType::Tree) => commit.tree,
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{hash} cannot be peeled to a {}", kind.unwrap()),
+                ))
+            }
+        };
+    }
+}
+
+fn missing_path(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::NotFound, message)
+}
+
+/// A path after `:` as a path from the top of the work tree: it is one
+/// already unless it starts with `./` or `../`, which are relative to the
+/// current directory.
+fn full_path(path: &str) -> std::io::Result<String> {
+    let relative = [".", ".."].contains(&path) || path.starts_with("./") || path.starts_with("../");
+    if !relative {
+        return Ok(path.to_string());
+    }
+    match pathspec::join(&repository::prefix(), path) {
+        Some(path) => Ok(path),
+        None => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "'{path}' is outside repository at '{}'",
+                repository::work_tree().canonicalize()?.display()
+            ),
+        )),
+    }
+}
+
+/// The entry of the index for `path`, at the stage `:<n>:` asks for.
+fn resolve_staged(path: &str) -> std::io::Result<String> {
+    let (stage, path) = match path.split_once(':') {
+        Some((n @ ("0" | "1" | "2" | "3"), path)) => (n.parse().unwrap(), path),
+        _ => (0, path),
+    };
+    let path = full_path(path)?;
+
+    let index = Index::open(&repository::index_file())?;
+    let mut entries = index.entries.iter().filter(|x| x.path == path);
+    match entries.clone().find(|x| x.stage() == stage) {
+        Some(entry) => Ok(hex::encode(entry.hash)),
+        None if entries.next().is_some() => Err(missing_path(format!(
+            "path '{path}' is in the index, but not at stage {stage}"
+        ))),
+        None => Err(missing_path(format!(
+            "path '{path}' does not exist (neither on disk nor in the index)"
+        ))),
+    }
+}
+
+/// The object at `path` in the tree of `base`, the tree itself for an
+/// empty path.
+fn resolve_path(base: &str, path: &str) -> std::io::Result<String> {
+    let mut hash = peel(&resolve(base)?, Some(ObjType::Tree))?;
+    let path = full_path(path)?;
+
+    for name in path.split('/').filter(|x| !x.is_empty()) {
+        let item = match Object::read_from_hash(&hash) {
+            Ok(Object::Tree(items)) => items.into_iter().find(|x| x.name == name),
+            _ => None,
+        };
+        let Some(item) = item else {
+            return Err(missing_path(format!(
+                "path '{path}' does not exist in '{base}'"
+            )));
+        };
+        hash = hex::encode(item.hash);
+    }
+
+    Ok(hash)
+}
+
+/// Resolves a revision such as `main`, `HEAD~2`, `v1.0^{tree}` or an
+/// abbreviated object name to a full object name. `<rev>:<path>` names
+/// the object at a path in the tree of a revision, `:<path>` and
+/// `:<n>:<path>` an entry of the index.
+pub fn resolve(rev: &str) -> std::io::Result<String> {
+    if let Some((base, path)) = rev.split_once(':') {
+        return match base {
+            "" => resolve_staged(path),
+            _ => resolve_path(base, path),
+        };
+    }
+
+    let (base, ops) = split(rev)?;
+    let Some(mut hash) = resolve_base(base)? else {
+        return Err(not_found(rev));
+    };
+
+    for op in ops {
+        hash = match op {
+            Op::Parent(0) => peel(&hash, Some(ObjType::Commit))?,
+            Op::Parent(n) => match commit(&hash)?.parents.get(n - 1) {
+                Some(parent) => parent.clone(),
+                None => return Err(not_found(rev)),
+            },
+            Op::Ancestor(n) => {
+                for _ in 0..n {
+                    hash = match commit(&hash)?.parents.first() {
+                        Some(parent) => parent.clone(),
+                        None => return Err(not_found(rev)),
+                    };
+                }
+                hash
+            }
+            Op::Peel(kind) => peel(&hash, kind)?,
+        };
+    }
+
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split, Op};
+    use crate::git::packfile::ObjType;
+
+    #[test]
+    fn split_plain_name() {
+        let (base, ops) = split("main").unwrap();
+        assert_eq!(base, "main");
+        assert!(ops.is_empty());
+    }
+
+    #[test]
+    fn split_suffixes() {
+        let (base, ops) = split("HEAD~3^2^{tree}^").unwrap();
+        assert_eq!(base, "HEAD");
+        assert_eq!(
+            ops,
+            vec![
+                Op::Ancestor(3),
+                Op::Parent(2),
+                Op::Peel(Some(ObjType::Tree)),
+                Op::Parent(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn split_bare_markers() {
+        let (_, ops) = split("v1.0~^{}").unwrap();
+        assert_eq!(ops, vec![Op::Ancestor(1), Op::Peel(None)]);
+    }
+
+    #[test]
+    fn split_rejects_unknown_type() {
+        assert!(split("HEAD^{bogus}").is_err());
+        assert!(split("HEAD^{tree").is_err());
+    }
+}
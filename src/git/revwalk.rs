@@ -0,0 +1,551 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+};
+
+use regex::Regex;
+
+use super::{
+    objects,
+    objects::{
+        commit::{self, Commit},
+        tag,
+        tree::{self, Files},
+    },
+    packfile::ObjType,
+    refs, revision,
+};
+
+const SEEN: u8 = 1;
+const UNINTERESTING: u8 = 1 << 1;
+/// Parents of the commit have been queued.
+const ADDED: u8 = 1 << 2;
+/// Reachable from the left side of a symmetric difference `a...b`.
+const LEFT: u8 = 1 << 3;
+
+/// Extra commits looked at once only uninteresting ones are queued, to
+/// tolerate committer dates that go backwards.
+const SLOP: usize = 5;
+
+/// Order of the commits output by [`RevWalk`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// Newest first as met by the walk, without waiting for the whole graph.
+    #[default]
+    Walk,
+    /// No parent before all its children, otherwise newest first.
+    Date,
+    /// No parent before all its children, lines of history kept together.
+    Topo,
+}
+
+/// Conditions a commit must meet to be output, they never affect traversal
+/// except `since` which also stops the walk at older commits.
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub max_count: Option<usize>,
+    pub skip: usize,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub authors: Vec<Regex>,
+    pub grep: Vec<Regex>,
+}
+
+impl Filter {
+    fn accepts(&self, commit: &Commit) -> bool {
+        let date = commit.committer.date;
+        let author = format!("{} <{}>", commit.author.name, commit.author.email);
+
+        self.since.is_none_or(|x| date >= x)
+            && self.until.is_none_or(|x| date <= x)
+            && (self.authors.is_empty() || self.authors.iter().any(|x| x.is_match(&author)))
+            && (self.grep.is_empty() || self.grep.iter().any(|x| x.is_match(&commit.message)))
+    }
+}
+
+/// Reads a commit object, failing when `hash` names anything else.
+pub fn read_commit(hash: &str) -> std::io::Result<Commit> {
+    let (kind, content) = objects::read_raw(hash)?;
+    if kind != ObjType::Commit {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{hash} is a {kind}, not a commit"),
+        ));
+    }
+
+    commit::parse(&mut content.as_slice())
+}
+
+/// Walks commit history newest first, by committer date.
+///
+/// Tips added with [`RevWalk::push`] are walked down to the commits reachable
+/// from tips added with [`RevWalk::hide`]. With `paths` set, history is
+/// simplified like git's default mode: commits that do not change the paths
+/// are skipped and, for merges, only a parent with identical paths is followed.
+#[derive(Default)]
+pub struct RevWalk {
+    pub first_parent: bool,
+    pub order: Order,
+    pub paths: Vec<String>,
+    pub filter: Filter,
+    commits: HashMap<String, Commit>,
+    flags: HashMap<String, u8>,
+    queue: BinaryHeap<(u64, Reverse<usize>, String)>,
+    sequence: usize,
+    hidden: Vec<String>,
+    tags: Vec<(String, String)>,
+    limited: Option<VecDeque<String>>,
+    trees: HashMap<String, Files>,
+    skipped: usize,
+    emitted: usize,
+}
+
+impl RevWalk {
+    pub fn new() -> RevWalk {
+        RevWalk::default()
+    }
+
+    fn load(&mut self, hash: &str) -> std::io::Result<&Commit> {
+        if !self.commits.contains_key(hash) {
+            let commit = read_commit(hash)?;
+            self.commits.insert(hash.to_string(), commit);
+        }
+
+        Ok(&self.commits[hash])
+    }
+
+    fn flag(&self, hash: &str) -> u8 {
+        self.flags.get(hash).copied().unwrap_or_default()
+    }
+
+    fn enqueue(&mut self, hash: &str) -> std::io::Result<()> {
+        let date = self.load(hash)?.committer.date;
+        *self.flags.entry(hash.to_string()).or_default() |= SEEN;
+        self.sequence += 1;
+        self.queue
+            .push((date, Reverse(self.sequence), hash.to_string()));
+        Ok(())
+    }
+
+    fn add_tip(&mut self, rev: &str, flags: u8) -> std::io::Result<String> {
+        let mut hash = rev.to_string();
+        loop {
+            let (kind, content) = objects::read_raw(&hash)?;
+            if kind != ObjType::Tag {
+                break;
+            }
+            let tag = tag::parse(&mut content.as_slice())?;
+            if flags & UNINTERESTING == 0 {
+                self.tags.push((hash, tag.name));
+            }
+            hash = tag.object;
+        }
+
+        let hash = revision::peel(&hash, Some(ObjType::Commit))?;
+        *self.flags.entry(hash.clone()).or_default() |= flags & LEFT;
+        if flags & UNINTERESTING != 0 {
+            self.hidden.push(hash.clone());
+            self.load(&hash)?;
+            self.mark_uninteresting(&hash);
+        }
+        if self.flag(&hash) & SEEN == 0 {
+            self.enqueue(&hash)?;
+        }
+        Ok(hash)
+    }
+
+    /// Starts the walk at the commit `hash` (a tag is peeled).
+    pub fn push(&mut self, hash: &str) -> std::io::Result<String> {
+        self.add_tip(hash, 0)
+    }
+
+    /// Adds revision arguments: `rev`, `^rev`, `a..b`, `a...b` or `--all`
+    /// for every ref and HEAD. A `--not` among them flips whether the
+    /// revisions after it are included or excluded.
+    pub fn add_revisions(&mut self, args: &[String]) -> std::io::Result<()> {
+        let mut flags = 0;
+        for arg in args {
+            match arg.as_str() {
+                "--not" => flags ^= UNINTERESTING,
+                "--all" => self.add_all(flags)?,
+                _ => self.add_revision_flags(arg, flags)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_all(&mut self, flags: u8) -> std::io::Result<()> {
+        let mut tips = refs::list("refs/")?;
+        tips.extend(
+            refs::head_commit()
+                .ok()
+                .flatten()
+                .map(|x| ("HEAD".to_string(), x)),
+        );
+        for (_, hash) in tips {
+            self.add_tip(&hash, flags)?;
+        }
+
+        Ok(())
+    }
+
+    fn add_revision_flags(&mut self, arg: &str, flags: u8) -> std::io::Result<()> {
+        let resolve = |x: &str| revision::resolve(if x.is_empty() { "HEAD" } else { x });
+        let not = flags ^ UNINTERESTING;
+
+        if let Some((left, right)) = arg.split_once("...") {
+            let (left, right) = (resolve(left)?, resolve(right)?);
+            self.add_tip(&left, flags | LEFT)?;
+            self.add_tip(&right, flags)?;
+            for base in merge_bases(&left, &right)? {
+                self.add_tip(&base, not)?;
+            }
+        } else if let Some((left, right)) = arg.split_once("..") {
+            self.add_tip(&resolve(left)?, not)?;
+            self.add_tip(&resolve(right)?, flags)?;
+        } else if let Some(rev) = arg.strip_prefix('^') {
+            self.add_tip(&resolve(rev)?, not)?;
+        } else {
+            self.add_tip(&resolve(arg)?, flags)?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether `hash` is reachable from the left side of an `a...b` range.
+    pub fn is_left(&self, hash: &str) -> bool {
+        self.flag(hash) & LEFT != 0
+    }
+
+    /// Commits excluded with [`RevWalk::hide`] or a negative revision.
+    pub fn hidden(&self) -> &[String] {
+        &self.hidden
+    }
+
+    /// Tag objects met while peeling included tips, with their tag names.
+    pub fn tags(&self) -> &[(String, String)] {
+        &self.tags
+    }
+
+    fn mark_uninteresting(&mut self, hash: &str) {
+        let mut stack = vec![hash.to_string()];
+
+        while let Some(hash) = stack.pop() {
+            let flags = self.flags.entry(hash.clone()).or_default();
+            if *flags & UNINTERESTING != 0 {
+                continue;
+            }
+            *flags |= UNINTERESTING;
+
+            // parents already queued must learn about it too
+            if *flags & ADDED != 0 {
+                if let Some(commit) = self.commits.get(&hash) {
+                    stack.extend(commit.parents.iter().cloned());
+                }
+            }
+        }
+    }
+
+    fn files(&mut self, tree: &str) -> std::io::Result<&Files> {
+        if !self.trees.contains_key(tree) {
+            let files = tree::flatten_matching(tree, &self.paths)?;
+            self.trees.insert(tree.to_string(), files);
+        }
+
+        Ok(&self.trees[tree])
+    }
+
+    /// Queues the parents of a commit and tells whether it should be shown.
+    fn process(&mut self, hash: &str) -> std::io::Result<bool> {
+        let commit = self.load(hash)?.clone();
+        let uninteresting = self.flag(hash) & UNINTERESTING != 0;
+        let mut parents = commit.parents.clone();
+        let mut show = !uninteresting;
+
+        if !uninteresting {
+            if self.first_parent {
+                parents.truncate(1);
+            }
+
+            if self.filter.since.is_some_and(|x| commit.committer.date < x) {
+                parents.clear();
+                show = false;
+            }
+        }
+
+        if show && !self.paths.is_empty() {
+            let files = self.files(&commit.tree)?.clone();
+            if parents.is_empty() {
+                show = !files.is_empty();
+            }
+
+            for parent in parents.clone() {
+                let tree = self.load(&parent)?.tree.clone();
+                if *self.files(&tree)? == files {
+                    parents = vec![parent];
+                    show = false;
+                    break;
+                }
+            }
+        }
+
+        let flags = self.flags.entry(hash.to_string()).or_default();
+        *flags |= ADDED;
+        let left = *flags & LEFT;
+
+        for parent in parents {
+            self.load(&parent)?;
+            *self.flags.entry(parent.clone()).or_default() |= left;
+            if uninteresting {
+                self.mark_uninteresting(&parent);
+            }
+            if self.flag(&parent) & SEEN == 0 {
+                self.enqueue(&parent)?;
+            }
+        }
+
+        Ok(show)
+    }
+
+    fn everybody_uninteresting(&self) -> bool {
+        self.queue
+            .iter()
+            .all(|(_, _, x)| self.flag(x) & UNINTERESTING != 0)
+    }
+
+    /// Walks until only uninteresting commits remain, needed before any
+    /// output when tips are hidden, as a commit may turn out to be reachable
+    /// from a hidden tip only after it was first seen.
+    fn limit(&mut self) -> std::io::Result<VecDeque<String>> {
+        let mut list = Vec::new();
+        let mut slop = SLOP;
+
+        while let Some((_, _, hash)) = self.queue.pop() {
+            if self.process(&hash)? {
+                list.push(hash);
+            }
+
+            if self.everybody_uninteresting() {
+                slop -= 1;
+                if slop == 0 {
+                    break;
+                }
+            } else {
+                slop = SLOP;
+            }
+        }
+
+        Ok(list
+            .into_iter()
+            .filter(|x| self.flag(x) & UNINTERESTING == 0)
+            .collect())
+    }
+
+    /// Reorders a limited list so that no commit comes after one of its
+    /// parents, picking the newest ready commit for [`Order::Date`] and the
+    /// last parent made ready for [`Order::Topo`].
+    fn sort(&self, list: VecDeque<String>) -> VecDeque<String> {
+        let mut indegree: HashMap<&str, usize> = list.iter().map(|x| (x.as_str(), 0)).collect();
+        for hash in list.iter() {
+            for parent in self.commits[hash].parents.iter() {
+                if let Some(count) = indegree.get_mut(parent.as_str()) {
+                    *count += 1;
+                }
+            }
+        }
+
+        let mut sequence = 0;
+        let mut key = |hash: &str| {
+            sequence += 1;
+            match self.order {
+                Order::Date => (self.commits[hash].committer.date, -sequence),
+                _ => (0, sequence),
+            }
+        };
+
+        let mut tips: Vec<&str> = list
+            .iter()
+            .map(|x| x.as_str())
+            .filter(|x| indegree[x] == 0)
+            .collect();
+        // a stack pops the last pushed tip first
+        if self.order == Order::Topo {
+            tips.reverse();
+        }
+
+        let mut queue = BinaryHeap::new();
+        for tip in tips {
+            queue.push((key(tip), tip));
+        }
+
+        let mut sorted = VecDeque::new();
+        while let Some((_, hash)) = queue.pop() {
+            for parent in self.commits[hash].parents.iter() {
+                if let Some(count) = indegree.get_mut(parent.as_str()) {
+                    *count -= 1;
+                    if *count == 0 {
+                        queue.push((key(parent), parent));
+                    }
+                }
+            }
+            sorted.push_back(hash.to_string());
+        }
+
+        sorted
+    }
+
+    pub fn next_commit(&mut self) -> std::io::Result<Option<(String, Commit)>> {
+        if self.limited.is_none() && (!self.hidden.is_empty() || self.order != Order::Walk) {
+            let list = self.limit()?;
+            self.limited = Some(match self.order {
+                Order::Walk => list,
+                _ => self.sort(list),
+            });
+        }
+
+        loop {
+            if self.filter.max_count.is_some_and(|x| self.emitted >= x) {
+                return Ok(None);
+            }
+
+            let hash = match self.limited.as_mut() {
+                Some(list) => match list.pop_front() {
+                    Some(hash) => hash,
+                    None => return Ok(None),
+                },
+                None => match self.queue.pop() {
+                    Some((_, _, hash)) => {
+                        if !self.process(&hash)? {
+                            continue;
+                        }
+                        hash
+                    }
+                    None => return Ok(None),
+                },
+            };
+
+            let commit = &self.commits[&hash];
+            if !self.filter.accepts(commit) {
+                continue;
+            }
+
+            if self.skipped < self.filter.skip {
+                self.skipped += 1;
+                continue;
+            }
+
+            self.emitted += 1;
+            return Ok(Some((hash.clone(), commit.clone())));
+        }
+    }
+}
+
+impl Iterator for RevWalk {
+    type Item = std::io::Result<(String, Commit)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_commit().transpose()
+    }
+}
+
+fn ancestors(
+    hash: &str,
+    commits: &mut HashMap<String, Commit>,
+) -> std::io::Result<HashSet<String>> {
+    let mut seen = HashSet::new();
+    let mut queue = vec![hash.to_string()];
+
+    while let Some(hash) = queue.pop() {
+        if !seen.insert(hash.clone()) {
+            continue;
+        }
+        if !commits.contains_key(&hash) {
+            commits.insert(hash.clone(), read_commit(&hash)?);
+        }
+        queue.extend(commits[&hash].parents.iter().cloned());
+    }
+
+    Ok(seen)
+}
+
+/// Best common ancestors of two commits, newest first.
+pub fn merge_bases(one: &str, two: &str) -> std::io::Result<Vec<String>> {
+    let mut commits = HashMap::new();
+    let left = ancestors(one, &mut commits)?;
+    let right = ancestors(two, &mut commits)?;
+    let common: HashSet<&String> = left.intersection(&right).collect();
+
+    // a common ancestor of another common ancestor is never the best one
+    let mut redundant = HashSet::new();
+    for hash in common.iter() {
+        for parent in commits[*hash].parents.clone() {
+            if !redundant.contains(&parent) {
+                redundant.extend(ancestors(&parent, &mut commits)?);
+            }
+        }
+    }
+
+    let mut bases = common
+        .into_iter()
+        .filter(|x| !redundant.contains(*x))
+        .cloned()
+        .collect::<Vec<_>>();
+    bases.sort_by_key(|x| Reverse(commits[x].committer.date));
+
+    Ok(bases)
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::Filter;
+    use crate::git::objects::commit::{Author, Commit};
+
+    fn commit(name: &str, date: u64, message: &str) -> Commit {
+        let who = Author {
+            name: name.to_string(),
+            email: format!("{}@domain.com", name.to_lowercase()),
+            date,
+            zone: "+0000".to_string(),
+        };
+
+        Commit {
+            tree: String::new(),
+            parents: Vec::new(),
+            author: who.clone(),
+            committer: who,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn filter_by_date_range() {
+        let filter = Filter {
+            since: Some(100),
+            until: Some(200),
+            ..Default::default()
+        };
+
+        assert!(filter.accepts(&commit("Ann", 150, "")));
+        assert!(filter.accepts(&commit("Ann", 200, "")));
+        assert!(!filter.accepts(&commit("Ann", 99, "")));
+        assert!(!filter.accepts(&commit("Ann", 201, "")));
+    }
+
+    #[test]
+    fn filter_by_author_and_message() {
+        let filter = Filter {
+            authors: vec![Regex::new("^Bob").unwrap()],
+            grep: vec![Regex::new("(?m)^fix:").unwrap(), Regex::new("bug").unwrap()],
+            ..Default::default()
+        };
+
+        assert!(filter.accepts(&commit("Bob", 0, "fix: crash")));
+        assert!(filter.accepts(&commit("Bob", 0, "subject\n\ncloses bug")));
+        assert!(!filter.accepts(&commit("Ann", 0, "fix: crash")));
+        assert!(!filter.accepts(&commit("Bob", 0, "feature")));
+        assert!(filter.accepts(&commit("Bob", 0, "subject\nfix: body")));
+    }
+}
@@ -0,0 +1,185 @@
+//! The pushing side of the smart HTTP protocol: the ref updates sent to
+//! `git-receive-pack` along with a pack of the objects it lacks, and the
+//! status it reports for each of them.
+
+use std::{
+    collections::HashMap,
+    io::{IsTerminal, Read},
+};
+
+use reqwest::blocking::Client;
+
+use super::{
+    fetch_pack::{self, Advertisement, AGENT},
+    list_objects::ObjectWalk,
+    objects,
+    pack_objects::PackWriter,
+    packfile::ObjType,
+    pktline::{read_line, write_flush, write_line, SideBand},
+    revision,
+    revwalk::RevWalk,
+};
+
+/// The name of no object, for refs created or deleted.
+const NULL: &str = "0000000000000000000000000000000000000000";
+
+/// A ref update asked of the remote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    pub name: String,
+    /// What the ref points at on the remote, `None` if it doesn't exist.
+    pub old: Option<String>,
+    /// What it should point at, `None` to delete it.
+    pub new: Option<String>,
+}
+
+/// What the remote reports after receiving a push.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Why the pack could not be unpacked, if it couldn't.
+    pub unpack: Option<String>,
+    /// Why a ref was not updated, by name, for those that weren't.
+    pub rejected: HashMap<String, String>,
+}
+
+fn invalid(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+/// Objects a remote whose refs point at `remote` needs to have `tips`,
+/// with the path they were found at. Tips that are neither commits nor
+/// tags of them are sent alone.
+pub fn objects(
+    tips: &[String],
+    remote: &[String],
+) -> std::io::Result<Vec<(String, Option<String>)>> {
+    let mut walk = RevWalk::new();
+    let mut loose = Vec::new();
+    for tip in tips {
+        match revision::peel(tip, Some(ObjType::Commit)) {
+            Ok(_) => {
+                walk.push(tip)?;
+            }
+            Err(_) => loose.push((tip.clone(), None)),
+        }
+    }
+
+    // only what we have of the remote can be left out
+    let hidden = remote
+        .iter()
+        .filter(|x| objects::contains(x))
+        .filter(|x| revision::peel(x, Some(ObjType::Commit)).is_ok())
+        .map(|x| format!("^{x}"))
+        .collect::<Vec<_>>();
+    walk.add_revisions(&hidden)?;
+
+    let mut objects = ObjectWalk::new(walk, None)?
+        .map(|x| x.map(|x| (x.hash, x.name.filter(|x| !x.is_empty()))))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    objects.extend(loose);
+    Ok(objects)
+}
+
+/// Asks `git-receive-pack` at `url` to carry out `commands`, sending it
+/// `objects` along with them. With `atomic`, the remote updates either
+/// all refs or none.
+pub fn push(
+    client: &Client,
+    url: &str,
+    advertisement: &Advertisement,
+    commands: &[Command],
+    objects: &[(String, Option<String>)],
+    atomic: bool,
+) -> std::io::Result<Report> {
+    if atomic && !advertisement.capabilities.has("atomic") {
+        return Err(std::io::Error::other(
+            "the receiving end does not support --atomic push",
+        ));
+    }
+    let status = match advertisement.capabilities.has("report-status-v2") {
+        true => "report-status-v2",
+        false => "report-status",
+    };
+    let capabilities = [status, "side-band-64k", "ofs-delta"]
+        .into_iter()
+        .filter(|x| advertisement.capabilities.has(x))
+        .chain(atomic.then_some("atomic"))
+        // progress is only for people watching
+        .chain(
+            (advertisement.capabilities.has("quiet") && !std::io::stderr().is_terminal())
+                .then_some("quiet"),
+        )
+        .chain(advertisement.capabilities.agent().map(|_| AGENT))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut request = Vec::new();
+    for (i, command) in commands.iter().enumerate() {
+        let line = format!(
+            "{} {} {}",
+            command.old.as_deref().unwrap_or(NULL),
+            command.new.as_deref().unwrap_or(NULL),
+            command.name
+        );
+        match i {
+            0 => write_line(&mut request, &format!("{line}\0{capabilities}"))?,
+            _ => write_line(&mut request, &line)?,
+        }
+    }
+    write_flush(&mut request)?;
+    // a push that only deletes refs sends no pack
+    if commands.iter().any(|x| x.new.is_some()) {
+        PackWriter::open()?.write(objects, &mut request)?;
+    }
+
+    let response = fetch_pack::post(client, url, "git-receive-pack", 0, request)?;
+    match advertisement.capabilities.has("side-band-64k") {
+        true => read_report(SideBand::new(response)),
+        false => read_report(response),
+    }
+}
+
+/// Reads the status `git-receive-pack` reports: how unpacking went, then
+/// `ok <ref>` or `ng <ref> <reason>` for each ref, possibly followed by
+/// `option` lines that say more about it.
+fn read_report(mut input: impl Read) -> std::io::Result<Report> {
+    let line = read_line(&mut input)?.ok_or_else(|| invalid("remote did not report status"))?;
+    let mut report = Report::default();
+    match line.strip_prefix("unpack ") {
+        Some("ok") => {}
+        Some(reason) => report.unpack = Some(reason.to_string()),
+        None => return Err(invalid(format!("unpack status not understood: '{line}'"))),
+    }
+
+    while let Some(line) = read_line(&mut input)? {
+        if line.starts_with("ok ") || line.starts_with("option ") {
+            continue;
+        }
+        let (name, reason) = line
+            .strip_prefix("ng ")
+            .and_then(|x| x.split_once(' '))
+            .ok_or_else(|| invalid(format!("invalid ref status from remote: '{line}'")))?;
+        report.rejected.insert(name.to_string(), reason.to_string());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_report;
+
+    #[test]
+    fn reports() {
+        let input = b"000eunpack ok\n0017ok refs/heads/main\n0020option refname refs/heads/x\n\
+            0028ng refs/heads/b deletion prohibited\n0000";
+        let report = read_report(&input[..]).unwrap();
+        assert_eq!(report.unpack, None);
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected["refs/heads/b"], "deletion prohibited");
+
+        let input = b"001dunpack index-pack failed\n0000";
+        let report = read_report(&input[..]).unwrap();
+        assert_eq!(report.unpack.as_deref(), Some("index-pack failed"));
+        assert!(read_report(&b"0000"[..]).is_err());
+    }
+}
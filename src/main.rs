@@ -1,27 +1,324 @@
 mod commands;
-mod objects;
 mod git;
+mod pager;
 
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches};
 use commands::{Args, Cli};
 
 fn main() {
-    let args = Args::parse();
+    let matches = Args::command().get_matches_from(commands::expand_shorthands(std::env::args()));
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    for dir in &args.directories {
+        std::env::set_current_dir(dir).unwrap_or_else(|e| {
+            let reason = commands::reason(&e);
+            commands::fatal(format!("cannot change to '{}': {reason}", dir.display()))
+        });
+    }
+    // like git, through the environment, which the pager inherits too
+    if let Some(git_dir) = &args.git_dir {
+        std::env::set_var("GIT_DIR", git_dir);
+    }
+    if let Some(work_tree) = &args.work_tree {
+        std::env::set_var("GIT_WORK_TREE", work_tree);
+    }
+    // init and clone make repositories, and hash-object needs one only
+    // to write to
+    match &args.command {
+        Cli::Init | Cli::Clone { .. } => {}
+        Cli::HashObject { .. } => {
+            let _ = git::repository::discover();
+        }
+        _ => git::repository::discover().unwrap_or_else(|e| commands::fatal(e)),
+    }
 
     match args.command {
         Cli::Init => commands::git_init::invoke(),
-        Cli::CatFile { pretty, object } => invoke!(cat_file, &object, pretty),
-        Cli::HashObject { write, file } => invoke!(hash_object, file, write),
+        Cli::CatFile {
+            pretty,
+            show_type,
+            size,
+            exists,
+            batch,
+            batch_check,
+            batch_command,
+            batch_all_objects,
+            buffer,
+            nul,
+            args,
+        } => invoke!(
+            cat_file,
+            args,
+            pretty,
+            show_type,
+            size,
+            exists,
+            batch,
+            batch_check,
+            batch_command,
+            batch_all_objects,
+            buffer,
+            nul
+        ),
+        Cli::HashObject {
+            kind,
+            write,
+            stdin,
+            stdin_paths,
+            literally,
+            path,
+            no_filters,
+            files,
+        } => invoke!(
+            hash_object,
+            files,
+            kind,
+            write,
+            stdin,
+            stdin_paths,
+            literally,
+            path,
+            no_filters
+        ),
         Cli::LsTree {
             name_only,
-            tree_hash,
-        } => invoke!(ls_tree, &tree_hash, name_only),
+            object_only,
+            recursive,
+            show_trees,
+            trees_only,
+            long,
+            nul,
+            full_name,
+            full_tree,
+            tree_ish,
+            paths,
+        } => invoke!(
+            ls_tree,
+            &tree_ish,
+            name_only,
+            object_only,
+            recursive,
+            show_trees,
+            trees_only,
+            long,
+            nul,
+            full_name,
+            full_tree,
+            paths
+        ),
         Cli::WriteTree {} => invoke!(write_tree),
         Cli::CommitTree {
             tree_hash,
             parent,
             message,
         } => invoke!(commit_tree, &tree_hash, parent, message),
-        Cli::Clone { source, dir } => invoke!(clone, &source, dir),
+        Cli::Clone {
+            source,
+            dir,
+            branch,
+            no_checkout,
+        } => invoke!(clone, &source, dir, branch, no_checkout),
+        Cli::Switch {
+            branch,
+            create,
+            force_create,
+            detach,
+            force,
+        } => invoke!(switch, branch, create, force_create, detach, force),
+        Cli::Checkout {
+            new_branch,
+            force_new_branch,
+            detach,
+            force,
+            rev,
+            paths,
+        } => invoke!(
+            checkout,
+            rev,
+            new_branch,
+            force_new_branch,
+            detach,
+            force,
+            paths
+        ),
+        Cli::Log {
+            oneline,
+            format,
+            date,
+            max_count,
+            skip,
+            first_parent,
+            reverse,
+            author,
+            grep,
+            ignore_case,
+            since,
+            until,
+            diff,
+            revisions,
+            paths,
+        } => invoke!(
+            log,
+            revisions,
+            oneline,
+            format,
+            date,
+            max_count,
+            skip,
+            first_parent,
+            reverse,
+            author,
+            grep,
+            ignore_case,
+            since,
+            until,
+            diff,
+            paths
+        ),
+        Cli::DiffTree {
+            recursive,
+            show_trees,
+            root,
+            no_commit_id,
+            diff,
+            revisions,
+            paths,
+        } => invoke!(
+            diff_tree,
+            revisions,
+            recursive,
+            show_trees,
+            root,
+            no_commit_id,
+            diff,
+            paths
+        ),
+        Cli::Diff {
+            cached,
+            diff,
+            revisions,
+            paths,
+        } => {
+            let pager = !args.no_pager;
+            invoke!(diff, revisions, cached, diff, paths, pager)
+        }
+        Cli::Show {
+            oneline,
+            format,
+            date,
+            no_patch,
+            diff,
+            revisions,
+            paths,
+        } => {
+            let pager = !args.no_pager;
+            invoke!(show, revisions, oneline, format, date, no_patch, diff, paths, pager)
+        }
+        Cli::RevList {
+            objects,
+            all: _,
+            not: _,
+            count,
+            max_count,
+            topo_order,
+            date_order,
+            left_right,
+            filter,
+            revisions: _,
+        } => {
+            let revisions = commands::revision_args(&matches, "rev-list");
+            invoke!(
+                rev_list, revisions, objects, count, max_count, topo_order, date_order, left_right,
+                filter
+            )
+        }
+        Cli::IndexPack {
+            stdin,
+            fix_thin,
+            output,
+            rev_index,
+            no_rev_index,
+            pack,
+        } => invoke!(
+            index_pack,
+            pack,
+            stdin,
+            fix_thin,
+            output,
+            rev_index,
+            no_rev_index
+        ),
+        Cli::PackObjects {
+            stdout,
+            revs,
+            all,
+            window,
+            depth,
+            base_name,
+        } => invoke!(pack_objects, base_name, stdout, revs, all, window, depth),
+        Cli::VerifyPack {
+            verbose,
+            stat_only,
+            packs,
+        } => invoke!(verify_pack, packs, verbose, stat_only),
+        Cli::Repack {
+            all,
+            loosen_unreachable,
+            delete,
+            quiet,
+        } => invoke!(repack {
+            all,
+            loosen_unreachable,
+            delete,
+            quiet
+        }),
+        Cli::Gc {
+            auto,
+            prune,
+            no_prune,
+            quiet,
+        } => invoke!(gc {
+            auto,
+            prune,
+            no_prune,
+            quiet
+        }),
+        Cli::Fsck {
+            connectivity_only,
+            lost_found,
+            strict,
+        } => invoke!(fsck {
+            connectivity_only,
+            lost_found,
+            strict
+        }),
+        Cli::Fetch {
+            remote,
+            refspecs,
+            prune,
+            tags,
+            force,
+            verbose,
+        } => invoke!(fetch, remote, refspecs, prune, tags, force, verbose),
+        Cli::Push {
+            remote,
+            refspecs,
+            force,
+            force_with_lease,
+            delete,
+            atomic,
+            tags,
+            verbose,
+        } => invoke!(
+            push,
+            remote,
+            refspecs,
+            force,
+            force_with_lease,
+            delete,
+            atomic,
+            tags,
+            verbose
+        ),
     }
 }
@@ -0,0 +1,80 @@
+//! Output of the commands meant to be read rather than parsed, sent
+//! through a pager when it goes to a terminal, as git does.
+
+use std::{
+    io::{IsTerminal, StdoutLock, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+};
+
+/// Standard output, or the input of a running pager.
+pub enum Output {
+    Stdout(StdoutLock<'static>),
+    Pager(Option<ChildStdin>, Child),
+}
+
+/// The pager to run: `$GIT_PAGER`, `$PAGER` or `less`, none when it is
+/// empty or `cat`.
+fn command() -> Option<String> {
+    let pager = std::env::var("GIT_PAGER")
+        .or_else(|_| std::env::var("PAGER"))
+        .unwrap_or_else(|_| "less".to_string());
+
+    match pager.as_str() {
+        "" | "cat" => None,
+        _ => Some(pager),
+    }
+}
+
+fn spawn(pager: &str) -> std::io::Result<Child> {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(pager).stdin(Stdio::piped());
+    // quit when the output fits one screen and keep colors
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    if std::env::var_os("LV").is_none() {
+        command.env("LV", "-c");
+    }
+    command.spawn()
+}
+
+/// Starts the pager if `enabled` and standard output is a terminal,
+/// writing to standard output directly otherwise or if it cannot run.
+pub fn start(enabled: bool) -> Output {
+    let stdout = std::io::stdout();
+    let pager = match enabled && stdout.is_terminal() {
+        true => command(),
+        false => None,
+    };
+
+    match pager.map(|x| spawn(&x)) {
+        Some(Ok(mut child)) => Output::Pager(child.stdin.take(), child),
+        _ => Output::Stdout(stdout.lock()),
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Stdout(out) => out.write(buf),
+            Output::Pager(stdin, _) => stdin.as_mut().unwrap().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Stdout(out) => out.flush(),
+            Output::Pager(stdin, _) => stdin.as_mut().unwrap().flush(),
+        }
+    }
+}
+
+/// Waits for the pager to be quit once everything was written.
+impl Drop for Output {
+    fn drop(&mut self) {
+        if let Output::Pager(stdin, child) = self {
+            drop(stdin.take());
+            let _ = child.wait();
+        }
+    }
+}
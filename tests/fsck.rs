@@ -0,0 +1,118 @@
+//! `fsck` on loose objects corrupted after git wrote them.
+
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+use flate2::{write::ZlibEncoder, Compression};
+
+/// A repository with one commit holding the file `f`.
+struct Repo {
+    root: PathBuf,
+}
+
+impl Repo {
+    fn new(name: &str) -> Repo {
+        let root = std::env::temp_dir().join(format!("fsck-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let repo = Repo { root };
+
+        repo.git(&["init", "-q"]);
+        std::fs::write(repo.root.join("f"), "hi\n").unwrap();
+        repo.git(&["add", "f"]);
+        repo.git(&["commit", "-q", "-m", "one"]);
+        repo
+    }
+
+    fn command(&self, program: &str, args: &[&str]) -> Output {
+        Command::new(program)
+            .args(args)
+            .current_dir(&self.root)
+            .env("HOME", &self.root)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_AUTHOR_NAME", "A U Thor")
+            .env("GIT_AUTHOR_EMAIL", "author@example.com")
+            .env("GIT_COMMITTER_NAME", "C O Mitter")
+            .env("GIT_COMMITTER_EMAIL", "committer@example.com")
+            .output()
+            .unwrap()
+    }
+
+    fn git(&self, args: &[&str]) -> String {
+        let output = self.command("git", args);
+        assert!(output.status.success(), "git {args:?}: {output:?}");
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    /// Replaces the loose object of `f` with `content`, returning its name.
+    fn corrupt(&self, content: impl FnOnce(Vec<u8>) -> Vec<u8>) -> String {
+        let hash = self.git(&["rev-parse", "HEAD:f"]);
+        let path = self
+            .root
+            .join(".git/objects")
+            .join(&hash[..2])
+            .join(&hash[2..]);
+        let data = content(std::fs::read(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&path, data).unwrap();
+        hash
+    }
+
+    /// Runs fsck, returning the exit code and what was reported.
+    fn fsck(&self) -> (i32, String) {
+        let output = self.command(env!("CARGO_BIN_EXE_git-starter-rust"), &["fsck"]);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        (output.status.code().unwrap(), stderr)
+    }
+}
+
+impl Drop for Repo {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+fn finds_nothing_wrong_with_what_git_wrote() {
+    let repo = Repo::new("clean");
+    assert_eq!(repo.fsck(), (0, String::new()));
+}
+
+#[test]
+fn finds_garbage_after_loose_objects() {
+    let repo = Repo::new("garbage");
+    let hash = repo.corrupt(|mut x| {
+        x.extend(b"junk");
+        x
+    });
+    let (code, stderr) = repo.fsck();
+    assert_eq!(code, 3);
+    assert!(stderr.starts_with(&format!("error: garbage at end of loose object '{hash}'\n")));
+    assert!(stderr.contains(&format!("error: {hash}: object corrupt or missing: ")));
+
+    // more content than the header says is garbage too
+    repo.corrupt(|_| compress(b"blob 3\0hi\nmore"));
+    let (code, stderr) = repo.fsck();
+    assert_eq!(code, 3);
+    assert!(stderr.contains("garbage at end of loose object"));
+}
+
+#[test]
+fn finds_malformed_sizes() {
+    let repo = Repo::new("size");
+    let hash = repo.corrupt(|_| compress(b"blob 3x\0hi\n"));
+    let (code, stderr) = repo.fsck();
+    assert_eq!(code, 3);
+    let path = format!(".git/objects/{}/{}", &hash[..2], &hash[2..]);
+    assert!(stderr.starts_with(&format!("error: unable to parse header of {path}\n")));
+    assert!(stderr.contains(&format!("error: {hash}: object corrupt or missing: {path}")));
+}
@@ -0,0 +1,300 @@
+//! `push` to a repository served over smart HTTP by `git http-backend`,
+//! the local repository being set up by git itself.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+};
+
+/// Answers one request with `git http-backend` run as a CGI script on the
+/// repositories in `root`.
+fn answer(stream: TcpStream, root: &Path) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut words = line.split_whitespace();
+    let method = words.next().unwrap_or_default().to_string();
+    let target = words.next().unwrap_or_default().to_string();
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        match line.trim_end().split_once(": ") {
+            Some((name, value)) => headers.push((name.to_ascii_lowercase(), value.to_string())),
+            None => break,
+        }
+    }
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(x, _)| x == name)
+            .map_or("", |(_, x)| x.as_str())
+    };
+    let mut body = vec![0; header("content-length").parse().unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+
+    let mut backend = Command::new("git")
+        .arg("http-backend")
+        .env("GIT_PROJECT_ROOT", root)
+        .env("GIT_HTTP_EXPORT_ALL", "1")
+        .env("PATH_INFO", path)
+        .env("QUERY_STRING", query)
+        .env("REQUEST_METHOD", &method)
+        .env("REMOTE_ADDR", "127.0.0.1")
+        .env("REMOTE_USER", "tester")
+        .env("CONTENT_TYPE", header("content-type"))
+        .env("CONTENT_LENGTH", body.len().to_string())
+        .env("GIT_PROTOCOL", header("git-protocol"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    backend.stdin.take().unwrap().write_all(&body)?;
+    let output = backend.wait_with_output()?.stdout;
+
+    let end = output
+        .windows(4)
+        .position(|x| x == b"\r\n\r\n")
+        .unwrap_or(output.len());
+    let mut status = "200 OK".to_string();
+    let mut response = Vec::new();
+    for line in String::from_utf8_lossy(&output[..end]).lines() {
+        match line.strip_prefix("Status: ") {
+            Some(value) => status = value.to_string(),
+            None => writeln!(response, "{line}\r")?,
+        }
+    }
+    let content = output.get(end + 4..).unwrap_or_default();
+
+    let mut stream = stream;
+    write!(stream, "HTTP/1.1 {status}\r\n")?;
+    stream.write_all(&response)?;
+    write!(
+        stream,
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        content.len()
+    )?;
+    stream.write_all(content)
+}
+
+/// Serves the repositories in `root` on a free local port, returning it.
+fn serve(root: &Path) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let root = root.to_path_buf();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let root = root.clone();
+            std::thread::spawn(move || answer(stream, &root));
+        }
+    });
+    port
+}
+
+/// A local repository on `main` with one commit, with `origin` an empty
+/// repository served over HTTP.
+struct Repos {
+    root: PathBuf,
+    url: String,
+}
+
+impl Repos {
+    fn new(name: &str) -> Repos {
+        let root = std::env::temp_dir().join(format!("push-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let url = format!("http://127.0.0.1:{}/remote.git", serve(&root));
+        let repos = Repos { root, url };
+
+        repos.git(&["init", "-q", "--bare", "-b", "main", "remote.git"]);
+        repos.git(&["-C", "remote.git", "config", "http.receivepack", "true"]);
+        repos.git(&["init", "-q", "-b", "main", "work"]);
+        repos.work(&["remote", "add", "origin", &repos.url]);
+        repos.commit("one");
+        repos
+    }
+
+    fn command(&self, program: &str, dir: &Path, args: &[&str]) -> Output {
+        Command::new(program)
+            .args(args)
+            .current_dir(dir)
+            .env("HOME", &self.root)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_AUTHOR_NAME", "A U Thor")
+            .env("GIT_AUTHOR_EMAIL", "author@example.com")
+            .env("GIT_COMMITTER_NAME", "C O Mitter")
+            .env("GIT_COMMITTER_EMAIL", "committer@example.com")
+            .output()
+            .unwrap()
+    }
+
+    /// Runs git in the directory holding both repositories.
+    fn git(&self, args: &[&str]) -> Output {
+        let output = self.command("git", &self.root, args);
+        assert!(output.status.success(), "git {args:?}: {output:?}");
+        output
+    }
+
+    /// Runs git in the local repository.
+    fn work(&self, args: &[&str]) -> String {
+        let output = self.command("git", &self.root.join("work"), args);
+        assert!(output.status.success(), "git {args:?}: {output:?}");
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    fn commit(&self, message: &str) {
+        std::fs::write(self.root.join("work/file"), message).unwrap();
+        self.work(&["add", "file"]);
+        self.work(&["commit", "-q", "-m", message]);
+    }
+
+    /// Pushes from the local repository, returning the exit code and what
+    /// was reported.
+    fn push(&self, args: &[&str]) -> (i32, String) {
+        let program = env!("CARGO_BIN_EXE_git-starter-rust");
+        let args = [&["push"], args].concat();
+        let output = self.command(program, &self.root.join("work"), &args);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        (output.status.code().unwrap(), stderr)
+    }
+
+    /// What `name` points at on the remote, `None` if it doesn't exist.
+    fn remote(&self, name: &str) -> Option<String> {
+        let dir = self.root.join("remote.git");
+        let output = self.command("git", &dir, &["rev-parse", "-q", "--verify", name]);
+        let hash = String::from_utf8(output.stdout).unwrap();
+        output.status.success().then(|| hash.trim().to_string())
+    }
+}
+
+impl Drop for Repos {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+#[test]
+fn pushes_new_branches_and_tracks_them() {
+    let repos = Repos::new("new");
+    let (code, stderr) = repos.push(&["origin", "main"]);
+    assert_eq!(code, 0, "{stderr}");
+    assert_eq!(
+        stderr,
+        format!("To {}\n * [new branch]      main -> main\n", repos.url)
+    );
+    let head = repos.work(&["rev-parse", "HEAD"]);
+    assert_eq!(repos.remote("main"), Some(head.clone()));
+    assert_eq!(repos.work(&["rev-parse", "origin/main"]), head);
+
+    assert_eq!(
+        repos.push(&["origin", "main"]),
+        (0, "Everything up-to-date\n".to_string())
+    );
+}
+
+#[test]
+fn refuses_non_fast_forwards_unless_forced() {
+    let repos = Repos::new("force");
+    repos.push(&["origin", "main"]);
+    let old = repos.work(&["rev-parse", "HEAD"]);
+    repos.work(&["commit", "-q", "--amend", "-m", "amended"]);
+    let new = repos.work(&["rev-parse", "HEAD"]);
+
+    let (code, stderr) = repos.push(&["origin", "main"]);
+    assert_eq!(code, 1);
+    assert!(stderr.contains(" ! [rejected]        main -> main (non-fast-forward)\n"));
+    assert!(stderr.contains("hint: Updates were rejected because the tip of your current branch"));
+    assert_eq!(repos.remote("main"), Some(old.clone()));
+
+    let (code, stderr) = repos.push(&["--force", "origin", "main"]);
+    assert_eq!(code, 0, "{stderr}");
+    let summary = format!("{}...{}", &old[..7], &new[..7]);
+    assert!(stderr.contains(&format!(" + {summary} main -> main (forced update)\n")));
+    assert_eq!(repos.remote("main"), Some(new));
+}
+
+#[test]
+fn leases_expect_what_the_remote_has() {
+    let repos = Repos::new("lease");
+    repos.push(&["origin", "main"]);
+    let old = repos.work(&["rev-parse", "HEAD"]);
+    repos.work(&["commit", "-q", "--amend", "-m", "amended"]);
+    let new = repos.work(&["rev-parse", "HEAD"]);
+
+    let (code, stderr) = repos.push(&[&format!("--force-with-lease=main:{new}"), "origin", "main"]);
+    assert_eq!(code, 1);
+    assert!(stderr.contains(" ! [rejected]        main -> main (stale info)\n"));
+    assert_eq!(repos.remote("main"), Some(old));
+
+    // the remote-tracking ref says what the remote had
+    let (code, stderr) = repos.push(&["--force-with-lease", "origin", "main"]);
+    assert_eq!(code, 0, "{stderr}");
+    assert_eq!(repos.remote("main"), Some(new));
+}
+
+#[test]
+fn deletes_and_pushes_atomically() {
+    let repos = Repos::new("atomic");
+    repos.work(&["branch", "topic"]);
+    repos.push(&["origin", "main", "topic"]);
+
+    let (code, stderr) = repos.push(&["--delete", "origin", "topic"]);
+    assert_eq!(code, 0, "{stderr}");
+    assert!(stderr.contains(" - [deleted]         topic\n"));
+    assert_eq!(repos.remote("refs/heads/topic"), None);
+
+    repos.work(&["commit", "-q", "--amend", "-m", "amended"]);
+    repos.work(&["branch", "other"]);
+    let (code, stderr) = repos.push(&["--atomic", "origin", "main", "other"]);
+    assert_eq!(code, 1);
+    assert!(stderr.contains(" ! [rejected]        main -> main (non-fast-forward)\n"));
+    assert!(stderr.contains(" ! [rejected]        other -> other (atomic push failed)\n"));
+    assert_eq!(repos.remote("refs/heads/other"), None);
+}
+
+#[test]
+fn pushes_tags_without_moving_them() {
+    let repos = Repos::new("tags");
+    repos.work(&["tag", "light"]);
+    repos.work(&["tag", "-a", "-m", "annotated", "v1"]);
+
+    let (code, stderr) = repos.push(&["--tags", "origin"]);
+    assert_eq!(code, 0, "{stderr}");
+    assert!(stderr.contains(" * [new tag]         light -> light\n"));
+    assert!(stderr.contains(" * [new tag]         v1 -> v1\n"));
+    assert_eq!(
+        repos.remote("refs/tags/v1"),
+        Some(repos.work(&["rev-parse", "v1"]))
+    );
+
+    repos.commit("two");
+    repos.work(&["tag", "-f", "light"]);
+    let (code, stderr) = repos.push(&["origin", "light"]);
+    assert_eq!(code, 1);
+    assert!(stderr.contains(" ! [rejected]        light -> light (already exists)\n"));
+}
+
+#[test]
+fn pushes_the_current_branch_to_its_upstream() {
+    let repos = Repos::new("default");
+    let (code, stderr) = repos.push(&[]);
+    assert_eq!(code, 128);
+    assert!(stderr.starts_with("fatal: The current branch main has no upstream branch."));
+
+    repos.work(&["config", "branch.main.remote", "origin"]);
+    repos.work(&["config", "branch.main.merge", "refs/heads/main"]);
+    let (code, stderr) = repos.push(&[]);
+    assert_eq!(code, 0, "{stderr}");
+    assert_eq!(
+        repos.remote("main"),
+        Some(repos.work(&["rev-parse", "HEAD"]))
+    );
+
+    repos.work(&["config", "branch.main.merge", "refs/heads/other"]);
+    let (code, stderr) = repos.push(&[]);
+    assert_eq!(code, 128);
+    assert!(stderr.contains("does not match\nthe name of your current branch"));
+}
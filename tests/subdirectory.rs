@@ -0,0 +1,154 @@
+//! Commands run from a subdirectory of the work tree, where paths given
+//! are relative to it, the repository being set up by git itself.
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+/// A repository with `f` and `sub/f` committed twice.
+struct Repo {
+    root: PathBuf,
+}
+
+impl Repo {
+    fn new(name: &str) -> Repo {
+        let root = std::env::temp_dir().join(format!("subdirectory-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        let repo = Repo { root };
+
+        repo.git(&["init", "-q"]);
+        for message in ["one", "two"] {
+            repo.write("f", &format!("top {message}\n"));
+            repo.write("sub/f", &format!("sub {message}\n"));
+            repo.git(&["add", "f", "sub/f"]);
+            repo.git(&["commit", "-q", "-m", message]);
+        }
+        repo
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        std::fs::write(self.root.join(path), content).unwrap();
+    }
+
+    fn read(&self, path: &str) -> String {
+        std::fs::read_to_string(self.root.join(path)).unwrap()
+    }
+
+    fn command(&self, program: &str, dir: &Path, args: &[&str]) -> Output {
+        Command::new(program)
+            .args(args)
+            .current_dir(dir)
+            .env("HOME", &self.root)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_AUTHOR_NAME", "A U Thor")
+            .env("GIT_AUTHOR_EMAIL", "author@example.com")
+            .env("GIT_COMMITTER_NAME", "C O Mitter")
+            .env("GIT_COMMITTER_EMAIL", "committer@example.com")
+            .output()
+            .unwrap()
+    }
+
+    fn git(&self, args: &[&str]) -> String {
+        let output = self.command("git", &self.root, args);
+        assert!(output.status.success(), "git {args:?}: {output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Runs a command in `sub`, returning what it printed, or the error it
+    /// failed with.
+    fn run(&self, args: &[&str]) -> Result<String, String> {
+        let program = env!("CARGO_BIN_EXE_git-starter-rust");
+        let output = self.command(program, &self.root.join("sub"), args);
+        match output.status.success() {
+            true => Ok(String::from_utf8(output.stdout).unwrap()),
+            false => Err(String::from_utf8(output.stderr).unwrap()),
+        }
+    }
+}
+
+impl Drop for Repo {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+#[test]
+fn checks_out_paths_in_the_current_directory() {
+    let repo = Repo::new("checkout");
+    repo.write("f", "top changed\n");
+    repo.write("sub/f", "sub changed\n");
+
+    repo.run(&["checkout", "--", "f"]).unwrap();
+    assert_eq!(repo.read("sub/f"), "sub two\n");
+    assert_eq!(repo.read("f"), "top changed\n");
+
+    repo.run(&["checkout", "HEAD~1", "--", "../f"]).unwrap();
+    assert_eq!(repo.read("f"), "top one\n");
+}
+
+#[test]
+fn selects_paths_in_the_current_directory() {
+    let repo = Repo::new("paths");
+    repo.write("sub/f", "sub changed\n");
+
+    assert_eq!(
+        repo.run(&["log", "--format=%s", "--", "f"]).unwrap(),
+        "two\none\n"
+    );
+    let diff = repo.run(&["diff", "--name-only", "--", "."]).unwrap();
+    assert_eq!(diff, "sub/f\n");
+    let diff = repo.run(&["diff", "--name-only", "--", "../f"]).unwrap();
+    assert_eq!(diff, "");
+    let show = repo.run(&["show", "--format=", "--name-only", "HEAD", "--", "f"]);
+    assert_eq!(show.unwrap(), "sub/f\n");
+
+    let error = repo.run(&["log", "--", "../../f"]).unwrap_err();
+    assert!(error.starts_with("fatal: ../../f: '../../f' is outside repository at "));
+}
+
+#[test]
+fn finds_paths_relative_to_the_current_directory() {
+    let repo = Repo::new("revisions");
+    assert_eq!(repo.run(&["show", "HEAD:./f"]).unwrap(), "sub two\n");
+    assert_eq!(repo.run(&["show", "HEAD~1:../f"]).unwrap(), "top one\n");
+    assert_eq!(repo.run(&["show", "HEAD:f"]).unwrap(), "top two\n");
+    assert_eq!(repo.run(&["show", ":./f"]).unwrap(), "sub two\n");
+}
+
+#[test]
+fn lists_trees_from_the_current_directory() {
+    let repo = Repo::new("ls-tree");
+    let ls_tree = |args: &[&str]| {
+        let args = [&["ls-tree", "--name-only"], args].concat();
+        repo.run(&args).unwrap()
+    };
+
+    assert_eq!(ls_tree(&["HEAD"]), "f\n");
+    assert_eq!(ls_tree(&["HEAD", ".."]), "../f\n./\n");
+    assert_eq!(ls_tree(&["HEAD", "../f"]), "../f\n");
+    assert_eq!(ls_tree(&["--full-name", "HEAD"]), "sub/f\n");
+    assert_eq!(ls_tree(&["--full-name", "HEAD", "../f"]), "f\n");
+    assert_eq!(ls_tree(&["--full-tree", "HEAD"]), "f\nsub\n");
+    assert_eq!(ls_tree(&["--full-tree", "HEAD", "sub/"]), "sub/f\n");
+    assert_eq!(ls_tree(&["-r", "-t", "HEAD", "f"]), "./\nf\n");
+}
+
+#[test]
+fn needs_a_repository() {
+    let dir = std::env::temp_dir().join(format!("subdirectory-none-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_git-starter-rust"))
+        .args(["log"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(output.status.code(), Some(128));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "fatal: not a git repository (or any of the parent directories): .git\n"
+    );
+}